@@ -0,0 +1,237 @@
+//! Animations shown behind the clock hands.
+//!
+//! An [`Animation`] draws one frame at a time for a given wall time and the
+//! time it has been running. The built-in ones are listed in [`Builtin`],
+//! which is all a new effect needs to be added to: a module here and a
+//! variant in [`Builtin`] and [`Effect`].
+//!
+//! The [`Scheduler`] runs the selected animation, cross-fades to the next one
+//! when the selection changes and draws the wake light, the active schedule
+//! [`Block`], a running [`Countdown`] and the clock hands on top. While the
+//! time is not set, the marks of [`manual::render_unset`] replace everything
+//! that follows the wall clock.
+
+use core::time::Duration;
+
+use chrono::NaiveDateTime;
+use rgb::RGB8;
+
+use crate::{
+    manual,
+    ring::{self, ClockFace, Frame, BLACK, FRAMES_PER_SECOND, RING_SIZE},
+    schedule::Block,
+    timer::Countdown,
+};
+
+mod chase;
+mod comet;
+mod rainbow;
+
+pub use chase::Chase;
+pub use comet::Comet;
+pub use rainbow::Rainbow;
+
+/// Length of the cross-fade between two animations.
+pub const CROSSFADE: Duration = Duration::from_secs(1);
+
+pub trait Animation {
+    /// Draw into `frame`, which is cleared before every call.
+    ///
+    /// `now` is the local wall time, `elapsed` the time since the animation
+    /// was started.
+    fn render(&mut self, now: &NaiveDateTime, elapsed: Duration, frame: &mut Frame);
+}
+
+/// Index of the frame due at `elapsed` within the current second.
+pub fn frame_index(elapsed: Duration) -> usize {
+    (elapsed.subsec_nanos() as u64 * FRAMES_PER_SECOND as u64 / 1_000_000_000) as usize
+}
+
+/// The built-in animations.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Builtin {
+    /// Only the clock hands.
+    Off,
+    #[default]
+    Comet,
+    Chase,
+    Rainbow,
+}
+
+impl Builtin {
+    pub const ALL: [Self; 4] = [Self::Off, Self::Comet, Self::Chase, Self::Rainbow];
+
+    /// Name used in the settings and the API.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Comet => "comet",
+            Self::Chase => "chase",
+            Self::Rainbow => "rainbow",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|b| b.name() == name)
+    }
+
+    /// A fresh instance of the animation.
+    pub fn create(self) -> Effect {
+        match self {
+            Self::Off => Effect::Off,
+            Self::Comet => Effect::Comet(Comet::default()),
+            Self::Chase => Effect::Chase(Chase::default()),
+            Self::Rainbow => Effect::Rainbow(Rainbow::default()),
+        }
+    }
+}
+
+/// A running built-in animation.
+#[derive(Copy, Clone, Debug)]
+pub enum Effect {
+    Off,
+    Comet(Comet),
+    Chase(Chase),
+    Rainbow(Rainbow),
+}
+
+impl Animation for Effect {
+    fn render(&mut self, now: &NaiveDateTime, elapsed: Duration, frame: &mut Frame) {
+        match self {
+            Self::Off => {}
+            Self::Comet(comet) => comet.render(now, elapsed, frame),
+            Self::Chase(chase) => chase.render(now, elapsed, frame),
+            Self::Rainbow(rainbow) => rainbow.render(now, elapsed, frame),
+        }
+    }
+}
+
+struct Running {
+    animation: Builtin,
+    effect: Effect,
+    started: Duration,
+}
+
+impl Running {
+    fn new(animation: Builtin, started: Duration) -> Self {
+        Self {
+            animation,
+            effect: animation.create(),
+            started,
+        }
+    }
+
+    fn render(&mut self, now: &NaiveDateTime, elapsed: Duration, frame: &mut Frame) {
+        frame.fill(BLACK);
+        let elapsed = elapsed.saturating_sub(self.started);
+        self.effect.render(now, elapsed, frame);
+    }
+}
+
+/// Picks the active animation and composes the frames.
+///
+/// All times are monotonic, measured from an arbitrary but fixed start such
+/// as boot.
+pub struct Scheduler {
+    current: Running,
+    /// Animation being faded out.
+    previous: Option<Running>,
+    fading: Frame,
+    wake: Option<RGB8>,
+    block: Option<Block>,
+    timer: Option<Countdown>,
+    unset: bool,
+}
+
+impl Scheduler {
+    pub fn new(animation: Builtin, elapsed: Duration) -> Self {
+        Self {
+            current: Running::new(animation, elapsed),
+            previous: None,
+            fading: [BLACK; RING_SIZE],
+            wake: None,
+            block: None,
+            timer: None,
+            unset: false,
+        }
+    }
+
+    pub fn active(&self) -> Builtin {
+        self.current.animation
+    }
+
+    /// Switch to `animation`, fading over from the current one. Selecting the
+    /// active animation again does nothing.
+    pub fn select(&mut self, animation: Builtin, elapsed: Duration) {
+        if animation != self.current.animation {
+            let next = Running::new(animation, elapsed);
+            self.previous = Some(core::mem::replace(&mut self.current, next));
+        }
+    }
+
+    /// Fill the ring with the wake color `wake` over the animation, `None`
+    /// while the wake light is off, see [`crate::night`].
+    pub fn set_wake(&mut self, wake: Option<RGB8>) {
+        self.wake = wake;
+    }
+
+    /// Show the schedule block `block` over the animation, `None` outside
+    /// of any block.
+    ///
+    /// The block's own animation is not selected here, see
+    /// [`Scheduler::select`].
+    pub fn set_block(&mut self, block: Option<Block>) {
+        self.block = block;
+    }
+
+    /// Show `timer` over the animation, `None` to stop showing one.
+    pub fn set_timer(&mut self, timer: Option<Countdown>) {
+        self.timer = timer;
+    }
+
+    /// Show that the time is not set instead of the animation, the wake
+    /// light and the schedule block, which would all follow a wrong clock.
+    /// The timer and the hands still show.
+    pub fn set_unset(&mut self, unset: bool) {
+        self.unset = unset;
+    }
+
+    /// Draw the frame for `elapsed` into `frame`, wake light, schedule block,
+    /// timer and clock hands included.
+    pub fn render(
+        &mut self,
+        now: &NaiveDateTime,
+        elapsed: Duration,
+        face: &ClockFace,
+        frame: &mut Frame,
+    ) {
+        self.current.render(now, elapsed, frame);
+
+        let fade = elapsed.saturating_sub(self.current.started);
+        if fade >= CROSSFADE {
+            self.previous = None;
+        }
+        if let Some(previous) = &mut self.previous {
+            previous.render(now, elapsed, &mut self.fading);
+            let amount = (fade.as_millis() * 255 / CROSSFADE.as_millis()) as u8;
+            for (pixel, old) in frame.iter_mut().zip(&self.fading) {
+                *pixel = ring::blend(*old, *pixel, amount);
+            }
+        }
+
+        if let Some(wake) = self.wake {
+            frame.fill(wake);
+        }
+        if let Some(block) = &self.block {
+            block.render(now, frame);
+        }
+        if self.unset {
+            frame.fill(BLACK);
+            manual::render_unset(elapsed, frame);
+        }
+        if let Some(timer) = &self.timer {
+            timer.render(elapsed, frame);
+        }
+        face.render(now, frame);
+    }
+}
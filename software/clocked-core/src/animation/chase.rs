@@ -0,0 +1,35 @@
+use core::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use super::Animation;
+use crate::ring::{hsv2rgb, Frame, Hsv, RING_SIZE};
+
+/// A single dot stepping one LED ahead every second, slowly changing color.
+#[derive(Copy, Clone, Debug)]
+pub struct Chase {
+    pub color: Hsv,
+}
+
+impl Default for Chase {
+    fn default() -> Self {
+        Self {
+            color: Hsv {
+                hue: 200,
+                sat: 255,
+                val: 32,
+            },
+        }
+    }
+}
+
+impl Animation for Chase {
+    fn render(&mut self, _now: &NaiveDateTime, elapsed: Duration, frame: &mut Frame) {
+        let steps = elapsed.as_secs();
+        let hue = (self.color.hue as u64 + steps) % 255;
+        frame[(steps % RING_SIZE as u64) as usize] = hsv2rgb(Hsv {
+            hue: hue as u8,
+            ..self.color
+        });
+    }
+}
@@ -0,0 +1,94 @@
+use core::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use super::{frame_index, Animation};
+use crate::ring::{hsv2rgb, ClockFace, Frame, Hsv, FRAMES_PER_SECOND, RING_SIZE};
+
+/// A comet running once around the ring every second, starting at the
+/// second marker. Its tail grows with its speed and its hue moves on a bit
+/// every lap.
+#[derive(Copy, Clone, Debug)]
+pub struct Comet {
+    pub color: Hsv,
+    /// Lap in progress and the second marker it started from.
+    lap: Option<(u64, usize)>,
+}
+
+impl Default for Comet {
+    fn default() -> Self {
+        Self {
+            color: Hsv {
+                hue: 200,
+                sat: 255,
+                val: 32,
+            },
+            lap: None,
+        }
+    }
+}
+
+impl Animation for Comet {
+    fn render(&mut self, now: &NaiveDateTime, elapsed: Duration, frame: &mut Frame) {
+        let lap = elapsed.as_secs();
+        let current_second = match self.lap {
+            Some((current, second)) if current == lap => second,
+            previous => {
+                if previous.is_some() {
+                    self.color.hue = (self.color.hue + 1) % 255;
+                }
+                let second = ClockFace::second_index(now);
+                self.lap = Some((lap, second));
+                second
+            }
+        };
+        self.draw(current_second, frame_index(elapsed), frame);
+    }
+}
+
+impl Comet {
+    /// Draw frame `index` (of [`FRAMES_PER_SECOND`]) of the lap starting at
+    /// `current_second`.
+    fn draw(&self, current_second: usize, index: usize, data: &mut Frame) {
+        let frames = FRAMES_PER_SECOND;
+        let t = index as f32 / frames as f32; // normalized time [0.0, 1.0]
+        let eased = ease_in_out_cubic(t);
+
+        // Ring position of the head, one full lap starting at the second marker
+        let position = (current_second as f32 + eased * 60.0) % 60.0;
+        let head = round(position) % RING_SIZE;
+
+        // Light trail length proportional to speed (first derivative of easing)
+        let speed = if index > 0 {
+            let t_prev = (index - 1) as f32 / frames as f32;
+            (ease_in_out_cubic(t) - ease_in_out_cubic(t_prev)) * 60.0
+        } else {
+            1.0
+        };
+        let trail_len = (1.0 + speed * 4.0).clamp(1.0, 20.0) as usize;
+
+        for i in 0..trail_len {
+            let led_pos = (head + RING_SIZE - i) % RING_SIZE;
+            let fade = 1.0 - (i as f32 / trail_len as f32);
+            data[led_pos] = hsv2rgb(Hsv {
+                hue: self.color.hue,
+                sat: 255,
+                val: (fade * self.color.val as f32) as u8,
+            });
+        }
+    }
+}
+
+fn ease_in_out_cubic(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        let u = -2.0 * t + 2.0;
+        1.0 - u * u * u / 2.0
+    }
+}
+
+/// `f32::round` for non-negative values, which `core` does not provide.
+fn round(x: f32) -> usize {
+    (x + 0.5) as usize
+}
@@ -0,0 +1,37 @@
+use core::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use super::Animation;
+use crate::ring::{hsv2rgb, Frame, Hsv, RING_SIZE};
+
+/// The whole color wheel spread over the ring, turning slowly.
+#[derive(Copy, Clone, Debug)]
+pub struct Rainbow {
+    /// Time for one turn.
+    pub period: Duration,
+    pub val: u8,
+}
+
+impl Default for Rainbow {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(20),
+            val: 16,
+        }
+    }
+}
+
+impl Animation for Rainbow {
+    fn render(&mut self, _now: &NaiveDateTime, elapsed: Duration, frame: &mut Frame) {
+        let period = self.period.as_millis().max(1);
+        let turn = (elapsed.as_millis() % period * 256 / period) as usize;
+        for (index, pixel) in frame.iter_mut().enumerate() {
+            *pixel = hsv2rgb(Hsv {
+                hue: ((index * 256 / RING_SIZE + turn) % 256) as u8,
+                sat: 255,
+                val: self.val,
+            });
+        }
+    }
+}
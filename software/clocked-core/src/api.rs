@@ -0,0 +1,592 @@
+//! REST API of the clock.
+//!
+//! Every endpoint lives under `/api/` and speaks JSON:
+//!
+//! | Method       | Path              | Body                                     |
+//! |--------------|-------------------|------------------------------------------|
+//! | `GET`        | `/api/status`     |                                          |
+//! | `GET`, `PUT` | `/api/settings`   | any subset of the settings               |
+//! | `GET`, `PUT` | `/api/animation`  | `{"animation": "rainbow"}`               |
+//! | `GET`, `PUT` | `/api/brightness` | `{"brightness": 128}`                    |
+//! | `GET`        | `/api/timer`      |                                          |
+//! | `POST`       | `/api/timer`      | `{"minutes": 15}` or `{"seconds": 90}`   |
+//! | `DELETE`     | `/api/timer`      |                                          |
+//! | `GET`        | `/api/firmware`   |                                          |
+//! | `POST`       | `/api/firmware`   | `{"url": "http://...", "sha256": "..."}` |
+//! | `PUT`        | `/api/firmware`   | the image, see below                     |
+//!
+//! `PUT` changes only the settings given and answers with the result, errors
+//! come back as `{"error": "..."}`. The Wi-Fi credentials are shown without
+//! the password and can only be changed through the setup network. The MQTT
+//! password and the API token can be set but are never shown.
+//!
+//! With an `api_token` in the settings every request but a `GET` needs it
+//! as `Authorization: Bearer <token>`, see [`authorized`]. Without one the
+//! API is open to the whole network.
+//!
+//! `POST /api/firmware` has the clock download an update, see
+//! [`crate::ota`], the digest is optional but the image has to be signed.
+//! A `PUT` uploads the image itself. It is too large to go through
+//! [`handle`], the server streams it into flash on its own.
+//!
+//! [`handle`] answers a request from a [`State`] snapshot and a copy of the
+//! settings. It does not change anything on the clock itself, the changes
+//! come back as a [`Command`] for the caller to carry out. That keeps the
+//! whole API runnable on the host with canned requests.
+
+use core::fmt::{self, Write};
+use core::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use heapless::String;
+use rgb::RGB8;
+
+use crate::{
+    animation::Builtin,
+    http::{Request, Status, Url},
+    json::{self, Array, Object, Value},
+    mdns,
+    ntp::{self, MAX_HOST_LEN as MAX_NTP_HOST_LEN},
+    ota::{self, Download, Firmware, MAX_URL_LEN},
+    schedule::{parse_time, Block},
+    settings::{Settings, MAX_TIME_ZONE_LEN},
+    timer,
+    tz::TimeZone,
+};
+
+/// What the API reports about the running clock.
+#[derive(Copy, Clone, Debug)]
+pub struct State<'a> {
+    /// Local wall time.
+    pub now: NaiveDateTime,
+    /// Abbreviation of the time zone in effect, such as `CEST`.
+    pub time_zone: &'a str,
+    /// Time since the last successful time sync, `None` if never synced.
+    pub since_sync: Option<Duration>,
+    pub uptime: Duration,
+    pub heap_used: usize,
+    pub heap_free: usize,
+    /// Signal strength of the Wi-Fi network in dBm.
+    pub rssi: Option<i8>,
+    /// Time left on the countdown, `None` if none is running.
+    pub timer: Option<Duration>,
+    pub firmware: Firmware<'a>,
+}
+
+/// Change for the caller to carry out after answering.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Store and apply the changed copy of the settings.
+    SaveSettings,
+    StartTimer(Duration),
+    StopTimer,
+    /// Download and install an update, then restart.
+    UpdateFirmware(Download),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: Status,
+    pub command: Option<Command>,
+}
+
+/// Longest API token, see [`authorized`].
+pub const MAX_TOKEN_LEN: usize = 64;
+/// Shortest API token.
+pub const MIN_TOKEN_LEN: usize = 8;
+
+/// Settings `PUT /api/settings` accepts, in the order they are shown.
+const SETTINGS: [&str; 16] = [
+    "time_zone",
+    "brightness",
+    "hour_color",
+    "minute_color",
+    "second_color",
+    "animation",
+    "max_current_ma",
+    "led_offset",
+    "led_reversed",
+    "schedule",
+    "night",
+    "mqtt",
+    "ntp",
+    "device_name",
+    "wifi",
+    "api_token",
+];
+
+/// Answer `request`, writing the JSON body to `body`.
+///
+/// `settings` is a copy of the current settings, changed in place by `PUT`
+/// requests. Unless the response asks for [`Command::SaveSettings`] it may
+/// be half changed and has to be thrown away. With status 500 the body did
+/// not fit into `body` and has to be thrown away as well.
+pub fn handle(
+    request: &Request<'_>,
+    state: &State<'_>,
+    settings: &mut Settings,
+    body: &mut impl Write,
+) -> Response {
+    route(request, state, settings, body).unwrap_or(Response {
+        status: Status::INTERNAL_SERVER_ERROR,
+        command: None,
+    })
+}
+
+/// Whether `request` may change the clock. Only `GET` is free once the
+/// settings have an API token, everything else has to bring it as
+/// `Authorization: Bearer <token>`.
+pub fn authorized(request: &Request<'_>, settings: &Settings) -> bool {
+    if request.method == "GET" || settings.api_token.is_empty() {
+        return true;
+    }
+    let Some(token) = request
+        .header("authorization")
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+    else {
+        return false;
+    };
+    // compared in constant time, so the answer does not tell how much of a
+    // guess was right
+    let expected = settings.api_token.as_bytes();
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Whether `token` can be an API token, 8 to 64 printable ASCII characters
+/// without spaces.
+pub fn is_valid_token(token: &str) -> bool {
+    (MIN_TOKEN_LEN..=MAX_TOKEN_LEN).contains(&token.len())
+        && token.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Write `{"error": message}`.
+pub fn write_error(body: &mut impl Write, message: &str) -> fmt::Result {
+    let mut object = Object::new(body)?;
+    object.str("error", message)?;
+    object.finish()
+}
+
+/// Write `{"installed": source}`, the answer to an update that was written
+/// to flash and starts with the next restart.
+pub fn write_installed(body: &mut impl Write, source: &str) -> fmt::Result {
+    let mut object = Object::new(body)?;
+    object.str("installed", source)?;
+    object.finish()
+}
+
+fn route(
+    request: &Request<'_>,
+    state: &State<'_>,
+    settings: &mut Settings,
+    body: &mut impl Write,
+) -> Result<Response, fmt::Error> {
+    let Some(resource) = request.path.strip_prefix("/api/") else {
+        return error(body, Status::NOT_FOUND, "not found");
+    };
+    if !authorized(request, settings) {
+        return error(body, Status::UNAUTHORIZED, "unauthorized");
+    }
+    match (request.method, resource) {
+        ("GET", "status") => ok(write_status(body, state, settings), None),
+        ("GET", "settings") => ok(write_settings(body, settings), None),
+        ("PUT", "settings") => put(request, settings, &SETTINGS, body, write_settings),
+        ("GET", "animation") => ok(write_animation(body, settings), None),
+        ("PUT", "animation") => put(request, settings, &["animation"], body, write_animation),
+        ("GET", "brightness") => ok(write_brightness(body, settings), None),
+        ("PUT", "brightness") => put(request, settings, &["brightness"], body, write_brightness),
+        ("GET", "timer") => ok(write_timer(body, state.timer), None),
+        ("POST", "timer") => {
+            let Some(duration) = timer_duration(request.body_str()) else {
+                return error(body, Status::BAD_REQUEST, "expected minutes or seconds");
+            };
+            let duration = duration.min(timer::MAX);
+            ok(
+                write_timer(body, Some(duration)),
+                Some(Command::StartTimer(duration)),
+            )
+        }
+        ("DELETE", "timer") => ok(write_timer(body, None), Some(Command::StopTimer)),
+        ("GET", "firmware") => ok(write_firmware(body, &state.firmware), None),
+        ("POST", "firmware") => match download(request.body_str()) {
+            Ok(download) => ok(
+                write_installed(body, &download.url),
+                Some(Command::UpdateFirmware(download)),
+            ),
+            Err(problem) => error(body, Status::BAD_REQUEST, problem),
+        },
+        (_, "status" | "settings" | "animation" | "brightness" | "timer" | "firmware") => {
+            error(body, Status::METHOD_NOT_ALLOWED, "method not allowed")
+        }
+        _ => error(body, Status::NOT_FOUND, "not found"),
+    }
+}
+
+fn ok(written: fmt::Result, command: Option<Command>) -> Result<Response, fmt::Error> {
+    written.map(|()| Response {
+        status: Status::OK,
+        command,
+    })
+}
+
+fn error(body: &mut impl Write, status: Status, message: &str) -> Result<Response, fmt::Error> {
+    write_error(body, message)?;
+    Ok(Response {
+        status,
+        command: None,
+    })
+}
+
+/// Apply the members of the request body, all of them listed in `allowed`,
+/// and answer with `write`.
+fn put<W: Write>(
+    request: &Request<'_>,
+    settings: &mut Settings,
+    allowed: &[&str],
+    body: &mut W,
+    write: fn(&mut W, &Settings) -> fmt::Result,
+) -> Result<Response, fmt::Error> {
+    let Ok(update @ Value::Object(_)) = json::parse(request.body_str()) else {
+        return error(body, Status::BAD_REQUEST, "expected a JSON object");
+    };
+    for (key, value) in update.members() {
+        let result = if allowed.contains(&key) {
+            set(settings, key, value)
+        } else {
+            Err("unknown setting")
+        };
+        if let Err(problem) = result {
+            let mut message: String<64> = String::new();
+            // a cut off message is still better than none
+            let _ = write!(message, "{problem}: {key}");
+            return error(body, Status::BAD_REQUEST, &message);
+        }
+    }
+    ok(write(body, settings), Some(Command::SaveSettings))
+}
+
+fn set(settings: &mut Settings, key: &str, value: Value<'_>) -> Result<(), &'static str> {
+    const INVALID: &str = "invalid value";
+    match key {
+        "time_zone" => {
+            let tz = value.as_str::<MAX_TIME_ZONE_LEN>().ok_or(INVALID)?;
+            TimeZone::parse(&tz).map_err(|_| "invalid time zone")?;
+            settings.time_zone = tz;
+        }
+        "brightness" => settings.brightness = value.as_int().ok_or(INVALID)?,
+        "hour_color" => settings.hour_color = color(value).ok_or(INVALID)?,
+        "minute_color" => settings.minute_color = color(value).ok_or(INVALID)?,
+        "second_color" => settings.second_color = color(value).ok_or(INVALID)?,
+        "animation" => {
+            let name = value.as_str::<16>().ok_or(INVALID)?;
+            settings.animation = Builtin::from_name(&name).ok_or("unknown animation")?;
+        }
+        "max_current_ma" => settings.max_current_ma = value.as_int().ok_or(INVALID)?,
+        "led_offset" => settings.led_offset = value.as_int().ok_or(INVALID)?,
+        "led_reversed" => settings.led_reversed = value.as_bool().ok_or(INVALID)?,
+        "schedule" => {
+            if !matches!(value, Value::Array(_)) {
+                return Err(INVALID);
+            }
+            settings.schedule.blocks.clear();
+            for item in value.items() {
+                let text = item.as_str::<80>().ok_or(INVALID)?;
+                let block = Block::parse(&text).map_err(|_| "invalid block")?;
+                settings
+                    .schedule
+                    .blocks
+                    .push(block)
+                    .map_err(|_| "too many blocks")?;
+            }
+        }
+        "night" => {
+            if !matches!(value, Value::Object(_)) {
+                return Err(INVALID);
+            }
+            let night = &mut settings.night;
+            for (key, value) in value.members() {
+                match key {
+                    "enabled" => night.enabled = value.as_bool().ok_or(INVALID)?,
+                    "start" => night.start = time(value).ok_or(INVALID)?,
+                    "wake" => night.wake = time(value).ok_or(INVALID)?,
+                    "level" => night.level = value.as_int().ok_or(INVALID)?,
+                    "wake_color" => night.wake_color = color(value).ok_or(INVALID)?,
+                    "wake_minutes" => night.wake_minutes = value.as_int().ok_or(INVALID)?,
+                    _ => return Err("unknown night setting"),
+                }
+            }
+        }
+        "mqtt" => {
+            if !matches!(value, Value::Object(_)) {
+                return Err(INVALID);
+            }
+            let mqtt = &mut settings.mqtt;
+            for (key, value) in value.members() {
+                match key {
+                    "enabled" => mqtt.enabled = value.as_bool().ok_or(INVALID)?,
+                    "host" => mqtt.host = value.as_str().ok_or(INVALID)?,
+                    "port" => mqtt.port = value.as_int().filter(|&p| p > 0).ok_or(INVALID)?,
+                    "username" => mqtt.username = value.as_str().ok_or(INVALID)?,
+                    "password" => mqtt.password = value.as_str().ok_or(INVALID)?,
+                    "discovery" => mqtt.discovery = value.as_bool().ok_or(INVALID)?,
+                    _ => return Err("unknown mqtt setting"),
+                }
+            }
+        }
+        "ntp" => {
+            if !matches!(value, Value::Object(_)) {
+                return Err(INVALID);
+            }
+            let ntp = &mut settings.ntp;
+            for (key, value) in value.members() {
+                match key {
+                    "servers" => {
+                        if !matches!(value, Value::Array(_)) {
+                            return Err(INVALID);
+                        }
+                        ntp.servers.clear();
+                        for item in value.items() {
+                            let host = item
+                                .as_str::<MAX_NTP_HOST_LEN>()
+                                .filter(|host| ntp::is_valid_host(host))
+                                .ok_or("invalid server")?;
+                            ntp.servers.push(host).map_err(|_| "too many servers")?;
+                        }
+                    }
+                    "dhcp" => ntp.dhcp = value.as_bool().ok_or(INVALID)?,
+                    "http" => {
+                        let url = value.as_str::<{ ntp::MAX_URL_LEN }>().ok_or(INVALID)?;
+                        if !url.is_empty() && Url::parse(&url).is_none() {
+                            return Err("expected an http:// URL");
+                        }
+                        ntp.http = url;
+                    }
+                    "interval" => {
+                        ntp.interval = value
+                            .as_int()
+                            .filter(|i| (ntp::MIN_INTERVAL..=ntp::MAX_INTERVAL).contains(i))
+                            .ok_or("invalid interval")?
+                    }
+                    "retry" => {
+                        ntp.retry = value
+                            .as_int()
+                            .filter(|&r| r >= ntp::MIN_RETRY)
+                            .ok_or("invalid retry")?
+                    }
+                    _ => return Err("unknown ntp setting"),
+                }
+            }
+        }
+        "device_name" => {
+            let name = value.as_str().ok_or(INVALID)?;
+            if !name.is_empty() && !mdns::is_valid_name(&name) {
+                return Err("invalid device name");
+            }
+            settings.device_name = name;
+        }
+        "wifi" => return Err("use the setup network to change"),
+        "api_token" => {
+            let token = value.as_str().ok_or(INVALID)?;
+            if !token.is_empty() && !is_valid_token(&token) {
+                return Err("invalid token");
+            }
+            settings.api_token = token;
+        }
+        _ => return Err("unknown setting"),
+    }
+    Ok(())
+}
+
+/// `"#rrggbb"`.
+fn color(value: Value<'_>) -> Option<RGB8> {
+    let text = value.as_str::<7>()?;
+    let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let [_, r, g, b] = u32::from_str_radix(hex, 16).ok()?.to_be_bytes();
+    Some(RGB8::new(r, g, b))
+}
+
+/// `"HH:MM"` as minute of the day.
+fn time(value: Value<'_>) -> Option<u16> {
+    parse_time(&value.as_str::<5>()?)
+}
+
+fn timer_duration(body: &str) -> Option<Duration> {
+    let request = json::parse(body).ok()?;
+    let seconds = match (request.get("minutes"), request.get("seconds")) {
+        (Some(minutes), None) => minutes.as_int::<u64>()?.checked_mul(60)?,
+        (None, Some(seconds)) => seconds.as_int::<u64>()?,
+        _ => return None,
+    };
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+fn download(body: &str) -> Result<Download, &'static str> {
+    let Ok(request @ Value::Object(_)) = json::parse(body) else {
+        return Err("expected a JSON object");
+    };
+    let url = request
+        .get("url")
+        .and_then(|url| url.as_str::<MAX_URL_LEN>())
+        .ok_or("expected a URL")?;
+    Url::parse(&url).ok_or("expected an http:// URL")?;
+    let digest = match request.get("sha256") {
+        None | Some(Value::Null) => None,
+        Some(digest) => {
+            let digest = digest.as_str::<64>().ok_or("invalid sha256")?;
+            Some(ota::parse_digest(&digest).ok_or("invalid sha256")?)
+        }
+    };
+    Ok(Download { url, digest })
+}
+
+struct Color(RGB8);
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"#{:02x}{:02x}{:02x}\"", self.0.r, self.0.g, self.0.b)
+    }
+}
+
+struct Time(u16);
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{:02}:{:02}\"", self.0 / 60, self.0 % 60)
+    }
+}
+
+fn write_status(body: &mut impl Write, state: &State<'_>, settings: &Settings) -> fmt::Result {
+    let mut status = Object::new(body)?;
+    let now = state.now;
+    write!(
+        status.key("time")?,
+        "\"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}\"",
+        now.year(),
+        now.month(),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )?;
+    status.str("time_zone", state.time_zone)?;
+    status.raw("synced", state.since_sync.is_some())?;
+    status.option("since_sync", state.since_sync.map(|d| d.as_secs()))?;
+    status.raw("uptime", state.uptime.as_secs())?;
+    let mut heap = Object::new(status.key("heap")?)?;
+    heap.raw("used", state.heap_used)?;
+    heap.raw("free", state.heap_free)?;
+    heap.finish()?;
+    status.option("rssi", state.rssi)?;
+    status.option("timer", state.timer.map(remaining_secs))?;
+    status.str("animation", settings.animation.name())?;
+    status.raw("brightness", settings.brightness)?;
+    status.str("version", state.firmware.version)?;
+    status.finish()
+}
+
+fn write_settings(body: &mut impl Write, settings: &Settings) -> fmt::Result {
+    let mut object = Object::new(body)?;
+    object.str("time_zone", &settings.time_zone)?;
+    object.raw("brightness", settings.brightness)?;
+    object.raw("hour_color", Color(settings.hour_color))?;
+    object.raw("minute_color", Color(settings.minute_color))?;
+    object.raw("second_color", Color(settings.second_color))?;
+    object.str("animation", settings.animation.name())?;
+    object.raw("max_current_ma", settings.max_current_ma)?;
+    object.raw("led_offset", settings.led_offset)?;
+    object.raw("led_reversed", settings.led_reversed)?;
+
+    let mut schedule = Array::new(object.key("schedule")?)?;
+    for block in &settings.schedule.blocks {
+        let mut text: String<80> = String::new();
+        write!(text, "{block}")?;
+        schedule.str(&text)?;
+    }
+    schedule.finish()?;
+
+    let night = &settings.night;
+    let mut night_object = Object::new(object.key("night")?)?;
+    night_object.raw("enabled", night.enabled)?;
+    night_object.raw("start", Time(night.start))?;
+    night_object.raw("wake", Time(night.wake))?;
+    night_object.raw("level", night.level)?;
+    night_object.raw("wake_color", Color(night.wake_color))?;
+    night_object.raw("wake_minutes", night.wake_minutes)?;
+    night_object.finish()?;
+
+    let mqtt = &settings.mqtt;
+    let mut mqtt_object = Object::new(object.key("mqtt")?)?;
+    mqtt_object.raw("enabled", mqtt.enabled)?;
+    mqtt_object.str("host", &mqtt.host)?;
+    mqtt_object.raw("port", mqtt.port)?;
+    mqtt_object.str("username", &mqtt.username)?;
+    mqtt_object.raw("discovery", mqtt.discovery)?;
+    mqtt_object.finish()?;
+
+    let ntp = &settings.ntp;
+    let mut ntp_object = Object::new(object.key("ntp")?)?;
+    let mut servers = Array::new(ntp_object.key("servers")?)?;
+    for server in &ntp.servers {
+        servers.str(server)?;
+    }
+    servers.finish()?;
+    ntp_object.raw("dhcp", ntp.dhcp)?;
+    ntp_object.str("http", &ntp.http)?;
+    ntp_object.raw("interval", ntp.interval)?;
+    ntp_object.raw("retry", ntp.retry)?;
+    ntp_object.finish()?;
+
+    object.str("device_name", &settings.device_name)?;
+    match &settings.wifi {
+        Some(wifi) => {
+            let mut wifi_object = Object::new(object.key("wifi")?)?;
+            wifi_object.str("ssid", &wifi.ssid)?;
+            wifi_object.finish()?;
+        }
+        None => object.raw("wifi", "null")?,
+    }
+    object.finish()
+}
+
+fn write_animation(body: &mut impl Write, settings: &Settings) -> fmt::Result {
+    let mut object = Object::new(body)?;
+    object.str("animation", settings.animation.name())?;
+    let mut available = Array::new(object.key("available")?)?;
+    for animation in Builtin::ALL {
+        available.str(animation.name())?;
+    }
+    available.finish()?;
+    object.finish()
+}
+
+fn write_brightness(body: &mut impl Write, settings: &Settings) -> fmt::Result {
+    let mut object = Object::new(body)?;
+    object.raw("brightness", settings.brightness)?;
+    object.finish()
+}
+
+fn write_timer(body: &mut impl Write, remaining: Option<Duration>) -> fmt::Result {
+    let mut object = Object::new(body)?;
+    object.raw("running", remaining.is_some())?;
+    object.option("remaining", remaining.map(remaining_secs))?;
+    object.finish()
+}
+
+fn write_firmware(body: &mut impl Write, firmware: &Firmware<'_>) -> fmt::Result {
+    let mut object = Object::new(body)?;
+    object.str("version", firmware.version)?;
+    object.str("partition", firmware.slot.name())?;
+    object.raw("trial", firmware.trial)?;
+    object.finish()
+}
+
+/// Whole seconds left, the one just started counts.
+fn remaining_secs(remaining: Duration) -> u64 {
+    remaining.as_nanos().div_ceil(1_000_000_000) as u64
+}
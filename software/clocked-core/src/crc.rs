@@ -0,0 +1,23 @@
+//! CRC-32 (IEEE 802.3), the same checksum zlib and the ESP-IDF tools use.
+
+/// Checksum of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Continue a checksum over more data, start with `0`.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    // bitwise instead of a table, settings records are small
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,522 @@
+//! DHCP messages (RFC 2131) and a tiny server for the setup access point.
+//!
+//! embassy-net only implements the client side, but phones joining the setup
+//! network need an address before they can open the setup page. Its client
+//! does not pass on the NTP servers (option 42) either, [`inform`] asks the
+//! DHCP server for them once the station has an address.
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+
+const OP_REQUEST: u8 = 1;
+const OP_REPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Fixed BOOTP part plus magic cookie, options follow.
+const OPTIONS_START: usize = 240;
+/// BOOTP requires messages of at least 300 bytes.
+const MIN_MESSAGE_LEN: usize = 300;
+
+pub const OPTION_PAD: u8 = 0;
+pub const OPTION_SUBNET_MASK: u8 = 1;
+pub const OPTION_ROUTER: u8 = 3;
+pub const OPTION_DNS_SERVER: u8 = 6;
+pub const OPTION_NTP_SERVERS: u8 = 42;
+pub const OPTION_REQUESTED_IP: u8 = 50;
+pub const OPTION_LEASE_TIME: u8 = 51;
+pub const OPTION_MESSAGE_TYPE: u8 = 53;
+pub const OPTION_SERVER_ID: u8 = 54;
+pub const OPTION_PARAMETER_REQUEST_LIST: u8 = 55;
+pub const OPTION_END: u8 = 255;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Decline = 4,
+    Ack = 5,
+    Nak = 6,
+    Release = 7,
+    Inform = 8,
+}
+
+impl MessageType {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Ack,
+            6 => Self::Nak,
+            7 => Self::Release,
+            8 => Self::Inform,
+            _ => return None,
+        })
+    }
+}
+
+/// Read only view of a received DHCP message.
+#[derive(Copy, Clone, Debug)]
+pub struct Packet<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        (data.len() >= OPTIONS_START && data[236..240] == MAGIC_COOKIE).then_some(Self { data })
+    }
+
+    pub fn op(&self) -> u8 {
+        self.data[0]
+    }
+
+    pub fn xid(&self) -> u32 {
+        u32::from_be_bytes([self.data[4], self.data[5], self.data[6], self.data[7]])
+    }
+
+    pub fn flags(&self) -> u16 {
+        u16::from_be_bytes([self.data[10], self.data[11]])
+    }
+
+    pub fn ciaddr(&self) -> [u8; 4] {
+        self.address(12)
+    }
+
+    pub fn yiaddr(&self) -> [u8; 4] {
+        self.address(16)
+    }
+
+    /// Hardware address of the client, assuming Ethernet (6 bytes).
+    pub fn chaddr(&self) -> [u8; 6] {
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&self.data[28..34]);
+        mac
+    }
+
+    fn address(&self, pos: usize) -> [u8; 4] {
+        let mut address = [0; 4];
+        address.copy_from_slice(&self.data[pos..pos + 4]);
+        address
+    }
+
+    /// All options as `(code, value)` pairs, stopping at the end option or
+    /// at the first truncated one.
+    pub fn options(&self) -> impl Iterator<Item = (u8, &'a [u8])> {
+        let data = self.data;
+        let mut pos = OPTIONS_START;
+        core::iter::from_fn(move || loop {
+            let code = *data.get(pos)?;
+            match code {
+                OPTION_PAD => pos += 1,
+                OPTION_END => return None,
+                _ => {
+                    let len = *data.get(pos + 1)? as usize;
+                    let value = data.get(pos + 2..pos + 2 + len)?;
+                    pos += 2 + len;
+                    return Some((code, value));
+                }
+            }
+        })
+    }
+
+    pub fn option(&self, code: u8) -> Option<&'a [u8]> {
+        self.options()
+            .find(|&(c, _)| c == code)
+            .map(|(_, value)| value)
+    }
+
+    pub fn message_type(&self) -> Option<MessageType> {
+        MessageType::from_u8(*self.option(OPTION_MESSAGE_TYPE)?.first()?)
+    }
+
+    fn address_option(&self, code: u8) -> Option<[u8; 4]> {
+        self.option(code)?.try_into().ok()
+    }
+
+    /// All addresses in an option listing some, e.g. [`OPTION_NTP_SERVERS`].
+    pub fn addresses(&self, code: u8) -> impl Iterator<Item = [u8; 4]> + 'a {
+        self.option(code)
+            .unwrap_or_default()
+            .chunks_exact(4)
+            .map(|address| address.try_into().expect("4 bytes"))
+    }
+
+    /// Whether this is the server acknowledging the client message `xid`.
+    pub fn is_ack_for(&self, xid: u32) -> bool {
+        self.op() == OP_REPLY && self.xid() == xid && self.message_type() == Some(MessageType::Ack)
+    }
+}
+
+/// DHCPINFORM of a client that already has `address`, asking the server for
+/// the options in `requested` (RFC 2131 section 3.4). Returns the length of
+/// the message, `None` if it does not fit into `out`.
+pub fn inform(
+    out: &mut [u8],
+    xid: u32,
+    chaddr: [u8; 6],
+    address: [u8; 4],
+    requested: &[u8],
+) -> Option<usize> {
+    let message = Builder::new(out, OP_REQUEST, xid, chaddr)?
+        .ciaddr(address)
+        .option(OPTION_MESSAGE_TYPE, &[MessageType::Inform as u8])?
+        .option(OPTION_PARAMETER_REQUEST_LIST, requested)?;
+    Some(message.finish())
+}
+
+/// Incrementally writes a DHCP message into a buffer.
+pub struct Builder<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Builder<'a> {
+    /// Start a message, `None` if `out` is too small for a minimum sized
+    /// message. All address fields start out as `0.0.0.0`.
+    pub fn new(out: &'a mut [u8], op: u8, xid: u32, chaddr: [u8; 6]) -> Option<Self> {
+        if out.len() < MIN_MESSAGE_LEN {
+            return None;
+        }
+        out[..OPTIONS_START].fill(0);
+        out[0] = op;
+        out[1] = HTYPE_ETHERNET;
+        out[2] = 6;
+        out[4..8].copy_from_slice(&xid.to_be_bytes());
+        out[28..34].copy_from_slice(&chaddr);
+        out[236..240].copy_from_slice(&MAGIC_COOKIE);
+        Some(Self {
+            out,
+            len: OPTIONS_START,
+        })
+    }
+
+    pub fn flags(self, flags: u16) -> Self {
+        self.out[10..12].copy_from_slice(&flags.to_be_bytes());
+        self
+    }
+
+    pub fn ciaddr(self, address: [u8; 4]) -> Self {
+        self.out[12..16].copy_from_slice(&address);
+        self
+    }
+
+    pub fn yiaddr(self, address: [u8; 4]) -> Self {
+        self.out[16..20].copy_from_slice(&address);
+        self
+    }
+
+    pub fn siaddr(self, address: [u8; 4]) -> Self {
+        self.out[20..24].copy_from_slice(&address);
+        self
+    }
+
+    /// Append an option, `None` if it does not fit.
+    pub fn option(mut self, code: u8, value: &[u8]) -> Option<Self> {
+        // keep room for the end option
+        let end = self.len + 2 + value.len();
+        if value.len() > 255 || end >= self.out.len() {
+            return None;
+        }
+        self.out[self.len] = code;
+        self.out[self.len + 1] = value.len() as u8;
+        self.out[self.len + 2..end].copy_from_slice(value);
+        self.len = end;
+        Some(self)
+    }
+
+    /// Terminate the options and pad to the minimum length, returns the
+    /// length of the message.
+    pub fn finish(self) -> usize {
+        self.out[self.len] = OPTION_END;
+        let len = (self.len + 1).max(MIN_MESSAGE_LEN);
+        self.out[self.len + 1..len].fill(0);
+        len
+    }
+}
+
+/// DHCP server handing out addresses in the /24 network of `address`.
+///
+/// Leases are remembered by hardware address and never expire, the setup
+/// access point only ever sees a handful of clients.
+pub struct Server<const N: usize> {
+    address: [u8; 4],
+    first_host: u8,
+    lease_time: u32,
+    leases: [Option<[u8; 6]>; N],
+}
+
+impl<const N: usize> Server<N> {
+    /// Server at `address`, offering `first_host` to `first_host + N - 1`
+    /// as last octet.
+    pub fn new(address: [u8; 4], first_host: u8, lease_time: u32) -> Self {
+        Self {
+            address,
+            first_host,
+            lease_time,
+            leases: [None; N],
+        }
+    }
+
+    fn host_address(&self, index: usize) -> [u8; 4] {
+        let [a, b, c, _] = self.address;
+        [a, b, c, self.first_host + index as u8]
+    }
+
+    /// Lease index for `mac`, allocating a free one if needed.
+    fn lease(&mut self, mac: [u8; 6]) -> Option<usize> {
+        if let Some(index) = self.leases.iter().position(|&l| l == Some(mac)) {
+            return Some(index);
+        }
+        let index = self.leases.iter().position(Option::is_none)?;
+        self.leases[index] = Some(mac);
+        Some(index)
+    }
+
+    /// Handle a client message, writing the reply to `out`.
+    ///
+    /// Returns the length of the reply, which is to be broadcast to
+    /// [`CLIENT_PORT`], or `None` if there is nothing to answer.
+    pub fn handle(&mut self, request: &[u8], out: &mut [u8]) -> Option<usize> {
+        let request = Packet::parse(request)?;
+        if request.op() != OP_REQUEST {
+            return None;
+        }
+        let mac = request.chaddr();
+        let (reply_type, yiaddr) = match request.message_type()? {
+            MessageType::Discover => {
+                let index = self.lease(mac)?;
+                (MessageType::Offer, self.host_address(index))
+            }
+            MessageType::Request => {
+                if let Some(server) = request.address_option(OPTION_SERVER_ID) {
+                    if server != self.address {
+                        // the client picked another server
+                        return None;
+                    }
+                }
+                let requested = request
+                    .address_option(OPTION_REQUESTED_IP)
+                    .unwrap_or(request.ciaddr());
+                match self.lease(mac).map(|index| self.host_address(index)) {
+                    Some(leased) if leased == requested => (MessageType::Ack, leased),
+                    _ => (MessageType::Nak, [0; 4]),
+                }
+            }
+            MessageType::Release => {
+                if let Some(lease) = self.leases.iter_mut().find(|l| **l == Some(mac)) {
+                    *lease = None;
+                }
+                return None;
+            }
+            _ => return None,
+        };
+
+        let builder = Builder::new(out, OP_REPLY, request.xid(), mac)?
+            .flags(request.flags())
+            .yiaddr(yiaddr)
+            .siaddr(self.address)
+            .option(OPTION_MESSAGE_TYPE, &[reply_type as u8])?
+            .option(OPTION_SERVER_ID, &self.address)?;
+        let builder = if reply_type == MessageType::Nak {
+            builder
+        } else {
+            builder
+                .option(OPTION_LEASE_TIME, &self.lease_time.to_be_bytes())?
+                .option(OPTION_SUBNET_MASK, &[255, 255, 255, 0])?
+                .option(OPTION_ROUTER, &self.address)?
+                .option(OPTION_DNS_SERVER, &self.address)?
+        };
+        Some(builder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: [u8; 4] = [192, 168, 4, 1];
+    const PHONE: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+    const LAPTOP: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+    const TABLET: [u8; 6] = [0x02, 0, 0, 0, 0, 0x03];
+
+    /// Message of a client, `options` after the message type.
+    fn client(
+        out: &mut [u8; 576],
+        message_type: MessageType,
+        mac: [u8; 6],
+        options: &[(u8, &[u8])],
+    ) -> usize {
+        let mut builder = Builder::new(out, OP_REQUEST, 0x1234_5678, mac)
+            .unwrap()
+            .flags(0x8000)
+            .option(OPTION_MESSAGE_TYPE, &[message_type as u8])
+            .unwrap();
+        for (code, value) in options {
+            builder = builder.option(*code, value).unwrap();
+        }
+        builder.finish()
+    }
+
+    /// Reply of `server` to a client message, `None` if there is none.
+    fn exchange<const N: usize>(
+        server: &mut Server<N>,
+        message_type: MessageType,
+        mac: [u8; 6],
+        options: &[(u8, &[u8])],
+    ) -> Option<([u8; 576], usize)> {
+        let mut request = [0; 576];
+        let len = client(&mut request, message_type, mac, options);
+        let mut reply = [0; 576];
+        let len = server.handle(&request[..len], &mut reply)?;
+        Some((reply, len))
+    }
+
+    fn discover<const N: usize>(server: &mut Server<N>, mac: [u8; 6]) -> Option<[u8; 4]> {
+        let (reply, len) = exchange(server, MessageType::Discover, mac, &[])?;
+        let reply = Packet::parse(&reply[..len]).unwrap();
+        assert_eq!(reply.message_type(), Some(MessageType::Offer));
+        Some(reply.yiaddr())
+    }
+
+    #[test]
+    fn discover_offer() {
+        let mut server = Server::<4>::new(SERVER, 2, 3600);
+        let (reply, len) = exchange(&mut server, MessageType::Discover, PHONE, &[]).unwrap();
+        assert_eq!(len, MIN_MESSAGE_LEN);
+        let reply = Packet::parse(&reply[..len]).unwrap();
+        assert_eq!(reply.op(), OP_REPLY);
+        assert_eq!(reply.xid(), 0x1234_5678);
+        assert_eq!(reply.flags(), 0x8000);
+        assert_eq!(reply.chaddr(), PHONE);
+        assert_eq!(reply.message_type(), Some(MessageType::Offer));
+        assert_eq!(reply.yiaddr(), [192, 168, 4, 2]);
+        assert_eq!(reply.option(OPTION_SERVER_ID), Some(&SERVER[..]));
+        assert_eq!(
+            reply.option(OPTION_LEASE_TIME),
+            Some(&[0, 0, 0x0e, 0x10][..])
+        );
+        assert_eq!(
+            reply.option(OPTION_SUBNET_MASK),
+            Some(&[255, 255, 255, 0][..])
+        );
+        assert_eq!(reply.option(OPTION_ROUTER), Some(&SERVER[..]));
+        assert!(reply.addresses(OPTION_DNS_SERVER).eq([SERVER]));
+
+        // a second discover gets the same address, another client the next
+        assert_eq!(discover(&mut server, PHONE), Some([192, 168, 4, 2]));
+        assert_eq!(discover(&mut server, LAPTOP), Some([192, 168, 4, 3]));
+    }
+
+    #[test]
+    fn request_ack() {
+        let mut server = Server::<4>::new(SERVER, 2, 3600);
+        let offered = discover(&mut server, PHONE).unwrap();
+        let (reply, len) = exchange(
+            &mut server,
+            MessageType::Request,
+            PHONE,
+            &[(OPTION_REQUESTED_IP, &offered), (OPTION_SERVER_ID, &SERVER)],
+        )
+        .unwrap();
+        let reply = Packet::parse(&reply[..len]).unwrap();
+        assert_eq!(reply.message_type(), Some(MessageType::Ack));
+        assert_eq!(reply.yiaddr(), offered);
+        assert!(reply.is_ack_for(0x1234_5678));
+        assert!(!reply.is_ack_for(0x1234_5679));
+        assert_eq!(
+            reply.option(OPTION_LEASE_TIME),
+            Some(&[0, 0, 0x0e, 0x10][..])
+        );
+
+        // the client picked the offer of another server
+        let other = [192, 168, 4, 254];
+        let request = [
+            (OPTION_REQUESTED_IP, &offered[..]),
+            (OPTION_SERVER_ID, &other),
+        ];
+        assert!(exchange(&mut server, MessageType::Request, PHONE, &request).is_none());
+
+        // an address from another network is refused
+        let (reply, len) = exchange(
+            &mut server,
+            MessageType::Request,
+            PHONE,
+            &[(OPTION_REQUESTED_IP, &[10, 0, 0, 7])],
+        )
+        .unwrap();
+        let reply = Packet::parse(&reply[..len]).unwrap();
+        assert_eq!(reply.message_type(), Some(MessageType::Nak));
+        assert_eq!(reply.yiaddr(), [0; 4]);
+        assert_eq!(reply.option(OPTION_LEASE_TIME), None);
+    }
+
+    #[test]
+    fn lease_exhaustion() {
+        let mut server = Server::<2>::new(SERVER, 2, 3600);
+        assert_eq!(discover(&mut server, PHONE), Some([192, 168, 4, 2]));
+        assert_eq!(discover(&mut server, LAPTOP), Some([192, 168, 4, 3]));
+        assert_eq!(discover(&mut server, TABLET), None);
+        // known clients keep their addresses
+        assert_eq!(discover(&mut server, LAPTOP), Some([192, 168, 4, 3]));
+
+        assert!(exchange(&mut server, MessageType::Release, PHONE, &[]).is_none());
+        assert_eq!(discover(&mut server, TABLET), Some([192, 168, 4, 2]));
+    }
+
+    #[test]
+    fn ignored_messages() {
+        let mut server = Server::<2>::new(SERVER, 2, 3600);
+        let mut request = [0; 576];
+        let len = client(&mut request, MessageType::Discover, PHONE, &[]);
+        let mut reply = [0; 576];
+        // too short, without the magic cookie, a reply and a decline
+        assert_eq!(
+            server.handle(&request[..OPTIONS_START - 1], &mut reply),
+            None
+        );
+        let mut no_cookie = request;
+        no_cookie[236] = 0;
+        assert_eq!(server.handle(&no_cookie[..len], &mut reply), None);
+        let mut from_server = request;
+        from_server[0] = OP_REPLY;
+        assert_eq!(server.handle(&from_server[..len], &mut reply), None);
+        assert!(exchange(&mut server, MessageType::Decline, PHONE, &[]).is_none());
+        // no room for the reply
+        assert_eq!(server.handle(&request[..len], &mut reply[..299]), None);
+    }
+
+    #[test]
+    fn options() {
+        let mut out = [0; 576];
+        let len = inform(&mut out, 7, PHONE, [192, 168, 1, 20], &[OPTION_NTP_SERVERS]).unwrap();
+        let packet = Packet::parse(&out[..len]).unwrap();
+        assert_eq!(packet.ciaddr(), [192, 168, 1, 20]);
+        assert_eq!(packet.message_type(), Some(MessageType::Inform));
+        assert_eq!(
+            packet.option(OPTION_PARAMETER_REQUEST_LIST),
+            Some(&[OPTION_NTP_SERVERS][..])
+        );
+
+        // pads are skipped, a truncated option ends the list
+        let mut data = [0; OPTIONS_START + 16];
+        data[236..240].copy_from_slice(&MAGIC_COOKIE);
+        #[rustfmt::skip]
+        let options = [
+            OPTION_PAD,
+            OPTION_NTP_SERVERS, 8, 192, 168, 1, 1, 10, 0, 0, 1,
+            OPTION_PAD,
+            OPTION_MESSAGE_TYPE, 1, 5,
+            OPTION_LEASE_TIME,
+        ];
+        data[OPTIONS_START..].copy_from_slice(&options);
+        let packet = Packet::parse(&data).unwrap();
+        assert!(packet
+            .addresses(OPTION_NTP_SERVERS)
+            .eq([[192, 168, 1, 1], [10, 0, 0, 1]]));
+        assert_eq!(packet.message_type(), Some(MessageType::Ack));
+        assert_eq!(packet.option(OPTION_LEASE_TIME), None);
+        assert_eq!(packet.options().count(), 2);
+    }
+}
@@ -0,0 +1,176 @@
+//! DNS message handling for the captive portal.
+//!
+//! While in setup mode the clock answers every `A` query with its own
+//! address, which makes phones and laptops show the setup page on their own.
+
+/// Size of the fixed DNS header.
+pub const HEADER_LEN: usize = 12;
+
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+
+/// Time to live of captive answers. Short, so clients forget about them
+/// quickly once the clock has left setup mode.
+const CAPTIVE_TTL: u32 = 60;
+
+/// Offset just past the encoded name starting at `pos`, following no
+/// compression pointers.
+pub fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)? as usize;
+        match len {
+            0 => return Some(pos + 1),
+            // compression pointer, two bytes and the name ends
+            l if l & 0xc0 == 0xc0 => return Some(pos + 2),
+            l if l & 0xc0 != 0 => return None,
+            l => pos += 1 + l,
+        }
+    }
+}
+
+/// Answer a query with `address` for every name.
+///
+/// Writes the response to `out` and returns its length. Queries for other
+/// record types get an empty answer, anything that is not a plain standard
+/// query with a single question is ignored.
+pub fn captive_response(query: &[u8], address: [u8; 4], out: &mut [u8]) -> Option<usize> {
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let is_query = flags & 0x8000 == 0;
+    let opcode = (flags >> 11) & 0xf;
+    let questions = u16::from_be_bytes([query[4], query[5]]);
+    if !is_query || opcode != 0 || questions != 1 {
+        return None;
+    }
+
+    let name_end = skip_name(query, HEADER_LEN)?;
+    let question_end = name_end + 4;
+    let question = query.get(HEADER_LEN..question_end)?;
+    let qtype = u16::from_be_bytes([query[name_end], query[name_end + 1]]);
+    let qclass = u16::from_be_bytes([query[name_end + 2], query[name_end + 3]]) & 0x7fff;
+    let answer = (qtype == TYPE_A || qtype == TYPE_ANY) && qclass == CLASS_IN;
+
+    let answer_len = if answer { 16 } else { 0 };
+    let len = question_end + answer_len;
+    let out = out.get_mut(..len)?;
+
+    // response, authoritative, recursion desired copied from the query
+    let flags = 0x8400 | (flags & 0x0100);
+    out[..2].copy_from_slice(&query[..2]);
+    out[2..4].copy_from_slice(&flags.to_be_bytes());
+    out[4..6].copy_from_slice(&1u16.to_be_bytes());
+    out[6..8].copy_from_slice(&(answer as u16).to_be_bytes());
+    out[8..12].fill(0);
+    out[HEADER_LEN..question_end].copy_from_slice(question);
+
+    if answer {
+        let a = &mut out[question_end..];
+        // pointer to the name in the question
+        a[..2].copy_from_slice(&(0xc000u16 | HEADER_LEN as u16).to_be_bytes());
+        a[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        a[4..6].copy_from_slice(&CLASS_IN.to_be_bytes());
+        a[6..10].copy_from_slice(&CAPTIVE_TTL.to_be_bytes());
+        a[10..12].copy_from_slice(&4u16.to_be_bytes());
+        a[12..16].copy_from_slice(&address);
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK: [u8; 4] = [192, 168, 4, 1];
+
+    /// Query with recursion desired for `connectivitycheck.gstatic.com`.
+    fn query(qtype: u16, out: &mut [u8; 64]) -> usize {
+        let mut len = HEADER_LEN;
+        out[..len].copy_from_slice(&[0xab, 0xcd, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in ["connectivitycheck", "gstatic", "com"] {
+            out[len] = label.len() as u8;
+            out[len + 1..len + 1 + label.len()].copy_from_slice(label.as_bytes());
+            len += 1 + label.len();
+        }
+        out[len] = 0;
+        out[len + 1..len + 3].copy_from_slice(&qtype.to_be_bytes());
+        out[len + 3..len + 5].copy_from_slice(&CLASS_IN.to_be_bytes());
+        len + 5
+    }
+
+    #[test]
+    fn answers_with_own_address() {
+        let mut request = [0; 64];
+        let len = query(TYPE_A, &mut request);
+        assert_eq!(skip_name(&request, HEADER_LEN), Some(len - 4));
+        let mut out = [0; 128];
+        let response_len = captive_response(&request[..len], CLOCK, &mut out).unwrap();
+        assert_eq!(response_len, len + 16);
+        // same id, response with recursion desired, one question and answer
+        assert_eq!(
+            out[..HEADER_LEN],
+            [0xab, 0xcd, 0x85, 0x00, 0, 1, 0, 1, 0, 0, 0, 0]
+        );
+        assert_eq!(out[HEADER_LEN..len], request[HEADER_LEN..len]);
+        #[rustfmt::skip]
+        let answer = [
+            0xc0, 0x0c, // name of the question
+            0, 1, 0, 1, // A, IN
+            0, 0, 0, 60, // TTL
+            0, 4, 192, 168, 4, 1,
+        ];
+        assert_eq!(out[len..response_len], answer);
+
+        // ANY as well, with the unicast response bit of mDNS in the class
+        let len = query(TYPE_ANY, &mut request);
+        request[len - 2] |= 0x80;
+        let response_len = captive_response(&request[..len], CLOCK, &mut out).unwrap();
+        assert_eq!(out[6..8], [0, 1]);
+        assert_eq!(out[len + 12..response_len], CLOCK);
+    }
+
+    #[test]
+    fn empty_answer_for_other_types() {
+        let mut request = [0; 64];
+        // AAAA
+        let len = query(28, &mut request);
+        let mut out = [0; 128];
+        assert_eq!(
+            captive_response(&request[..len], CLOCK, &mut out),
+            Some(len)
+        );
+        assert_eq!(out[2..8], [0x85, 0x00, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn ignored_queries() {
+        let mut request = [0; 64];
+        let len = query(TYPE_A, &mut request);
+        let mut out = [0; 128];
+        let mut response = request;
+        response[2] |= 0x80;
+        let mut status = request;
+        status[2] |= 2 << 3;
+        let mut two_questions = request;
+        two_questions[5] = 2;
+        let mut bad_label = request;
+        bad_label[HEADER_LEN] = 0x40;
+        for query in [
+            &request[..HEADER_LEN - 1],
+            &request[..len - 1],
+            &response[..len],
+            &status[..len],
+            &two_questions[..len],
+            &bad_label[..len],
+        ] {
+            assert_eq!(captive_response(query, CLOCK, &mut out), None);
+        }
+        // no room for the answer
+        assert_eq!(
+            captive_response(&request[..len], CLOCK, &mut out[..len]),
+            None
+        );
+    }
+}
@@ -0,0 +1,518 @@
+//! Ed25519 signatures (RFC 8032), which firmware updates are signed with,
+//! see [`crate::ota`].
+//!
+//! A port of the signing and verification in TweetNaCl: field elements are
+//! 16 limbs of 16 bits in `i64`, points are extended coordinates
+//! `(X, Y, Z, T)` and scalar multiplication is a constant time ladder. Small
+//! rather than fast, a verification takes a fraction of a second on the
+//! clock and happens once per update.
+//!
+//! The secret key is the 32 byte seed of RFC 8032, the public key is derived
+//! from it with [`public_key`].
+
+// the index loops follow TweetNaCl, where limbs of several numbers meet
+#![allow(clippy::needless_range_loop)]
+
+use crate::sha512::Sha512;
+
+pub type PublicKey = [u8; 32];
+pub type Seed = [u8; 32];
+pub type Signature = [u8; 64];
+
+/// Element of the field modulo 2^255 - 19.
+type Field = [i64; 16];
+/// Point on the curve in extended coordinates.
+type Point = [Field; 4];
+
+const ZERO: Field = [0; 16];
+const ONE: Field = {
+    let mut one = ZERO;
+    one[0] = 1;
+    one
+};
+/// The curve constant d.
+#[rustfmt::skip]
+const D: Field = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070,
+    0xe898, 0x7779, 0x4079, 0x8cc7, 0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+/// 2 d.
+#[rustfmt::skip]
+const D2: Field = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0,
+    0xd130, 0xeef3, 0x80f2, 0x198e, 0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+/// Coordinates of the base point.
+#[rustfmt::skip]
+const X: Field = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c,
+    0xdc5c, 0xfdd6, 0xe231, 0xc0a4, 0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+#[rustfmt::skip]
+const Y: Field = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+];
+/// A square root of -1.
+#[rustfmt::skip]
+const I: Field = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43,
+    0xd7a7, 0x3dfb, 0x0099, 0x2b4d, 0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+/// Order of the base point, little endian.
+#[rustfmt::skip]
+const L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58,
+    0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// Public key of the secret `seed`.
+pub fn public_key(seed: &Seed) -> PublicKey {
+    let (scalar, _) = expand(seed);
+    pack(&scalar_base(&scalar))
+}
+
+/// Sign `message` with the secret `seed`.
+pub fn sign(seed: &Seed, message: &[u8]) -> Signature {
+    let (scalar, prefix) = expand(seed);
+    let r = reduce(&hash(&[&prefix, message]));
+    let big_r = pack(&scalar_base(&r));
+    let h = reduce(&hash(&[&big_r, &public_key(seed), message]));
+
+    // s = r + h a modulo L
+    let mut x = [0i64; 64];
+    for i in 0..32 {
+        x[i] = i64::from(r[i]);
+    }
+    for i in 0..32 {
+        for j in 0..32 {
+            x[i + j] += i64::from(h[i]) * i64::from(scalar[j]);
+        }
+    }
+    let mut signature = [0; 64];
+    signature[..32].copy_from_slice(&big_r);
+    signature[32..].copy_from_slice(&mod_l(&mut x));
+    signature
+}
+
+/// Whether `signature` is the one of `key` for `message`.
+pub fn verify(key: &PublicKey, message: &[u8], signature: &Signature) -> bool {
+    let (big_r, s) = signature.split_at(32);
+    let mut s_bytes = [0; 32];
+    s_bytes.copy_from_slice(s);
+    // only one s per signature, RFC 8032 5.1.7
+    if !is_reduced(&s_bytes) {
+        return false;
+    }
+    let Some(negated_key) = unpack_negated(key) else {
+        return false;
+    };
+    let h = reduce(&hash(&[big_r, key, message]));
+    // s B - h A must come out as R
+    let mut p = scalar_mult(negated_key, &h);
+    add(&mut p, &scalar_base(&s_bytes));
+    pack(&p) == big_r
+}
+
+/// Public key from 64 hex digits.
+pub fn parse_key(hex: &str) -> Option<PublicKey> {
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        let pair = core::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(key)
+}
+
+/// The clamped scalar and the prefix for the nonce from `seed`.
+fn expand(seed: &Seed) -> ([u8; 32], [u8; 32]) {
+    let d = hash(&[seed]);
+    let mut scalar = [0; 32];
+    let mut prefix = [0; 32];
+    scalar.copy_from_slice(&d[..32]);
+    prefix.copy_from_slice(&d[32..]);
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    (scalar, prefix)
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; 64] {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finish()
+}
+
+/// Whether the scalar `s` is below L.
+fn is_reduced(s: &[u8; 32]) -> bool {
+    for i in (0..32).rev() {
+        let l = L[i] as u8;
+        if s[i] != l {
+            return s[i] < l;
+        }
+    }
+    false
+}
+
+/// `x` modulo L, `x` holds one byte per limb and is used up.
+fn mod_l(x: &mut [i64; 64]) -> [u8; 32] {
+    for i in (32..64).rev() {
+        let mut carry = 0;
+        for j in i - 32..i - 12 {
+            x[j] += carry - 16 * x[i] * L[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+        }
+        x[i - 12] += carry;
+        x[i] = 0;
+    }
+    let mut carry = 0;
+    for j in 0..32 {
+        x[j] += carry - (x[31] >> 4) * L[j];
+        carry = x[j] >> 8;
+        x[j] &= 255;
+    }
+    for j in 0..32 {
+        x[j] -= carry * L[j];
+    }
+    let mut reduced = [0; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        reduced[i] = (x[i] & 255) as u8;
+    }
+    reduced
+}
+
+/// A 512 bit hash modulo L.
+fn reduce(h: &[u8; 64]) -> [u8; 32] {
+    let mut x = [0i64; 64];
+    for (limb, byte) in x.iter_mut().zip(h) {
+        *limb = i64::from(*byte);
+    }
+    mod_l(&mut x)
+}
+
+/// Bring the limbs back to 16 bits, 2^256 being 38 in the field.
+fn carry(o: &mut Field) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+/// Swap `p` and `q` if `swap` is 1, without branching on it.
+fn select(p: &mut Field, q: &mut Field, swap: i64) {
+    let mask = !(swap - 1);
+    for i in 0..16 {
+        let t = mask & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+    }
+}
+
+/// The fully reduced little endian bytes of `n`.
+fn pack_field(n: &Field) -> [u8; 32] {
+    let mut t = *n;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+    let mut m = ZERO;
+    for _ in 0..2 {
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let borrow = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - borrow);
+    }
+    let mut bytes = [0; 32];
+    for i in 0..16 {
+        bytes[2 * i] = t[i] as u8;
+        bytes[2 * i + 1] = (t[i] >> 8) as u8;
+    }
+    bytes
+}
+
+fn unpack_field(bytes: &[u8; 32]) -> Field {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = i64::from(bytes[2 * i]) + (i64::from(bytes[2 * i + 1]) << 8);
+    }
+    o[15] &= 0x7fff;
+    o
+}
+
+fn differ(a: &Field, b: &Field) -> bool {
+    pack_field(a) != pack_field(b)
+}
+
+fn parity(a: &Field) -> u8 {
+    pack_field(a)[0] & 1
+}
+
+fn sum(a: &Field, b: &Field) -> Field {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = a[i] + b[i];
+    }
+    o
+}
+
+fn difference(a: &Field, b: &Field) -> Field {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = a[i] - b[i];
+    }
+    o
+}
+
+fn product(a: &Field, b: &Field) -> Field {
+    let mut t = [0i64; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+    let mut o = ZERO;
+    o.copy_from_slice(&t[..16]);
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn square(a: &Field) -> Field {
+    product(a, a)
+}
+
+/// `i` to the power of p - 2, its inverse.
+fn invert(i: &Field) -> Field {
+    let mut c = *i;
+    for a in (0..=253).rev() {
+        c = square(&c);
+        if a != 2 && a != 4 {
+            c = product(&c, i);
+        }
+    }
+    c
+}
+
+/// `i` to the power of (p - 5) / 8, for square roots.
+fn pow2523(i: &Field) -> Field {
+    let mut c = *i;
+    for a in (0..=250).rev() {
+        c = square(&c);
+        if a != 1 {
+            c = product(&c, i);
+        }
+    }
+    c
+}
+
+/// `p += q`.
+fn add(p: &mut Point, q: &Point) {
+    let a = product(&difference(&p[1], &p[0]), &difference(&q[1], &q[0]));
+    let b = product(&sum(&p[0], &p[1]), &sum(&q[0], &q[1]));
+    let c = product(&product(&p[3], &q[3]), &D2);
+    let d = product(&p[2], &q[2]);
+    let d = sum(&d, &d);
+    let e = difference(&b, &a);
+    let f = difference(&d, &c);
+    let g = sum(&d, &c);
+    let h = sum(&b, &a);
+    *p = [
+        product(&e, &f),
+        product(&h, &g),
+        product(&g, &f),
+        product(&e, &h),
+    ];
+}
+
+fn swap(p: &mut Point, q: &mut Point, bit: u8) {
+    for (p, q) in p.iter_mut().zip(q) {
+        select(p, q, i64::from(bit));
+    }
+}
+
+fn pack(p: &Point) -> [u8; 32] {
+    let z = invert(&p[2]);
+    let x = product(&p[0], &z);
+    let y = product(&p[1], &z);
+    let mut bytes = pack_field(&y);
+    bytes[31] ^= parity(&x) << 7;
+    bytes
+}
+
+/// `s q`, with the same steps for every scalar.
+fn scalar_mult(mut q: Point, s: &[u8; 32]) -> Point {
+    let mut p = [ZERO, ONE, ONE, ZERO];
+    for i in (0..256).rev() {
+        let bit = (s[i / 8] >> (i & 7)) & 1;
+        swap(&mut p, &mut q, bit);
+        add(&mut q, &p);
+        let doubled = p;
+        add(&mut p, &doubled);
+        swap(&mut p, &mut q, bit);
+    }
+    p
+}
+
+/// `s B`.
+fn scalar_base(s: &[u8; 32]) -> Point {
+    scalar_mult([X, Y, ONE, product(&X, &Y)], s)
+}
+
+/// The negated point of a public key, `None` if it is not on the curve.
+fn unpack_negated(key: &PublicKey) -> Option<Point> {
+    let z = ONE;
+    let y = unpack_field(key);
+    let num = square(&y);
+    let den = product(&num, &D);
+    let num = difference(&num, &z);
+    let den = sum(&z, &den);
+
+    let den2 = square(&den);
+    let den4 = square(&den2);
+    let den6 = product(&den4, &den2);
+    let mut t = product(&den6, &num);
+    t = product(&t, &den);
+    t = pow2523(&t);
+    t = product(&t, &num);
+    t = product(&t, &den);
+    t = product(&t, &den);
+    let mut x = product(&t, &den);
+
+    if differ(&product(&square(&x), &den), &num) {
+        x = product(&x, &I);
+    }
+    if differ(&product(&square(&x), &den), &num) {
+        return None;
+    }
+    if parity(&x) == key[31] >> 7 {
+        x = difference(&ZERO, &x);
+    }
+    let t = product(&x, &y);
+    Some([x, y, z, t])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        let mut bytes = [0; N];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        bytes
+    }
+
+    /// Seed, public key, message and signature of the tests 1 to 3 in
+    /// RFC 8032 7.1.
+    const VECTORS: [(&str, &str, &[u8], &str); 3] = [
+        (
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            b"\x72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            b"\xaf\x82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn rfc_8032() {
+        for (seed, key, message, signature) in VECTORS {
+            let seed = bytes(seed);
+            let key = bytes(key);
+            let signature = bytes(signature);
+            assert_eq!(public_key(&seed), key);
+            assert_eq!(sign(&seed, message), signature);
+            assert!(verify(&key, message, &signature));
+        }
+    }
+
+    #[test]
+    fn forgeries() {
+        let (seed, key, _, _) = VECTORS[2];
+        let seed = bytes(seed);
+        let key = bytes(key);
+        let signature = sign(&seed, b"firmware");
+        assert!(verify(&key, b"firmware", &signature));
+
+        assert!(!verify(&key, b"firmwarE", &signature));
+        for at in [0, 31, 32, 63] {
+            let mut flipped = signature;
+            flipped[at] ^= 1;
+            assert!(!verify(&key, b"firmware", &flipped));
+        }
+        let other = public_key(&bytes(VECTORS[0].0));
+        assert!(!verify(&other, b"firmware", &signature));
+        assert!(!verify(&key, b"firmware", &[0; 64]));
+    }
+
+    #[test]
+    fn non_canonical_s() {
+        let (seed, key, _, _) = VECTORS[0];
+        let signature = sign(&bytes(seed), b"firmware");
+        // s + L is the same scalar, but only s may be accepted
+        let mut s = [0i64; 64];
+        let mut carry = 0;
+        for i in 0..32 {
+            let limb = i64::from(signature[32 + i]) + L[i] + carry;
+            s[i] = limb & 255;
+            carry = limb >> 8;
+        }
+        let mut malleated = signature;
+        for i in 0..32 {
+            malleated[32 + i] = s[i] as u8;
+        }
+        assert_eq!(carry, 0);
+        assert!(verify(&bytes(key), b"firmware", &signature));
+        assert!(!verify(&bytes(key), b"firmware", &malleated));
+    }
+
+    #[test]
+    fn keys() {
+        let key = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+        assert_eq!(parse_key(key), Some(bytes(key)));
+        assert_eq!(parse_key(&key[2..]), None);
+        assert_eq!(
+            parse_key("+75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"),
+            None
+        );
+        // y = 2 gives no point on the curve
+        let mut off_curve = [0; 32];
+        off_curve[0] = 2;
+        assert_eq!(unpack_negated(&off_curve), None);
+        assert!(!verify(&off_curve, b"", &[0; 64]));
+    }
+}
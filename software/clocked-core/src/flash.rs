@@ -0,0 +1,86 @@
+//! In-memory NOR flash.
+//!
+//! Behaves like the ESP32's SPI flash as seen through `esp-storage`: erasing
+//! sets whole sectors to `0xff`, writing can only clear bits. Used to run the
+//! settings store on the host.
+
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// Sector size of the ESP32 flash.
+pub const SECTOR_SIZE: usize = 4096;
+
+pub struct MemFlash<const SIZE: usize> {
+    data: [u8; SIZE],
+    /// Number of sector erases so far, to check for wear.
+    pub erases: u32,
+    /// Number of write calls so far.
+    pub writes: u32,
+}
+
+impl<const SIZE: usize> Default for MemFlash<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> MemFlash<SIZE> {
+    /// Fully erased flash.
+    pub const fn new() -> Self {
+        Self {
+            data: [0xff; SIZE],
+            erases: 0,
+            writes: 0,
+        }
+    }
+
+    /// Raw contents, e.g. to corrupt them on purpose.
+    pub fn data_mut(&mut self) -> &mut [u8; SIZE] {
+        &mut self.data
+    }
+}
+
+impl<const SIZE: usize> ErrorType for MemFlash<SIZE> {
+    type Error = NorFlashErrorKind;
+}
+
+impl<const SIZE: usize> ReadNorFlash for MemFlash<SIZE> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        SIZE
+    }
+}
+
+impl<const SIZE: usize> NorFlash for MemFlash<SIZE> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        self.data[from as usize..to as usize].fill(0xff);
+        self.erases += (to - from) / SECTOR_SIZE as u32;
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        for (cell, byte) in self.data[offset..offset + bytes.len()]
+            .iter_mut()
+            .zip(bytes)
+        {
+            *cell &= byte;
+        }
+        self.writes += 1;
+        Ok(())
+    }
+}
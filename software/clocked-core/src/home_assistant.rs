@@ -0,0 +1,290 @@
+//! The clock as a Home Assistant device over MQTT.
+//!
+//! With discovery on, the clock announces two entities under
+//! [`DISCOVERY_PREFIX`]:
+//!
+//! - a light for the ring, its brightness, its effects (the animations) and
+//!   its color (the minute hand), using the JSON schema on `clocked/<id>/set`
+//! - a number for the countdown in minutes on `clocked/<id>/timer/set`, 0
+//!   stops it
+//!
+//! Both read their state from the retained JSON on `clocked/<id>/state` and
+//! go unavailable with the retained will on `clocked/<id>/availability`.
+//!
+//! Like the REST API, [`Light::handle`] only changes a copy of the settings
+//! and returns the [`Command`] for the caller to carry out.
+
+use core::fmt::{self, Write};
+use core::time::Duration;
+
+use heapless::String;
+use rgb::RGB8;
+
+use crate::{
+    animation::Builtin,
+    api::Command,
+    json::{self, Array, Object, Value},
+    settings::Settings,
+    timer,
+};
+
+pub const DISCOVERY_PREFIX: &str = "homeassistant";
+pub const MAX_TOPIC_LEN: usize = 96;
+/// Payloads on the availability topic.
+pub const ONLINE: &str = "online";
+pub const OFFLINE: &str = "offline";
+
+pub type Topic = String<MAX_TOPIC_LEN>;
+
+/// Topics of the clock with the device id `id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Topics {
+    pub state: Topic,
+    pub availability: Topic,
+    /// Commands for the light.
+    pub set: Topic,
+    pub timer_set: Topic,
+}
+
+impl Topics {
+    pub fn new(id: &str) -> Self {
+        let topic = |suffix: &str| {
+            let mut topic = Topic::new();
+            // the id is a short device name, a cut off topic is never used
+            let _ = write!(topic, "clocked/{id}/{suffix}");
+            topic
+        };
+        Self {
+            state: topic("state"),
+            availability: topic("availability"),
+            set: topic("set"),
+            timer_set: topic("timer/set"),
+        }
+    }
+
+    /// Topics to subscribe to.
+    pub fn commands(&self) -> [&str; 2] {
+        [&self.set, &self.timer_set]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Entity {
+    Light,
+    Timer,
+}
+
+impl Entity {
+    pub const ALL: [Self; 2] = [Self::Light, Self::Timer];
+
+    fn component(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Timer => "number",
+        }
+    }
+
+    fn object_id(self) -> &'static str {
+        match self {
+            Self::Light => "ring",
+            Self::Timer => "timer",
+        }
+    }
+
+    /// Retained topic of the discovery message.
+    pub fn config_topic(self, id: &str) -> Topic {
+        let mut topic = Topic::new();
+        let _ = write!(
+            topic,
+            "{DISCOVERY_PREFIX}/{}/{id}/{}/config",
+            self.component(),
+            self.object_id()
+        );
+        topic
+    }
+
+    /// Discovery message of the entity on the clock with the device id `id`.
+    pub fn write_config(self, id: &str, version: &str, w: &mut impl Write) -> fmt::Result {
+        let topics = Topics::new(id);
+        let mut config = Object::new(w)?;
+        match self {
+            Self::Light => config.str("name", "Ring")?,
+            Self::Timer => config.str("name", "Timer")?,
+        }
+        write!(config.key("unique_id")?, "\"{id}-{}\"", self.object_id())?;
+        config.str("state_topic", &topics.state)?;
+        config.str("availability_topic", &topics.availability)?;
+        match self {
+            Self::Light => {
+                config.str("schema", "json")?;
+                config.str("command_topic", &topics.set)?;
+                config.raw("brightness", true)?;
+                let mut modes = Array::new(config.key("supported_color_modes")?)?;
+                modes.str("rgb")?;
+                modes.finish()?;
+                config.raw("effect", true)?;
+                let mut effects = Array::new(config.key("effect_list")?)?;
+                for animation in Builtin::ALL {
+                    effects.str(animation.name())?;
+                }
+                effects.finish()?;
+            }
+            Self::Timer => {
+                config.str("command_topic", &topics.timer_set)?;
+                config.str("command_template", "{{ value | int }}")?;
+                config.str("value_template", "{{ value_json.timer }}")?;
+                config.raw("min", 0)?;
+                config.raw("max", timer::MAX.as_secs() / 60)?;
+                config.raw("step", 1)?;
+                config.str("unit_of_measurement", "min")?;
+                config.str("icon", "mdi:timer-outline")?;
+            }
+        }
+        let mut device = Object::new(config.key("device")?)?;
+        let mut identifiers = Array::new(device.key("identifiers")?)?;
+        identifiers.str(id)?;
+        identifiers.finish()?;
+        device.str("name", id)?;
+        device.str("manufacturer", "clocked")?;
+        device.str("model", "clocked")?;
+        device.str("sw_version", version)?;
+        device.finish()?;
+        config.finish()
+    }
+}
+
+/// What the clock publishes on the state topic, published again whenever it
+/// changes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct State {
+    pub brightness: u8,
+    pub color: RGB8,
+    pub animation: Builtin,
+    /// Minutes left on the countdown, the one just started counts.
+    pub timer: u64,
+}
+
+impl State {
+    /// `timer` is the time left on the countdown, `None` without one.
+    pub fn new(settings: &Settings, timer: Option<Duration>) -> Self {
+        Self {
+            brightness: settings.brightness,
+            color: settings.minute_color,
+            animation: settings.animation,
+            timer: timer.map_or(0, |left| left.as_nanos().div_ceil(60_000_000_000) as u64),
+        }
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> fmt::Result {
+        let mut state = Object::new(w)?;
+        state.str("state", if self.brightness > 0 { "ON" } else { "OFF" })?;
+        state.raw("brightness", self.brightness)?;
+        state.str("color_mode", "rgb")?;
+        let mut color = Object::new(state.key("color")?)?;
+        color.raw("r", self.color.r)?;
+        color.raw("g", self.color.g)?;
+        color.raw("b", self.color.b)?;
+        color.finish()?;
+        state.str("effect", self.animation.name())?;
+        state.raw("timer", self.timer)?;
+        state.finish()
+    }
+}
+
+/// Handles the commands, remembering the brightness to go back to when the
+/// light is turned on again.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Light {
+    restore: u8,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self { restore: u8::MAX }
+    }
+}
+
+impl Light {
+    /// Apply a message on one of the command `topics` to `settings`.
+    ///
+    /// `Ok(None)` for topics that are not commands. On error `settings` may
+    /// be half changed and has to be thrown away.
+    pub fn handle(
+        &mut self,
+        topics: &Topics,
+        topic: &str,
+        payload: &[u8],
+        settings: &mut Settings,
+    ) -> Result<Option<Command>, &'static str> {
+        let payload = core::str::from_utf8(payload).map_err(|_| "not UTF-8")?;
+        if topic == topics.timer_set.as_str() {
+            timer_command(payload).map(Some)
+        } else if topic == topics.set.as_str() {
+            self.light_command(payload, settings)
+                .map(|()| Some(Command::SaveSettings))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn light_command(
+        &mut self,
+        payload: &str,
+        settings: &mut Settings,
+    ) -> Result<(), &'static str> {
+        const INVALID: &str = "invalid value";
+        let command @ Value::Object(_) = json::parse(payload).map_err(|_| "expected JSON")? else {
+            return Err("expected a JSON object");
+        };
+        let mut on = None;
+        for (key, value) in command.members() {
+            match key {
+                "state" => match value.as_str::<3>().as_deref() {
+                    Some("ON") => on = Some(true),
+                    Some("OFF") => on = Some(false),
+                    _ => return Err(INVALID),
+                },
+                "brightness" => settings.brightness = value.as_int().ok_or(INVALID)?,
+                "color" => {
+                    let channel = |name| value.get(name).and_then(|c| c.as_int::<u8>());
+                    let (Some(r), Some(g), Some(b)) = (channel("r"), channel("g"), channel("b"))
+                    else {
+                        return Err(INVALID);
+                    };
+                    settings.minute_color = RGB8::new(r, g, b);
+                }
+                "effect" => {
+                    let name = value.as_str::<16>().ok_or(INVALID)?;
+                    settings.animation = Builtin::from_name(&name).ok_or("unknown effect")?;
+                }
+                // sent along by Home Assistant, nothing to do
+                "transition" | "color_mode" => {}
+                _ => return Err("unknown command"),
+            }
+        }
+        match on {
+            Some(false) => {
+                if settings.brightness > 0 {
+                    self.restore = settings.brightness;
+                }
+                settings.brightness = 0;
+            }
+            Some(true) if settings.brightness == 0 => settings.brightness = self.restore,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Minutes as sent by the number entity, 0 stops the countdown.
+fn timer_command(payload: &str) -> Result<Command, &'static str> {
+    // "5" or, without the command template, "5.0"
+    let minutes = payload.trim();
+    let minutes = minutes.strip_suffix(".0").unwrap_or(minutes);
+    match minutes.parse::<u64>().map_err(|_| "expected minutes")? {
+        0 => Ok(Command::StopTimer),
+        minutes => Ok(Command::StartTimer(
+            Duration::from_secs(minutes.saturating_mul(60)).min(timer::MAX),
+        )),
+    }
+}
@@ -0,0 +1,382 @@
+//! Just enough HTTP/1.1 for the clock's own web pages.
+//!
+//! Requests are parsed in place from the receive buffer, one request per
+//! connection, and every response closes the connection afterwards.
+//!
+//! The other way round, [`Url`], [`write_request`] and [`Response`] are what
+//! the clock needs to fetch a file from a plain HTTP server, or only the
+//! time from its `Date` header, see [`parse_date`].
+
+use core::fmt::{self, Write};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+
+use heapless::{String, Vec};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The head or body has not been received completely yet.
+    Incomplete,
+    /// Not a valid HTTP/1.x request or response.
+    Malformed,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    /// Path without the query string.
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    headers: &'a str,
+    pub body: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    /// Parse a request from the start of `buf`.
+    ///
+    /// Returns [`Error::Incomplete`] until the head and a body of the length
+    /// announced in `Content-Length` are in the buffer.
+    pub fn parse(buf: &'a [u8]) -> Result<Self, Error> {
+        let (mut request, body_start) = Self::parse_head(buf)?;
+        request.body = buf
+            .get(body_start..body_start + request.content_length()?)
+            .ok_or(Error::Incomplete)?;
+        Ok(request)
+    }
+
+    /// Parse only the head of a request, for bodies too large to receive in
+    /// one piece. Returns the request with an empty body and the offset of
+    /// the body in `buf`.
+    pub fn parse_head(buf: &'a [u8]) -> Result<(Self, usize), Error> {
+        let (head, body_start) = split_head(buf)?;
+        let (request_line, headers) = head.split_once("\r\n").unwrap_or((head, ""));
+
+        let mut parts = request_line.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(Error::Malformed);
+        };
+        if method.is_empty() || !version.starts_with("HTTP/1.") {
+            return Err(Error::Malformed);
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+
+        let request = Self {
+            method,
+            path,
+            query,
+            headers,
+            body: &[],
+        };
+        Ok((request, body_start))
+    }
+
+    /// Value of the first header called `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        find_header(self.headers, name)
+    }
+
+    /// Length of the body from `Content-Length`, 0 without one.
+    pub fn content_length(&self) -> Result<usize, Error> {
+        content_length(self.headers).map(|len| len.unwrap_or(0))
+    }
+
+    /// Body as text, empty if it is not UTF-8.
+    pub fn body_str(&self) -> &'a str {
+        core::str::from_utf8(self.body).unwrap_or("")
+    }
+}
+
+/// Head of a response from a server.
+#[derive(Copy, Clone, Debug)]
+pub struct Response<'a> {
+    pub status: u16,
+    headers: &'a str,
+}
+
+impl<'a> Response<'a> {
+    /// Parse the head of a response from the start of `buf`, returns it and
+    /// the offset of the body in `buf`.
+    pub fn parse_head(buf: &'a [u8]) -> Result<(Self, usize), Error> {
+        let (head, body_start) = split_head(buf)?;
+        let (status_line, headers) = head.split_once("\r\n").unwrap_or((head, ""));
+        let mut parts = status_line.splitn(3, ' ');
+        let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+            return Err(Error::Malformed);
+        };
+        if !version.starts_with("HTTP/1.") || status.len() != 3 {
+            return Err(Error::Malformed);
+        }
+        let status = status.parse().map_err(|_| Error::Malformed)?;
+        Ok((Self { status, headers }, body_start))
+    }
+
+    /// Value of the first header called `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        find_header(self.headers, name)
+    }
+
+    /// Length of the body from `Content-Length`, `None` if the server did
+    /// not say and the body ends with the connection.
+    pub fn content_length(&self) -> Result<Option<usize>, Error> {
+        content_length(self.headers)
+    }
+
+    /// When the server sent the response, from the `Date` header.
+    pub fn date(&self) -> Option<NaiveDateTime> {
+        parse_date(self.header("date")?)
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// UTC time of an HTTP date (RFC 7231 section 7.1.1.1).
+///
+/// Takes the IMF-fixdate servers are supposed to send,
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, the obsolete RFC 850 and asctime forms,
+/// `Sunday, 06-Nov-94 08:49:37 GMT` and `Sun Nov  6 08:49:37 1994`, and what
+/// servers send instead: any case, a missing weekday, single digit days,
+/// `UTC` or a numeric zone. Two digit years are 1970 to 2069.
+pub fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    let mut tokens: Vec<&str, 8> = Vec::new();
+    for token in value.split([' ', '\t', ',']).filter(|t| !t.is_empty()) {
+        // the date of RFC 850, but not the sign of a zone
+        if token.starts_with(['+', '-']) {
+            tokens.push(token).ok()?;
+            continue;
+        }
+        for part in token.split('-') {
+            tokens.push(part).ok()?;
+        }
+    }
+    let mut tokens = tokens.as_slice();
+    if let [weekday, rest @ ..] = tokens {
+        if month_number(weekday).is_none() && weekday.bytes().all(|b| b.is_ascii_alphabetic()) {
+            tokens = rest;
+        }
+    }
+    let (day, month, year, time, zone) = match *tokens {
+        [day, month_name, year, time, ref zone @ ..] if number(day).is_some() => {
+            (day, month_number(month_name)?, year, time, zone)
+        }
+        [month_name, day, time, year, ref zone @ ..] => {
+            (day, month_number(month_name)?, year, time, zone)
+        }
+        _ => return None,
+    };
+    let offset = match *zone {
+        [] => 0,
+        [zone] => zone_offset(zone)?,
+        _ => return None,
+    };
+    let year = match (year.len(), number(year)?) {
+        (2, year) if year < 70 => 2000 + year,
+        (2, year) => 1900 + year,
+        (4, year) => year,
+        _ => return None,
+    };
+    let mut clock = time.split(':');
+    let (Some(hour), Some(minute)) = (clock.next(), clock.next()) else {
+        return None;
+    };
+    let second = clock.next().map_or(Some(0), number)?;
+    if clock.next().is_some() {
+        return None;
+    }
+    let date = NaiveDate::from_ymd_opt(year as i32, month, number(day)?)?;
+    // a leap second is as good as the second before
+    let time = date.and_hms_opt(number(hour)?, number(minute)?, second.min(59))?;
+    Some(time - TimeDelta::minutes(offset))
+}
+
+/// Month 1 to 12 from its English name or the first three letters of it.
+fn month_number(name: &str) -> Option<u32> {
+    MONTHS
+        .iter()
+        .position(|month| {
+            month.eq_ignore_ascii_case(name)
+                || name.len() == 3 && month[..3].eq_ignore_ascii_case(name)
+        })
+        .map(|index| index as u32 + 1)
+}
+
+/// Up to four plain digits.
+fn number(text: &str) -> Option<u32> {
+    let digits = !text.is_empty() && text.len() <= 4 && text.bytes().all(|b| b.is_ascii_digit());
+    digits.then(|| text.parse().ok())?
+}
+
+/// Minutes the zone is ahead of UTC.
+fn zone_offset(zone: &str) -> Option<i64> {
+    if ["gmt", "utc", "ut", "z"]
+        .iter()
+        .any(|utc| zone.eq_ignore_ascii_case(utc))
+    {
+        return Some(0);
+    }
+    let (sign, digits) = match zone.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    let hhmm = number(digits).filter(|_| digits.len() == 4)? as i64;
+    Some(sign * (hhmm / 100 * 60 + hhmm % 100))
+}
+
+/// An `http://` URL, there is no TLS on the clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Url<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    /// Path with the query string, `/` if the URL has none.
+    pub path: &'a str,
+}
+
+impl<'a> Url<'a> {
+    pub fn parse(url: &'a str) -> Option<Self> {
+        let rest = url
+            .get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("http://"))
+            .map(|_| &url[7..])?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok().filter(|&port| port > 0)?),
+            None => (authority, 80),
+        };
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.';
+        if host.is_empty() || !host.chars().all(valid) || path.contains(char::is_whitespace) {
+            return None;
+        }
+        Some(Self { host, port, path })
+    }
+}
+
+/// Write a request without a body for `url`, including the blank line.
+pub fn write_request(w: &mut impl Write, method: &str, url: &Url<'_>) -> fmt::Result {
+    write!(w, "{method} {} HTTP/1.1\r\nHost: {}", url.path, url.host)?;
+    if url.port != 80 {
+        write!(w, ":{}", url.port)?;
+    }
+    w.write_str("\r\nUser-Agent: clocked\r\nConnection: close\r\n\r\n")
+}
+
+/// The head up to the blank line and the offset of the body.
+fn split_head(buf: &[u8]) -> Result<(&str, usize), Error> {
+    let head_len = buf
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or(Error::Incomplete)?;
+    let head = core::str::from_utf8(&buf[..head_len]).map_err(|_| Error::Malformed)?;
+    Ok((head, head_len + 4))
+}
+
+fn find_header<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers.split("\r\n").find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+fn content_length(headers: &str) -> Result<Option<usize>, Error> {
+    find_header(headers, "content-length")
+        .map(|len| len.parse().map_err(|_| Error::Malformed))
+        .transpose()
+}
+
+/// Status line of a response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Status(pub u16, pub &'static str);
+
+impl Status {
+    pub const OK: Status = Status(200, "OK");
+    pub const NO_CONTENT: Status = Status(204, "No Content");
+    pub const FOUND: Status = Status(302, "Found");
+    pub const BAD_REQUEST: Status = Status(400, "Bad Request");
+    pub const UNAUTHORIZED: Status = Status(401, "Unauthorized");
+    pub const FORBIDDEN: Status = Status(403, "Forbidden");
+    pub const NOT_FOUND: Status = Status(404, "Not Found");
+    pub const METHOD_NOT_ALLOWED: Status = Status(405, "Method Not Allowed");
+    pub const INTERNAL_SERVER_ERROR: Status = Status(500, "Internal Server Error");
+    pub const BAD_GATEWAY: Status = Status(502, "Bad Gateway");
+}
+
+/// Write the status line and headers of a response, including the blank line.
+///
+/// `extra` is written verbatim and must consist of complete `\r\n`
+/// terminated header lines.
+pub fn write_head(
+    w: &mut impl Write,
+    status: Status,
+    content_type: &str,
+    content_length: usize,
+    extra: &str,
+) -> fmt::Result {
+    write!(
+        w,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+        status.0, status.1, content_type, content_length, extra
+    )
+}
+
+/// Look up `key` in an `application/x-www-form-urlencoded` string and decode it.
+///
+/// `None` if the key is missing, the value does not fit into `N` bytes or is
+/// not valid UTF-8 after decoding.
+pub fn form_field<const N: usize>(form: &str, key: &str) -> Option<String<N>> {
+    let value = form.split('&').find_map(|pair| {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        (k == key).then_some(v)
+    })?;
+    let mut decoded: Vec<u8, N> = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(c) = bytes.next() {
+        let c = match c {
+            b'+' => b' ',
+            b'%' => {
+                let hi = (bytes.next()? as char).to_digit(16)?;
+                let lo = (bytes.next()? as char).to_digit(16)?;
+                (hi * 16 + lo) as u8
+            }
+            c => c,
+        };
+        decoded.push(c).ok()?;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Write `text` with the HTML special characters escaped.
+pub fn write_html_escaped(w: &mut impl Write, text: &str) -> fmt::Result {
+    for c in text.chars() {
+        match c {
+            '&' => w.write_str("&amp;")?,
+            '<' => w.write_str("&lt;")?,
+            '>' => w.write_str("&gt;")?,
+            '"' => w.write_str("&quot;")?,
+            '\'' => w.write_str("&#39;")?,
+            c => w.write_char(c)?,
+        }
+    }
+    Ok(())
+}
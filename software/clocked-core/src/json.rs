@@ -0,0 +1,428 @@
+//! Just enough JSON for the clock's API.
+//!
+//! [`parse`] checks a whole document and returns its top level [`Value`].
+//! Strings, objects and arrays keep pointing into the parsed text and are
+//! only decoded or walked on demand with [`Value::as_str`],
+//! [`Value::members`] and [`Value::items`], so nothing is allocated.
+//!
+//! [`Object`] and [`Array`] write JSON to any [`fmt::Write`].
+
+use core::fmt::{self, Write};
+
+use heapless::String;
+
+/// Deepest nesting of objects and arrays accepted by [`parse`].
+pub const MAX_DEPTH: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not valid JSON, at the given byte offset.
+    Syntax(usize),
+    /// Nested deeper than [`MAX_DEPTH`].
+    TooDeep,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Value<'a> {
+    Null,
+    Bool(bool),
+    /// The number as written.
+    Number(&'a str),
+    /// Contents between the quotes, escapes not decoded yet.
+    String(&'a str),
+    /// Text of the array including the brackets.
+    Array(&'a str),
+    /// Text of the object including the braces.
+    Object(&'a str),
+}
+
+/// Parse a complete document, surrounding whitespace is allowed.
+pub fn parse(text: &str) -> Result<Value<'_>, Error> {
+    let mut parser = Parser { text, pos: 0 };
+    parser.whitespace();
+    let value = parser.value(0)?;
+    parser.whitespace();
+    if parser.pos != text.len() {
+        return Err(Error::Syntax(parser.pos));
+    }
+    Ok(value)
+}
+
+impl<'a> Value<'a> {
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Integral numbers only, `None` for fractions and out of range values.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// A number that fits into `T`, such as a `u8` brightness.
+    pub fn as_int<T: TryFrom<i64>>(&self) -> Option<T> {
+        T::try_from(self.as_i64()?).ok()
+    }
+
+    /// The decoded string, `None` if it is not a string or longer than `N`
+    /// bytes.
+    pub fn as_str<const N: usize>(&self) -> Option<String<N>> {
+        let Self::String(raw) = *self else {
+            return None;
+        };
+        let mut decoded = String::new();
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            let c = match c {
+                '\\' => match chars.next()? {
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let high = hex4(&mut chars)?;
+                        if (0xd800..0xdc00).contains(&high) {
+                            // surrogate pair
+                            if chars.next()? != '\\' || chars.next()? != 'u' {
+                                return None;
+                            }
+                            let low = hex4(&mut chars)?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return None;
+                            }
+                            char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))?
+                        } else {
+                            char::from_u32(high)?
+                        }
+                    }
+                    c => c,
+                },
+                c => c,
+            };
+            decoded.push(c).ok()?;
+        }
+        Some(decoded)
+    }
+
+    /// Key and value of every member of an object, nothing for other values.
+    ///
+    /// Keys are not decoded, they are compared as written.
+    pub fn members(&self) -> Members<'a> {
+        let text = match *self {
+            Self::Object(text) => text,
+            _ => "",
+        };
+        Members(Parser { text, pos: 1 })
+    }
+
+    /// First member called `key` of an object.
+    pub fn get(&self, key: &str) -> Option<Value<'a>> {
+        self.members().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Every element of an array, nothing for other values.
+    pub fn items(&self) -> Items<'a> {
+        let text = match *self {
+            Self::Array(text) => text,
+            _ => "",
+        };
+        Items(Parser { text, pos: 1 })
+    }
+}
+
+fn hex4(chars: &mut core::str::Chars<'_>) -> Option<u32> {
+    (0..4).try_fold(0, |value, _| Some(value * 16 + chars.next()?.to_digit(16)?))
+}
+
+/// Iterator over the members of an object, see [`Value::members`].
+pub struct Members<'a>(Parser<'a>);
+
+impl<'a> Iterator for Members<'a> {
+    type Item = (&'a str, Value<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let p = &mut self.0;
+        // the text has been checked by `parse` already
+        p.whitespace();
+        p.eat(b',');
+        p.whitespace();
+        if p.peek()? != b'"' {
+            return None;
+        }
+        let key = p.string().ok()?;
+        p.whitespace();
+        p.eat(b':');
+        p.whitespace();
+        let value = p.value(0).ok()?;
+        Some((key, value))
+    }
+}
+
+/// Iterator over the elements of an array, see [`Value::items`].
+pub struct Items<'a>(Parser<'a>);
+
+impl<'a> Iterator for Items<'a> {
+    type Item = Value<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let p = &mut self.0;
+        p.whitespace();
+        p.eat(b',');
+        p.whitespace();
+        if p.peek()? == b']' {
+            return None;
+        }
+        p.value(0).ok()
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Error> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(Error::Syntax(self.pos))
+        }
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value<'a>, Error> {
+        let start = self.pos;
+        match self.peek() {
+            Some(b'{') => {
+                self.nested(depth, b'}', |p| {
+                    p.string()?;
+                    p.whitespace();
+                    p.expect(b':')?;
+                    p.whitespace();
+                    p.value(depth + 1).map(drop)
+                })?;
+                Ok(Value::Object(&self.text[start..self.pos]))
+            }
+            Some(b'[') => {
+                self.nested(depth, b']', |p| p.value(depth + 1).map(drop))?;
+                Ok(Value::Array(&self.text[start..self.pos]))
+            }
+            Some(b'"') => self.string().map(Value::String),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number().map(Value::Number),
+            _ => Err(Error::Syntax(start)),
+        }
+    }
+
+    /// Object or array ending in `close`, with `element` parsing one member.
+    fn nested(
+        &mut self,
+        depth: usize,
+        close: u8,
+        mut element: impl FnMut(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if depth >= MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        self.pos += 1;
+        self.whitespace();
+        if self.eat(close) {
+            return Ok(());
+        }
+        loop {
+            element(self)?;
+            self.whitespace();
+            if self.eat(close) {
+                return Ok(());
+            }
+            self.expect(b',')?;
+            self.whitespace();
+        }
+    }
+
+    /// A string, returns the contents between the quotes.
+    fn string(&mut self) -> Result<&'a str, Error> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => {
+                            self.pos += 1
+                        }
+                        Some(b'u') => {
+                            let hex = self.text.get(self.pos + 1..self.pos + 5);
+                            if !hex.is_some_and(|h| h.bytes().all(|b| b.is_ascii_hexdigit())) {
+                                return Err(Error::Syntax(self.pos));
+                            }
+                            self.pos += 5;
+                        }
+                        _ => return Err(Error::Syntax(self.pos)),
+                    }
+                }
+                Some(0..=0x1f) | None => return Err(Error::Syntax(self.pos)),
+                Some(_) => self.pos += 1,
+            }
+        }
+        let contents = &self.text[start..self.pos];
+        self.pos += 1;
+        Ok(contents)
+    }
+
+    fn number(&mut self) -> Result<&'a str, Error> {
+        let start = self.pos;
+        self.eat(b'-');
+        let digits = |p: &mut Self| {
+            let from = p.pos;
+            while p.peek().is_some_and(|b| b.is_ascii_digit()) {
+                p.pos += 1;
+            }
+            p.pos > from
+        };
+        if !digits(self) {
+            return Err(Error::Syntax(self.pos));
+        }
+        if self.eat(b'.') && !digits(self) {
+            return Err(Error::Syntax(self.pos));
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            if !self.eat(b'+') {
+                self.eat(b'-');
+            }
+            if !digits(self) {
+                return Err(Error::Syntax(self.pos));
+            }
+        }
+        Ok(&self.text[start..self.pos])
+    }
+
+    fn literal(&mut self, word: &str, value: Value<'a>) -> Result<Value<'a>, Error> {
+        if self.text[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(Error::Syntax(self.pos))
+        }
+    }
+}
+
+/// Write `text` as a JSON string, quotes included.
+pub fn write_str(w: &mut impl Write, text: &str) -> fmt::Result {
+    w.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+/// Writes the members of an object, call [`Object::finish`] to close it.
+pub struct Object<'w, W: Write> {
+    w: &'w mut W,
+    first: bool,
+}
+
+impl<'w, W: Write> Object<'w, W> {
+    pub fn new(w: &'w mut W) -> Result<Self, fmt::Error> {
+        w.write_char('{')?;
+        Ok(Self { w, first: true })
+    }
+
+    /// Write the key of the next member, the caller writes the value.
+    pub fn key(&mut self, key: &str) -> Result<&mut W, fmt::Error> {
+        if !self.first {
+            self.w.write_char(',')?;
+        }
+        self.first = false;
+        write_str(self.w, key)?;
+        self.w.write_char(':')?;
+        Ok(self.w)
+    }
+
+    pub fn str(&mut self, key: &str, value: &str) -> fmt::Result {
+        write_str(self.key(key)?, value)
+    }
+
+    /// A number or other value written as is by its `Display` impl.
+    pub fn raw(&mut self, key: &str, value: impl fmt::Display) -> fmt::Result {
+        write!(self.key(key)?, "{value}")
+    }
+
+    /// `null` for `None`.
+    pub fn option(&mut self, key: &str, value: Option<impl fmt::Display>) -> fmt::Result {
+        match value {
+            Some(value) => self.raw(key, value),
+            None => self.raw(key, "null"),
+        }
+    }
+
+    pub fn finish(self) -> fmt::Result {
+        self.w.write_char('}')
+    }
+}
+
+/// Writes the elements of an array, call [`Array::finish`] to close it.
+pub struct Array<'w, W: Write> {
+    w: &'w mut W,
+    first: bool,
+}
+
+impl<'w, W: Write> Array<'w, W> {
+    pub fn new(w: &'w mut W) -> Result<Self, fmt::Error> {
+        w.write_char('[')?;
+        Ok(Self { w, first: true })
+    }
+
+    /// Start the next element, the caller writes it.
+    pub fn item(&mut self) -> Result<&mut W, fmt::Error> {
+        if !self.first {
+            self.w.write_char(',')?;
+        }
+        self.first = false;
+        Ok(self.w)
+    }
+
+    pub fn str(&mut self, value: &str) -> fmt::Result {
+        write_str(self.item()?, value)
+    }
+
+    pub fn finish(self) -> fmt::Result {
+        self.w.write_char(']')
+    }
+}
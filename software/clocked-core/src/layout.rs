@@ -0,0 +1,88 @@
+//! Mapping of the logical ring onto the physical strip.
+//!
+//! Animations and the clock face draw 60 minute slots, slot 0 at twelve
+//! o'clock running clockwise. The strip behind the mirror can have a
+//! different number of LEDs (120 on the 144 LED/m mirror, two per minute
+//! pocket), start anywhere on the ring and run either way round. [`Layout`]
+//! describes how it is mounted and [`Mapped`] turns any [`FrameSink`] for
+//! the physical strip into one for the logical ring.
+
+use rgb::RGB8;
+
+use crate::ring::{Frame, FrameSink, BLACK, RING_SIZE};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Number of LEDs on the strip.
+    pub leds: usize,
+    /// Strip index of the first LED at or after twelve o'clock.
+    pub offset: usize,
+    /// The strip runs counter-clockwise.
+    pub reversed: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new(RING_SIZE)
+    }
+}
+
+impl Layout {
+    /// `leds` LEDs, starting at twelve o'clock and running clockwise.
+    pub fn new(leds: usize) -> Self {
+        Self {
+            leds,
+            offset: 0,
+            reversed: false,
+        }
+    }
+
+    /// Logical slot shown by strip LED `led`.
+    ///
+    /// With more LEDs than slots neighbouring LEDs share a slot, with fewer
+    /// some slots are not shown at all.
+    pub fn slot(&self, led: usize) -> usize {
+        let leds = self.leds.max(1);
+        let offset = self.offset % leds;
+        let position = if self.reversed {
+            (offset + leds - led % leds) % leds
+        } else {
+            (led % leds + leds - offset) % leds
+        };
+        position * RING_SIZE / leds
+    }
+
+    /// The pixels of `frame` in strip order.
+    pub fn map<'a>(&'a self, frame: &'a Frame) -> impl Iterator<Item = RGB8> + Clone + 'a {
+        (0..self.leds).map(move |led| frame[self.slot(led)])
+    }
+}
+
+/// A sink for the physical strip, taking logical frames.
+pub struct Mapped<S> {
+    pub sink: S,
+    pub layout: Layout,
+}
+
+impl<S> Mapped<S> {
+    pub fn new(sink: S, layout: Layout) -> Self {
+        Self { sink, layout }
+    }
+}
+
+impl<S: FrameSink> FrameSink for Mapped<S> {
+    type Error = S::Error;
+
+    /// Takes up to [`RING_SIZE`] logical pixels, missing ones are off.
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<RGB8>,
+    {
+        let mut frame = [BLACK; RING_SIZE];
+        for (slot, pixel) in frame.iter_mut().zip(iterator) {
+            *slot = pixel.into();
+        }
+        self.sink.write(self.layout.map(&frame))
+    }
+}
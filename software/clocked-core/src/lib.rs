@@ -0,0 +1,33 @@
+//! Target independent building blocks of the clocked firmware.
+//!
+//! Everything in here is plain `no_std` code without any ESP specifics, so it
+//! builds and runs on the host as well as on the clock.
+#![no_std]
+
+pub mod animation;
+pub mod api;
+pub mod crc;
+pub mod dhcp;
+pub mod dns;
+pub mod ed25519;
+pub mod flash;
+pub mod home_assistant;
+pub mod http;
+pub mod json;
+pub mod layout;
+pub mod manual;
+pub mod mdns;
+pub mod mqtt;
+pub mod night;
+pub mod ntp;
+pub mod ota;
+pub mod power;
+pub mod ring;
+pub mod rtc;
+pub mod schedule;
+pub mod settings;
+pub mod sha256;
+pub mod sha512;
+pub mod timer;
+pub mod tz;
+pub mod wifi;
@@ -0,0 +1,352 @@
+//! Setting the clock by hand, for clocks that never see a network.
+//!
+//! The time is set on the serial console with a [`Command`] or with the
+//! button through the [`Editor`], both in local time, which [`to_utc`] turns
+//! into the UTC the RTC runs on.
+//!
+//! Until the time came from anywhere, the ring shows [`render_unset`] under
+//! the hands. The time is saved as [`LastTime`] every now and then, so after a
+//! power cut the clock continues from there instead of from 1970, see
+//! [`resume`]. That time is behind by the length of the outage and still
+//! counts as not set.
+
+use core::time::Duration;
+
+use chrono::{Datelike, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike};
+use rgb::RGB8;
+
+use crate::{
+    animation::frame_index,
+    ring::{blend, ClockFace, Frame, BLACK, FRAMES_PER_SECOND, RING_SIZE},
+    tz::TimeZone,
+};
+
+/// Years that can be set, one LED each when set with the button.
+pub const FIRST_YEAR: i32 = 2025;
+pub const LAST_YEAR: i32 = FIRST_YEAR + RING_SIZE as i32 - 1;
+/// The [`Editor`] gives up after this long without a press.
+pub const EDITOR_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often the time is saved when nothing else calls for it.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Color of the day, month and year while setting them.
+pub const DATE: RGB8 = RGB8::new(0, 24, 24);
+/// Color of the marks while the time is not set.
+pub const UNSET: RGB8 = RGB8::new(48, 0, 0);
+
+/// What the console prints for `help`.
+pub const HELP: &str = "\
+time                       show the time
+time HH:MM[:SS]            set the local time of day
+date YYYY-MM-DD            set the local date
+set YYYY-MM-DD HH:MM[:SS]  set both";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Unknown command or the wrong number of arguments.
+    Command,
+    /// Not a `YYYY-MM-DD` date between [`FIRST_YEAR`] and [`LAST_YEAR`].
+    Date,
+    /// Not a `HH:MM` or `HH:MM:SS` time.
+    Time,
+}
+
+/// A line typed on the serial console.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// `help`
+    Help,
+    /// `time`, show the time and whether it is set.
+    Show,
+    /// `time HH:MM[:SS]`, `date YYYY-MM-DD` or `set YYYY-MM-DD HH:MM[:SS]`
+    /// (also with a `T` in between), local time. What is left out stays as
+    /// it is.
+    Set {
+        date: Option<NaiveDate>,
+        time: Option<NaiveTime>,
+    },
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, ParseError> {
+        let mut words = line.split_whitespace();
+        let command = match (words.next(), words.next(), words.next()) {
+            (Some("help"), None, _) => Self::Help,
+            (Some("time"), None, _) => Self::Show,
+            (Some("time"), Some(time), None) => Self::Set {
+                date: None,
+                time: Some(parse_time(time)?),
+            },
+            (Some("date"), Some(date), None) => Self::Set {
+                date: Some(parse_date(date)?),
+                time: None,
+            },
+            (Some("set"), Some(date), Some(time)) => Self::Set {
+                date: Some(parse_date(date)?),
+                time: Some(parse_time(time)?),
+            },
+            (Some("set"), Some(both), None) => {
+                let (date, time) = both.split_once('T').ok_or(ParseError::Time)?;
+                Self::Set {
+                    date: Some(parse_date(date)?),
+                    time: Some(parse_time(time)?),
+                }
+            }
+            _ => return Err(ParseError::Command),
+        };
+        match words.next() {
+            Some(_) => Err(ParseError::Command),
+            None => Ok(command),
+        }
+    }
+
+    /// The local time `now` changed as the command says, `None` if it does
+    /// not set the time.
+    pub fn apply(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        match *self {
+            Self::Set { date, time } => Some(NaiveDateTime::new(
+                date.unwrap_or(now.date()),
+                time.unwrap_or(now.time()),
+            )),
+            Self::Help | Self::Show => None,
+        }
+    }
+}
+
+/// Up to four decimal digits, nothing else.
+fn number(text: &str) -> Option<u32> {
+    if text.is_empty() || text.len() > 4 || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+fn parse_date(text: &str) -> Result<NaiveDate, ParseError> {
+    let mut parts = text.split('-').map(number);
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::Date);
+    };
+    NaiveDate::from_ymd_opt(year as i32, month, day)
+        .filter(|date| (FIRST_YEAR..=LAST_YEAR).contains(&date.year()))
+        .ok_or(ParseError::Date)
+}
+
+fn parse_time(text: &str) -> Result<NaiveTime, ParseError> {
+    let mut parts = text.split(':').map(number);
+    let (Some(Some(hour)), Some(Some(minute)), second) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::Time);
+    };
+    let second = match second {
+        None => 0,
+        Some(Some(second)) if parts.next().is_none() => second,
+        Some(_) => return Err(ParseError::Time),
+    };
+    NaiveTime::from_hms_opt(hour, minute, second).ok_or(ParseError::Time)
+}
+
+/// UTC of the local time `local` in `tz`.
+///
+/// A time in the hour repeated when the clocks go back is taken as the
+/// first of the two, one in the hour skipped when they go forward as if the
+/// clocks had not changed yet.
+pub fn to_utc(tz: &TimeZone, local: NaiveDateTime) -> NaiveDateTime {
+    match tz.from_local(local) {
+        LocalResult::Single(utc) | LocalResult::Ambiguous(utc, _) => utc,
+        // the clocks change at most once a day
+        LocalResult::None => {
+            local - TimeDelta::seconds(tz.offset_at(local - TimeDelta::days(1)) as i64)
+        }
+    }
+}
+
+/// Field the [`Editor`] is changing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    Hour,
+    Minute,
+    Day,
+    Month,
+    Year,
+}
+
+/// Setting the time with a single button.
+///
+/// A short press counts the current field up, a long one moves on to the
+/// next. The long press on the year sets the time, without any press for
+/// [`EDITOR_TIMEOUT`] the change is dropped. Times are monotonic like the
+/// [`Scheduler`](crate::animation::Scheduler)'s.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Editor {
+    time: NaiveDateTime,
+    field: Field,
+    touched: Duration,
+}
+
+impl Editor {
+    /// Start from the local time `now` without its seconds, at `elapsed`.
+    pub fn new(now: NaiveDateTime, elapsed: Duration) -> Self {
+        let date = date(
+            now.year().clamp(FIRST_YEAR, LAST_YEAR),
+            now.month(),
+            now.day(),
+        );
+        Self {
+            time: date
+                .and_hms_opt(now.hour(), now.minute(), 0)
+                .unwrap_or_default(),
+            field: Field::Hour,
+            touched: elapsed,
+        }
+    }
+
+    /// Local time as set so far.
+    pub fn time(&self) -> NaiveDateTime {
+        self.time
+    }
+
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    /// Count the current field up by one, wrapping around at its end
+    /// without touching the others.
+    pub fn increment(&mut self, elapsed: Duration) {
+        self.touched = elapsed;
+        let t = self.time;
+        let (mut year, mut month, mut day) = (t.year(), t.month(), t.day());
+        let (mut hour, mut minute) = (t.hour(), t.minute());
+        match self.field {
+            Field::Hour => hour = (hour + 1) % 24,
+            Field::Minute => minute = (minute + 1) % 60,
+            Field::Day => day = day % last_day(year, month) + 1,
+            Field::Month => month = month % 12 + 1,
+            Field::Year if year >= LAST_YEAR => year = FIRST_YEAR,
+            Field::Year => year += 1,
+        }
+        self.time = date(year, month, day)
+            .and_hms_opt(hour, minute, 0)
+            .unwrap_or_default();
+    }
+
+    /// Move on to the next field, returns the local time to set after the
+    /// last one.
+    pub fn next(&mut self, elapsed: Duration) -> Option<NaiveDateTime> {
+        self.touched = elapsed;
+        self.field = match self.field {
+            Field::Hour => Field::Minute,
+            Field::Minute => Field::Day,
+            Field::Day => Field::Month,
+            Field::Month => Field::Year,
+            Field::Year => return Some(self.time),
+        };
+        None
+    }
+
+    /// Nothing was pressed for [`EDITOR_TIMEOUT`].
+    pub fn is_expired(&self, elapsed: Duration) -> bool {
+        elapsed >= self.touched + EDITOR_TIMEOUT
+    }
+
+    /// Draw the field being set into the cleared `frame`.
+    ///
+    /// Hour and minute show as hands in the colors of `face`, day, month
+    /// and year as an arc of that many LEDs, five per month and one per year
+    /// from [`FIRST_YEAR`]. The hand or the end of the arc blinks.
+    pub fn render(&self, elapsed: Duration, face: &ClockFace, frame: &mut Frame) {
+        let on = frame_index(elapsed) < FRAMES_PER_SECOND / 2;
+        let blink = |field: Field, color: RGB8| {
+            if self.field == field && !on {
+                BLACK
+            } else {
+                color
+            }
+        };
+        let len = match self.field {
+            Field::Hour | Field::Minute => {
+                let hands = ClockFace {
+                    hour: blink(Field::Hour, face.hour),
+                    minute: blink(Field::Minute, face.minute),
+                    second: BLACK,
+                };
+                hands.render(&self.time, frame);
+                return;
+            }
+            Field::Day => self.time.day() as usize,
+            Field::Month => self.time.month() as usize * 5,
+            Field::Year => (self.time.year() - FIRST_YEAR) as usize + 1,
+        };
+        let len = len.min(RING_SIZE);
+        frame[..len - 1].fill(DATE);
+        frame[len - 1] = if on { DATE } else { BLACK };
+    }
+}
+
+/// Number of days in `month` of `year`.
+fn last_day(year: i32, month: u32) -> u32 {
+    (28..=31)
+        .rev()
+        .find(|&day| NaiveDate::from_ymd_opt(year, month, day).is_some())
+        .unwrap_or(28)
+}
+
+/// The date, with the day moved back to the end of shorter months.
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day.min(last_day(year, month))).unwrap_or_default()
+}
+
+/// Draw the marks at twelve, three, six and nine o'clock into `frame`,
+/// pulsing in [`UNSET`] every two seconds.
+pub fn render_unset(elapsed: Duration, frame: &mut Frame) {
+    let phase = (elapsed.as_secs() % 2) as usize * FRAMES_PER_SECOND + frame_index(elapsed);
+    let amount = (phase.abs_diff(FRAMES_PER_SECOND) * 255 / FRAMES_PER_SECOND) as u8;
+    let color = blend(BLACK, UNSET, amount);
+    for pixel in frame.iter_mut().step_by(RING_SIZE / 4) {
+        *pixel = color;
+    }
+}
+
+/// The time saved before a restart or power cut.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LastTime {
+    /// UTC.
+    pub time: NaiveDateTime,
+    /// The time came from NTP, a web server or was set by hand.
+    pub known: bool,
+}
+
+/// What to make of the RTC after a start, see [`resume`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// The RTC kept running through a restart and has the known time.
+    Kept,
+    /// The RTC lost power, continue from the saved time.
+    Restored(NaiveDateTime),
+    /// Keep counting on the RTC, the time was not known before either.
+    Unknown,
+}
+
+/// Compare the time `rtc` after a start with the time saved before.
+pub fn resume(rtc: NaiveDateTime, last: Option<LastTime>) -> Resume {
+    match last {
+        // without power the RTC starts over from 1970
+        Some(last) if rtc < last.time => Resume::Restored(last.time),
+        Some(LastTime { known: true, .. }) => Resume::Kept,
+        _ => Resume::Unknown,
+    }
+}
+
+/// Whether to save the UTC time `now`, `known` or not, over `last`, which
+/// was saved `since` ago.
+///
+/// Besides every [`SAVE_INTERVAL`] that is as soon as the time becomes
+/// known or is set back, a time behind the saved one would be taken for a
+/// power cut by [`resume`].
+pub fn save_due(last: Option<LastTime>, now: NaiveDateTime, known: bool, since: Duration) -> bool {
+    match last {
+        // nothing worth resuming from yet
+        None => known,
+        Some(last) => last.known != known || now < last.time || since >= SAVE_INTERVAL,
+    }
+}
@@ -0,0 +1,409 @@
+//! Multicast DNS responder (RFC 6762) with DNS-SD (RFC 6763).
+//!
+//! The clock answers for `<name>.local` and advertises its web UI as an
+//! `_http._tcp` service called `<name>`, so browsers and apps find it
+//! without knowing the address DHCP handed out. The name is a setting and
+//! defaults to the device id, `clocked-` and the end of the MAC address.
+//!
+//! Only the records of the clock itself are handled. There is no probing for
+//! conflicts, the device id is unique enough on a home network.
+
+use core::fmt::Write as _;
+
+use heapless::String;
+
+use crate::dns::HEADER_LEN;
+
+pub const PORT: u16 = 5353;
+/// IPv4 multicast group of mDNS.
+pub const GROUP: [u8; 4] = [224, 0, 0, 251];
+/// Longest device name, a single DNS label.
+pub const MAX_NAME_LEN: usize = 32;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on the class of records that replace all cached ones of the name.
+const CACHE_FLUSH: u16 = 0x8000;
+/// Set on the class of a question that prefers a unicast answer.
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// Time to live of records bound to the host, RFC 6762 section 10.
+const HOST_TTL: u32 = 120;
+/// Time to live of the other records.
+const OTHER_TTL: u32 = 4500;
+/// Time to live in answers to plain DNS resolvers, RFC 6762 section 6.7.
+const LEGACY_TTL: u32 = 10;
+
+const SERVICE: &str = "_http._tcp.local";
+const SERVICES: &str = "_services._dns-sd._udp.local";
+/// Longest name the responder handles, `<name>._http._tcp.local`.
+const MAX_FULL_NAME_LEN: usize = MAX_NAME_LEN + SERVICE.len() + 1;
+
+/// Whether `name` works as device name: letters, digits and hyphens, not
+/// starting or ending with a hyphen.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// What the clock announces about itself.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Host<'a> {
+    /// Device name, see [`is_valid_name`].
+    pub name: &'a str,
+    pub address: [u8; 4],
+    /// Port of the web UI.
+    pub port: u16,
+}
+
+/// Records of [`Host`] to send.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Records {
+    /// `<name>.local` to the address.
+    address: bool,
+    /// `_http._tcp.local` to the service instance.
+    service: bool,
+    /// Service instance to host and port.
+    srv: bool,
+    /// Service instance to its text, the path of the web UI.
+    txt: bool,
+    /// `_services._dns-sd._udp.local` to `_http._tcp.local`.
+    services: bool,
+}
+
+impl Records {
+    const ALL: Self = Self {
+        address: true,
+        service: true,
+        srv: true,
+        txt: true,
+        services: true,
+    };
+
+    fn count(&self) -> u16 {
+        [
+            self.address,
+            self.service,
+            self.srv,
+            self.txt,
+            self.services,
+        ]
+        .into_iter()
+        .filter(|&set| set)
+        .count() as u16
+    }
+
+    fn with(self, other: Self) -> Self {
+        Self {
+            address: self.address || other.address,
+            service: self.service || other.service,
+            srv: self.srv || other.srv,
+            txt: self.txt || other.txt,
+            services: self.services || other.services,
+        }
+    }
+
+    fn without(self, other: Self) -> Self {
+        Self {
+            address: self.address && !other.address,
+            service: self.service && !other.service,
+            srv: self.srv && !other.srv,
+            txt: self.txt && !other.txt,
+            services: self.services && !other.services,
+        }
+    }
+
+    /// Records a resolver needs next to `self`, RFC 6763 section 12.
+    fn additional(self) -> Self {
+        Self {
+            address: self.service || self.srv,
+            srv: self.service,
+            txt: self.service,
+            ..Self::default()
+        }
+        .without(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+}
+
+/// Where a response goes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    /// The multicast group, for everyone on the network to cache.
+    Multicast,
+    /// Only the one asking: a query sent from a port other than [`PORT`],
+    /// i.e. a plain DNS resolver, or a question asking for it.
+    Unicast,
+}
+
+/// Answer an mDNS query about `host`, written to `out`.
+///
+/// `from_port` is the source port of the query. Returns the length of the
+/// response and where to send it, or `None` if no question is about the
+/// clock.
+pub fn respond(
+    query: &[u8],
+    from_port: u16,
+    host: &Host<'_>,
+    out: &mut [u8],
+) -> Option<(usize, Destination)> {
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let is_query = flags & 0x8000 == 0;
+    let opcode = (flags >> 11) & 0xf;
+    if !is_query || opcode != 0 {
+        return None;
+    }
+    let questions = u16::from_be_bytes([query[4], query[5]]);
+
+    let names = Names::new(host.name)?;
+    let mut answers = Records::default();
+    let mut unicast = from_port != PORT;
+    let mut pos = HEADER_LEN;
+    for _ in 0..questions {
+        let (name, end) = read_name(query, pos)?;
+        let field = query.get(end..end + 4)?;
+        let qtype = u16::from_be_bytes([field[0], field[1]]);
+        let qclass = u16::from_be_bytes([field[2], field[3]]);
+        pos = end + 4;
+        if qclass & !UNICAST_RESPONSE != CLASS_IN {
+            continue;
+        }
+        let asked = names.matching(&name, qtype);
+        if !asked.is_empty() && qclass & UNICAST_RESPONSE != 0 {
+            unicast = true;
+        }
+        answers = answers.with(asked);
+    }
+    if answers.is_empty() {
+        return None;
+    }
+
+    let legacy = from_port != PORT;
+    let mut w = Writer { out, pos: 0 };
+    if legacy {
+        // a plain resolver expects its id and questions back
+        w.bytes(&query[..2])?;
+        w.u16(0x8400)?;
+        w.bytes(&query[4..6])?;
+    } else {
+        w.bytes(&[0, 0, 0x84, 0, 0, 0])?;
+    }
+    let additional = answers.additional();
+    w.u16(answers.count())?;
+    w.u16(0)?;
+    w.u16(additional.count())?;
+    if legacy {
+        w.bytes(&query[HEADER_LEN..pos])?;
+    }
+    w.records(&names, host, answers, legacy)?;
+    w.records(&names, host, additional, legacy)?;
+    let destination = match unicast {
+        true => Destination::Unicast,
+        false => Destination::Multicast,
+    };
+    Some((w.pos, destination))
+}
+
+/// Unsolicited response with all records of `host`, sent when the clock
+/// joins the network or changes its name or address, RFC 6762 section 8.3.
+pub fn announce(host: &Host<'_>, out: &mut [u8]) -> Option<usize> {
+    let names = Names::new(host.name)?;
+    let mut w = Writer { out, pos: 0 };
+    w.bytes(&[0, 0, 0x84, 0, 0, 0])?;
+    w.u16(Records::ALL.count())?;
+    w.bytes(&[0, 0, 0, 0])?;
+    w.records(&names, host, Records::ALL, false)?;
+    Some(w.pos)
+}
+
+/// The names the clock answers for.
+struct Names {
+    host: String<MAX_FULL_NAME_LEN>,
+    instance: String<MAX_FULL_NAME_LEN>,
+}
+
+impl Names {
+    fn new(name: &str) -> Option<Self> {
+        if !is_valid_name(name) {
+            return None;
+        }
+        let mut host = String::new();
+        let mut instance = String::new();
+        write!(host, "{name}.local").ok()?;
+        write!(instance, "{name}.{SERVICE}").ok()?;
+        Some(Self { host, instance })
+    }
+
+    /// Records answering a question for `name` and `qtype`.
+    fn matching(&self, name: &str, qtype: u16) -> Records {
+        let is = |wanted: u16| qtype == wanted || qtype == TYPE_ANY;
+        if name.eq_ignore_ascii_case(&self.host) {
+            Records {
+                address: is(TYPE_A),
+                ..Records::default()
+            }
+        } else if name.eq_ignore_ascii_case(SERVICE) {
+            Records {
+                service: is(TYPE_PTR),
+                ..Records::default()
+            }
+        } else if name.eq_ignore_ascii_case(&self.instance) {
+            Records {
+                srv: is(TYPE_SRV),
+                txt: is(TYPE_TXT),
+                ..Records::default()
+            }
+        } else if name.eq_ignore_ascii_case(SERVICES) {
+            Records {
+                services: is(TYPE_PTR),
+                ..Records::default()
+            }
+        } else {
+            Records::default()
+        }
+    }
+}
+
+/// The name starting at `pos` in dotted form and the offset just past it,
+/// following compression pointers. Names too long to be one of the clock's
+/// come back empty.
+fn read_name(message: &[u8], mut pos: usize) -> Option<(String<MAX_FULL_NAME_LEN>, usize)> {
+    let mut name = String::new();
+    let mut fits = true;
+    let mut end = None;
+    // a name can't hold more pointers than labels, this stops loops
+    for _ in 0..128 {
+        let len = *message.get(pos)? as usize;
+        match len {
+            0 => {
+                if !fits {
+                    name.clear();
+                }
+                return Some((name, end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xc0 == 0xc0 => {
+                let low = *message.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = (l & 0x3f) << 8 | low;
+            }
+            l if l & 0xc0 != 0 => return None,
+            l => {
+                let label = core::str::from_utf8(message.get(pos + 1..pos + 1 + l)?).ok()?;
+                if !name.is_empty() {
+                    fits &= name.push('.').is_ok();
+                }
+                fits &= name.push_str(label).is_ok();
+                pos += 1 + l;
+            }
+        }
+    }
+    None
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Option<()> {
+        self.out
+            .get_mut(self.pos..self.pos + bytes.len())?
+            .copy_from_slice(bytes);
+        self.pos += bytes.len();
+        Some(())
+    }
+
+    fn u16(&mut self, value: u16) -> Option<()> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// A dotted name, without compression.
+    fn name(&mut self, name: &str) -> Option<()> {
+        for label in name.split('.') {
+            self.bytes(&[label.len() as u8])?;
+            self.bytes(label.as_bytes())?;
+        }
+        self.bytes(&[0])
+    }
+
+    /// Record head up to the data length, `unique` records replace what
+    /// caches hold for their name.
+    fn record(
+        &mut self,
+        name: &str,
+        rtype: u16,
+        ttl: u32,
+        unique: bool,
+        legacy: bool,
+    ) -> Option<()> {
+        self.name(name)?;
+        self.u16(rtype)?;
+        let flush = if unique && !legacy { CACHE_FLUSH } else { 0 };
+        self.u16(CLASS_IN | flush)?;
+        let ttl = if legacy { ttl.min(LEGACY_TTL) } else { ttl };
+        self.bytes(&ttl.to_be_bytes())
+    }
+
+    /// Data of a record, prefixed with its length.
+    fn data(&mut self, write: impl FnOnce(&mut Self) -> Option<()>) -> Option<()> {
+        let len_at = self.pos;
+        self.u16(0)?;
+        write(self)?;
+        let len = (self.pos - len_at - 2) as u16;
+        self.out[len_at..len_at + 2].copy_from_slice(&len.to_be_bytes());
+        Some(())
+    }
+
+    fn records(
+        &mut self,
+        names: &Names,
+        host: &Host<'_>,
+        records: Records,
+        legacy: bool,
+    ) -> Option<()> {
+        if records.address {
+            self.record(&names.host, TYPE_A, HOST_TTL, true, legacy)?;
+            self.data(|w| w.bytes(&host.address))?;
+        }
+        if records.service {
+            self.record(SERVICE, TYPE_PTR, OTHER_TTL, false, legacy)?;
+            self.data(|w| w.name(&names.instance))?;
+        }
+        if records.srv {
+            self.record(&names.instance, TYPE_SRV, HOST_TTL, true, legacy)?;
+            self.data(|w| {
+                // priority and weight
+                w.bytes(&[0, 0, 0, 0])?;
+                w.u16(host.port)?;
+                w.name(&names.host)
+            })?;
+        }
+        if records.txt {
+            const PATH: &[u8] = b"path=/";
+            self.record(&names.instance, TYPE_TXT, OTHER_TTL, true, legacy)?;
+            self.data(|w| {
+                w.bytes(&[PATH.len() as u8])?;
+                w.bytes(PATH)
+            })?;
+        }
+        if records.services {
+            self.record(SERVICES, TYPE_PTR, OTHER_TTL, false, legacy)?;
+            self.data(|w| w.name(SERVICE))?;
+        }
+        Some(())
+    }
+}
@@ -0,0 +1,345 @@
+//! MQTT 3.1.1 packets, as much as a client needs that publishes at QoS 0
+//! and subscribes to a few command topics.
+//!
+//! Packets are encoded into and decoded from caller supplied buffers, the
+//! transport is up to the caller. Incoming QoS 1 messages are understood so
+//! they can be acknowledged, QoS 2 is never asked for and not supported.
+//!
+//! [`Broker`] holds the connection settings stored with the other
+//! [`Settings`](crate::settings::Settings).
+
+use core::str;
+
+use heapless::String;
+
+pub const DEFAULT_PORT: u16 = 1883;
+pub const MAX_HOST_LEN: usize = 64;
+pub const MAX_USERNAME_LEN: usize = 32;
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+/// The remaining length takes at most four bytes.
+const MAX_REMAINING_LEN: usize = 268_435_455;
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The packet has not been received completely yet.
+    Incomplete,
+    /// Not a valid packet.
+    Malformed,
+    /// The packet does not fit into the output buffer.
+    TooLarge,
+}
+
+/// Broker to publish to, `enabled` with an empty host does nothing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Broker {
+    pub enabled: bool,
+    /// Name or IPv4 address.
+    pub host: String<MAX_HOST_LEN>,
+    pub port: u16,
+    /// Empty to connect without credentials.
+    pub username: String<MAX_USERNAME_LEN>,
+    pub password: String<MAX_PASSWORD_LEN>,
+    /// Announce the clock to Home Assistant, see
+    /// [`home_assistant`](crate::home_assistant).
+    pub discovery: bool,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: DEFAULT_PORT,
+            username: String::new(),
+            password: String::new(),
+            discovery: true,
+        }
+    }
+}
+
+/// Message the broker publishes for the client when the connection is lost.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub retain: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    /// Seconds, the client has to send something at least this often.
+    pub keep_alive: u16,
+    pub will: Option<Will<'a>>,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Publish<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub retain: bool,
+    /// Set for QoS 1 messages, which have to be acknowledged.
+    pub packet_id: Option<u16>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    ConnAck {
+        session_present: bool,
+        /// 0 if the connection was accepted.
+        code: u8,
+    },
+    Publish(Publish<'a>),
+    PubAck(u16),
+    SubAck {
+        packet_id: u16,
+        /// Granted QoS per topic, 0x80 for a refused subscription.
+        codes: &'a [u8],
+    },
+    PingResp,
+    /// A packet a client does not expect, identified by its type.
+    Other(u8),
+}
+
+/// Encode a CONNECT packet with a clean session, returns its length.
+pub fn connect(packet: &Connect<'_>, out: &mut [u8]) -> Result<usize, Error> {
+    let will_len = packet
+        .will
+        .map_or(0, |will| 2 + will.topic.len() + 2 + will.payload.len());
+    let len = 10
+        + 2
+        + packet.client_id.len()
+        + will_len
+        + packet.username.map_or(0, |name| 2 + name.len())
+        + packet.password.map_or(0, |password| 2 + password.len());
+
+    let mut flags = 0x02;
+    if let Some(will) = packet.will {
+        flags |= 0x04 | (will.retain as u8) << 5;
+    }
+    if packet.username.is_some() {
+        flags |= 0x80;
+    }
+    if packet.password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut w = Writer::new(out);
+    w.header(CONNECT << 4, len)?;
+    w.str("MQTT")?;
+    w.u8(4)?;
+    w.u8(flags)?;
+    w.u16(packet.keep_alive)?;
+    w.str(packet.client_id)?;
+    if let Some(will) = packet.will {
+        w.str(will.topic)?;
+        w.u16(u16::try_from(will.payload.len()).map_err(|_| Error::TooLarge)?)?;
+        w.bytes(will.payload)?;
+    }
+    if let Some(username) = packet.username {
+        w.str(username)?;
+    }
+    if let Some(password) = packet.password {
+        w.str(password)?;
+    }
+    Ok(w.pos)
+}
+
+/// Encode a PUBLISH packet, at QoS 1 if it has a packet id.
+pub fn publish(packet: &Publish<'_>, out: &mut [u8]) -> Result<usize, Error> {
+    let len = 2 + packet.topic.len() + packet.packet_id.map_or(0, |_| 2) + packet.payload.len();
+    let qos = packet.packet_id.map_or(0, |_| 1);
+    let mut w = Writer::new(out);
+    w.header(PUBLISH << 4 | qos << 1 | packet.retain as u8, len)?;
+    w.str(packet.topic)?;
+    if let Some(id) = packet.packet_id {
+        w.u16(id)?;
+    }
+    w.bytes(packet.payload)?;
+    Ok(w.pos)
+}
+
+/// Acknowledge a QoS 1 message.
+pub fn puback(packet_id: u16, out: &mut [u8]) -> Result<usize, Error> {
+    let mut w = Writer::new(out);
+    w.header(PUBACK << 4, 2)?;
+    w.u16(packet_id)?;
+    Ok(w.pos)
+}
+
+/// Subscribe to `topics` at QoS 0.
+pub fn subscribe(packet_id: u16, topics: &[&str], out: &mut [u8]) -> Result<usize, Error> {
+    let len = 2 + topics
+        .iter()
+        .map(|topic| 2 + topic.len() + 1)
+        .sum::<usize>();
+    let mut w = Writer::new(out);
+    w.header(SUBSCRIBE << 4 | 0x02, len)?;
+    w.u16(packet_id)?;
+    for topic in topics {
+        w.str(topic)?;
+        w.u8(0)?;
+    }
+    Ok(w.pos)
+}
+
+pub fn ping(out: &mut [u8]) -> Result<usize, Error> {
+    let mut w = Writer::new(out);
+    w.header(PINGREQ << 4, 0)?;
+    Ok(w.pos)
+}
+
+pub fn disconnect(out: &mut [u8]) -> Result<usize, Error> {
+    let mut w = Writer::new(out);
+    w.header(DISCONNECT << 4, 0)?;
+    Ok(w.pos)
+}
+
+/// Decode the packet at the start of `buf`, returns it and its length.
+pub fn decode(buf: &[u8]) -> Result<(Packet<'_>, usize), Error> {
+    let &first = buf.first().ok_or(Error::Incomplete)?;
+    let mut len = 0usize;
+    let mut header_len = 1;
+    loop {
+        let &byte = buf.get(header_len).ok_or(Error::Incomplete)?;
+        len |= ((byte & 0x7f) as usize) << (7 * (header_len - 1));
+        header_len += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if header_len == 5 {
+            return Err(Error::Malformed);
+        }
+    }
+    let body = buf
+        .get(header_len..header_len + len)
+        .ok_or(Error::Incomplete)?;
+    let mut r = Reader { data: body };
+
+    let kind = first >> 4;
+    let flags = first & 0x0f;
+    let packet = match kind {
+        CONNACK => {
+            let session_present = r.u8()? & 0x01 != 0;
+            let code = r.u8()?;
+            Packet::ConnAck {
+                session_present,
+                code,
+            }
+        }
+        PUBLISH => {
+            let qos = (flags >> 1) & 0x03;
+            let topic = r.str()?;
+            let packet_id = match qos {
+                0 => None,
+                1 => Some(r.u16()?),
+                _ => return Err(Error::Malformed),
+            };
+            Packet::Publish(Publish {
+                topic,
+                payload: r.data,
+                retain: flags & 0x01 != 0,
+                packet_id,
+            })
+        }
+        PUBACK => Packet::PubAck(r.u16()?),
+        SUBACK => Packet::SubAck {
+            packet_id: r.u16()?,
+            codes: r.data,
+        },
+        PINGRESP => Packet::PingResp,
+        other => Packet::Other(other),
+    };
+    Ok((packet, header_len + len))
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(out: &'a mut [u8]) -> Self {
+        Self { out, pos: 0 }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.out
+            .get_mut(self.pos..self.pos + bytes.len())
+            .ok_or(Error::TooLarge)?
+            .copy_from_slice(bytes);
+        self.pos += bytes.len();
+        Ok(())
+    }
+
+    fn u8(&mut self, value: u8) -> Result<(), Error> {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> Result<(), Error> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    fn str(&mut self, value: &str) -> Result<(), Error> {
+        self.u16(u16::try_from(value.len()).map_err(|_| Error::TooLarge)?)?;
+        self.bytes(value.as_bytes())
+    }
+
+    /// Fixed header with the remaining length `len`.
+    fn header(&mut self, first: u8, mut len: usize) -> Result<(), Error> {
+        if len > MAX_REMAINING_LEN {
+            return Err(Error::TooLarge);
+        }
+        self.u8(first)?;
+        loop {
+            let byte = (len & 0x7f) as u8;
+            len >>= 7;
+            if len == 0 {
+                return self.u8(byte);
+            }
+            self.u8(byte | 0x80)?;
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.data.len() {
+            return Err(Error::Malformed);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn str(&mut self) -> Result<&'a str, Error> {
+        let len = self.u16()? as usize;
+        str::from_utf8(self.bytes(len)?).map_err(|_| Error::Malformed)
+    }
+}
@@ -0,0 +1,83 @@
+//! Night mode and the "OK to wake" light.
+//!
+//! From [`NightMode::start`] until [`NightMode::wake`] the ring only shows
+//! the clock hands at [`NightMode::level`], 0 turns it off completely. At
+//! the wake time it lights up in [`NightMode::wake_color`] for
+//! [`NightMode::wake_minutes`], the sign for a child that it may get up now.
+//!
+//! A schedule block with its own brightness overrides the night level, see
+//! [`Settings::program`](crate::settings::Settings::program).
+
+use chrono::{NaiveDateTime, Timelike};
+use rgb::RGB8;
+
+use crate::schedule::MINUTES_PER_DAY;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Day,
+    Night,
+    /// The wake color is showing.
+    Wake,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NightMode {
+    pub enabled: bool,
+    /// Minute of the day the night starts at.
+    pub start: u16,
+    /// Minute of the day the night ends and the wake color comes on.
+    pub wake: u16,
+    /// Brightness during the night.
+    pub level: u8,
+    pub wake_color: RGB8,
+    /// How long the wake color shows, 0 to skip it.
+    pub wake_minutes: u16,
+}
+
+impl Default for NightMode {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: 19 * 60 + 30,
+            wake: 7 * 60,
+            level: 16,
+            wake_color: RGB8::new(0, 24, 6),
+            wake_minutes: 30,
+        }
+    }
+}
+
+impl NightMode {
+    /// Phase at local time `now`.
+    pub fn phase(&self, now: &NaiveDateTime) -> Phase {
+        if !self.enabled {
+            return Phase::Day;
+        }
+        let minute = (now.hour() * 60 + now.minute()) as u16;
+        let wake_end = (self.wake + self.wake_minutes % MINUTES_PER_DAY) % MINUTES_PER_DAY;
+        if within(self.start, self.wake, minute) {
+            Phase::Night
+        } else if within(self.wake, wake_end, minute) {
+            Phase::Wake
+        } else {
+            Phase::Day
+        }
+    }
+
+    /// Brightness in `phase` instead of the configured `level`, the night
+    /// only ever dims.
+    pub fn level(&self, phase: Phase, level: u8) -> u8 {
+        match phase {
+            Phase::Night => level.min(self.level),
+            Phase::Day | Phase::Wake => level,
+        }
+    }
+}
+
+/// `minute` lies in `from..to`, which may run over midnight and is empty if
+/// `from == to`.
+fn within(from: u16, to: u16, minute: u16) -> bool {
+    let since = (minute + MINUTES_PER_DAY - from) % MINUTES_PER_DAY;
+    since < (to + MINUTES_PER_DAY - from) % MINUTES_PER_DAY
+}
@@ -0,0 +1,688 @@
+//! SNTP (RFC 4330): timestamps, packets, choosing among several servers and
+//! disciplining the clock with the result.
+//!
+//! An NTP timestamp is a 32 bit count of seconds since 1900-01-01 plus a 32 bit
+//! binary fraction of a second. The seconds wrap around on 2036-02-07, the
+//! start of era 1. Following RFC 4330 section 3, timestamps with the most
+//! significant bit cleared are taken to be in era 1, which keeps the
+//! conversion correct from 1968 until 2104.
+//!
+//! Every sync asks several servers. Each answer becomes a [`Sample`], the
+//! offset of the local clock with an interval it is certainly in, and
+//! [`select`] keeps the offset most servers agree on (Marzullo's algorithm),
+//! so a single wrong server cannot move the clock. [`Discipline`] applies
+//! that offset: large ones at once, small ones slewed in over a few seconds
+//! so the second hand never jumps, and it learns the drift of the local
+//! oscillator from one sync to the next to correct it in between.
+//!
+//! Which servers are asked and how often is up to the [`Config`] in the
+//! settings. Where NTP is blocked the `Date` of a web server is the fallback,
+//! see [`Sample::from_seconds`].
+
+use core::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime};
+use heapless::{String, Vec};
+
+/// Seconds between the NTP epoch (1900-01-01) and the Unix epoch (1970-01-01).
+pub const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// Length of one NTP era in seconds.
+const ERA: i64 = 1 << 32;
+
+/// Raw 64 bit NTP timestamp.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NtpTimestamp {
+    pub seconds: u32,
+    pub fraction: u32,
+}
+
+impl NtpTimestamp {
+    pub const fn new(seconds: u32, fraction: u32) -> Self {
+        Self { seconds, fraction }
+    }
+
+    /// Parse a timestamp in network byte order, as found in an NTP packet.
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Self {
+        Self {
+            seconds: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            fraction: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        let s = self.seconds.to_be_bytes();
+        let f = self.fraction.to_be_bytes();
+        [s[0], s[1], s[2], s[3], f[0], f[1], f[2], f[3]]
+    }
+
+    /// Timestamp for the given number of microseconds since the Unix epoch.
+    pub fn from_unix_micros(micros: i64) -> Self {
+        let seconds = micros.div_euclid(1_000_000) + NTP_UNIX_OFFSET;
+        let micros = micros.rem_euclid(1_000_000) as u32;
+        Self {
+            // truncating to 32 bits is exactly the era wrap around
+            seconds: seconds as u32,
+            fraction: micros_to_fraction(micros),
+        }
+    }
+
+    /// Seconds since the Unix epoch, taking the NTP era into account.
+    pub fn unix_seconds(self) -> i64 {
+        let seconds = self.seconds as i64;
+        if self.seconds & 0x8000_0000 != 0 {
+            // era 0, 1968-01-20 until 2036-02-07
+            seconds - NTP_UNIX_OFFSET
+        } else {
+            // era 1, 2036-02-07 until 2104-02-26
+            seconds + ERA - NTP_UNIX_OFFSET
+        }
+    }
+
+    /// Microseconds since the Unix epoch, including the fractional second.
+    pub fn unix_micros(self) -> i64 {
+        self.unix_seconds() * 1_000_000 + fraction_to_micros(self.fraction) as i64
+    }
+
+    /// UTC date and time of this timestamp.
+    pub fn to_datetime(self) -> NaiveDateTime {
+        // always in range, chrono covers far more than the two NTP eras
+        DateTime::from_timestamp_micros(self.unix_micros())
+            .expect("NTP timestamps are within chrono's range")
+            .naive_utc()
+    }
+}
+
+/// Convert an NTP fraction (units of 2^-32 s) to microseconds, rounding down.
+pub fn fraction_to_micros(fraction: u32) -> u32 {
+    ((fraction as u64 * 1_000_000) >> 32) as u32
+}
+
+/// Convert microseconds (below one second) to an NTP fraction.
+///
+/// Rounds up, so that [`fraction_to_micros`] gives back the same value.
+pub fn micros_to_fraction(micros: u32) -> u32 {
+    debug_assert!(micros < 1_000_000);
+    (((micros as u64) << 32).div_ceil(1_000_000)) as u32
+}
+
+pub const PORT: u16 = 123;
+pub const PACKET_LEN: usize = 48;
+/// Most samples [`select`] looks at.
+pub const MAX_SAMPLES: usize = 8;
+
+/// Offsets up to this are slewed, larger ones stepped, in microseconds.
+pub const STEP_THRESHOLD: i64 = 2_000_000;
+/// How much faster or slower than real time the clock runs while slewing,
+/// in parts per million. 1 s takes 20 s to slew in.
+pub const SLEW_RATE_PPM: i64 = 50_000;
+/// Largest drift of the oscillator that is corrected, in parts per billion.
+pub const MAX_DRIFT_PPB: i64 = 500_000;
+/// Shortest time between two syncs to learn the drift from, below it the
+/// offset is mostly network jitter.
+pub const MIN_DRIFT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Share of the measured drift error corrected per sync.
+const DRIFT_GAIN: i64 = 2;
+
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+/// Leap indicator of a server that is not synchronized itself.
+const LEAP_ALARM: u8 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    TooShort,
+    /// Not a server reply to a client request.
+    NotAServer,
+    /// Stratum 0, the server asks us to go away or slow down.
+    KissOfDeath,
+    /// The server has no good time itself.
+    Unsynchronized,
+    /// Not the answer to the request sent last.
+    WrongOriginate,
+}
+
+/// Client request sent at `transmit`, the server echoes the timestamp back
+/// as originate timestamp.
+pub fn request(transmit: NtpTimestamp) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    packet[0] = VERSION << 3 | MODE_CLIENT;
+    packet[40..48].copy_from_slice(&transmit.to_be_bytes());
+    packet
+}
+
+/// The parts of a server reply needed for a [`Sample`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Reply {
+    pub stratum: u8,
+    /// Round trip to the reference clock of the server, in microseconds.
+    pub root_delay: i64,
+    /// Error the server admits to, in microseconds.
+    pub root_dispersion: i64,
+    /// When the server received the request.
+    pub receive: NtpTimestamp,
+    /// When the server sent the reply.
+    pub transmit: NtpTimestamp,
+}
+
+impl Reply {
+    /// Parse the reply to the request sent at `originate`.
+    pub fn parse(packet: &[u8], originate: NtpTimestamp) -> Result<Self, Error> {
+        let packet: &[u8; PACKET_LEN] = packet
+            .get(..PACKET_LEN)
+            .and_then(|p| p.try_into().ok())
+            .ok_or(Error::TooShort)?;
+        let timestamp = |at: usize| {
+            NtpTimestamp::from_be_bytes(packet[at..at + 8].try_into().expect("8 bytes"))
+        };
+        let short = |at: usize| u32::from_be_bytes(packet[at..at + 4].try_into().expect("4 bytes"));
+
+        let leap = packet[0] >> 6;
+        let mode = packet[0] & 0x7;
+        let stratum = packet[1];
+        if mode != MODE_SERVER {
+            return Err(Error::NotAServer);
+        }
+        if stratum == 0 {
+            return Err(Error::KissOfDeath);
+        }
+        if leap == LEAP_ALARM || stratum > 15 {
+            return Err(Error::Unsynchronized);
+        }
+        if timestamp(24) != originate {
+            return Err(Error::WrongOriginate);
+        }
+        let transmit = timestamp(40);
+        if transmit == NtpTimestamp::default() {
+            return Err(Error::Unsynchronized);
+        }
+        Ok(Self {
+            stratum,
+            root_delay: short_to_micros(short(4) as i32 as i64),
+            root_dispersion: short_to_micros(short(8) as i64),
+            receive: timestamp(32),
+            transmit,
+        })
+    }
+
+    /// Sample of the local clock, which sent the request at `sent` and got
+    /// the reply at `received`, both in microseconds since the Unix epoch.
+    pub fn sample(&self, sent: i64, received: i64) -> Sample {
+        let receive = self.receive.unix_micros();
+        let transmit = self.transmit.unix_micros();
+        Sample {
+            offset: ((receive - sent) + (transmit - received)) / 2,
+            delay: ((received - sent) - (transmit - receive)).max(0),
+            error: self.root_delay.max(0) / 2 + self.root_dispersion,
+        }
+    }
+}
+
+/// NTP short format, 16.16 bit fixed point seconds.
+fn short_to_micros(short: i64) -> i64 {
+    (short * 1_000_000) >> 16
+}
+
+/// What one server says about the local clock, in microseconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sample {
+    /// Time to add to the local clock.
+    pub offset: i64,
+    /// Round trip of request and reply.
+    pub delay: i64,
+    /// Error of the server itself.
+    pub error: i64,
+}
+
+impl Sample {
+    /// The true offset is at most this far from [`Sample::offset`].
+    pub fn bound(&self) -> i64 {
+        self.delay / 2 + self.error
+    }
+
+    /// Sample of a server that only tells the time to the second, e.g. in
+    /// an HTTP `Date` header, asked at `sent` and answering at `received`.
+    pub fn from_seconds(time: NaiveDateTime, sent: i64, received: i64) -> Self {
+        // the time was cut to the second, its middle is the best guess
+        let server = time.and_utc().timestamp_micros() + 500_000;
+        Sample {
+            offset: server - (sent + received) / 2,
+            delay: (received - sent).max(0),
+            error: 500_000,
+        }
+    }
+
+    fn interval(&self) -> (i64, i64) {
+        (self.offset - self.bound(), self.offset + self.bound())
+    }
+}
+
+/// Offset the servers agree on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// Median offset of the servers in agreement.
+    pub offset: i64,
+    /// Samples in agreement.
+    pub agreeing: usize,
+    /// Samples looked at.
+    pub total: usize,
+}
+
+/// Pick the offset of the majority of `samples`.
+///
+/// The largest group of samples with overlapping intervals must be more than
+/// half of them, otherwise there is no telling which servers are wrong and
+/// the answer is `None`. Samples past [`MAX_SAMPLES`] are ignored.
+pub fn select(samples: &[Sample]) -> Option<Selection> {
+    let samples = &samples[..samples.len().min(MAX_SAMPLES)];
+    // interval starts sort before ends at the same value, touching
+    // intervals agree
+    let mut edges: Vec<(i64, i8), { 2 * MAX_SAMPLES }> = Vec::new();
+    for sample in samples {
+        let (low, high) = sample.interval();
+        let _ = edges.push((low, -1));
+        let _ = edges.push((high, 1));
+    }
+    edges.sort_unstable();
+
+    let mut overlapping = 0;
+    let mut most = 0;
+    let mut agreed = (0, 0);
+    for (index, &(value, edge)) in edges.iter().enumerate() {
+        if edge < 0 {
+            overlapping += 1;
+            if overlapping > most {
+                most = overlapping;
+                agreed = (value, edges[index + 1].0);
+            }
+        } else {
+            overlapping -= 1;
+        }
+    }
+    if most == 0 || 2 * most <= samples.len() {
+        return None;
+    }
+
+    let mut offsets: Vec<i64, MAX_SAMPLES> = samples
+        .iter()
+        .filter(|sample| {
+            let (low, high) = sample.interval();
+            low <= agreed.1 && high >= agreed.0
+        })
+        .map(|sample| sample.offset)
+        .collect();
+    offsets.sort_unstable();
+    let middle = offsets.len() / 2;
+    let offset = if offsets.len().is_multiple_of(2) {
+        (offsets[middle - 1] + offsets[middle]) / 2
+    } else {
+        offsets[middle]
+    };
+    Some(Selection {
+        offset,
+        agreeing: offsets.len(),
+        total: samples.len(),
+    })
+}
+
+/// How to change the time base after a sync, see [`Discipline::update`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Adjustment {
+    /// Move the time base by this many microseconds at once.
+    Step(i64),
+    /// Move the time base by the `shift` the correction ran up so far, the
+    /// displayed time does not change. `offset` is slewed in from now on.
+    Slew { shift: i64, offset: i64 },
+}
+
+impl Adjustment {
+    /// Microseconds to add to the time base.
+    pub fn shift(&self) -> i64 {
+        match *self {
+            Self::Step(shift) | Self::Slew { shift, .. } => shift,
+        }
+    }
+}
+
+/// Keeps a free running time base close to the servers between syncs.
+///
+/// The displayed time is the time base plus [`Discipline::correction`],
+/// which grows with the learned drift of the oscillator and with the part of
+/// the last offset slewed in so far. Times are the monotonic uptime, offsets
+/// in microseconds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Discipline {
+    /// Uptime of the last sync, `None` before the first.
+    anchor: Option<Duration>,
+    drift_ppb: i64,
+    /// Offset slewed in since the anchor.
+    slew: i64,
+    /// The anchor is a time not from NTP, the next offset is its error
+    /// rather than drift.
+    coarse: bool,
+}
+
+impl Discipline {
+    pub const fn new() -> Self {
+        Self {
+            anchor: None,
+            drift_ppb: 0,
+            slew: 0,
+            coarse: false,
+        }
+    }
+
+    /// Learned drift of the time base, positive if it runs slow.
+    pub fn drift_ppb(&self) -> i64 {
+        self.drift_ppb
+    }
+
+    /// Microseconds to add to the time base at uptime `now`.
+    pub fn correction(&self, now: Duration) -> i64 {
+        let Some(anchor) = self.anchor else {
+            return 0;
+        };
+        let elapsed = now.saturating_sub(anchor).as_micros() as i64;
+        self.drift_ppb * elapsed / 1_000_000_000 + self.slewed(elapsed)
+    }
+
+    /// Part of the offset slewed in `elapsed` microseconds after the anchor.
+    fn slewed(&self, elapsed: i64) -> i64 {
+        (elapsed * SLEW_RATE_PPM / 1_000_000).min(self.slew.abs()) * self.slew.signum()
+    }
+
+    /// Take the `offset` measured at uptime `now` against the displayed time.
+    ///
+    /// The first offset and those beyond [`STEP_THRESHOLD`] are stepped. The
+    /// others are slewed, and what is left of them once the slew still
+    /// outstanding is taken off is drift the estimate missed.
+    pub fn update(&mut self, now: Duration, offset: i64) -> Adjustment {
+        let accrued = self.correction(now);
+        let previous = self.anchor.replace(now);
+        let coarse = core::mem::take(&mut self.coarse);
+        let Some(anchor) = previous.filter(|_| offset.abs() <= STEP_THRESHOLD) else {
+            self.slew = 0;
+            return Adjustment::Step(accrued + offset);
+        };
+
+        let elapsed = now.saturating_sub(anchor);
+        if elapsed >= MIN_DRIFT_INTERVAL && !coarse {
+            let elapsed = elapsed.as_micros() as i64;
+            let outstanding = self.slew - self.slewed(elapsed);
+            let missed = (offset - outstanding) * 1_000_000_000 / elapsed;
+            self.drift_ppb =
+                (self.drift_ppb + missed / DRIFT_GAIN).clamp(-MAX_DRIFT_PPB, MAX_DRIFT_PPB);
+        }
+        self.slew = offset;
+        Adjustment::Slew {
+            shift: accrued,
+            offset,
+        }
+    }
+
+    /// Start over at uptime `now` from a time set by other means, with the
+    /// time base set to the displayed time. The drift is kept, the slew
+    /// dropped.
+    pub fn restart(&mut self, now: Duration) {
+        self.anchor = Some(now);
+        self.slew = 0;
+        self.coarse = true;
+    }
+}
+
+/// Servers a [`Config`] holds, the ones from DHCP come on top.
+pub const MAX_SERVERS: usize = 4;
+pub const MAX_HOST_LEN: usize = 48;
+pub const MAX_URL_LEN: usize = 64;
+/// One server from each zone of the NTP pool.
+pub const DEFAULT_SERVERS: [&str; MAX_SERVERS] = [
+    "0.pool.ntp.org",
+    "1.pool.ntp.org",
+    "2.pool.ntp.org",
+    "3.pool.ntp.org",
+];
+/// Bounds of [`Config::interval`] in seconds.
+pub const MIN_INTERVAL: u32 = 64;
+pub const MAX_INTERVAL: u32 = 86_400;
+/// Shortest [`Config::retry`] in seconds, RFC 4330 section 10 does not allow
+/// asking a server more often.
+pub const MIN_RETRY: u16 = 15;
+/// Web server whose `Date` is taken while no NTP server answers.
+pub const DEFAULT_HTTP: &str = "http://www.pool.ntp.org/";
+
+/// Where and how often the clock asks for the time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Names or IPv4 addresses, asked after the servers from DHCP.
+    pub servers: Vec<String<MAX_HOST_LEN>, MAX_SERVERS>,
+    /// Also ask the servers the DHCP server announces in option 42.
+    pub dhcp: bool,
+    /// `http://` URL whose `Date` header sets the clock when no NTP server
+    /// answers, empty for none.
+    pub http: String<MAX_URL_LEN>,
+    /// Seconds between syncs.
+    pub interval: u32,
+    /// Seconds before the first retry of a failed sync, doubled with every
+    /// further failure up to [`Config::interval`].
+    pub retry: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            servers: DEFAULT_SERVERS
+                .iter()
+                .map(|host| String::try_from(*host).unwrap())
+                .collect(),
+            dhcp: true,
+            http: String::try_from(DEFAULT_HTTP).unwrap(),
+            interval: 3600,
+            retry: MIN_RETRY,
+        }
+    }
+}
+
+impl Config {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval as u64)
+    }
+
+    /// Time to wait after `failures` failed syncs in a row.
+    pub fn retry_delay(&self, failures: u32) -> Duration {
+        let doublings = failures.saturating_sub(1).min(16);
+        let delay = (self.retry as u64) << doublings;
+        Duration::from_secs(delay.min(self.interval as u64))
+    }
+}
+
+/// Host name or IPv4 address: letters, digits, `-` and `.`.
+pub fn is_valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// 2036-02-07 06:28:16 UTC, the first second of era 1.
+    const ERA_1_UNIX: i64 = 2_085_978_496;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, s)
+            .unwrap()
+    }
+
+    #[test]
+    fn eras() {
+        let cases = [
+            // last second of era 0 and first of era 1
+            (0xffff_ffff, ERA_1_UNIX - 1, utc(2036, 2, 7, 6, 28, 15)),
+            (0x0000_0000, ERA_1_UNIX, utc(2036, 2, 7, 6, 28, 16)),
+            (0x0000_0001, ERA_1_UNIX + 1, utc(2036, 2, 7, 6, 28, 17)),
+            // the Unix epoch and the time before it that still counts as era 0
+            (0x83aa_7e80, 0, utc(1970, 1, 1, 0, 0, 0)),
+            (0x83aa_7e7f, -1, utc(1969, 12, 31, 23, 59, 59)),
+            (0x8000_0000, -61_505_152, utc(1968, 1, 20, 3, 14, 8)),
+            // one second earlier is taken to be the end of era 1
+            (
+                0x7fff_ffff,
+                ERA_1_UNIX + 0x7fff_ffff,
+                utc(2104, 2, 26, 9, 42, 23),
+            ),
+            // 2026-10-17 12:00:00
+            (0xee7d_e1c0, 1_792_238_400, utc(2026, 10, 17, 12, 0, 0)),
+        ];
+        for (seconds, unix, time) in cases {
+            let timestamp = NtpTimestamp::new(seconds, 0);
+            assert_eq!(timestamp.unix_seconds(), unix, "{seconds:#x}");
+            assert_eq!(timestamp.to_datetime(), time, "{seconds:#x}");
+            assert_eq!(NtpTimestamp::from_unix_micros(unix * 1_000_000), timestamp);
+        }
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(fraction_to_micros(0), 0);
+        assert_eq!(fraction_to_micros(0x8000_0000), 500_000);
+        assert_eq!(fraction_to_micros(0xffff_ffff), 999_999);
+        // 1 us is 4294.967296 units, rounded down that is less than 1 us
+        assert_eq!(fraction_to_micros(4294), 0);
+        assert_eq!(fraction_to_micros(4295), 1);
+        assert_eq!(micros_to_fraction(0), 0);
+        assert_eq!(micros_to_fraction(1), 4295);
+        assert_eq!(micros_to_fraction(500_000), 0x8000_0000);
+        assert_eq!(micros_to_fraction(999_999), 0xffff_ef3a);
+        for micros in 0..1_000_000 {
+            assert_eq!(fraction_to_micros(micros_to_fraction(micros)), micros);
+        }
+    }
+
+    #[test]
+    fn micros() {
+        // half a second before the epoch
+        let timestamp = NtpTimestamp::from_unix_micros(-500_000);
+        assert_eq!(timestamp, NtpTimestamp::new(0x83aa_7e7f, 0x8000_0000));
+        assert_eq!(timestamp.unix_micros(), -500_000);
+        // a quarter second into era 1
+        let timestamp = NtpTimestamp::from_unix_micros(ERA_1_UNIX * 1_000_000 + 250_000);
+        assert_eq!(timestamp, NtpTimestamp::new(0, 0x4000_0000));
+        assert_eq!(timestamp.unix_micros(), ERA_1_UNIX * 1_000_000 + 250_000);
+        assert_eq!(
+            timestamp.to_be_bytes(),
+            [0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            NtpTimestamp::from_be_bytes(timestamp.to_be_bytes()),
+            timestamp
+        );
+    }
+
+    /// Reply of a stratum 2 server with 1/32 s root delay and 1/16 s root
+    /// dispersion that received at `receive` and sent at `transmit`.
+    fn packet(originate: NtpTimestamp, receive: NtpTimestamp, transmit: NtpTimestamp) -> [u8; 48] {
+        let mut packet = [0; PACKET_LEN];
+        packet[..4].copy_from_slice(&[0x24, 2, 6, 0xe9]);
+        packet[4..8].copy_from_slice(&[0x00, 0x00, 0x08, 0x00]);
+        packet[8..12].copy_from_slice(&[0x00, 0x00, 0x10, 0x00]);
+        packet[12..16].copy_from_slice(b"\xc0\xa8\x01\x01");
+        packet[16..24].copy_from_slice(&receive.to_be_bytes());
+        packet[24..32].copy_from_slice(&originate.to_be_bytes());
+        packet[32..40].copy_from_slice(&receive.to_be_bytes());
+        packet[40..48].copy_from_slice(&transmit.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn request_packet() {
+        let packet = request(NtpTimestamp::new(0xee7d_e1c0, 0x8000_0000));
+        assert_eq!(packet[0], 0x23);
+        assert!(packet[1..40].iter().all(|&b| b == 0));
+        assert_eq!(packet[40..48], [0xee, 0x7d, 0xe1, 0xc0, 0x80, 0, 0, 0]);
+    }
+
+    #[test]
+    fn known_replies() {
+        // asked at 2026-10-17 12:00:00 by a clock 1.5 s behind, 20 ms each
+        // way and 2 ms in the server
+        let sent = 1_792_238_400_000_000;
+        let originate = NtpTimestamp::from_unix_micros(sent);
+        let receive = NtpTimestamp::new(0xee7d_e1c1, micros_to_fraction(520_000));
+        let transmit = NtpTimestamp::new(0xee7d_e1c1, micros_to_fraction(522_000));
+        #[rustfmt::skip]
+        let known = [
+            0x24, 0x02, 0x06, 0xe9, 0x00, 0x00, 0x08, 0x00,
+            0x00, 0x00, 0x10, 0x00, 0xc0, 0xa8, 0x01, 0x01,
+            0xee, 0x7d, 0xe1, 0xc1, 0x85, 0x1e, 0xb8, 0x52,
+            0xee, 0x7d, 0xe1, 0xc0, 0x00, 0x00, 0x00, 0x00,
+            0xee, 0x7d, 0xe1, 0xc1, 0x85, 0x1e, 0xb8, 0x52,
+            0xee, 0x7d, 0xe1, 0xc1, 0x85, 0xa1, 0xca, 0xc1,
+        ];
+        assert_eq!(packet(originate, receive, transmit), known);
+        let reply = Reply::parse(&known, originate).unwrap();
+        assert_eq!(reply.stratum, 2);
+        assert_eq!(reply.root_delay, 31_250);
+        assert_eq!(reply.root_dispersion, 62_500);
+        let sample = reply.sample(sent, sent + 42_000);
+        assert_eq!(
+            sample,
+            Sample {
+                offset: 1_500_000,
+                delay: 40_000,
+                error: 78_125,
+            }
+        );
+        assert_eq!(sample.bound(), 98_125);
+
+        // the same across the start of era 1, received in era 0 and sent
+        // in era 1
+        let sent = ERA_1_UNIX * 1_000_000 - 1_521_000;
+        let originate = NtpTimestamp::from_unix_micros(sent);
+        assert_eq!(originate.seconds, 0xffff_fffe);
+        let receive = NtpTimestamp::new(0xffff_ffff, micros_to_fraction(999_000));
+        let transmit = NtpTimestamp::new(0, micros_to_fraction(1_000));
+        let reply = Reply::parse(&packet(originate, receive, transmit), originate).unwrap();
+        let sample = reply.sample(sent, sent + 42_000);
+        assert_eq!(sample.offset, 1_500_000);
+        assert_eq!(sample.delay, 40_000);
+        assert_eq!(
+            reply.transmit.to_datetime(),
+            utc(2036, 2, 7, 6, 28, 16) + chrono::TimeDelta::milliseconds(1)
+        );
+    }
+
+    #[test]
+    fn bad_replies() {
+        let originate = NtpTimestamp::new(0xee7d_e1c0, 0);
+        let receive = NtpTimestamp::new(0xee7d_e1c1, 0);
+        let good = packet(originate, receive, receive);
+        let changed = |at: usize, byte: u8| {
+            let mut packet = good;
+            packet[at] = byte;
+            packet
+        };
+        let cases = [
+            (&good[..47], Error::TooShort),
+            (&changed(0, 0x23)[..], Error::NotAServer),
+            (&changed(1, 0)[..], Error::KissOfDeath),
+            (&changed(0, 0xe4)[..], Error::Unsynchronized),
+            (&changed(1, 16)[..], Error::Unsynchronized),
+            (&changed(31, 1)[..], Error::WrongOriginate),
+        ];
+        for (packet, error) in cases {
+            assert_eq!(Reply::parse(packet, originate), Err(error));
+        }
+        let unset = packet(originate, receive, NtpTimestamp::default());
+        assert_eq!(Reply::parse(&unset, originate), Err(Error::Unsynchronized));
+        // longer packets carry extensions and are fine
+        let mut long = [0; 60];
+        long[..48].copy_from_slice(&good);
+        assert!(Reply::parse(&long, originate).is_ok());
+    }
+}
@@ -0,0 +1,744 @@
+//! Firmware updates over the air.
+//!
+//! The flash holds two app partitions, `ota_0` and `ota_1`, and the
+//! `otadata` partition telling the bootloader which of them to start. An
+//! update is written into the partition that is not running by [`Update`],
+//! which checks the image with a [`Verifier`] on the way, and only a complete
+//! and intact image is selected with [`Otadata::select`].
+//!
+//! Only signed images are accepted. The signing tool appends a block of
+//! [`SIGNATURE_BLOCK_LEN`] bytes to the file, `CLOCKSIG` and the Ed25519
+//! signature of the image's appended SHA-256 digest, see [`signature_block`].
+//! The [`Verifier`] checks it against the public key built into the running
+//! firmware, so whoever can reach the API can't install their own firmware.
+//!
+//! Like the ESP-IDF's app rollback, a new image starts on trial. On its first
+//! start [`boot`] finds it `New`, it is marked `PendingVerify` and has
+//! [`HEALTH_TIMEOUT`] to prove itself, see [`Trial`]. If it does not, or the
+//! clock restarts before it did, [`Otadata::roll_back`] marks it `Invalid`
+//! and the bootloader goes back to the previous image.
+//!
+//! Each of the two `otadata` sectors holds one entry, the bootloader starts
+//! the app of the valid entry with the highest sequence number:
+//!
+//! ```text
+//! sequence u32 | label [u8; 20] | state u32 | crc32 of the sequence u32
+//! ```
+
+use core::time::Duration;
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use heapless::String;
+
+use crate::{
+    crc::crc32_update,
+    ed25519::{self, PublicKey, Seed},
+    flash::SECTOR_SIZE,
+    sha256::{Digest, Sha256},
+};
+
+/// Chip id in the header of images built for the ESP32-S3.
+pub const CHIP_ESP32S3: u16 = 9;
+/// Time a new image has to confirm itself before it is rolled back.
+pub const HEALTH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+pub const OTADATA_SIZE: u32 = 2 * SECTOR_SIZE as u32;
+pub const MAX_URL_LEN: usize = 128;
+/// Signature block at the end of a signed file, see [`signature_block`].
+pub const SIGNATURE_BLOCK_LEN: usize = SIGNATURE_MAGIC.len() + 64;
+
+const IMAGE_MAGIC: u8 = 0xe9;
+const HEADER_LEN: usize = 24;
+const SEGMENT_HEADER_LEN: usize = 8;
+const DIGEST_LEN: usize = 32;
+const MAX_SEGMENTS: u8 = 16;
+const CHECKSUM_SEED: u8 = 0xef;
+const SIGNATURE_MAGIC: [u8; 8] = *b"CLOCKSIG";
+const ENTRY_LEN: usize = 32;
+/// Bytes collected before writing them to flash.
+const PAGE_LEN: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// Does not start like an ESP app image.
+    NotAnImage,
+    /// Built for another chip, with its chip id.
+    WrongChip(u16),
+    /// Larger than the partition.
+    TooLarge,
+    /// Ends before the last segment, the checksum or the digest.
+    Truncated,
+    /// The checksum over the segments does not match.
+    Checksum,
+    /// Built without the SHA-256 digest appended.
+    NoDigest,
+    /// The appended digest does not match the image.
+    Corrupted,
+    /// Intact, but not the image asked for.
+    Mismatch,
+    /// Does not end in a signature block.
+    Unsigned,
+    /// The signature is not one of the update key.
+    BadSignature,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Flash(E),
+    Image(ImageError),
+    /// The partition is not sector aligned.
+    InvalidRegion,
+}
+
+/// A complete and intact image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub len: u32,
+    pub entry: u32,
+    pub segments: u8,
+    /// Digest of the whole file.
+    pub digest: Digest,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Header,
+    SegmentHeader,
+    /// Bytes left in the segment.
+    Segment(u32),
+    /// Zeros up to the checksum, which ends a 16 byte line.
+    Padding,
+    Checksum,
+    Digest,
+    /// Anything after the image, only part of the file's digest, the
+    /// signature block ends it.
+    Trailer,
+}
+
+/// Checks an ESP-IDF app image as it arrives in pieces.
+///
+/// ```text
+/// header (24 bytes) | segments: load address u32, length u32, data | padding
+/// | checksum u8 | SHA-256 of all of the above | ... | signature block
+/// ```
+///
+/// The checksum is the XOR of all segment data bytes and `0xef`. The file
+/// ends in the signature of the appended digest by the update key, see
+/// [`signature_block`]. An expected digest of the whole file, as published
+/// with a release, can be given on top.
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    chip: u16,
+    max_len: u32,
+    expected: Option<Digest>,
+    key: PublicKey,
+    /// The whole file, to compare with `expected`.
+    file: Sha256,
+    /// The image up to the checksum, to compare with the appended digest.
+    image: Sha256,
+    part: Part,
+    /// Header, segment header or digest being collected.
+    buf: [u8; DIGEST_LEN],
+    buf_len: usize,
+    /// Bytes received so far.
+    len: u32,
+    entry: u32,
+    segments: u8,
+    segments_left: u8,
+    checksum: u8,
+    appended: Option<Digest>,
+    /// The last bytes of the trailer, the signature block once complete.
+    tail: [u8; SIGNATURE_BLOCK_LEN],
+    tail_len: usize,
+}
+
+impl Verifier {
+    /// Expect an image for `chip` of at most `max_len` bytes, signed with
+    /// the secret key of `key`.
+    pub fn new(chip: u16, max_len: u32, expected: Option<Digest>, key: PublicKey) -> Self {
+        Self {
+            chip,
+            max_len,
+            expected,
+            key,
+            file: Sha256::new(),
+            image: Sha256::new(),
+            part: Part::Header,
+            buf: [0; DIGEST_LEN],
+            buf_len: 0,
+            len: 0,
+            entry: 0,
+            segments: 0,
+            segments_left: 0,
+            checksum: CHECKSUM_SEED,
+            appended: None,
+            tail: [0; SIGNATURE_BLOCK_LEN],
+            tail_len: 0,
+        }
+    }
+
+    /// Bytes received so far.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Check the next piece of the image, fails as soon as it is clear that
+    /// the image is no good.
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), ImageError> {
+        if u64::from(self.len) + data.len() as u64 > u64::from(self.max_len) {
+            return Err(ImageError::TooLarge);
+        }
+        self.file.update(data);
+        while !data.is_empty() {
+            let n = match self.part {
+                Part::Header => self.collect(data, HEADER_LEN),
+                Part::SegmentHeader => self.collect(data, SEGMENT_HEADER_LEN),
+                Part::Segment(left) => {
+                    let n = (left as usize).min(data.len());
+                    self.checksum = data[..n].iter().fold(self.checksum, |sum, b| sum ^ b);
+                    n
+                }
+                Part::Padding => ((15 - self.len % 16) as usize).min(data.len()),
+                Part::Checksum => 1,
+                Part::Digest => self.collect(data, DIGEST_LEN),
+                Part::Trailer => data.len(),
+            };
+            let (bytes, rest) = data.split_at(n);
+            data = rest;
+            self.len += n as u32;
+            match self.part {
+                Part::Digest => {}
+                Part::Trailer => self.keep_tail(bytes),
+                _ => self.image.update(bytes),
+            }
+
+            self.part = match self.part {
+                Part::Header if self.buf_len == HEADER_LEN => {
+                    self.header()?;
+                    Part::SegmentHeader
+                }
+                Part::SegmentHeader if self.buf_len == SEGMENT_HEADER_LEN => {
+                    self.buf_len = 0;
+                    self.segments_left -= 1;
+                    let b = &self.buf;
+                    match u32::from_le_bytes([b[4], b[5], b[6], b[7]]) {
+                        len if len % 4 != 0 => return Err(ImageError::NotAnImage),
+                        len if len > self.max_len => return Err(ImageError::TooLarge),
+                        0 => self.after_segment(),
+                        len => Part::Segment(len),
+                    }
+                }
+                Part::Segment(left) if left as usize == n => self.after_segment(),
+                Part::Segment(left) => Part::Segment(left - n as u32),
+                Part::Padding if self.len % 16 == 15 => Part::Checksum,
+                Part::Checksum if bytes[0] != self.checksum => return Err(ImageError::Checksum),
+                Part::Checksum => Part::Digest,
+                Part::Digest if self.buf_len == DIGEST_LEN => {
+                    self.appended = Some(self.buf);
+                    Part::Trailer
+                }
+                part => part,
+            };
+        }
+        Ok(())
+    }
+
+    /// Check the end of the image, all of it has been passed to
+    /// [`update`](Self::update).
+    pub fn finish(self) -> Result<Image, ImageError> {
+        let Some(appended) = self.appended else {
+            return Err(ImageError::Truncated);
+        };
+        if self.image.finish() != appended {
+            return Err(ImageError::Corrupted);
+        }
+        let (magic, signature) = self.tail.split_at(SIGNATURE_MAGIC.len());
+        if self.tail_len < SIGNATURE_BLOCK_LEN || magic != SIGNATURE_MAGIC {
+            return Err(ImageError::Unsigned);
+        }
+        let mut signature_bytes = [0; 64];
+        signature_bytes.copy_from_slice(signature);
+        if !ed25519::verify(&self.key, &appended, &signature_bytes) {
+            return Err(ImageError::BadSignature);
+        }
+        let digest = self.file.finish();
+        if self.expected.is_some_and(|expected| expected != digest) {
+            return Err(ImageError::Mismatch);
+        }
+        Ok(Image {
+            len: self.len,
+            entry: self.entry,
+            segments: self.segments,
+            digest,
+        })
+    }
+
+    /// The digest appended to the image, once it came by.
+    pub fn appended(&self) -> Option<Digest> {
+        self.appended
+    }
+
+    /// Keep the last [`SIGNATURE_BLOCK_LEN`] bytes of the trailer.
+    fn keep_tail(&mut self, bytes: &[u8]) {
+        if bytes.len() >= SIGNATURE_BLOCK_LEN {
+            self.tail
+                .copy_from_slice(&bytes[bytes.len() - SIGNATURE_BLOCK_LEN..]);
+            self.tail_len = SIGNATURE_BLOCK_LEN;
+            return;
+        }
+        let kept = self.tail_len.min(SIGNATURE_BLOCK_LEN - bytes.len());
+        self.tail
+            .copy_within(self.tail_len - kept..self.tail_len, 0);
+        self.tail[kept..kept + bytes.len()].copy_from_slice(bytes);
+        self.tail_len = kept + bytes.len();
+    }
+
+    /// Copy bytes into `buf` until it holds `len` of them.
+    fn collect(&mut self, data: &[u8], len: usize) -> usize {
+        let n = (len - self.buf_len).min(data.len());
+        self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+        self.buf_len += n;
+        n
+    }
+
+    fn header(&mut self) -> Result<(), ImageError> {
+        let h = &self.buf;
+        if h[0] != IMAGE_MAGIC || h[1] == 0 || h[1] > MAX_SEGMENTS {
+            return Err(ImageError::NotAnImage);
+        }
+        let chip = u16::from_le_bytes([h[12], h[13]]);
+        if chip != self.chip {
+            return Err(ImageError::WrongChip(chip));
+        }
+        if h[23] != 1 {
+            return Err(ImageError::NoDigest);
+        }
+        self.segments = h[1];
+        self.segments_left = h[1];
+        self.entry = u32::from_le_bytes([h[4], h[5], h[6], h[7]]);
+        self.buf_len = 0;
+        Ok(())
+    }
+
+    /// The checksum ends a 16 byte line, the line before is padded with zeros.
+    fn after_segment(&self) -> Part {
+        if self.segments_left > 0 {
+            Part::SegmentHeader
+        } else if self.len % 16 == 15 {
+            Part::Checksum
+        } else {
+            Part::Padding
+        }
+    }
+}
+
+/// Writes an image into an app partition while checking it.
+pub struct Update<F> {
+    flash: F,
+    base: u32,
+    verifier: Verifier,
+    page: [u8; PAGE_LEN],
+    page_len: usize,
+    /// Bytes written to flash.
+    written: u32,
+    /// Bytes erased from the start of the partition.
+    erased: u32,
+}
+
+impl<F: NorFlash> Update<F> {
+    /// Write to the `size` bytes of `flash` at `base` for `chip`, the
+    /// partition is erased as the image arrives. The image has to be signed
+    /// for `key`.
+    pub fn new(
+        flash: F,
+        base: u32,
+        size: u32,
+        chip: u16,
+        expected: Option<Digest>,
+        key: PublicKey,
+    ) -> Result<Self, Error<F::Error>> {
+        let sector = F::ERASE_SIZE as u32;
+        if !base.is_multiple_of(sector) || !size.is_multiple_of(sector) || size == 0 {
+            return Err(Error::InvalidRegion);
+        }
+        Ok(Self {
+            flash,
+            base,
+            verifier: Verifier::new(chip, size, expected, key),
+            page: [0xff; PAGE_LEN],
+            page_len: 0,
+            written: 0,
+            erased: 0,
+        })
+    }
+
+    /// Bytes received so far.
+    pub fn received(&self) -> u32 {
+        self.verifier.len()
+    }
+
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), Error<F::Error>> {
+        self.verifier.update(data).map_err(Error::Image)?;
+        while !data.is_empty() {
+            let n = (PAGE_LEN - self.page_len).min(data.len());
+            self.page[self.page_len..self.page_len + n].copy_from_slice(&data[..n]);
+            self.page_len += n;
+            data = &data[n..];
+            if self.page_len == PAGE_LEN {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the rest and check the image, the partition is only worth
+    /// selecting if this succeeds.
+    pub fn finish(mut self) -> Result<(F, Image), Error<F::Error>> {
+        self.flush()?;
+        let image = self.verifier.finish().map_err(Error::Image)?;
+        Ok((self.flash, image))
+    }
+
+    fn flush(&mut self) -> Result<(), Error<F::Error>> {
+        if self.page_len == 0 {
+            return Ok(());
+        }
+        let len = self.page_len.next_multiple_of(F::WRITE_SIZE);
+        self.page[self.page_len..len].fill(0xff);
+        let end = self.written + len as u32;
+        while self.erased < end {
+            let start = self.base + self.erased;
+            self.flash
+                .erase(start, start + F::ERASE_SIZE as u32)
+                .map_err(Error::Flash)?;
+            self.erased += F::ERASE_SIZE as u32;
+        }
+        self.flash
+            .write(self.base + self.written, &self.page[..len])
+            .map_err(Error::Flash)?;
+        self.written = end;
+        self.page_len = 0;
+        Ok(())
+    }
+}
+
+/// One of the two app partitions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Slot {
+    Ota0,
+    Ota1,
+}
+
+impl Slot {
+    pub fn other(self) -> Self {
+        match self {
+            Self::Ota0 => Self::Ota1,
+            Self::Ota1 => Self::Ota0,
+        }
+    }
+
+    /// Name in the partition table.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ota0 => "ota_0",
+            Self::Ota1 => "ota_1",
+        }
+    }
+}
+
+/// State of the image an `otadata` entry selects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageState {
+    /// Written, not started yet.
+    New,
+    /// Started, not confirmed yet.
+    PendingVerify,
+    Valid,
+    /// Rolled back, the bootloader skips the entry.
+    Invalid,
+    /// Marked by the bootloader, skipped as well.
+    Aborted,
+    /// Written without rollback, e.g. by the ESP-IDF tools.
+    Undefined,
+}
+
+impl ImageState {
+    fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Self::New,
+            1 => Self::PendingVerify,
+            2 => Self::Valid,
+            3 => Self::Invalid,
+            4 => Self::Aborted,
+            _ => Self::Undefined,
+        }
+    }
+
+    fn to_u32(self) -> u32 {
+        match self {
+            Self::New => 0,
+            Self::PendingVerify => 1,
+            Self::Valid => 2,
+            Self::Invalid => 3,
+            Self::Aborted => 4,
+            Self::Undefined => u32::MAX,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub sequence: u32,
+    pub state: ImageState,
+}
+
+impl Entry {
+    /// App partition the entry selects.
+    pub fn slot(&self) -> Slot {
+        // the bootloader counts from 1
+        if self.sequence.is_multiple_of(2) {
+            Slot::Ota1
+        } else {
+            Slot::Ota0
+        }
+    }
+
+    pub fn encode(&self) -> [u8; ENTRY_LEN] {
+        let mut entry = [0xff; ENTRY_LEN];
+        entry[..4].copy_from_slice(&self.sequence.to_le_bytes());
+        entry[24..28].copy_from_slice(&self.state.to_u32().to_le_bytes());
+        entry[28..].copy_from_slice(&sequence_crc(self.sequence).to_le_bytes());
+        entry
+    }
+
+    /// `None` for an erased or torn entry.
+    pub fn decode(entry: &[u8; ENTRY_LEN]) -> Option<Self> {
+        let word = |at: usize| {
+            u32::from_le_bytes([entry[at], entry[at + 1], entry[at + 2], entry[at + 3]])
+        };
+        let sequence = word(0);
+        if sequence == 0 || sequence == u32::MAX || word(28) != sequence_crc(sequence) {
+            return None;
+        }
+        Some(Self {
+            sequence,
+            state: ImageState::from_u32(word(24)),
+        })
+    }
+
+    fn bootable(&self) -> bool {
+        !matches!(self.state, ImageState::Invalid | ImageState::Aborted)
+    }
+}
+
+/// Same as the bootloader's, the CRC-32 of the sequence number started with
+/// all bits set.
+fn sequence_crc(sequence: u32) -> u32 {
+    crc32_update(u32::MAX, &sequence.to_le_bytes())
+}
+
+/// The two entries of the `otadata` partition.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Otadata {
+    pub entries: [Option<Entry>; 2],
+}
+
+impl Otadata {
+    pub fn read<F: ReadNorFlash>(flash: &mut F, base: u32) -> Result<Self, F::Error> {
+        let mut otadata = Self::default();
+        for (index, entry) in otadata.entries.iter_mut().enumerate() {
+            let mut bytes = [0; ENTRY_LEN];
+            flash.read(base + index as u32 * SECTOR_SIZE as u32, &mut bytes)?;
+            *entry = Entry::decode(&bytes);
+        }
+        Ok(otadata)
+    }
+
+    /// Index of the entry the bootloader follows.
+    pub fn active(&self) -> Option<usize> {
+        (0..2)
+            .filter_map(|index| Some((index, self.entries[index]?)))
+            .filter(|(_, entry)| entry.bootable())
+            .max_by_key(|(_, entry)| entry.sequence)
+            .map(|(index, _)| index)
+    }
+
+    pub fn active_entry(&self) -> Option<Entry> {
+        self.entries[self.active()?]
+    }
+
+    /// App partition the bootloader starts, `ota_0` as long as there is no
+    /// entry, e.g. right after flashing by cable.
+    pub fn boot_slot(&self) -> Slot {
+        self.active_entry().map_or(Slot::Ota0, |entry| entry.slot())
+    }
+
+    /// Start the image in `slot` from the next start on, as a new image on
+    /// trial. The entry of the running image stays to go back to.
+    pub fn select<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        base: u32,
+        slot: Slot,
+    ) -> Result<(), F::Error> {
+        let (index, sequence) = match self.active() {
+            Some(active) => (1 - active, self.entries[active].map_or(0, |e| e.sequence)),
+            None => (0, 0),
+        };
+        let mut entry = Entry {
+            sequence: sequence + 1,
+            state: ImageState::New,
+        };
+        if entry.slot() != slot {
+            entry.sequence += 1;
+        }
+        self.write(flash, base, index, entry)
+    }
+
+    /// Change the state of the running image, if it came with an entry.
+    pub fn mark<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        base: u32,
+        state: ImageState,
+    ) -> Result<(), F::Error> {
+        let Some(index) = self.active() else {
+            return Ok(());
+        };
+        let Some(entry) = self.entries[index] else {
+            return Ok(());
+        };
+        self.write(flash, base, index, Entry { state, ..entry })
+    }
+
+    /// Mark the running image `Invalid`, returns the slot started next.
+    ///
+    /// Without an entry for the previous image, which is the case after the
+    /// first update of a clock flashed by cable, one is written for it.
+    pub fn roll_back<F: NorFlash>(&mut self, flash: &mut F, base: u32) -> Result<Slot, F::Error> {
+        let Some(active) = self.active() else {
+            return Ok(self.boot_slot());
+        };
+        self.mark(flash, base, ImageState::Invalid)?;
+        if self.active().is_none() {
+            // the next sequence number selects the other slot
+            let sequence = self.entries[active].map_or(0, |entry| entry.sequence) + 1;
+            let entry = Entry {
+                sequence,
+                state: ImageState::Valid,
+            };
+            self.write(flash, base, 1 - active, entry)?;
+        }
+        Ok(self.boot_slot())
+    }
+
+    fn write<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        base: u32,
+        index: usize,
+        entry: Entry,
+    ) -> Result<(), F::Error> {
+        let start = base + index as u32 * SECTOR_SIZE as u32;
+        flash.erase(start, start + SECTOR_SIZE as u32)?;
+        flash.write(start, &entry.encode())?;
+        self.entries[index] = Some(entry);
+        Ok(())
+    }
+}
+
+/// What to do about the running image right after start.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Boot {
+    /// Confirmed before or flashed by cable, nothing to do.
+    Confirmed,
+    /// First start of an update: mark it `PendingVerify` and let a
+    /// [`Trial`] decide.
+    Trial,
+    /// The update started before without confirming itself, it crashed or
+    /// hung. Roll back and restart.
+    RollBack,
+}
+
+pub fn boot(otadata: &Otadata) -> Boot {
+    match otadata.active_entry().map(|entry| entry.state) {
+        Some(ImageState::New) => Boot::Trial,
+        Some(ImageState::PendingVerify) => Boot::RollBack,
+        _ => Boot::Confirmed,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// Mark the image `Valid`.
+    Confirm,
+    /// Roll back and restart.
+    RollBack,
+}
+
+/// A new image proving itself, healthy means it reached the network.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Trial {
+    started: Duration,
+}
+
+impl Trial {
+    /// Trial starting at uptime `now`.
+    pub fn new(now: Duration) -> Self {
+        Self { started: now }
+    }
+
+    /// `None` while the image still has time.
+    pub fn check(&self, now: Duration, healthy: bool) -> Option<Verdict> {
+        if healthy {
+            Some(Verdict::Confirm)
+        } else if now.saturating_sub(self.started) >= HEALTH_TIMEOUT {
+            Some(Verdict::RollBack)
+        } else {
+            None
+        }
+    }
+}
+
+/// What the API reports about the firmware.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Firmware<'a> {
+    pub version: &'a str,
+    /// Partition the firmware runs from.
+    pub slot: Slot,
+    /// Rolled back unless it confirms itself.
+    pub trial: bool,
+}
+
+/// An update to fetch, see [`crate::http::Url`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Download {
+    pub url: String<MAX_URL_LEN>,
+    /// Digest of the whole file, checked on top of the appended one.
+    pub digest: Option<Digest>,
+}
+
+/// The block to append to a file to sign the image with the appended
+/// `digest`, see [`Verifier::appended`].
+pub fn signature_block(seed: &Seed, digest: &Digest) -> [u8; SIGNATURE_BLOCK_LEN] {
+    let mut block = [0; SIGNATURE_BLOCK_LEN];
+    block[..SIGNATURE_MAGIC.len()].copy_from_slice(&SIGNATURE_MAGIC);
+    block[SIGNATURE_MAGIC.len()..].copy_from_slice(&ed25519::sign(seed, digest));
+    block
+}
+
+/// Digest from 64 hex digits.
+pub fn parse_digest(hex: &str) -> Option<Digest> {
+    if hex.len() != 2 * DIGEST_LEN || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut digest = [0; DIGEST_LEN];
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        let pair = core::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(digest)
+}
@@ -0,0 +1,81 @@
+//! Current budget of the LED strip.
+//!
+//! A WS2812B draws about 20 mA per color channel at full duty plus roughly
+//! 1 mA for its controller, so 60 LEDs at full white would need well over
+//! 3.5 A, more than the 15 W a USB-C port delivers without negotiation.
+//! [`Limiter`] estimates the current of every frame and lowers the global
+//! brightness just enough to stay below a configured ceiling.
+
+use rgb::RGB8;
+
+use crate::ring::brightness;
+
+/// Current of one channel at full duty, in microamps.
+pub const CHANNEL_UA: u32 = 20_000;
+/// Quiescent current of one LED, in microamps.
+pub const IDLE_UA: u32 = 1_000;
+
+/// Estimated current for showing `pixels`, in milliamps.
+pub fn estimate_ma(pixels: impl Iterator<Item = RGB8>) -> u32 {
+    let (count, duty) = pixels.fold((0, 0), |(count, duty), c| {
+        (count + 1, duty + c.r as u32 + c.g as u32 + c.b as u32)
+    });
+    (count * IDLE_UA + duty * CHANNEL_UA / 255).div_ceil(1000)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limited {
+    /// Brightness level to use instead of the requested one.
+    pub level: u8,
+    /// Estimated current at the requested level, in milliamps.
+    pub requested_ma: u32,
+    /// Estimated current at `level`, in milliamps.
+    pub limited_ma: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limiter {
+    /// Current available to the strip, in milliamps.
+    pub max_ma: u32,
+}
+
+impl Limiter {
+    pub fn new(max_ma: u32) -> Self {
+        Self { max_ma }
+    }
+
+    /// Brightness to show `pixels` with instead of `level`, `None` if they
+    /// stay within the budget at `level`.
+    ///
+    /// `pixels` are the colors of the physical strip, see
+    /// [`Layout::map`](crate::layout::Layout::map).
+    ///
+    /// Only ever lowers the brightness, so the colors keep their ratios. If
+    /// even the quiescent current exceeds the budget the level drops to 0.
+    pub fn limit<I>(&self, pixels: I, level: u8) -> Option<Limited>
+    where
+        I: Iterator<Item = RGB8> + Clone,
+    {
+        let at = |level| estimate_ma(brightness(pixels.clone(), level));
+        let requested_ma = at(level);
+        if requested_ma <= self.max_ma {
+            return None;
+        }
+
+        // the LEDs' own draw does not scale, estimate linearly from the rest
+        // and step down over the rounding of the brightness scaling
+        let idle_ma = at(0);
+        let available = self.max_ma.saturating_sub(idle_ma);
+        let mut limited = (level as u32 * available / (requested_ma - idle_ma).max(1)) as u8;
+        let mut limited_ma = at(limited);
+        while limited_ma > self.max_ma && limited > 0 {
+            limited -= 1;
+            limited_ma = at(limited);
+        }
+        Some(Limited {
+            level: limited,
+            requested_ma,
+            limited_ma,
+        })
+    }
+}
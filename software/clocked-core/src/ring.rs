@@ -0,0 +1,136 @@
+//! Rendering of the LED ring.
+//!
+//! Frames are plain arrays with one color per LED, LED 0 sits at twelve
+//! o'clock. Drawing them does not know anything about the LED driver, the
+//! finished frame is handed to a [`FrameSink`]. On the clock that is the
+//! WS2812 strip, on the host the simulator's terminal.
+//!
+//! The hour marker moves five LEDs per hour and advances one more LED every
+//! 12 minutes, just like the hour hand of the analogue clock behind the
+//! mirror.
+
+use chrono::Timelike;
+use rgb::RGB8;
+
+/// Number of LEDs on the ring, one for each minute.
+pub const RING_SIZE: usize = 60;
+
+/// One color per LED of the ring.
+pub type Frame = [RGB8; RING_SIZE];
+
+/// Frames drawn per second.
+pub const FRAMES_PER_SECOND: usize = 60;
+
+pub const BLACK: RGB8 = RGB8::new(0, 0, 0);
+
+/// Something that shows frames, e.g. the LED strip.
+///
+/// Same signature as `smart_leds::SmartLedsWrite::write`, so LED drivers
+/// only need a thin wrapper.
+pub trait FrameSink {
+    type Error;
+
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<RGB8>;
+}
+
+/// Write `frame` to `sink`, scaled to `level` (255 is full brightness).
+pub fn show<S: FrameSink>(sink: &mut S, frame: &Frame, level: u8) -> Result<(), S::Error> {
+    sink.write(brightness(frame.iter().copied(), level))
+}
+
+/// Scale all colors to `level`, the same way `smart_leds::brightness` does.
+pub fn brightness(iter: impl Iterator<Item = RGB8>, level: u8) -> impl Iterator<Item = RGB8> {
+    let scale = move |c: u8| (c as u16 * (level as u16 + 1) / 256) as u8;
+    iter.map(move |c| RGB8::new(scale(c.r), scale(c.g), scale(c.b)))
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Hsv {
+    pub hue: u8,
+    pub sat: u8,
+    pub val: u8,
+}
+
+/// Integer HSV to RGB conversion, identical to `smart_leds::hsv::hsv2rgb`.
+pub fn hsv2rgb(hsv: Hsv) -> RGB8 {
+    let v = hsv.val as u16;
+    let s = hsv.sat as u16;
+    // position within the current sixth of the hue circle
+    let f = (hsv.hue as u16 * 2 % 85) * 3;
+
+    let p = (v * (255 - s) / 255) as u8;
+    let q = (v * (255 - (s * f) / 255) / 255) as u8;
+    let t = (v * (255 - (s * (255 - f)) / 255) / 255) as u8;
+    let v = v as u8;
+    match hsv.hue {
+        0..=42 => RGB8::new(v, t, p),
+        43..=84 => RGB8::new(q, v, p),
+        85..=127 => RGB8::new(p, v, t),
+        128..=169 => RGB8::new(p, q, v),
+        170..=212 => RGB8::new(t, p, v),
+        213..=254 => RGB8::new(v, p, q),
+        255 => RGB8::new(v, t, p),
+    }
+}
+
+/// Colors used for the three clock hands.
+#[derive(Copy, Clone, Debug)]
+pub struct ClockFace {
+    pub hour: RGB8,
+    pub minute: RGB8,
+    pub second: RGB8,
+}
+
+impl Default for ClockFace {
+    fn default() -> Self {
+        Self {
+            hour: RGB8::new(32, 0, 0),
+            minute: RGB8::new(0, 32, 0),
+            second: RGB8::new(0, 0, 32),
+        }
+    }
+}
+
+impl ClockFace {
+    /// LED index of the hour marker.
+    pub fn hour_index<T: Timelike>(time: &T) -> usize {
+        ((time.hour() % 12) * 5 + time.minute() / 12) as usize
+    }
+
+    /// LED index of the minute marker.
+    pub fn minute_index<T: Timelike>(time: &T) -> usize {
+        time.minute() as usize % RING_SIZE
+    }
+
+    /// LED index of the second marker.
+    pub fn second_index<T: Timelike>(time: &T) -> usize {
+        // chrono reports leap seconds as second 59, so this never overflows
+        time.second() as usize % RING_SIZE
+    }
+
+    /// Draw the hour, minute and second markers on top of `data`.
+    ///
+    /// Markers are added to whatever is already in the buffer, so hands
+    /// sharing an LED mix their colors instead of hiding each other.
+    pub fn render<T: Timelike>(&self, time: &T, data: &mut Frame) {
+        add(&mut data[Self::second_index(time)], self.second);
+        add(&mut data[Self::minute_index(time)], self.minute);
+        add(&mut data[Self::hour_index(time)], self.hour);
+    }
+}
+
+/// Mix `b` into `a`, `amount` 0 keeps `a`, 255 gives `b`.
+pub fn blend(a: RGB8, b: RGB8, amount: u8) -> RGB8 {
+    let mix =
+        |a: u8, b: u8| ((a as u16 * (255 - amount as u16) + b as u16 * amount as u16) / 255) as u8;
+    RGB8::new(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+}
+
+fn add(pixel: &mut RGB8, color: RGB8) {
+    pixel.r = pixel.r.saturating_add(color.r);
+    pixel.g = pixel.g.saturating_add(color.g);
+    pixel.b = pixel.b.saturating_add(color.b);
+}
@@ -0,0 +1,268 @@
+//! Battery-backed RTC chips on I²C.
+//!
+//! The RTC of the ESP32-S3 loses the time along with the power, an external
+//! DS3231 or PCF8563 with a coin cell does not. Both are a [`TimeSource`]:
+//! read once at the start and set again whenever the clock got a better time,
+//! see [`WriteBack`]. [`detect`] finds out which of them is on the bus.
+//!
+//! The chips keep the time in BCD registers and only know two digit years,
+//! a century bit counts from 2000 to 2199. They run on UTC like the RTC.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeDelta, Timelike};
+
+mod ds3231;
+mod pcf8563;
+
+pub use ds3231::Ds3231;
+pub use pcf8563::Pcf8563;
+
+/// A chip that is off by less than this is left alone, it only counts whole
+/// seconds.
+pub const MAX_ERROR: TimeDelta = TimeDelta::seconds(2);
+/// Shortest time between two writes to measure the drift of the chip over,
+/// a second off in a week is 1.7 ppm.
+pub const MIN_TRIM_INTERVAL: TimeDelta = TimeDelta::days(7);
+
+/// An I²C bus.
+///
+/// Same signatures as the `embedded_hal::i2c::I2c` methods the chips need,
+/// so bus drivers only need a thin wrapper.
+pub trait Bus {
+    type Error;
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error>;
+
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8])
+        -> Result<(), Self::Error>;
+}
+
+/// Something that keeps the time while the clock is off.
+pub trait TimeSource {
+    type Error;
+
+    /// The UTC time, `None` if the source lost it, e.g. with an empty
+    /// battery.
+    fn read(&mut self) -> Result<Option<NaiveDateTime>, Self::Error>;
+
+    /// Set the source to the UTC time `time`, truncated to whole seconds.
+    /// Times a chip can't hold, before 2000 or after 2199, are not written.
+    fn write(&mut self, time: NaiveDateTime) -> Result<(), Self::Error>;
+
+    /// Slow the source down by `ppb` parts per billion, a negative value
+    /// speeds it up. Sources without a way to do that ignore it.
+    fn trim(&mut self, ppb: i64) -> Result<(), Self::Error> {
+        let _ = ppb;
+        Ok(())
+    }
+}
+
+/// `value` from 0 to 99 in binary coded decimal.
+pub fn bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// The value of the BCD `byte`, `None` if a digit is not decimal.
+pub fn from_bcd(byte: u8) -> Option<u8> {
+    let (tens, ones) = (byte >> 4, byte & 0x0f);
+    (tens < 10 && ones < 10).then_some(tens * 10 + ones)
+}
+
+/// The time from decoded register values, `None` if they are out of range.
+fn decode(
+    century: bool,
+    year: u8,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+) -> Option<NaiveDateTime> {
+    let year = 2000 + i32::from(year) + if century { 100 } else { 0 };
+    NaiveDate::from_ymd_opt(year, month.into(), day.into())?.and_hms_opt(
+        hour.into(),
+        minute.into(),
+        second.into(),
+    )
+}
+
+/// The registers of `time` in BCD as seconds, minutes, hours, day of the
+/// month, month with the century in bit 7 and year, `None` if the year is
+/// out of range.
+fn encode(time: NaiveDateTime) -> Option<[u8; 6]> {
+    let year = u8::try_from(time.year() - 2000).ok().filter(|y| *y < 200)?;
+    let century = if year >= 100 { 0x80 } else { 0 };
+    Some([
+        bcd(time.second() as u8),
+        bcd(time.minute() as u8),
+        bcd(time.hour() as u8),
+        bcd(time.day() as u8),
+        century | bcd(time.month() as u8),
+        bcd(year % 100),
+    ])
+}
+
+/// One of the supported chips.
+pub enum Chip<B> {
+    Ds3231(Ds3231<B>),
+    Pcf8563(Pcf8563<B>),
+}
+
+impl<B: Bus> Chip<B> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ds3231(_) => "DS3231",
+            Self::Pcf8563(_) => "PCF8563",
+        }
+    }
+}
+
+impl<B: Bus> TimeSource for Chip<B> {
+    type Error = B::Error;
+
+    fn read(&mut self) -> Result<Option<NaiveDateTime>, B::Error> {
+        match self {
+            Self::Ds3231(chip) => chip.read(),
+            Self::Pcf8563(chip) => chip.read(),
+        }
+    }
+
+    fn write(&mut self, time: NaiveDateTime) -> Result<(), B::Error> {
+        match self {
+            Self::Ds3231(chip) => chip.write(time),
+            Self::Pcf8563(chip) => chip.write(time),
+        }
+    }
+
+    fn trim(&mut self, ppb: i64) -> Result<(), B::Error> {
+        match self {
+            Self::Ds3231(chip) => chip.trim(ppb),
+            Self::Pcf8563(chip) => chip.trim(ppb),
+        }
+    }
+}
+
+/// Look for a chip on `bus`, returns the bus if none answers.
+///
+/// A DS1307 answers at the address of the DS3231 and would be taken for
+/// one, it is not supported.
+pub fn detect<B: Bus>(mut bus: B) -> Result<Chip<B>, B> {
+    let mut byte = [0u8];
+    if bus
+        .write_read(ds3231::ADDRESS, &[ds3231::STATUS], &mut byte)
+        .is_ok()
+    {
+        return Ok(Chip::Ds3231(Ds3231::new(bus)));
+    }
+    if bus
+        .write_read(pcf8563::ADDRESS, &[pcf8563::CONTROL_1], &mut byte)
+        .is_ok()
+    {
+        return Ok(Chip::Pcf8563(Pcf8563::new(bus)));
+    }
+    Err(bus)
+}
+
+/// What to do with the [`TimeSource`] after the clock got a new time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Update {
+    /// It is close enough.
+    Keep,
+    /// Set it to the clock's time, trimmed by `trim` ppb first if its drift
+    /// since the last write is known.
+    Write { trim: Option<i64> },
+}
+
+/// Decides when to set the [`TimeSource`] again and how fast it runs.
+///
+/// The chip is only set when it is off by [`MAX_ERROR`], so its error piles
+/// up between writes and tells its drift once enough time has passed, see
+/// [`MIN_TRIM_INTERVAL`]. The drift is only measured between two writes of
+/// a time from NTP.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteBack {
+    /// UTC time of the last write since the start and whether it came from
+    /// NTP.
+    written: Option<(NaiveDateTime, bool)>,
+}
+
+impl WriteBack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare the source's time `source` with the clock's UTC time `now`,
+    /// `precise` if that came from NTP. The source counts whole seconds, so
+    /// `now` is compared without its fraction.
+    pub fn update(
+        &mut self,
+        source: Option<NaiveDateTime>,
+        now: NaiveDateTime,
+        precise: bool,
+    ) -> Update {
+        let Some(source) = source else {
+            self.written = Some((now, precise));
+            return Update::Write { trim: None };
+        };
+        let ahead = source - now.with_nanosecond(0).unwrap_or(now);
+        if ahead.abs() < MAX_ERROR {
+            return Update::Keep;
+        }
+        let trim = self
+            .written
+            .filter(|(_, was_precise)| precise && *was_precise)
+            .map(|(written, _)| now - written)
+            .filter(|elapsed| *elapsed >= MIN_TRIM_INTERVAL)
+            .and_then(|elapsed| {
+                let ppb = ahead.num_microseconds()? as i128 * 1_000_000_000
+                    / elapsed.num_microseconds()? as i128;
+                Some(ppb as i64)
+            });
+        self.written = Some((now, precise));
+        Update::Write { trim }
+    }
+}
+
+/// A chip on the bus for the driver tests, its registers written and read
+/// from the address sent first.
+#[cfg(test)]
+mod mock {
+    use super::Bus;
+
+    pub struct Registers {
+        pub address: u8,
+        pub registers: [u8; 32],
+    }
+
+    impl Registers {
+        pub fn new(address: u8) -> Self {
+            Self {
+                address,
+                registers: [0; 32],
+            }
+        }
+    }
+
+    impl Bus for Registers {
+        /// No chip at the address.
+        type Error = ();
+
+        fn write(&mut self, address: u8, write: &[u8]) -> Result<(), ()> {
+            let (&first, data) = write.split_first().ok_or(())?;
+            if address != self.address {
+                return Err(());
+            }
+            let first = usize::from(first);
+            self.registers[first..first + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), ()> {
+            if address != self.address || write.len() != 1 {
+                return Err(());
+            }
+            let first = usize::from(write[0]);
+            read.copy_from_slice(&self.registers[first..first + read.len()]);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use chrono::{Datelike, NaiveDateTime};
+
+use super::{decode, encode, from_bcd, Bus, TimeSource};
+
+pub const ADDRESS: u8 = 0x68;
+/// First of the seconds, minutes, hours, weekday, day, month and year
+/// registers.
+const SECONDS: u8 = 0x00;
+pub const STATUS: u8 = 0x0f;
+const AGING: u8 = 0x10;
+/// Oscillator stop flag, set when the chip lost the time.
+const OSF: u8 = 0x80;
+/// 12 hour mode in the hours register, bit 5 is then PM.
+const HOURS_12: u8 = 0x40;
+const PM: u8 = 0x20;
+
+/// A DS3231, a temperature compensated RTC.
+///
+/// Its aging offset trims the oscillator by about 0.1 ppm per step at room
+/// temperature, positive slows it down. A new offset is taken with the next
+/// temperature conversion, at most 64 s later.
+pub struct Ds3231<B> {
+    bus: B,
+}
+
+impl<B: Bus> Ds3231<B> {
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+
+    fn register(&mut self, register: u8) -> Result<u8, B::Error> {
+        let mut byte = [0];
+        self.bus.write_read(ADDRESS, &[register], &mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+impl<B: Bus> TimeSource for Ds3231<B> {
+    type Error = B::Error;
+
+    fn read(&mut self) -> Result<Option<NaiveDateTime>, B::Error> {
+        if self.register(STATUS)? & OSF != 0 {
+            return Ok(None);
+        }
+        let mut r = [0; 7];
+        self.bus.write_read(ADDRESS, &[SECONDS], &mut r)?;
+        Ok(time(&r))
+    }
+
+    fn write(&mut self, time: NaiveDateTime) -> Result<(), B::Error> {
+        let Some([second, minute, hour, day, month, year]) = encode(time) else {
+            return Ok(());
+        };
+        let weekday = time.weekday().number_from_monday() as u8;
+        self.bus.write(
+            ADDRESS,
+            &[SECONDS, second, minute, hour, weekday, day, month, year],
+        )?;
+        let status = self.register(STATUS)?;
+        self.bus.write(ADDRESS, &[STATUS, status & !OSF])
+    }
+
+    fn trim(&mut self, ppb: i64) -> Result<(), B::Error> {
+        let steps = (ppb + 50 * ppb.signum()) / 100;
+        if steps == 0 {
+            return Ok(());
+        }
+        let aging = self.register(AGING)? as i8;
+        let aging = (i64::from(aging) + steps).clamp(i8::MIN.into(), i8::MAX.into()) as i8;
+        self.bus.write(ADDRESS, &[AGING, aging as u8])
+    }
+}
+
+/// The time in the registers from [`SECONDS`] on.
+fn time(r: &[u8; 7]) -> Option<NaiveDateTime> {
+    let hour = if r[2] & HOURS_12 != 0 {
+        let hour = from_bcd(r[2] & 0x1f).filter(|h| (1..=12).contains(h))?;
+        hour % 12 + if r[2] & PM != 0 { 12 } else { 0 }
+    } else {
+        from_bcd(r[2] & 0x3f)?
+    };
+    decode(
+        r[5] & 0x80 != 0,
+        from_bcd(r[6])?,
+        from_bcd(r[5] & 0x1f)?,
+        from_bcd(r[4] & 0x3f)?,
+        hour,
+        from_bcd(r[1] & 0x7f)?,
+        from_bcd(r[0] & 0x7f)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtc::mock::Registers;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").unwrap()
+    }
+
+    fn chip() -> Ds3231<Registers> {
+        Ds3231::new(Registers::new(ADDRESS))
+    }
+
+    /// The time registers after writing `text`.
+    fn written(chip: &mut Ds3231<Registers>, text: &str) -> [u8; 7] {
+        chip.write(time(text)).unwrap();
+        chip.bus.registers[..7].try_into().unwrap()
+    }
+
+    #[test]
+    fn century() {
+        let mut chip = chip();
+        // Thursday, the weekday counts from 1 on Monday
+        assert_eq!(
+            written(&mut chip, "2099-12-31T23:59:59"),
+            [0x59, 0x59, 0x23, 4, 0x31, 0x12, 0x99]
+        );
+        assert_eq!(chip.read(), Ok(Some(time("2099-12-31T23:59:59"))));
+        assert_eq!(
+            written(&mut chip, "2100-01-01T00:00:00"),
+            [0x00, 0x00, 0x00, 5, 0x01, 0x81, 0x00]
+        );
+        assert_eq!(chip.read(), Ok(Some(time("2100-01-01T00:00:00"))));
+
+        // the chip itself flips the century bit going from 99 to 00
+        chip.bus.registers[..7].copy_from_slice(&[0x00, 0x00, 0x00, 5, 0x01, 0x81, 0x00]);
+        assert_eq!(chip.read(), Ok(Some(time("2100-01-01T00:00:00"))));
+        chip.bus.registers[..7].copy_from_slice(&[0x07, 0x08, 0x09, 1, 0x02, 0x03, 0x04]);
+        assert_eq!(chip.read(), Ok(Some(time("2004-03-02T09:08:07"))));
+
+        // times the chip can't hold are not written
+        chip.write(time("1999-12-31T23:59:59")).unwrap();
+        chip.write(time("2200-01-01T00:00:00")).unwrap();
+        assert_eq!(chip.read(), Ok(Some(time("2004-03-02T09:08:07"))));
+    }
+
+    #[test]
+    fn hours_12() {
+        let mut chip = chip();
+        for (hours, hour) in [(0x52, 0), (0x41, 1), (0x52 | PM, 12), (0x51 | PM, 23)] {
+            chip.bus.registers[..7].copy_from_slice(&[0, 0, hours, 3, 0x15, 0x01, 0x25]);
+            let read = chip.read().unwrap().unwrap();
+            assert_eq!(
+                read,
+                time("2025-01-15T00:00:00") + chrono::TimeDelta::hours(hour)
+            );
+        }
+        // hour 13 and 0 do not exist on a 12 hour clock
+        chip.bus.registers[2] = 0x53;
+        assert_eq!(chip.read(), Ok(None));
+        chip.bus.registers[2] = 0x40;
+        assert_eq!(chip.read(), Ok(None));
+    }
+
+    #[test]
+    fn lost_time() {
+        let mut chip = chip();
+        written(&mut chip, "2026-10-17T12:00:00");
+        // the oscillator stopped, e.g. the battery ran empty
+        chip.bus.registers[usize::from(STATUS)] = OSF | 0x08;
+        assert_eq!(chip.read(), Ok(None));
+        // writing clears the flag and keeps the other status bits
+        written(&mut chip, "2026-10-17T12:00:01");
+        assert_eq!(chip.bus.registers[usize::from(STATUS)], 0x08);
+        assert_eq!(chip.read(), Ok(Some(time("2026-10-17T12:00:01"))));
+
+        // registers that are no time
+        chip.bus.registers[5] = 0x13;
+        assert_eq!(chip.read(), Ok(None));
+        chip.bus.registers[5] = 0x02;
+        chip.bus.registers[4] = 0x30;
+        assert_eq!(chip.read(), Ok(None));
+        chip.bus.registers[4] = 0x1a;
+        assert_eq!(chip.read(), Ok(None));
+        // no chip answering
+        chip.bus.address = 0x50;
+        assert_eq!(chip.read(), Err(()));
+    }
+
+    #[test]
+    fn trim() {
+        let mut chip = chip();
+        let aging = |chip: &Ds3231<Registers>| chip.bus.registers[usize::from(AGING)] as i8;
+        // 0.1 ppm per step, rounded to the nearest one
+        for (ppb, expected) in [
+            (100, 1),
+            (49, 1),
+            (50, 2),
+            (250, 5),
+            (-149, 4),
+            (-150, 2),
+            (-1000, -8),
+        ] {
+            chip.trim(ppb).unwrap();
+            assert_eq!(aging(&chip), expected, "{ppb} ppb");
+        }
+        // clamped to the register
+        chip.trim(20_000).unwrap();
+        assert_eq!(aging(&chip), i8::MAX);
+        chip.trim(-50_000).unwrap();
+        assert_eq!(aging(&chip), i8::MIN);
+        chip.trim(-100).unwrap();
+        assert_eq!(aging(&chip), i8::MIN);
+    }
+}
@@ -0,0 +1,141 @@
+use chrono::{Datelike, NaiveDateTime};
+
+use super::{decode, encode, from_bcd, Bus, TimeSource};
+
+pub const ADDRESS: u8 = 0x51;
+pub const CONTROL_1: u8 = 0x00;
+/// First of the seconds, minutes, hours, day, weekday, month and year
+/// registers.
+const SECONDS: u8 = 0x02;
+/// Voltage low flag in the seconds register, set when the chip lost the
+/// time.
+const VL: u8 = 0x80;
+
+/// A PCF8563, a low power RTC without a way to trim it.
+///
+/// The datasheet reads a set century bit as 19xx, here it is 21xx as the chip
+/// sets it going from 2099 to 2100.
+pub struct Pcf8563<B> {
+    bus: B,
+}
+
+impl<B: Bus> Pcf8563<B> {
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+impl<B: Bus> TimeSource for Pcf8563<B> {
+    type Error = B::Error;
+
+    fn read(&mut self) -> Result<Option<NaiveDateTime>, B::Error> {
+        let mut r = [0; 7];
+        self.bus.write_read(ADDRESS, &[SECONDS], &mut r)?;
+        if r[0] & VL != 0 {
+            return Ok(None);
+        }
+        Ok(time(&r))
+    }
+
+    fn write(&mut self, time: NaiveDateTime) -> Result<(), B::Error> {
+        let Some([second, minute, hour, day, month, year]) = encode(time) else {
+            return Ok(());
+        };
+        let weekday = time.weekday().num_days_from_sunday() as u8;
+        // clears VL along with the seconds
+        self.bus.write(
+            ADDRESS,
+            &[SECONDS, second, minute, hour, day, weekday, month, year],
+        )?;
+        // runs the clock in case it was stopped
+        self.bus.write(ADDRESS, &[CONTROL_1, 0])
+    }
+}
+
+/// The time in the registers from [`SECONDS`] on.
+fn time(r: &[u8; 7]) -> Option<NaiveDateTime> {
+    decode(
+        r[5] & 0x80 != 0,
+        from_bcd(r[6])?,
+        from_bcd(r[5] & 0x1f)?,
+        from_bcd(r[3] & 0x3f)?,
+        from_bcd(r[2] & 0x3f)?,
+        from_bcd(r[1] & 0x7f)?,
+        from_bcd(r[0] & 0x7f)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtc::mock::Registers;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").unwrap()
+    }
+
+    fn chip() -> Pcf8563<Registers> {
+        Pcf8563::new(Registers::new(ADDRESS))
+    }
+
+    /// The time registers after writing `text`.
+    fn written(chip: &mut Pcf8563<Registers>, text: &str) -> [u8; 7] {
+        chip.write(time(text)).unwrap();
+        chip.bus.registers[2..9].try_into().unwrap()
+    }
+
+    #[test]
+    fn century() {
+        let mut chip = chip();
+        // Thursday, the weekday counts from 0 on Sunday
+        assert_eq!(
+            written(&mut chip, "2099-12-31T23:59:59"),
+            [0x59, 0x59, 0x23, 0x31, 4, 0x12, 0x99]
+        );
+        assert_eq!(chip.read(), Ok(Some(time("2099-12-31T23:59:59"))));
+        assert_eq!(
+            written(&mut chip, "2100-01-01T00:00:00"),
+            [0x00, 0x00, 0x00, 0x01, 5, 0x81, 0x00]
+        );
+        assert_eq!(chip.read(), Ok(Some(time("2100-01-01T00:00:00"))));
+
+        // unused bits are ignored
+        chip.bus.registers[2..9].copy_from_slice(&[0x07, 0x88, 0xc9, 0xc2, 0xf8, 0x63, 0x04]);
+        assert_eq!(chip.read(), Ok(Some(time("2004-03-02T09:08:07"))));
+    }
+
+    #[test]
+    fn lost_time() {
+        let mut chip = chip();
+        written(&mut chip, "2026-10-17T12:00:00");
+        // the voltage dropped too low to keep the time
+        chip.bus.registers[usize::from(SECONDS)] |= VL;
+        assert_eq!(chip.read(), Ok(None));
+        // writing clears the flag and runs the clock again if it was stopped
+        chip.bus.registers[usize::from(CONTROL_1)] = 0x20;
+        written(&mut chip, "2026-10-17T12:00:01");
+        assert_eq!(chip.bus.registers[usize::from(SECONDS)], 0x01);
+        assert_eq!(chip.bus.registers[usize::from(CONTROL_1)], 0);
+        assert_eq!(chip.read(), Ok(Some(time("2026-10-17T12:00:01"))));
+
+        // registers that are no time
+        chip.bus.registers[7] = 0x13;
+        assert_eq!(chip.read(), Ok(None));
+        chip.bus.registers[7] = 0x02;
+        chip.bus.registers[5] = 0x30;
+        assert_eq!(chip.read(), Ok(None));
+    }
+
+    #[test]
+    fn no_trim() {
+        let mut chip = chip();
+        written(&mut chip, "2026-10-17T12:00:00");
+        let registers = chip.bus.registers;
+        chip.trim(1000).unwrap();
+        assert_eq!(chip.bus.registers, registers);
+    }
+}
@@ -0,0 +1,501 @@
+//! Daily routine as color-coded time blocks.
+//!
+//! A [`Schedule`] is a list of named [`Block`]s, each covering a time of day
+//! on some days of the week, e.g. "play" from 15:00 to 17:00 in green on
+//! school days and "sleep" from 19:30 to 07:00 in blue every day. While a
+//! block is active the ring paints what is left of it within the current hour
+//! in the block's color, and the block can bring its own animation.
+//!
+//! A block that ends at or before its start runs over midnight and belongs to
+//! the day it starts on, so "sleep" on Fridays lasts into Saturday morning.
+//!
+//! Blocks have a compact text form, used by the simulator and the API:
+//!
+//! ```text
+//! 19:30-07:00 daily 000030 sleep @8
+//! 08:00-12:30 mon-fri 003000 school rainbow
+//! ```
+//!
+//! `@8` sets the brightness while the block is active, overriding the
+//! configured one and night mode.
+
+use core::fmt;
+
+use chrono::{Datelike, NaiveDateTime, Timelike, Weekday};
+use heapless::{String, Vec};
+use rgb::RGB8;
+
+use crate::{
+    animation::Builtin,
+    ring::{Frame, RING_SIZE},
+};
+
+pub const MAX_BLOCKS: usize = 8;
+pub const MAX_NAME_LEN: usize = 16;
+
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Missing or malformed `HH:MM-HH:MM` range.
+    Time,
+    /// Missing or unknown days.
+    Days,
+    /// Missing or malformed `RRGGBB` color.
+    Color,
+    /// Missing or too long name.
+    Name,
+    /// Unknown animation.
+    Animation,
+    /// Brightness not in 0 to 255.
+    Level,
+    /// Unexpected text after a complete block.
+    Trailing,
+}
+
+/// Days of the week, bit 0 is Monday.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Days(pub u8);
+
+impl Days {
+    pub const DAILY: Self = Self(0x7f);
+    pub const WEEKDAYS: Self = Self(0x1f);
+    pub const WEEKENDS: Self = Self(0x60);
+
+    pub fn contains(self, day: Weekday) -> bool {
+        self.0 & 1 << day.num_days_from_monday() != 0
+    }
+
+    /// `daily`, `weekdays`, `weekends` or a comma separated list of days and
+    /// ranges of days such as `mon,wed-fri` or `sun-thu`.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "daily" => return Some(Self::DAILY),
+            "weekdays" => return Some(Self::WEEKDAYS),
+            "weekends" => return Some(Self::WEEKENDS),
+            _ => {}
+        }
+        let day = |name: &str| DAY_NAMES.iter().position(|&d| d == name);
+        let mut days = 0u8;
+        for part in text.split(',') {
+            let (first, last) = match part.split_once('-') {
+                Some((first, last)) => (day(first)?, day(last)?),
+                None => (day(part)?, day(part)?),
+            };
+            // ranges may wrap around the week, `sun-thu`
+            let mut index = first;
+            days |= 1 << index;
+            while index != last {
+                index = (index + 1) % 7;
+                days |= 1 << index;
+            }
+        }
+        Some(Self(days))
+    }
+}
+
+impl fmt::Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::DAILY => return f.write_str("daily"),
+            Self::WEEKDAYS => return f.write_str("weekdays"),
+            Self::WEEKENDS => return f.write_str("weekends"),
+            _ => {}
+        }
+        let set = |index: usize| index < 7 && self.0 & 1 << index != 0;
+        let mut separator = "";
+        let mut index = 0;
+        while index < 7 {
+            if !set(index) {
+                index += 1;
+                continue;
+            }
+            let mut last = index;
+            while set(last + 1) {
+                last += 1;
+            }
+            f.write_str(separator)?;
+            f.write_str(DAY_NAMES[index])?;
+            if last > index {
+                write!(f, "-{}", DAY_NAMES[last])?;
+            }
+            separator = ",";
+            index = last + 1;
+        }
+        Ok(())
+    }
+}
+
+/// A named stretch of the day.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub name: String<MAX_NAME_LEN>,
+    /// Days the block starts on.
+    pub days: Days,
+    /// Minute of the day the block starts at, 0 to 1439.
+    pub start: u16,
+    /// Minute of the day the block ends at, at or before `start` if it runs
+    /// over midnight. Equal to `start` for a whole day.
+    pub end: u16,
+    pub color: RGB8,
+    /// Animation to run instead of the configured one.
+    pub animation: Option<Builtin>,
+    /// Brightness instead of the configured one, also at night.
+    pub level: Option<u8>,
+}
+
+impl Block {
+    /// Length in minutes.
+    pub fn length(&self) -> u16 {
+        match (self.end + MINUTES_PER_DAY - self.start) % MINUTES_PER_DAY {
+            0 => MINUTES_PER_DAY,
+            length => length,
+        }
+    }
+
+    /// Minutes left of the block at local time `now`, counting the current
+    /// one, `None` if the block is not active.
+    pub fn remaining(&self, now: &NaiveDateTime) -> Option<u16> {
+        let minute = (now.hour() * 60 + now.minute()) as u16;
+        // minutes since the block last started, on whichever day
+        let since = (minute + MINUTES_PER_DAY - self.start) % MINUTES_PER_DAY;
+        if since >= self.length() {
+            return None;
+        }
+        let day = if minute >= self.start {
+            now.weekday()
+        } else {
+            now.weekday().pred()
+        };
+        self.days.contains(day).then(|| self.length() - since)
+    }
+
+    /// Paint the rest of the block within the current hour over `frame`,
+    /// from the minute hand onwards.
+    pub fn render(&self, now: &NaiveDateTime, frame: &mut Frame) {
+        if let Some(remaining) = self.remaining(now) {
+            let minute = now.minute() as usize;
+            let slots = (remaining as usize).min(RING_SIZE - minute);
+            frame[minute..minute + slots].fill(self.color);
+        }
+    }
+
+    /// Parse the text form, see the [module documentation](self).
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut fields = text.split_whitespace();
+        let (start, end) = fields
+            .next()
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(start, end)| Some((parse_time(start)?, parse_time(end)?)))
+            .ok_or(ParseError::Time)?;
+        let days = fields
+            .next()
+            .and_then(Days::parse)
+            .ok_or(ParseError::Days)?;
+        let color = fields
+            .next()
+            .and_then(parse_color)
+            .ok_or(ParseError::Color)?;
+        let name = fields
+            .next()
+            .and_then(|name| String::try_from(name).ok())
+            .ok_or(ParseError::Name)?;
+        let mut animation = None;
+        let mut level = None;
+        for field in fields.by_ref() {
+            if let Some(value) = field.strip_prefix('@') {
+                level = Some(value.parse().map_err(|_| ParseError::Level)?);
+                break;
+            }
+            if animation.is_some() {
+                return Err(ParseError::Trailing);
+            }
+            animation = Some(Builtin::from_name(field).ok_or(ParseError::Animation)?);
+        }
+        if fields.next().is_some() {
+            return Err(ParseError::Trailing);
+        }
+        Ok(Self {
+            name,
+            days,
+            start,
+            end,
+            color,
+            animation,
+            level,
+        })
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02} {} {:02x}{:02x}{:02x} {}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60,
+            self.days,
+            self.color.r,
+            self.color.g,
+            self.color.b,
+            self.name
+        )?;
+        if let Some(animation) = self.animation {
+            write!(f, " {}", animation.name())?;
+        }
+        if let Some(level) = self.level {
+            write!(f, " @{level}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Minute of the day of a `HH:MM` time.
+pub fn parse_time(text: &str) -> Option<u16> {
+    let (hours, minutes) = text.split_once(':')?;
+    // `parse` would take a sign as well
+    let digits = hours.bytes().chain(minutes.bytes());
+    if text.len() != 5 || !digits.clone().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: u16 = hours.parse().ok()?;
+    let minutes: u16 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+fn parse_color(text: &str) -> Option<RGB8> {
+    if text.len() != 6 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(text, 16).ok()?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Some(RGB8::new(r, g, b))
+}
+
+/// The week's blocks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schedule {
+    pub blocks: Vec<Block, MAX_BLOCKS>,
+}
+
+impl Schedule {
+    /// The block active at local time `now`, the first one listed if several
+    /// overlap.
+    pub fn active(&self, now: &NaiveDateTime) -> Option<&Block> {
+        self.blocks
+            .iter()
+            .find(|block| block.remaining(now).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").unwrap()
+    }
+
+    /// Minutes left of `block` at the local times, 2026-10-16 is a Friday.
+    fn check(block: &str, cases: &[(&str, Option<u16>)]) {
+        let block = Block::parse(block).unwrap();
+        for (now, remaining) in cases {
+            assert_eq!(block.remaining(&time(now)), *remaining, "{block} at {now}");
+        }
+    }
+
+    #[test]
+    fn end_minute() {
+        check(
+            "15:00-17:00 weekdays 003000 play",
+            &[
+                ("2026-10-16T14:59:59", None),
+                ("2026-10-16T15:00:00", Some(120)),
+                ("2026-10-16T16:59:00", Some(1)),
+                ("2026-10-16T16:59:59", Some(1)),
+                ("2026-10-16T17:00:00", None),
+                ("2026-10-17T15:30:00", None),
+            ],
+        );
+    }
+
+    #[test]
+    fn over_midnight() {
+        // belongs to Friday, so it runs on Saturday morning only
+        check(
+            "22:00-02:00 fri 000030 late",
+            &[
+                ("2026-10-15T23:00:00", None),
+                ("2026-10-16T01:00:00", None),
+                ("2026-10-16T21:59:00", None),
+                ("2026-10-16T22:00:00", Some(240)),
+                ("2026-10-16T23:59:00", Some(121)),
+                ("2026-10-17T00:00:00", Some(120)),
+                ("2026-10-17T01:00:00", Some(60)),
+                ("2026-10-17T01:59:00", Some(1)),
+                ("2026-10-17T02:00:00", None),
+                ("2026-10-17T22:30:00", None),
+            ],
+        );
+        // Sunday night runs into Monday, the next week
+        check(
+            "23:00-01:00 sun 000030 late",
+            &[
+                ("2026-10-18T00:30:00", None),
+                ("2026-10-18T23:00:00", Some(120)),
+                ("2026-10-19T00:30:00", Some(30)),
+                ("2026-10-19T01:00:00", None),
+                ("2026-10-19T23:30:00", None),
+            ],
+        );
+        // ending at midnight is not over it
+        check(
+            "20:00-00:00 sat 000030 evening",
+            &[
+                ("2026-10-17T23:59:00", Some(1)),
+                ("2026-10-18T00:00:00", None),
+            ],
+        );
+    }
+
+    #[test]
+    fn whole_day() {
+        let block = Block::parse("06:00-06:00 mon 300000 monday").unwrap();
+        assert_eq!(block.length(), MINUTES_PER_DAY);
+        check(
+            "06:00-06:00 mon 300000 monday",
+            &[
+                ("2026-10-19T05:59:00", None),
+                ("2026-10-19T06:00:00", Some(1440)),
+                ("2026-10-19T23:59:00", Some(361)),
+                ("2026-10-20T05:59:00", Some(1)),
+                ("2026-10-20T06:00:00", None),
+            ],
+        );
+        check(
+            "00:00-00:00 daily 300000 always",
+            &[
+                ("2026-10-17T00:00:00", Some(1440)),
+                ("2026-10-18T23:59:59", Some(1)),
+            ],
+        );
+    }
+
+    #[test]
+    fn overlapping_blocks() {
+        let mut schedule = Schedule::default();
+        for block in [
+            "15:00-17:00 daily 003000 play",
+            "16:00-18:00 daily 000030 read",
+        ] {
+            schedule.blocks.push(Block::parse(block).unwrap()).unwrap();
+        }
+        fn active<'a>(schedule: &'a Schedule, now: &str) -> Option<&'a str> {
+            schedule.active(&time(now)).map(|block| block.name.as_str())
+        }
+        // the first one listed wins
+        assert_eq!(active(&schedule, "2026-10-16T15:30:00"), Some("play"));
+        assert_eq!(active(&schedule, "2026-10-16T16:30:00"), Some("play"));
+        assert_eq!(active(&schedule, "2026-10-16T17:00:00"), Some("read"));
+        assert_eq!(active(&schedule, "2026-10-16T18:00:00"), None);
+        schedule.blocks.reverse();
+        assert_eq!(active(&schedule, "2026-10-16T15:30:00"), Some("play"));
+        assert_eq!(active(&schedule, "2026-10-16T16:30:00"), Some("read"));
+    }
+
+    #[test]
+    fn days() {
+        for (text, bits, shown) in [
+            ("daily", 0x7f, "daily"),
+            ("weekdays", 0x1f, "weekdays"),
+            ("weekends", 0x60, "weekends"),
+            ("mon-fri", 0x1f, "weekdays"),
+            ("wed", 0x04, "wed"),
+            ("mon,wed-fri", 0x1d, "mon,wed-fri"),
+            // ranges wrap around the week, but are shown from Monday on
+            ("sun-thu", 0x4f, "mon-thu,sun"),
+            ("fri-mon", 0x71, "mon,fri-sun"),
+            ("sat-fri", 0x7f, "daily"),
+            ("thu,tue", 0x0a, "tue,thu"),
+        ] {
+            let days = Days::parse(text).unwrap();
+            assert_eq!(days, Days(bits), "{text}");
+            let mut display: String<32> = String::new();
+            fmt::write(&mut display, format_args!("{days}")).unwrap();
+            assert_eq!(display, shown, "{text}");
+            assert_eq!(Days::parse(&display), Some(days), "{text}");
+        }
+        assert!(Days::parse("fri").unwrap().contains(Weekday::Fri));
+        assert!(!Days::parse("fri").unwrap().contains(Weekday::Sat));
+        for text in ["", "mon,", "mon-", "-fri", "monday", "Mon", "mon--fri"] {
+            assert_eq!(Days::parse(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let block = Block::parse("19:30-07:00 sun-thu 0000ff sleep comet @8").unwrap();
+        assert_eq!(block.start, 19 * 60 + 30);
+        assert_eq!(block.end, 7 * 60);
+        assert_eq!(block.color, RGB8::new(0, 0, 255));
+        assert_eq!(block.animation, Some(Builtin::Comet));
+        assert_eq!(block.level, Some(8));
+        let mut text: String<64> = String::new();
+        fmt::write(&mut text, format_args!("{block}")).unwrap();
+        assert_eq!(text, "19:30-07:00 mon-thu,sun 0000ff sleep comet @8");
+        assert_eq!(Block::parse(&text), Ok(block));
+
+        for (text, error) in [
+            ("", ParseError::Time),
+            ("19:30 daily 000030 sleep", ParseError::Time),
+            ("24:00-07:00 daily 000030 sleep", ParseError::Time),
+            ("19:60-07:00 daily 000030 sleep", ParseError::Time),
+            ("9:30-07:00 daily 000030 sleep", ParseError::Time),
+            ("+1:30-07:00 daily 000030 sleep", ParseError::Time),
+            ("19:30-07:+5 daily 000030 sleep", ParseError::Time),
+            ("19:30-07:00 someday 000030 sleep", ParseError::Days),
+            ("19:30-07:00 daily +12345 sleep", ParseError::Color),
+            ("19:30-07:00 daily 00003g sleep", ParseError::Color),
+            ("19:30-07:00 daily 0030 sleep", ParseError::Color),
+            ("19:30-07:00 daily 000030", ParseError::Name),
+            (
+                "19:30-07:00 daily 000030 a-name-far-too-long",
+                ParseError::Name,
+            ),
+            (
+                "19:30-07:00 daily 000030 sleep sparkle",
+                ParseError::Animation,
+            ),
+            ("19:30-07:00 daily 000030 sleep @256", ParseError::Level),
+            (
+                "19:30-07:00 daily 000030 sleep comet rainbow",
+                ParseError::Trailing,
+            ),
+            (
+                "19:30-07:00 daily 000030 sleep @8 comet",
+                ParseError::Trailing,
+            ),
+        ] {
+            assert_eq!(Block::parse(text), Err(error), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("00:00"), Some(0));
+        assert_eq!(parse_time("23:59"), Some(1439));
+        for text in [
+            "+1:30", "01:+5", "-1:30", " 1:30", "1:30", "01:30 ", "0130", "01.30",
+        ] {
+            assert_eq!(parse_time(text), None, "{text:?}");
+        }
+        assert_eq!(parse_color("ff8000"), Some(RGB8::new(255, 128, 0)));
+        assert_eq!(parse_color("FF8000"), Some(RGB8::new(255, 128, 0)));
+        for text in ["+12345", "-12345", "12345", "1234567", "12 345", "gg0000"] {
+            assert_eq!(parse_color(text), None, "{text:?}");
+        }
+    }
+}
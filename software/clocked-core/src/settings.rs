@@ -0,0 +1,998 @@
+//! Persistent settings.
+//!
+//! Settings live in a dedicated flash partition as a log of records. Every
+//! save appends a new record behind the previous one, so a sector is only
+//! erased once it is full and the store moves on to the next one. On start
+//! the record with the highest sequence number and a valid checksum wins.
+//!
+//! ```text
+//! magic "CLKS" | schema version u16 | payload len u16 | sequence u32 | crc32 u32 | payload
+//! ```
+//!
+//! The checksum covers the first 12 header bytes and the payload. New
+//! settings are only ever appended to the end of the payload and the schema
+//! version is bumped with them. Decoding an older payload leaves the newer
+//! fields at their defaults, which is all the migration needed; a payload
+//! written by a newer firmware decodes the fields this one knows about.
+
+use chrono::{DateTime, NaiveDateTime};
+use embedded_storage::nor_flash::NorFlash;
+use heapless::String;
+use rgb::RGB8;
+
+use crate::{
+    animation::Builtin,
+    api::{self, MAX_TOKEN_LEN},
+    crc::crc32_update,
+    http::Url,
+    layout::Layout,
+    manual::LastTime,
+    mdns::{self, MAX_NAME_LEN as MAX_DEVICE_NAME_LEN},
+    mqtt::Broker,
+    night::{NightMode, Phase},
+    ntp::{self, MAX_HOST_LEN as MAX_NTP_HOST_LEN},
+    ring::ClockFace,
+    schedule::{Block, Days, Schedule, MAX_BLOCKS, MAX_NAME_LEN, MINUTES_PER_DAY},
+    wifi::{Credentials, MAX_PASSWORD_LEN, MAX_SSID_LEN},
+};
+
+/// Current schema version, bump when appending fields.
+pub const SCHEMA_VERSION: u16 = 12;
+pub const MAX_PAYLOAD_LEN: usize = 1024;
+pub const MAX_TIME_ZONE_LEN: usize = 64;
+
+const MAGIC: [u8; 4] = *b"CLKS";
+const HEADER_LEN: usize = 16;
+const MAX_RECORD_LEN: usize = HEADER_LEN + MAX_PAYLOAD_LEN;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub wifi: Option<Credentials>,
+    /// POSIX TZ rule, see [`crate::tz`].
+    pub time_zone: String<MAX_TIME_ZONE_LEN>,
+    /// Global brightness, 255 leaves the colors as they are.
+    pub brightness: u8,
+    pub hour_color: RGB8,
+    pub minute_color: RGB8,
+    pub second_color: RGB8,
+    /// Animation behind the hands, since version 2.
+    pub animation: Builtin,
+    /// Current ceiling for the LED strip in milliamps, see
+    /// [`crate::power`]. Since version 3.
+    pub max_current_ma: u16,
+    /// How the strip is mounted, the LED count is fixed by the firmware.
+    /// Since version 4.
+    pub led_offset: u16,
+    pub led_reversed: bool,
+    /// Daily routine shown over the face, since version 5. The blocks'
+    /// brightness since version 6.
+    pub schedule: Schedule,
+    /// Since version 6.
+    pub night: NightMode,
+    /// Since version 7.
+    pub mqtt: Broker,
+    /// Name on the network, see [`crate::mdns`]. Empty for the device id.
+    /// Since version 8.
+    pub device_name: String<MAX_DEVICE_NAME_LEN>,
+    /// Since version 9, the HTTP fallback since version 10.
+    pub ntp: ntp::Config,
+    /// Saved by the clock itself to resume from after a power cut, not a
+    /// setting, see [`crate::manual::resume`].
+    pub last_time: Option<LastTime>,
+    /// Token changes through the REST API need, empty for none, see
+    /// [`crate::api::authorized`]. Since version 12.
+    pub api_token: String<MAX_TOKEN_LEN>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            wifi: None,
+            time_zone: String::try_from("CET-1CEST,M3.5.0,M10.5.0/3").unwrap(),
+            brightness: 255,
+            hour_color: RGB8::new(32, 0, 0),
+            minute_color: RGB8::new(0, 32, 0),
+            second_color: RGB8::new(0, 0, 32),
+            animation: Builtin::default(),
+            // leaves a good 1 A of the 3 A USB budget for the board
+            max_current_ma: 2000,
+            led_offset: 0,
+            led_reversed: false,
+            schedule: Schedule::default(),
+            night: NightMode::default(),
+            mqtt: Broker::default(),
+            device_name: String::new(),
+            ntp: ntp::Config::default(),
+            last_time: None,
+            api_token: String::new(),
+        }
+    }
+}
+
+/// What the ring shows at some time, see [`Settings::program`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub animation: Builtin,
+    /// Active schedule block.
+    pub block: Option<Block>,
+    /// Color filling the ring while the wake light is on.
+    pub wake: Option<RGB8>,
+    /// Brightness, before current limiting.
+    pub level: u8,
+}
+
+impl Settings {
+    /// Layout of a strip with `leds` LEDs mounted as configured.
+    pub fn layout(&self, leds: usize) -> Layout {
+        Layout {
+            leds,
+            offset: self.led_offset as usize,
+            reversed: self.led_reversed,
+        }
+    }
+
+    /// Animation, overlays and brightness at local time `now`.
+    ///
+    /// The configured brightness is dimmed by night mode, which also stops
+    /// the animation. An active schedule block overrides both with its own
+    /// animation and brightness, if it has them.
+    pub fn program(&self, now: &NaiveDateTime) -> Program {
+        let block = self.schedule.active(now).cloned();
+        let phase = self.night.phase(now);
+        let animation = match phase {
+            Phase::Day => self.animation,
+            Phase::Night | Phase::Wake => Builtin::Off,
+        };
+        let level = self.night.level(phase, self.brightness);
+        Program {
+            animation: block
+                .as_ref()
+                .and_then(|b| b.animation)
+                .unwrap_or(animation),
+            level: block.as_ref().and_then(|b| b.level).unwrap_or(level),
+            wake: (phase == Phase::Wake).then_some(self.night.wake_color),
+            block,
+        }
+    }
+
+    /// Name on the network, the device `id` unless one is set.
+    pub fn device_name<'a>(&'a self, id: &'a str) -> &'a str {
+        match self.device_name.as_str() {
+            "" => id,
+            name => name,
+        }
+    }
+
+    pub fn clock_face(&self) -> ClockFace {
+        ClockFace {
+            hour: self.hour_color,
+            minute: self.minute_color,
+            second: self.second_color,
+        }
+    }
+
+    /// Encode with the current schema, returns the payload length or `None`
+    /// if it does not fit into `out`.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let mut w = Writer { out, pos: 0 };
+        // version 1
+        match &self.wifi {
+            Some(wifi) => {
+                w.u8(1)?;
+                w.str(&wifi.ssid)?;
+                w.str(&wifi.password)?;
+            }
+            None => w.u8(0)?,
+        }
+        w.str(&self.time_zone)?;
+        w.u8(self.brightness)?;
+        w.color(self.hour_color)?;
+        w.color(self.minute_color)?;
+        w.color(self.second_color)?;
+        // version 2
+        w.str(self.animation.name())?;
+        // version 3
+        w.u16(self.max_current_ma)?;
+        // version 4
+        w.u16(self.led_offset)?;
+        w.u8(self.led_reversed as u8)?;
+        // version 5
+        w.u8(self.schedule.blocks.len() as u8)?;
+        for block in &self.schedule.blocks {
+            w.str(&block.name)?;
+            w.u8(block.days.0)?;
+            w.u16(block.start)?;
+            w.u16(block.end)?;
+            w.color(block.color)?;
+            w.str(block.animation.map_or("", Builtin::name))?;
+        }
+        // version 6
+        let night = &self.night;
+        w.u8(night.enabled as u8)?;
+        w.u16(night.start)?;
+        w.u16(night.wake)?;
+        w.u8(night.level)?;
+        w.color(night.wake_color)?;
+        w.u16(night.wake_minutes)?;
+        // one flag and level per block, in the order of version 5
+        for block in &self.schedule.blocks {
+            w.u8(block.level.is_some() as u8)?;
+            w.u8(block.level.unwrap_or(0))?;
+        }
+        // version 7
+        let mqtt = &self.mqtt;
+        w.u8(mqtt.enabled as u8)?;
+        w.str(&mqtt.host)?;
+        w.u16(mqtt.port)?;
+        w.str(&mqtt.username)?;
+        w.str(&mqtt.password)?;
+        w.u8(mqtt.discovery as u8)?;
+        // version 8
+        w.str(&self.device_name)?;
+        // version 9
+        let ntp = &self.ntp;
+        w.u8(ntp.servers.len() as u8)?;
+        for server in &ntp.servers {
+            w.str(server)?;
+        }
+        w.u8(ntp.dhcp as u8)?;
+        w.u32(ntp.interval)?;
+        w.u16(ntp.retry)?;
+        // version 10
+        w.str(&ntp.http)?;
+        // version 11, 0 without a saved time
+        let last_time = self.last_time.as_ref();
+        w.u32(last_time.map_or(0, |last| last.time.and_utc().timestamp() as u32))?;
+        w.u8(last_time.is_some_and(|last| last.known) as u8)?;
+        // version 12
+        w.str(&self.api_token)?;
+        Some(w.pos)
+    }
+
+    /// Decode a payload written with schema `version`.
+    pub fn decode(version: u16, payload: &[u8]) -> Option<Self> {
+        let mut r = Reader { data: payload };
+        let mut settings = Self::default();
+        if version < 1 {
+            return None;
+        }
+        settings.wifi = match r.u8()? {
+            0 => None,
+            _ => {
+                let ssid = r.str::<MAX_SSID_LEN>()?;
+                let password = r.str::<MAX_PASSWORD_LEN>()?;
+                Some(Credentials::new(&ssid, &password)?)
+            }
+        };
+        settings.time_zone = r.str()?;
+        settings.brightness = r.u8()?;
+        settings.hour_color = r.color()?;
+        settings.minute_color = r.color()?;
+        settings.second_color = r.color()?;
+        if version < 2 {
+            return Some(settings);
+        }
+        // unknown to this firmware, keep the default
+        settings.animation = Builtin::from_name(&r.str::<16>()?).unwrap_or_default();
+        if version < 3 {
+            return Some(settings);
+        }
+        settings.max_current_ma = r.u16()?;
+        if version < 4 {
+            return Some(settings);
+        }
+        settings.led_offset = r.u16()?;
+        settings.led_reversed = r.u8()? != 0;
+        if version < 5 {
+            return Some(settings);
+        }
+        let count = r.u8()? as usize;
+        // invalid blocks and ones beyond MAX_BLOCKS are read and skipped but
+        // still take their brightness later
+        let mut valid = [false; MAX_BLOCKS];
+        for index in 0..count {
+            let block = Block {
+                name: r.str::<MAX_NAME_LEN>()?,
+                days: Days(r.u8()?),
+                start: r.u16()?,
+                end: r.u16()?,
+                color: r.color()?,
+                // an animation unknown to this firmware is left out
+                animation: Builtin::from_name(&r.str::<16>()?),
+                level: None,
+            };
+            if let Some(valid) = valid.get_mut(index) {
+                *valid = block.start < MINUTES_PER_DAY
+                    && block.end < MINUTES_PER_DAY
+                    && settings.schedule.blocks.push(block).is_ok();
+            }
+        }
+        if version < 6 {
+            return Some(settings);
+        }
+        let night = NightMode {
+            enabled: r.u8()? != 0,
+            start: r.u16()?,
+            wake: r.u16()?,
+            level: r.u8()?,
+            wake_color: r.color()?,
+            wake_minutes: r.u16()?,
+        };
+        if night.start < MINUTES_PER_DAY && night.wake < MINUTES_PER_DAY {
+            settings.night = night;
+        }
+        let mut blocks = settings.schedule.blocks.iter_mut();
+        for index in 0..count {
+            let level = (r.u8()? != 0).then_some(r.u8()?);
+            if !valid.get(index).copied().unwrap_or(false) {
+                continue;
+            }
+            if let Some(block) = blocks.next() {
+                block.level = level;
+            }
+        }
+        if version < 7 {
+            return Some(settings);
+        }
+        settings.mqtt = Broker {
+            enabled: r.u8()? != 0,
+            host: r.str()?,
+            port: r.u16()?,
+            username: r.str()?,
+            password: r.str()?,
+            discovery: r.u8()? != 0,
+        };
+        if version < 8 {
+            return Some(settings);
+        }
+        let device_name = r.str()?;
+        if mdns::is_valid_name(&device_name) {
+            settings.device_name = device_name;
+        }
+        if version < 9 {
+            return Some(settings);
+        }
+        let ntp = &mut settings.ntp;
+        ntp.servers.clear();
+        for _ in 0..r.u8()? {
+            let server = r.str::<MAX_NTP_HOST_LEN>()?;
+            if ntp::is_valid_host(&server) {
+                // more servers than this firmware keeps are dropped
+                let _ = ntp.servers.push(server);
+            }
+        }
+        ntp.dhcp = r.u8()? != 0;
+        let interval = r.u32()?;
+        if (ntp::MIN_INTERVAL..=ntp::MAX_INTERVAL).contains(&interval) {
+            ntp.interval = interval;
+        }
+        let retry = r.u16()?;
+        if retry >= ntp::MIN_RETRY {
+            ntp.retry = retry;
+        }
+        if version < 10 {
+            return Some(settings);
+        }
+        let http = r.str()?;
+        if http.is_empty() || Url::parse(&http).is_some() {
+            ntp.http = http;
+        }
+        if version < 11 {
+            return Some(settings);
+        }
+        let time = r.u32()?;
+        let known = r.u8()? != 0;
+        settings.last_time = DateTime::from_timestamp(time as i64, 0)
+            .filter(|_| time != 0)
+            .map(|time| LastTime {
+                time: time.naive_utc(),
+                known,
+            });
+        if version < 12 {
+            return Some(settings);
+        }
+        let token = r.str()?;
+        if token.is_empty() || api::is_valid_token(&token) {
+            settings.api_token = token;
+        }
+        Some(settings)
+    }
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Option<()> {
+        self.out
+            .get_mut(self.pos..self.pos + bytes.len())?
+            .copy_from_slice(bytes);
+        self.pos += bytes.len();
+        Some(())
+    }
+
+    fn u8(&mut self, value: u8) -> Option<()> {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> Option<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> Option<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn str(&mut self, value: &str) -> Option<()> {
+        self.u8(u8::try_from(value.len()).ok()?)?;
+        self.bytes(value.as_bytes())
+    }
+
+    fn color(&mut self, color: RGB8) -> Option<()> {
+        self.bytes(&[color.r, color.g, color.b])
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let b = self.bytes(2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let b = self.bytes(4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn str<const N: usize>(&mut self) -> Option<String<N>> {
+        let len = self.u8()? as usize;
+        let bytes = self.bytes(len)?;
+        String::try_from(core::str::from_utf8(bytes).ok()?).ok()
+    }
+
+    fn color(&mut self) -> Option<RGB8> {
+        let c = self.bytes(3)?;
+        Some(RGB8::new(c[0], c[1], c[2]))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    Flash(E),
+    /// The encoded settings exceed [`MAX_PAYLOAD_LEN`].
+    TooLarge,
+    /// The region is not sector aligned or has less than two sectors.
+    InvalidRegion,
+    /// There is no store to save to, opening it failed.
+    Unavailable,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Latest {
+    offset: u32,
+    version: u16,
+    len: u16,
+}
+
+/// Settings store on a region of NOR flash.
+pub struct Store<F> {
+    flash: F,
+    base: u32,
+    sectors: u32,
+    sequence: u32,
+    latest: Option<Latest>,
+    /// Sector receiving the next record.
+    sector: u32,
+    /// Absolute offset of the next record.
+    write_offset: u32,
+}
+
+impl<F: NorFlash> Store<F> {
+    const SECTOR_SIZE: u32 = F::ERASE_SIZE as u32;
+
+    /// Open the store on `size` bytes of `flash` starting at `base` and look
+    /// for the newest record.
+    pub fn open(flash: F, base: u32, size: u32) -> Result<Self, Error<F::Error>> {
+        if !base.is_multiple_of(Self::SECTOR_SIZE)
+            || !size.is_multiple_of(Self::SECTOR_SIZE)
+            || size < 2 * Self::SECTOR_SIZE
+        {
+            return Err(Error::InvalidRegion);
+        }
+        let mut store = Self {
+            flash,
+            base,
+            sectors: size / Self::SECTOR_SIZE,
+            sequence: 0,
+            latest: None,
+            sector: 0,
+            write_offset: base,
+        };
+        store.scan()?;
+        Ok(store)
+    }
+
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    fn record_len(payload_len: usize) -> u32 {
+        let align = F::WRITE_SIZE.max(4);
+        (HEADER_LEN + payload_len).div_ceil(align) as u32 * align as u32
+    }
+
+    fn scan(&mut self) -> Result<(), Error<F::Error>> {
+        let mut record = [0u8; MAX_RECORD_LEN];
+        for sector in 0..self.sectors {
+            let start = self.base + sector * Self::SECTOR_SIZE;
+            let end = start + Self::SECTOR_SIZE;
+            let mut pos = start;
+            let mut newest_here = false;
+            while pos + HEADER_LEN as u32 <= end {
+                let header = &mut record[..HEADER_LEN];
+                self.flash.read(pos, header).map_err(Error::Flash)?;
+                if header.iter().all(|&b| b == 0xff) {
+                    break;
+                }
+                let version = u16::from_le_bytes([header[4], header[5]]);
+                let len = u16::from_le_bytes([header[6], header[7]]);
+                let sequence = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+                let crc = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+                let record_len = Self::record_len(len as usize);
+                if header[..4] != MAGIC || len as usize > MAX_PAYLOAD_LEN || pos + record_len > end
+                {
+                    // not ours, do not write into this sector any more
+                    pos = end;
+                    break;
+                }
+                let payload = &mut record[HEADER_LEN..HEADER_LEN + len as usize];
+                self.flash
+                    .read(pos + HEADER_LEN as u32, payload)
+                    .map_err(Error::Flash)?;
+                let valid = record_checksum(
+                    &record[..12],
+                    &record[HEADER_LEN..HEADER_LEN + len as usize],
+                ) == crc;
+                if valid && (self.latest.is_none() || sequence > self.sequence) {
+                    self.sequence = sequence;
+                    self.latest = Some(Latest {
+                        offset: pos,
+                        version,
+                        len,
+                    });
+                    newest_here = true;
+                }
+                // a torn record still takes up its space
+                pos += record_len;
+            }
+            if newest_here {
+                self.sector = sector;
+                self.write_offset = pos;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_payload<'b>(
+        &mut self,
+        latest: Latest,
+        buf: &'b mut [u8; MAX_PAYLOAD_LEN],
+    ) -> Result<&'b [u8], Error<F::Error>> {
+        let payload = &mut buf[..latest.len as usize];
+        self.flash
+            .read(latest.offset + HEADER_LEN as u32, payload)
+            .map_err(Error::Flash)?;
+        Ok(payload)
+    }
+
+    /// The stored settings.
+    ///
+    /// Without any record this falls back to the Wi-Fi credentials record
+    /// written by earlier firmware at the start of the region, and to the
+    /// defaults after that. A migrated legacy record is saved right away.
+    pub fn load(&mut self) -> Result<Settings, Error<F::Error>> {
+        let mut buf = [0u8; MAX_PAYLOAD_LEN];
+        if let Some(latest) = self.latest {
+            let payload = self.read_payload(latest, &mut buf)?;
+            // the checksum matched, so this only fails for broken firmware
+            return Ok(Settings::decode(latest.version, payload).unwrap_or_default());
+        }
+        let legacy = &mut buf[..Credentials::ENCODED_LEN];
+        self.flash.read(self.base, legacy).map_err(Error::Flash)?;
+        match Credentials::decode(legacy) {
+            Some(wifi) => {
+                let settings = Settings {
+                    wifi: Some(wifi),
+                    ..Default::default()
+                };
+                self.save(&settings)?;
+                Ok(settings)
+            }
+            None => Ok(Settings::default()),
+        }
+    }
+
+    /// Append `settings` as a new record, unless they did not change.
+    pub fn save(&mut self, settings: &Settings) -> Result<(), Error<F::Error>> {
+        let mut record = [0xffu8; MAX_RECORD_LEN];
+        let len = settings
+            .encode(&mut record[HEADER_LEN..])
+            .ok_or(Error::TooLarge)?;
+
+        if let Some(latest) = self.latest {
+            let mut stored = [0u8; MAX_PAYLOAD_LEN];
+            let stored = self.read_payload(latest, &mut stored)?;
+            if latest.version == SCHEMA_VERSION && stored == &record[HEADER_LEN..HEADER_LEN + len] {
+                return Ok(());
+            }
+        }
+
+        let record_len = Self::record_len(len);
+        let sector_end = self.base + (self.sector + 1) * Self::SECTOR_SIZE;
+        if self.latest.is_none() || self.write_offset + record_len > sector_end {
+            if self.latest.is_some() {
+                self.sector = (self.sector + 1) % self.sectors;
+            }
+            let start = self.base + self.sector * Self::SECTOR_SIZE;
+            self.flash
+                .erase(start, start + Self::SECTOR_SIZE)
+                .map_err(Error::Flash)?;
+            self.write_offset = start;
+        }
+
+        let sequence = self.sequence.wrapping_add(1);
+        record[..4].copy_from_slice(&MAGIC);
+        record[4..6].copy_from_slice(&SCHEMA_VERSION.to_le_bytes());
+        record[6..8].copy_from_slice(&(len as u16).to_le_bytes());
+        record[8..12].copy_from_slice(&sequence.to_le_bytes());
+        let crc = record_checksum(&record[..12], &record[HEADER_LEN..HEADER_LEN + len]);
+        record[12..16].copy_from_slice(&crc.to_le_bytes());
+        self.flash
+            .write(self.write_offset, &record[..record_len as usize])
+            .map_err(Error::Flash)?;
+
+        self.sequence = sequence;
+        self.latest = Some(Latest {
+            offset: self.write_offset,
+            version: SCHEMA_VERSION,
+            len: len as u16,
+        });
+        self.write_offset += record_len;
+        Ok(())
+    }
+}
+
+fn record_checksum(header: &[u8], payload: &[u8]) -> u32 {
+    crc32_update(crc32_update(0, header), payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash::{MemFlash, SECTOR_SIZE};
+
+    const SECTORS: usize = 3;
+    type Flash = MemFlash<{ SECTORS * SECTOR_SIZE }>;
+
+    fn open(flash: Flash) -> Store<Flash> {
+        Store::open(flash, 0, (SECTORS * SECTOR_SIZE) as u32).unwrap()
+    }
+
+    fn with_brightness(brightness: u8) -> Settings {
+        Settings {
+            brightness,
+            ..Default::default()
+        }
+    }
+
+    /// Length of the default settings as a record.
+    fn record_len() -> usize {
+        let mut payload = [0; MAX_PAYLOAD_LEN];
+        let len = Settings::default().encode(&mut payload).unwrap();
+        (HEADER_LEN + len).div_ceil(4) * 4
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut store = open(Flash::new());
+        assert_eq!(store.load().unwrap(), Settings::default());
+        let settings = Settings {
+            wifi: Credentials::new("home", "secret12"),
+            brightness: 64,
+            led_offset: 30,
+            last_time: Some(LastTime {
+                time: DateTime::from_timestamp(1_792_245_600, 0)
+                    .unwrap()
+                    .naive_utc(),
+                known: true,
+            }),
+            ..Default::default()
+        };
+        store.save(&settings).unwrap();
+        let mut store = open(store.into_inner());
+        assert_eq!(store.load().unwrap(), settings);
+
+        // saving the same settings again writes nothing
+        let writes = store.flash.writes;
+        store.save(&settings).unwrap();
+        assert_eq!(store.flash.writes, writes);
+    }
+
+    #[test]
+    fn regions() {
+        for (base, size) in [
+            (0, SECTOR_SIZE),
+            (1, 2 * SECTOR_SIZE),
+            (0, 2 * SECTOR_SIZE + 1),
+        ] {
+            let store = Store::open(Flash::new(), base as u32, size as u32);
+            assert!(matches!(store, Err(Error::InvalidRegion)));
+        }
+    }
+
+    #[test]
+    fn bad_checksum_falls_back() {
+        let mut store = open(Flash::new());
+        store.save(&with_brightness(1)).unwrap();
+        store.save(&with_brightness(2)).unwrap();
+        let mut flash = store.into_inner();
+        // a bit of the second record's payload cleared
+        flash.data_mut()[record_len() + HEADER_LEN + 3] &= 0xfe;
+        let mut store = open(flash);
+        assert_eq!(store.load().unwrap(), with_brightness(1));
+
+        // the next record goes behind the broken one and wins
+        store.save(&with_brightness(3)).unwrap();
+        assert_eq!(open(store.into_inner()).load().unwrap(), with_brightness(3));
+    }
+
+    #[test]
+    fn torn_record_falls_back() {
+        let mut store = open(Flash::new());
+        store.save(&with_brightness(1)).unwrap();
+        store.save(&with_brightness(2)).unwrap();
+        let mut flash = store.into_inner();
+        // the power went while writing the end of the second record
+        let end = 2 * record_len();
+        flash.data_mut()[end - 8..end].fill(0xff);
+        assert_eq!(open(flash).load().unwrap(), with_brightness(1));
+    }
+
+    #[test]
+    fn sectors_roll_over() {
+        let per_sector = SECTOR_SIZE / record_len();
+        let mut store = open(Flash::new());
+        let mut saves = 0;
+        // three times round all sectors
+        for brightness in (0..=255).cycle().take(3 * SECTORS * per_sector) {
+            store.save(&with_brightness(brightness)).unwrap();
+            saves += 1;
+            if saves % 40 == 0 {
+                store = open(store.into_inner());
+                assert_eq!(store.load().unwrap(), with_brightness(brightness));
+            }
+        }
+        let flash = store.into_inner();
+        // one erase per sector and round
+        assert_eq!(flash.erases, 3 * SECTORS as u32);
+        assert_eq!(flash.writes, saves);
+        let mut store = open(flash);
+        let last = ((3 * SECTORS * per_sector - 1) % 256) as u8;
+        assert_eq!(store.load().unwrap(), with_brightness(last));
+    }
+
+    #[test]
+    fn legacy_credentials() {
+        let mut flash = Flash::new();
+        let wifi = Credentials::new("home", "secret12").unwrap();
+        let encoded = wifi.encode();
+        flash.data_mut()[..encoded.len()].copy_from_slice(&encoded);
+        let mut store = open(flash);
+        let expected = Settings {
+            wifi: Some(wifi),
+            ..Default::default()
+        };
+        assert_eq!(store.load().unwrap(), expected);
+        // migrated into a record right away
+        assert_eq!(open(store.into_inner()).load().unwrap(), expected);
+    }
+
+    /// The fields each schema version appended, written out by hand.
+    const FIELDS: [&[u8]; SCHEMA_VERSION as usize] = [
+        // 1: Wi-Fi, time zone, brightness and the hand colors
+        b"\x01\x04home\x08secret12\x03UTC\x80\x01\x02\x03\x04\x05\x06\x07\x08\x09",
+        // 2: animation
+        b"\x07rainbow",
+        // 3: current budget, 1500 mA
+        b"\xdc\x05",
+        // 4: LED offset 30, reversed
+        b"\x1e\x00\x01",
+        // 5: one block, school mon-fri 08:00-12:30 in green
+        b"\x01\x06school\x1f\xe0\x01\xee\x02\x00\x30\x00\x00",
+        // 6: night 19:30-07:00 at 8, 30 minutes of wake light, block at 64
+        b"\x01\x92\x04\xa4\x01\x08\x00\x10\x00\x1e\x00\x01\x40",
+        // 7: MQTT broker with discovery
+        b"\x01\x06broker\x5b\x07\x00\x00\x01",
+        // 8: device name
+        b"\x07kitchen",
+        // 9: one NTP server, no DHCP, every 2 hours, retry after 30 s
+        b"\x01\x07ntp.lan\x00\x20\x1c\x00\x00\x1e\x00",
+        // 10: HTTP fallback
+        b"\x10http://time.lan/",
+        // 11: the time saved at 2026-10-17 12:00 UTC, known
+        b"\x40\x63\xd3\x6a\x01",
+        // 12: API token
+        b"\x0dcorrect-horse",
+    ];
+
+    /// The settings [`FIELDS`] up to `version` decode to.
+    fn expected(version: u16) -> Settings {
+        let mut s = Settings {
+            wifi: Credentials::new("home", "secret12"),
+            time_zone: String::try_from("UTC").unwrap(),
+            brightness: 128,
+            hour_color: RGB8::new(1, 2, 3),
+            minute_color: RGB8::new(4, 5, 6),
+            second_color: RGB8::new(7, 8, 9),
+            ..Default::default()
+        };
+        if version >= 2 {
+            s.animation = Builtin::Rainbow;
+        }
+        if version >= 3 {
+            s.max_current_ma = 1500;
+        }
+        if version >= 4 {
+            s.led_offset = 30;
+            s.led_reversed = true;
+        }
+        if version >= 5 {
+            let block = Block::parse("08:00-12:30 mon-fri 003000 school").unwrap();
+            s.schedule.blocks.push(block).unwrap();
+        }
+        if version >= 6 {
+            s.night = NightMode {
+                enabled: true,
+                start: 19 * 60 + 30,
+                wake: 7 * 60,
+                level: 8,
+                wake_color: RGB8::new(0, 16, 0),
+                wake_minutes: 30,
+            };
+            s.schedule.blocks[0].level = Some(64);
+        }
+        if version >= 7 {
+            s.mqtt = Broker {
+                enabled: true,
+                host: String::try_from("broker").unwrap(),
+                port: 1883,
+                username: String::new(),
+                password: String::new(),
+                discovery: true,
+            };
+        }
+        if version >= 8 {
+            s.device_name = String::try_from("kitchen").unwrap();
+        }
+        if version >= 9 {
+            s.ntp.servers.clear();
+            s.ntp
+                .servers
+                .push(String::try_from("ntp.lan").unwrap())
+                .unwrap();
+            s.ntp.dhcp = false;
+            s.ntp.interval = 7200;
+            s.ntp.retry = 30;
+        }
+        if version >= 10 {
+            s.ntp.http = String::try_from("http://time.lan/").unwrap();
+        }
+        if version >= 11 {
+            s.last_time = Some(LastTime {
+                time: DateTime::from_timestamp(1_792_238_400, 0)
+                    .unwrap()
+                    .naive_utc(),
+                known: true,
+            });
+        }
+        if version >= 12 {
+            s.api_token = String::try_from("correct-horse").unwrap();
+        }
+        s
+    }
+
+    fn payload(version: u16, buf: &mut [u8; MAX_PAYLOAD_LEN]) -> usize {
+        let mut len = 0;
+        for field in &FIELDS[..version as usize] {
+            buf[len..len + field.len()].copy_from_slice(field);
+            len += field.len();
+        }
+        len
+    }
+
+    #[test]
+    fn older_schemas() {
+        let mut buf = [0; MAX_PAYLOAD_LEN];
+        for version in 1..=SCHEMA_VERSION {
+            let len = payload(version, &mut buf);
+            let decoded = Settings::decode(version, &buf[..len]);
+            assert_eq!(decoded, Some(expected(version)), "schema {version}");
+            // cut short it is broken
+            assert_eq!(Settings::decode(version, &buf[..len - 1]), None);
+        }
+        // the current schema writes the same bytes
+        let len = payload(SCHEMA_VERSION, &mut buf);
+        let mut out = [0; MAX_PAYLOAD_LEN];
+        let encoded = expected(SCHEMA_VERSION).encode(&mut out).unwrap();
+        assert_eq!(&out[..encoded], &buf[..len]);
+        assert_eq!(Settings::decode(0, &buf[..len]), None);
+    }
+
+    #[test]
+    fn older_schema_in_flash() {
+        let mut buf = [0; MAX_PAYLOAD_LEN];
+        let len = payload(4, &mut buf);
+        let mut flash = Flash::new();
+        let data = flash.data_mut();
+        data[..4].copy_from_slice(&MAGIC);
+        data[4..6].copy_from_slice(&4u16.to_le_bytes());
+        data[6..8].copy_from_slice(&(len as u16).to_le_bytes());
+        data[8..12].copy_from_slice(&7u32.to_le_bytes());
+        let crc = record_checksum(&data[..12], &buf[..len]);
+        data[12..16].copy_from_slice(&crc.to_le_bytes());
+        data[HEADER_LEN..HEADER_LEN + len].copy_from_slice(&buf[..len]);
+        let mut store = open(flash);
+        assert_eq!(store.load().unwrap(), expected(4));
+
+        // saving writes the current schema behind it
+        store.save(&expected(4)).unwrap();
+        let latest = store.latest.unwrap();
+        assert_eq!(latest.version, SCHEMA_VERSION);
+        assert_eq!(store.sequence, 8);
+        assert_eq!(open(store.into_inner()).load().unwrap(), expected(4));
+    }
+
+    #[test]
+    fn blocks_beyond_the_limit() {
+        let mut buf = [0; MAX_PAYLOAD_LEN];
+        let mut len = payload(4, &mut buf);
+        let count = MAX_BLOCKS + 2;
+        let mut push = |bytes: &[u8]| {
+            buf[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+        push(&[count as u8]);
+        for _ in 0..count {
+            push(b"\x06school\x1f\xe0\x01\xee\x02\x00\x30\x00\x00");
+        }
+        push(FIELDS[5].split_at(11).0);
+        for index in 0..count {
+            push(&[1, index as u8]);
+        }
+        let settings = Settings::decode(6, &buf[..len]).unwrap();
+        assert_eq!(settings.night, expected(6).night);
+        assert_eq!(settings.schedule.blocks.len(), MAX_BLOCKS);
+        for (index, block) in settings.schedule.blocks.iter().enumerate() {
+            assert_eq!(block.level, Some(index as u8));
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Countdown timer shown on the ring.
+//!
+//! While a [`Countdown`] runs the ring shows one slot per started minute
+//! left as an arc from twelve o'clock, so a 15 minute timer starts with a
+//! quarter of the ring lit and loses a slot every minute. During the last
+//! minute the arc counts down the seconds instead and pulses. When the time
+//! is up a celebration spins around the ring for [`CELEBRATION`].
+//!
+//! The timer is drawn over the running animation, the clock hands stay on
+//! top of it.
+
+use core::time::Duration;
+
+use rgb::RGB8;
+
+use crate::{
+    animation::frame_index,
+    ring::{blend, hsv2rgb, Frame, Hsv, BLACK, FRAMES_PER_SECOND, RING_SIZE},
+};
+
+/// Longest countdown, one slot per minute.
+pub const MAX: Duration = Duration::from_secs(RING_SIZE as u64 * 60);
+/// How long the celebration plays once the time is up.
+pub const CELEBRATION: Duration = Duration::from_secs(10);
+/// Color of the remaining minutes.
+pub const ARC: RGB8 = RGB8::new(40, 16, 0);
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// A running countdown.
+///
+/// Times are monotonic like the [`Scheduler`](crate::animation::Scheduler)'s.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Countdown {
+    pub duration: Duration,
+    pub started: Duration,
+}
+
+impl Countdown {
+    /// Count down `duration`, at most [`MAX`], from `started`.
+    pub fn new(duration: Duration, started: Duration) -> Self {
+        Self {
+            duration: duration.min(MAX),
+            started,
+        }
+    }
+
+    fn end(&self) -> Duration {
+        self.started + self.duration
+    }
+
+    /// Time left at `elapsed`, zero once the time is up.
+    pub fn remaining(&self, elapsed: Duration) -> Duration {
+        self.end().saturating_sub(elapsed)
+    }
+
+    /// The time is up, the celebration may still be playing.
+    pub fn is_done(&self, elapsed: Duration) -> bool {
+        elapsed >= self.end()
+    }
+
+    /// The celebration is over as well, nothing is shown any more.
+    pub fn is_over(&self, elapsed: Duration) -> bool {
+        elapsed >= self.end() + CELEBRATION
+    }
+
+    /// Add `by` to the time left, which stays at most [`MAX`].
+    pub fn extend(&mut self, by: Duration, elapsed: Duration) {
+        let remaining = (self.remaining(elapsed) + by).min(MAX);
+        self.duration = elapsed.saturating_sub(self.started) + remaining;
+    }
+
+    /// Draw the timer at `elapsed` over `frame`.
+    pub fn render(&self, elapsed: Duration, frame: &mut Frame) {
+        if !self.is_done(elapsed) {
+            let remaining = self.remaining(elapsed);
+            if remaining > MINUTE {
+                let minutes = remaining.as_nanos().div_ceil(MINUTE.as_nanos()) as usize;
+                frame[..minutes.min(RING_SIZE)].fill(ARC);
+            } else {
+                let seconds = remaining.as_nanos().div_ceil(1_000_000_000) as usize;
+                // brightest at the start of every second, fading towards
+                // half brightness and back
+                let phase = frame_index(elapsed);
+                let half = FRAMES_PER_SECOND / 2;
+                let amount = 128 + (phase.abs_diff(half) * 127 / half) as u8;
+                frame[..seconds.min(RING_SIZE)].fill(blend(BLACK, ARC, amount));
+            }
+        } else if !self.is_over(elapsed) {
+            celebrate(elapsed - self.end(), frame);
+        }
+    }
+}
+
+/// A quickly turning rainbow, flashing twice a second.
+fn celebrate(since: Duration, frame: &mut Frame) {
+    let millis = since.as_millis() as usize;
+    if millis % 500 >= 400 {
+        frame.fill(BLACK);
+        return;
+    }
+    let turn = millis * 256 / 1000;
+    for (index, pixel) in frame.iter_mut().enumerate() {
+        *pixel = hsv2rgb(Hsv {
+            hue: ((index * 256 / RING_SIZE + turn) % 256) as u8,
+            sat: 255,
+            val: 48,
+        });
+    }
+}
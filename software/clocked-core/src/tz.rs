@@ -0,0 +1,575 @@
+//! Time zones described by POSIX TZ strings.
+//!
+//! There is no room for the IANA database on the clock, but a single POSIX
+//! rule such as `CET-1CEST,M3.5.0,M10.5.0/3` describes the current offsets and
+//! daylight saving transitions of a zone completely:
+//!
+//! ```text
+//! std offset [dst [offset] [,start[/time],end[/time]]]
+//! ```
+//!
+//! Note that POSIX offsets count westwards, `CET-1` is one hour *ahead* of
+//! UTC. Internally all offsets are stored as seconds east of UTC.
+
+use chrono::{Datelike, LocalResult, NaiveDate, NaiveDateTime, TimeDelta};
+use heapless::String;
+
+/// Maximum length of a zone abbreviation.
+pub const MAX_NAME_LEN: usize = 15;
+
+/// Default transition time of a rule, 02:00 local time.
+const DEFAULT_RULE_TIME: i32 = 2 * 3600;
+
+/// Rules used when a TZ string names a DST zone but gives no transitions
+/// (the US rules, same as glibc).
+const DEFAULT_START: Rule = Rule::MonthWeekDay {
+    month: 3,
+    week: 2,
+    weekday: 0,
+};
+const DEFAULT_END: Rule = Rule::MonthWeekDay {
+    month: 11,
+    week: 1,
+    weekday: 0,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Missing or malformed zone abbreviation.
+    Name,
+    /// Missing or out of range UTC offset.
+    Offset,
+    /// Malformed transition rule or rule time.
+    Rule,
+    /// Unexpected characters after a complete TZ string.
+    Trailing,
+}
+
+/// Day of the year a transition happens on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// `Jn`: day 1 to 365, February 29 is never counted.
+    Julian1(u16),
+    /// `n`: day 0 to 365, February 29 is counted in leap years.
+    Julian0(u16),
+    /// `Mm.w.d`: day `d` (0 is Sunday) of week `w` (5 is the last) of month `m`.
+    MonthWeekDay { month: u8, week: u8, weekday: u8 },
+}
+
+impl Rule {
+    fn date(self, year: i32) -> NaiveDate {
+        match self {
+            Rule::Julian1(day) => {
+                let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+                let ordinal = if leap && day >= 60 { day + 1 } else { day };
+                NaiveDate::from_yo_opt(year, ordinal as u32).expect("validated when parsing")
+            }
+            Rule::Julian0(day) => NaiveDate::from_yo_opt(year, day as u32 + 1)
+                // day 365 only exists in leap years, use Dec 31 otherwise
+                .unwrap_or_else(|| NaiveDate::from_ymd_opt(year, 12, 31).unwrap()),
+            Rule::MonthWeekDay {
+                month,
+                week,
+                weekday,
+            } => {
+                let first =
+                    NaiveDate::from_ymd_opt(year, month as u32, 1).expect("validated when parsing");
+                let first_weekday = first.weekday().num_days_from_sunday();
+                let mut day = 1 + (7 + weekday as u32 - first_weekday) % 7 + (week as u32 - 1) * 7;
+                // week 5 means the last one, which might only be the 4th
+                while NaiveDate::from_ymd_opt(year, month as u32, day).is_none() {
+                    day -= 7;
+                }
+                NaiveDate::from_ymd_opt(year, month as u32, day).unwrap()
+            }
+        }
+    }
+}
+
+/// A transition rule together with its local time of day in seconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub rule: Rule,
+    pub time: i32,
+}
+
+impl Transition {
+    /// Local wall time of the transition in `year`.
+    fn local(self, year: i32) -> NaiveDateTime {
+        self.rule.date(year).and_hms_opt(0, 0, 0).unwrap() + TimeDelta::seconds(self.time as i64)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DaylightSaving {
+    pub name: String<MAX_NAME_LEN>,
+    /// Offset in seconds east of UTC while DST is in effect.
+    pub offset: i32,
+    /// Start of DST, in local standard time.
+    pub start: Transition,
+    /// End of DST, in local daylight time.
+    pub end: Transition,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeZone {
+    pub name: String<MAX_NAME_LEN>,
+    /// Standard offset in seconds east of UTC.
+    pub offset: i32,
+    pub dst: Option<DaylightSaving>,
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        Self::utc()
+    }
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        Self {
+            name: String::try_from("UTC").unwrap(),
+            offset: 0,
+            dst: None,
+        }
+    }
+
+    /// Parse a POSIX TZ string like `CET-1CEST,M3.5.0,M10.5.0/3`.
+    pub fn parse(tz: &str) -> Result<Self, ParseError> {
+        let mut p = Parser {
+            rest: tz.as_bytes(),
+        };
+        let name = p.name()?;
+        let offset = -p.offset(24)?;
+        let dst = if p.rest.is_empty() {
+            None
+        } else {
+            let dst_name = p.name()?;
+            let dst_offset = match p.peek() {
+                Some(b',') | None => offset + 3600,
+                Some(_) => -p.offset(24)?,
+            };
+            let (start, end) = if p.eat(b',') {
+                let start = p.transition()?;
+                if !p.eat(b',') {
+                    return Err(ParseError::Rule);
+                }
+                (start, p.transition()?)
+            } else {
+                (
+                    Transition {
+                        rule: DEFAULT_START,
+                        time: DEFAULT_RULE_TIME,
+                    },
+                    Transition {
+                        rule: DEFAULT_END,
+                        time: DEFAULT_RULE_TIME,
+                    },
+                )
+            };
+            Some(DaylightSaving {
+                name: dst_name,
+                offset: dst_offset,
+                start,
+                end,
+            })
+        };
+        if !p.rest.is_empty() {
+            return Err(ParseError::Trailing);
+        }
+        Ok(Self { name, offset, dst })
+    }
+
+    /// Whether daylight saving time is in effect at the given UTC time.
+    pub fn is_dst(&self, utc: NaiveDateTime) -> bool {
+        let Some(dst) = &self.dst else {
+            return false;
+        };
+        // transitions are defined per local year, the standard time year
+        // is close enough as both transitions are checked
+        let year = (utc + TimeDelta::seconds(self.offset as i64)).year();
+        let start = dst.start.local(year) - TimeDelta::seconds(self.offset as i64);
+        let end = dst.end.local(year) - TimeDelta::seconds(dst.offset as i64);
+        if start < end {
+            // northern hemisphere, DST in the middle of the year
+            start <= utc && utc < end
+        } else {
+            // southern hemisphere, DST around new year
+            utc < end || start <= utc
+        }
+    }
+
+    /// Offset in seconds east of UTC at the given UTC time.
+    pub fn offset_at(&self, utc: NaiveDateTime) -> i32 {
+        match &self.dst {
+            Some(dst) if self.is_dst(utc) => dst.offset,
+            _ => self.offset,
+        }
+    }
+
+    /// Abbreviation in effect at the given UTC time, e.g. `CEST`.
+    pub fn name_at(&self, utc: NaiveDateTime) -> &str {
+        match &self.dst {
+            Some(dst) if self.is_dst(utc) => &dst.name,
+            _ => &self.name,
+        }
+    }
+
+    /// Convert UTC to local wall time.
+    pub fn to_local(&self, utc: NaiveDateTime) -> NaiveDateTime {
+        utc + TimeDelta::seconds(self.offset_at(utc) as i64)
+    }
+
+    /// Convert local wall time to UTC.
+    ///
+    /// When the clocks go back the hour repeats and both candidates are
+    /// returned as [`LocalResult::Ambiguous`], earlier one first. Local times
+    /// inside the hour skipped when the clocks go forward give
+    /// [`LocalResult::None`].
+    pub fn from_local(&self, local: NaiveDateTime) -> LocalResult<NaiveDateTime> {
+        let candidate = |offset: i32| {
+            let utc = local - TimeDelta::seconds(offset as i64);
+            (self.offset_at(utc) == offset).then_some(utc)
+        };
+        let standard = candidate(self.offset);
+        let daylight = self
+            .dst
+            .as_ref()
+            .filter(|dst| dst.offset != self.offset)
+            .and_then(|dst| candidate(dst.offset));
+        match (standard, daylight) {
+            (Some(a), Some(b)) => LocalResult::Ambiguous(a.min(b), a.max(b)),
+            (Some(utc), None) | (None, Some(utc)) => LocalResult::Single(utc),
+            (None, None) => LocalResult::None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.rest.first().copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.rest = &self.rest[1..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'a [u8] {
+        let len = self
+            .rest
+            .iter()
+            .position(|&c| !f(c))
+            .unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        taken
+    }
+
+    /// Abbreviation, either alphabetic or quoted like `<+0330>`.
+    fn name(&mut self) -> Result<String<MAX_NAME_LEN>, ParseError> {
+        let name = if self.eat(b'<') {
+            let name = self.take_while(|c| c != b'>');
+            if !self.eat(b'>') {
+                return Err(ParseError::Name);
+            }
+            name
+        } else {
+            self.take_while(|c| c.is_ascii_alphabetic())
+        };
+        if name.len() < 3 {
+            return Err(ParseError::Name);
+        }
+        core::str::from_utf8(name)
+            .ok()
+            .and_then(|name| String::try_from(name).ok())
+            .ok_or(ParseError::Name)
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        if digits.is_empty() || digits.len() > 3 {
+            return None;
+        }
+        Some(digits.iter().fold(0, |n, &d| n * 10 + (d - b'0') as u32))
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds, hours limited to `max_hours`.
+    fn offset(&mut self, max_hours: u32) -> Result<i32, ParseError> {
+        self.time(max_hours).ok_or(ParseError::Offset)
+    }
+
+    fn time(&mut self, max_hours: u32) -> Option<i32> {
+        let sign = if self.eat(b'-') {
+            -1
+        } else {
+            self.eat(b'+');
+            1
+        };
+        let hours = self.number().filter(|&h| h <= max_hours)?;
+        let mut seconds = hours * 3600;
+        if self.eat(b':') {
+            seconds += self.number().filter(|&m| m < 60)? * 60;
+            if self.eat(b':') {
+                seconds += self.number().filter(|&s| s < 60)?;
+            }
+        }
+        Some(sign * seconds as i32)
+    }
+
+    fn transition(&mut self) -> Result<Transition, ParseError> {
+        let rule = if self.eat(b'J') {
+            match self.number() {
+                Some(day @ 1..=365) => Rule::Julian1(day as u16),
+                _ => return Err(ParseError::Rule),
+            }
+        } else if self.eat(b'M') {
+            let month = self.number().filter(|m| (1..=12).contains(m));
+            let week = self
+                .eat(b'.')
+                .then(|| self.number())
+                .flatten()
+                .filter(|w| (1..=5).contains(w));
+            let weekday = self
+                .eat(b'.')
+                .then(|| self.number())
+                .flatten()
+                .filter(|&d| d <= 6);
+            match (month, week, weekday) {
+                (Some(month), Some(week), Some(weekday)) => Rule::MonthWeekDay {
+                    month: month as u8,
+                    week: week as u8,
+                    weekday: weekday as u8,
+                },
+                _ => return Err(ParseError::Rule),
+            }
+        } else {
+            match self.number() {
+                Some(day @ 0..=365) => Rule::Julian0(day as u16),
+                _ => return Err(ParseError::Rule),
+            }
+        };
+        let time = if self.eat(b'/') {
+            // RFC 8536 allows -167 to 167 hours here
+            self.time(167).ok_or(ParseError::Rule)?
+        } else {
+            DEFAULT_RULE_TIME
+        };
+        Ok(Transition { rule, time })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CET: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+    const SYDNEY: &str = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").unwrap()
+    }
+
+    /// UTC times and the local time and abbreviation they show as.
+    fn check(tz: &str, cases: &[(&str, &str, &str)]) {
+        let tz = TimeZone::parse(tz).unwrap();
+        for (utc, local, name) in cases {
+            assert_eq!(tz.to_local(time(utc)), time(local), "{utc}");
+            assert_eq!(tz.name_at(time(utc)), *name, "{utc}");
+        }
+    }
+
+    #[test]
+    fn cet_transitions() {
+        check(
+            CET,
+            &[
+                ("2026-01-15T12:00:00", "2026-01-15T13:00:00", "CET"),
+                // last Sunday of March, 02:00 CET
+                ("2026-03-29T00:59:59", "2026-03-29T01:59:59", "CET"),
+                ("2026-03-29T01:00:00", "2026-03-29T03:00:00", "CEST"),
+                ("2026-07-15T12:00:00", "2026-07-15T14:00:00", "CEST"),
+                // last Sunday of October, 03:00 CEST
+                ("2026-10-25T00:59:59", "2026-10-25T02:59:59", "CEST"),
+                ("2026-10-25T01:00:00", "2026-10-25T02:00:00", "CET"),
+                // the last Sunday on the 31st
+                ("2027-10-31T00:59:59", "2027-10-31T02:59:59", "CEST"),
+                ("2027-10-31T01:00:00", "2027-10-31T02:00:00", "CET"),
+            ],
+        );
+    }
+
+    #[test]
+    fn repeated_and_skipped_hours() {
+        let tz = TimeZone::parse(CET).unwrap();
+        let from_local = |local| tz.from_local(time(local));
+        // the clocks go back from 03:00 to 02:00
+        assert_eq!(
+            from_local("2026-10-25T01:59:59"),
+            LocalResult::Single(time("2026-10-24T23:59:59"))
+        );
+        for (local, first, second) in [
+            (
+                "2026-10-25T02:00:00",
+                "2026-10-25T00:00:00",
+                "2026-10-25T01:00:00",
+            ),
+            (
+                "2026-10-25T02:30:00",
+                "2026-10-25T00:30:00",
+                "2026-10-25T01:30:00",
+            ),
+            (
+                "2026-10-25T02:59:59",
+                "2026-10-25T00:59:59",
+                "2026-10-25T01:59:59",
+            ),
+        ] {
+            assert_eq!(
+                from_local(local),
+                LocalResult::Ambiguous(time(first), time(second))
+            );
+        }
+        assert_eq!(
+            from_local("2026-10-25T03:00:00"),
+            LocalResult::Single(time("2026-10-25T02:00:00"))
+        );
+        // and forward from 02:00 to 03:00
+        assert_eq!(
+            from_local("2026-03-29T01:59:59"),
+            LocalResult::Single(time("2026-03-29T00:59:59"))
+        );
+        assert_eq!(from_local("2026-03-29T02:00:00"), LocalResult::None);
+        assert_eq!(from_local("2026-03-29T02:59:59"), LocalResult::None);
+        assert_eq!(
+            from_local("2026-03-29T03:00:00"),
+            LocalResult::Single(time("2026-03-29T01:00:00"))
+        );
+    }
+
+    #[test]
+    fn southern_hemisphere() {
+        check(
+            SYDNEY,
+            &[
+                ("2026-01-15T00:00:00", "2026-01-15T11:00:00", "AEDT"),
+                // first Sunday of April, 03:00 AEDT
+                ("2026-04-04T15:59:59", "2026-04-05T02:59:59", "AEDT"),
+                ("2026-04-04T16:00:00", "2026-04-05T02:00:00", "AEST"),
+                ("2026-07-15T00:00:00", "2026-07-15T10:00:00", "AEST"),
+                // first Sunday of October, 02:00 AEST
+                ("2026-10-03T15:59:59", "2026-10-04T01:59:59", "AEST"),
+                ("2026-10-03T16:00:00", "2026-10-04T03:00:00", "AEDT"),
+                // DST goes on over the new year
+                ("2026-12-31T12:59:59", "2026-12-31T23:59:59", "AEDT"),
+                ("2026-12-31T13:00:00", "2027-01-01T00:00:00", "AEDT"),
+            ],
+        );
+    }
+
+    #[test]
+    fn default_rules() {
+        // without rules the US ones apply, second Sunday of March to the
+        // first of November at 02:00
+        check(
+            "EST5EDT",
+            &[
+                ("2026-03-08T06:59:59", "2026-03-08T01:59:59", "EST"),
+                ("2026-03-08T07:00:00", "2026-03-08T03:00:00", "EDT"),
+                ("2026-11-01T05:59:59", "2026-11-01T01:59:59", "EDT"),
+                ("2026-11-01T06:00:00", "2026-11-01T01:00:00", "EST"),
+            ],
+        );
+        check(
+            "<+0330>-3:30",
+            &[("2026-07-15T12:00:00", "2026-07-15T15:30:00", "+0330")],
+        );
+        check(
+            "UTC0",
+            &[("2026-07-15T12:00:00", "2026-07-15T12:00:00", "UTC")],
+        );
+    }
+
+    #[test]
+    fn julian_days() {
+        let date = |rule: Rule, year| {
+            let date = rule.date(year);
+            (date.month(), date.day())
+        };
+        // Jn never counts February 29, day 60 is always March 1
+        assert_eq!(date(Rule::Julian1(59), 2028), (2, 28));
+        assert_eq!(date(Rule::Julian1(60), 2027), (3, 1));
+        assert_eq!(date(Rule::Julian1(60), 2028), (3, 1));
+        assert_eq!(date(Rule::Julian1(365), 2028), (12, 31));
+        // n counts from 0 and includes February 29
+        assert_eq!(date(Rule::Julian0(0), 2027), (1, 1));
+        assert_eq!(date(Rule::Julian0(59), 2027), (3, 1));
+        assert_eq!(date(Rule::Julian0(59), 2028), (2, 29));
+        assert_eq!(date(Rule::Julian0(365), 2027), (12, 31));
+        assert_eq!(date(Rule::Julian0(365), 2028), (12, 31));
+
+        let tz = TimeZone::parse("XST-2XDT-3,J60/1:30,300").unwrap();
+        let dst = tz.dst.as_ref().unwrap();
+        assert_eq!(dst.offset, 3 * 3600);
+        assert_eq!(
+            dst.start,
+            Transition {
+                rule: Rule::Julian1(60),
+                time: 5400,
+            }
+        );
+        assert_eq!(
+            dst.end,
+            Transition {
+                rule: Rule::Julian0(300),
+                time: DEFAULT_RULE_TIME,
+            }
+        );
+        check(
+            "XST-2XDT-3,J60/1:30,300",
+            &[
+                // March 1 at 01:30 XST
+                ("2028-02-29T23:29:59", "2028-03-01T01:29:59", "XST"),
+                ("2028-02-29T23:30:00", "2028-03-01T02:30:00", "XDT"),
+                // day 300 is October 27 in leap years, at 02:00 XDT
+                ("2028-10-26T22:59:59", "2028-10-27T01:59:59", "XDT"),
+                ("2028-10-26T23:00:00", "2028-10-27T01:00:00", "XST"),
+            ],
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for (tz, error) in [
+            ("", ParseError::Name),
+            ("C1", ParseError::Name),
+            ("<+0330-3:30", ParseError::Name),
+            ("CET", ParseError::Offset),
+            ("CET-25", ParseError::Offset),
+            ("CET-1:60", ParseError::Offset),
+            ("CET-1000", ParseError::Offset),
+            ("CET-1C", ParseError::Name),
+            ("CET-1CEST,M3.5.0", ParseError::Rule),
+            ("CET-1CEST,M13.5.0,M10.5.0", ParseError::Rule),
+            ("CET-1CEST,M3.6.0,M10.5.0", ParseError::Rule),
+            ("CET-1CEST,M3.5.7,M10.5.0", ParseError::Rule),
+            ("CET-1CEST,M3.5,M10.5.0", ParseError::Rule),
+            ("CET-1CEST,J0,J365", ParseError::Rule),
+            ("CET-1CEST,366,0", ParseError::Rule),
+            ("CET-1CEST,M3.5.0,M10.5.0/168", ParseError::Rule),
+            ("CET-1 ", ParseError::Name),
+            ("CET-1CEST-2 ", ParseError::Trailing),
+            ("CET-1CEST,M3.5.0,M10.5.0/3x", ParseError::Trailing),
+        ] {
+            assert_eq!(TimeZone::parse(tz), Err(error), "{tz:?}");
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Wi-Fi station credentials and their flash record.
+//!
+//! The record is a fixed size block so it can be written to a flash sector
+//! as is:
+//!
+//! ```text
+//! magic "CLKW" | version | ssid len | ssid [32] | password len | password [64] | crc32
+//! ```
+//!
+//! Erased flash reads as `0xff`, which never matches the magic.
+
+use heapless::String;
+
+use crate::crc::crc32;
+
+pub const MAX_SSID_LEN: usize = 32;
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+const MAGIC: [u8; 4] = *b"CLKW";
+const VERSION: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub ssid: String<MAX_SSID_LEN>,
+    pub password: String<MAX_PASSWORD_LEN>,
+}
+
+impl Credentials {
+    /// Size of an encoded record in bytes.
+    pub const ENCODED_LEN: usize = 4 + 1 + 1 + MAX_SSID_LEN + 1 + MAX_PASSWORD_LEN + 4;
+
+    /// Validated credentials.
+    ///
+    /// The password has to be empty for open networks, a WPA passphrase of 8
+    /// to 63 characters or a raw 64 digit hex key.
+    pub fn new(ssid: &str, password: &str) -> Option<Self> {
+        if ssid.is_empty() {
+            return None;
+        }
+        let valid_password = match password.len() {
+            0 | 8..=63 => true,
+            64 => password.bytes().all(|c| c.is_ascii_hexdigit()),
+            _ => false,
+        };
+        if !valid_password {
+            return None;
+        }
+        Some(Self {
+            ssid: String::try_from(ssid).ok()?,
+            password: String::try_from(password).ok()?,
+        })
+    }
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut record = [0u8; Self::ENCODED_LEN];
+        record[..4].copy_from_slice(&MAGIC);
+        record[4] = VERSION;
+        let mut pos = 5;
+        for (field, len) in [
+            (self.ssid.as_bytes(), MAX_SSID_LEN),
+            (self.password.as_bytes(), MAX_PASSWORD_LEN),
+        ] {
+            record[pos] = field.len() as u8;
+            record[pos + 1..pos + 1 + field.len()].copy_from_slice(field);
+            pos += 1 + len;
+        }
+        let crc = crc32(&record[..pos]);
+        record[pos..].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    /// Decode a record, `None` if it is missing or corrupted.
+    pub fn decode(record: &[u8]) -> Option<Self> {
+        let record = record.get(..Self::ENCODED_LEN)?;
+        let (data, crc) = record.split_at(Self::ENCODED_LEN - 4);
+        if data[..4] != MAGIC || data[4] != VERSION || crc32(data).to_le_bytes() != crc {
+            return None;
+        }
+        let field = |pos: usize, max: usize| {
+            let len = data[pos] as usize;
+            (len <= max)
+                .then(|| core::str::from_utf8(&data[pos + 1..pos + 1 + len]).ok())
+                .flatten()
+        };
+        let ssid = field(5, MAX_SSID_LEN)?;
+        let password = field(5 + 1 + MAX_SSID_LEN, MAX_PASSWORD_LEN)?;
+        Self::new(ssid, password)
+    }
+}
@@ -0,0 +1,418 @@
+//! Canned requests against the REST API.
+//!
+//! Every case sends one request through the firmware's HTTP parser and
+//! [`clocked_core::api::handle`], with a fixed clock state and the default
+//! settings, and the transcripts of all cases are compared with
+//! `golden/api.txt`, see [`crate::transcript`].
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use clocked_core::{
+    api::{self, State},
+    http::Request,
+    ota::{Firmware, Slot},
+    settings::Settings,
+    wifi::Credentials,
+};
+
+use crate::{golden::Mode, transcript};
+
+pub struct Case {
+    pub name: &'static str,
+    pub method: &'static str,
+    pub path: &'static str,
+    pub body: &'static str,
+    /// Value of the `Authorization` header.
+    pub authorization: Option<&'static str>,
+}
+
+const fn case(
+    name: &'static str,
+    method: &'static str,
+    path: &'static str,
+    body: &'static str,
+) -> Case {
+    Case {
+        name,
+        method,
+        path,
+        body,
+        authorization: None,
+    }
+}
+
+/// `case` sent with the header `Authorization: <authorization>`.
+const fn authorized(authorization: &'static str, case: Case) -> Case {
+    Case {
+        authorization: Some(authorization),
+        ..case
+    }
+}
+
+/// Token of the clock [`LOCKED_CASES`] go to.
+const TOKEN: &str = "correct-horse";
+
+pub const CASES: &[Case] = &[
+    case("status", "GET", "/api/status", ""),
+    case("settings", "GET", "/api/settings", ""),
+    case(
+        "settings-put",
+        "PUT",
+        "/api/settings",
+        r##"{"brightness": 96, "hour_color": "#400000", "night": {"enabled": true, "wake": "06:45"},
+            "schedule": ["19:30-07:00 daily 000030 sleep @8", "15:00-17:00 mon-fri 003000 play rainbow"]}"##,
+    ),
+    case(
+        "settings-time-zone",
+        "PUT",
+        "/api/settings",
+        r#"{"time_zone": "GMT0BST,M3.5.0/1,M10.5.0"}"#,
+    ),
+    case(
+        "settings-bad-time-zone",
+        "PUT",
+        "/api/settings",
+        r#"{"time_zone": "Europe/Berlin"}"#,
+    ),
+    case(
+        "settings-unknown",
+        "PUT",
+        "/api/settings",
+        r#"{"volume": 11}"#,
+    ),
+    case(
+        "settings-wifi",
+        "PUT",
+        "/api/settings",
+        r#"{"wifi": {"ssid": "other"}}"#,
+    ),
+    case(
+        "settings-bad-block",
+        "PUT",
+        "/api/settings",
+        r#"{"schedule": ["25:00-07:00 daily 000030 sleep"]}"#,
+    ),
+    case("settings-not-json", "PUT", "/api/settings", "brightness=3"),
+    case(
+        "settings-mqtt",
+        "PUT",
+        "/api/settings",
+        r#"{"mqtt": {"enabled": true, "host": "homeassistant.local", "username": "clock", "password": "secret"}}"#,
+    ),
+    case(
+        "settings-bad-port",
+        "PUT",
+        "/api/settings",
+        r#"{"mqtt": {"port": 0}}"#,
+    ),
+    case(
+        "settings-device-name",
+        "PUT",
+        "/api/settings",
+        r#"{"device_name": "kitchen-clock"}"#,
+    ),
+    case(
+        "settings-bad-device-name",
+        "PUT",
+        "/api/settings",
+        r#"{"device_name": "kitchen clock"}"#,
+    ),
+    case(
+        "settings-ntp",
+        "PUT",
+        "/api/settings",
+        r#"{"ntp": {"servers": ["ntp.school.example", "10.0.0.1"], "dhcp": false, "interval": 7200, "retry": 30}}"#,
+    ),
+    case(
+        "settings-bad-ntp-server",
+        "PUT",
+        "/api/settings",
+        r#"{"ntp": {"servers": ["ntp server"]}}"#,
+    ),
+    case(
+        "settings-too-many-ntp-servers",
+        "PUT",
+        "/api/settings",
+        r#"{"ntp": {"servers": ["a", "b", "c", "d", "e"]}}"#,
+    ),
+    case(
+        "settings-bad-ntp-interval",
+        "PUT",
+        "/api/settings",
+        r#"{"ntp": {"interval": 10}}"#,
+    ),
+    case(
+        "settings-ntp-http",
+        "PUT",
+        "/api/settings",
+        r#"{"ntp": {"http": "http://10.0.0.1:8080/"}}"#,
+    ),
+    case(
+        "settings-bad-ntp-http",
+        "PUT",
+        "/api/settings",
+        r#"{"ntp": {"http": "https://example.com/"}}"#,
+    ),
+    case("animation", "GET", "/api/animation", ""),
+    case(
+        "animation-put",
+        "PUT",
+        "/api/animation",
+        r#"{"animation": "rainbow"}"#,
+    ),
+    case(
+        "animation-unknown",
+        "PUT",
+        "/api/animation",
+        r#"{"animation": "fireworks"}"#,
+    ),
+    case(
+        "animation-other-setting",
+        "PUT",
+        "/api/animation",
+        r#"{"brightness": 10}"#,
+    ),
+    case("brightness", "GET", "/api/brightness", ""),
+    case(
+        "brightness-put",
+        "PUT",
+        "/api/brightness",
+        r#"{"brightness": 40}"#,
+    ),
+    case(
+        "brightness-too-high",
+        "PUT",
+        "/api/brightness",
+        r#"{"brightness": 300}"#,
+    ),
+    case("timer", "GET", "/api/timer", ""),
+    case("timer-minutes", "POST", "/api/timer", r#"{"minutes": 15}"#),
+    case("timer-seconds", "POST", "/api/timer", r#"{"seconds": 90}"#),
+    case(
+        "timer-too-long",
+        "POST",
+        "/api/timer",
+        r#"{"minutes": 120}"#,
+    ),
+    case("timer-zero", "POST", "/api/timer", r#"{"minutes": 0}"#),
+    case(
+        "timer-both",
+        "POST",
+        "/api/timer",
+        r#"{"minutes": 1, "seconds": 1}"#,
+    ),
+    case("timer-stop", "DELETE", "/api/timer", ""),
+    case("firmware", "GET", "/api/firmware", ""),
+    case(
+        "firmware-download",
+        "POST",
+        "/api/firmware",
+        r#"{"url": "http://192.168.1.10:8000/clocked.bin",
+            "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"}"#,
+    ),
+    case(
+        "firmware-https",
+        "POST",
+        "/api/firmware",
+        r#"{"url": "https://example.com/clocked.bin"}"#,
+    ),
+    case(
+        "firmware-bad-digest",
+        "POST",
+        "/api/firmware",
+        r#"{"url": "http://example.com/clocked.bin", "sha256": "9f86d0"}"#,
+    ),
+    case(
+        "settings-api-token",
+        "PUT",
+        "/api/settings",
+        r#"{"api_token": "correct-horse"}"#,
+    ),
+    case(
+        "settings-short-api-token",
+        "PUT",
+        "/api/settings",
+        r#"{"api_token": "horse"}"#,
+    ),
+    case(
+        "settings-api-token-with-space",
+        "PUT",
+        "/api/settings",
+        r#"{"api_token": "correct horse"}"#,
+    ),
+    case("wrong-method", "DELETE", "/api/settings", ""),
+    case("not-found", "GET", "/api/alarm", ""),
+];
+
+/// Requests to a clock with the API token [`TOKEN`].
+pub const LOCKED_CASES: &[Case] = &[
+    case("locked-status", "GET", "/api/status", ""),
+    case("locked-settings", "GET", "/api/settings", ""),
+    case(
+        "locked-brightness",
+        "PUT",
+        "/api/brightness",
+        r#"{"brightness": 40}"#,
+    ),
+    authorized(
+        "Bearer correct-horse",
+        case(
+            "locked-brightness-token",
+            "PUT",
+            "/api/brightness",
+            r#"{"brightness": 40}"#,
+        ),
+    ),
+    authorized(
+        "bearer correct-horse",
+        case(
+            "locked-brightness-lowercase",
+            "PUT",
+            "/api/brightness",
+            r#"{"brightness": 40}"#,
+        ),
+    ),
+    authorized(
+        "Bearer correct-horsf",
+        case(
+            "locked-brightness-wrong-token",
+            "PUT",
+            "/api/brightness",
+            r#"{"brightness": 40}"#,
+        ),
+    ),
+    authorized(
+        "Bearer correct",
+        case(
+            "locked-brightness-short-token",
+            "PUT",
+            "/api/brightness",
+            r#"{"brightness": 40}"#,
+        ),
+    ),
+    authorized(
+        "Basic Y2xvY2s6Y29ycmVjdC1ob3JzZQ==",
+        case(
+            "locked-brightness-basic",
+            "PUT",
+            "/api/brightness",
+            r#"{"brightness": 40}"#,
+        ),
+    ),
+    case(
+        "locked-settings-put",
+        "PUT",
+        "/api/settings",
+        r#"{"mqtt": {"host": "evil.example"}}"#,
+    ),
+    case("locked-timer", "POST", "/api/timer", r#"{"minutes": 15}"#),
+    case("locked-timer-stop", "DELETE", "/api/timer", ""),
+    case(
+        "locked-firmware",
+        "POST",
+        "/api/firmware",
+        r#"{"url": "http://192.168.1.10:8000/clocked.bin"}"#,
+    ),
+    authorized(
+        "Bearer correct-horse",
+        case(
+            "locked-remove-token",
+            "PUT",
+            "/api/settings",
+            r#"{"api_token": ""}"#,
+        ),
+    ),
+];
+
+/// Run all cases, returns `false` if the transcript differs.
+pub fn run(mode: Mode) -> bool {
+    transcript::run(
+        "api",
+        || {
+            let requests = CASES.len() + LOCKED_CASES.len();
+            Ok((transcript()?, format!("{requests} requests")))
+        },
+        mode,
+    )
+}
+
+/// The clock the requests are answered for.
+fn state() -> State<'static> {
+    State {
+        now: NaiveDateTime::parse_from_str("2025-05-04T16:30:05", "%Y-%m-%dT%H:%M:%S").unwrap(),
+        time_zone: "CEST",
+        since_sync: Some(Duration::from_secs(42)),
+        uptime: Duration::from_secs(3600),
+        heap_used: 23_456,
+        heap_free: 107_616,
+        rssi: Some(-61),
+        timer: Some(Duration::from_millis(299_500)),
+        firmware: Firmware {
+            version: "0.1.0",
+            slot: Slot::Ota1,
+            trial: false,
+        },
+    }
+}
+
+fn transcript() -> Result<String, String> {
+    let settings = Settings {
+        wifi: Credentials::new("home", "correct horse"),
+        ..Settings::default()
+    };
+    let mut locked = settings.clone();
+    let _ = locked.api_token.push_str(TOKEN);
+
+    let mut transcript = String::from(
+        "# REST API transcript, see src/api.rs\n\
+         # one request per case, then status, body and the command for the clock\n",
+    );
+    for case in CASES {
+        request(&mut transcript, case, &settings)?;
+    }
+    let _ = writeln!(transcript, "\n# with the API token {TOKEN:?}");
+    for case in LOCKED_CASES {
+        request(&mut transcript, case, &locked)?;
+    }
+    Ok(transcript)
+}
+
+/// Send `case` to a clock with `settings` and add it to `transcript`.
+fn request(transcript: &mut String, case: &Case, settings: &Settings) -> Result<(), String> {
+    let authorization = case
+        .authorization
+        .map(|value| format!("Authorization: {value}\r\n"))
+        .unwrap_or_default();
+    let raw = format!(
+        "{} {} HTTP/1.1\r\nHost: clocked.local\r\n{authorization}Content-Length: {}\r\n\r\n{}",
+        case.method,
+        case.path,
+        case.body.len(),
+        case.body
+    );
+    let request = Request::parse(raw.as_bytes())
+        .map_err(|e| format!("{}: unable to parse request ({e:?})", case.name))?;
+
+    // every case starts from the same settings
+    let mut changed = settings.clone();
+    let mut body = String::new();
+    let response = api::handle(&request, &state(), &mut changed, &mut body);
+
+    let _ = writeln!(transcript, "\n=== {}", case.name);
+    let _ = writeln!(transcript, "> {} {}", case.method, case.path);
+    if let Some(value) = case.authorization {
+        let _ = writeln!(transcript, "> Authorization: {value}");
+    }
+    for line in case.body.lines() {
+        let _ = writeln!(transcript, "> {}", line.trim());
+    }
+    let _ = writeln!(transcript, "< {} {}", response.status.0, response.status.1);
+    let _ = writeln!(transcript, "< {body}");
+    let _ = writeln!(transcript, "command: {:?}", response.command);
+    if changed.api_token != settings.api_token {
+        let _ = writeln!(transcript, "api_token: {:?}", changed.api_token.as_str());
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Virtual clock, the simulator's stand-in for the RTC.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta};
+use clocked_core::tz::TimeZone;
+
+/// UTC time that only moves when told to, so runs are reproducible no
+/// matter how fast they are drawn.
+pub struct VirtualClock {
+    now: NaiveDateTime,
+    tz: TimeZone,
+}
+
+impl VirtualClock {
+    pub fn new(start: NaiveDateTime, tz: TimeZone) -> Self {
+        Self { now: start, tz }
+    }
+
+    /// Current local wall time, like `Clock::now_local` on the clock.
+    pub fn now_local(&self) -> NaiveDateTime {
+        self.tz.to_local(self.now)
+    }
+
+    pub fn tz_name(&self) -> &str {
+        self.tz.name_at(self.now)
+    }
+
+    pub fn advance(&mut self, by: TimeDelta) {
+        self.now += by;
+    }
+}
+
+/// The host's current UTC time, truncated to whole seconds.
+pub fn system_time() -> NaiveDateTime {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+    DateTime::from_timestamp(seconds, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
@@ -0,0 +1,339 @@
+//! Golden frame checks.
+//!
+//! Every case runs an animation on a virtual clock for a fixed number of frames
+//! and compares the result with the stored frames in `golden/<name>.txt`.
+//! `clocked-sim golden check` lists every pixel that differs,
+//! `clocked-sim golden bless` rewrites the files after an intended change.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use clocked_core::{
+    animation::Builtin,
+    ring::brightness,
+    ring::{Frame, FRAMES_PER_SECOND, RING_SIZE},
+    schedule::Block,
+    settings::Settings,
+    tz::TimeZone,
+};
+use rgb::RGB8;
+
+use crate::{clock::VirtualClock, scene::Scene};
+
+/// Differences listed per case before cutting the report short.
+const MAX_REPORTED: usize = 20;
+const OFF: &str = "------";
+
+pub struct Case {
+    pub name: &'static str,
+    /// POSIX TZ string.
+    pub tz: &'static str,
+    /// UTC start time, `YYYY-MM-DDTHH:MM:SS`.
+    pub start: &'static str,
+    pub frames: usize,
+    pub animation: Builtin,
+    /// Frame at which to switch to another animation.
+    pub switch: Option<(usize, Builtin)>,
+    /// Countdown started with the first frame.
+    pub timer: Option<Duration>,
+    /// Schedule blocks in their text form.
+    pub blocks: &'static [&'static str],
+    /// Night mode with its default times and levels.
+    pub night: bool,
+    /// The time is not set, see [`clocked_core::manual`].
+    pub unset: bool,
+}
+
+/// Defaults of the cases, every case sets its name and start time.
+const BASE: Case = Case {
+    name: "",
+    tz: "UTC0",
+    start: "2025-01-01T00:00:00",
+    frames: 2 * FRAMES_PER_SECOND,
+    animation: Builtin::Comet,
+    switch: None,
+    timer: None,
+    blocks: &[],
+    night: false,
+    unset: false,
+};
+
+pub const CASES: &[Case] = &[
+    // comet sweeps while the hour marker moves on at noon
+    Case {
+        name: "noon",
+        start: "2025-01-01T11:59:58",
+        frames: 3 * FRAMES_PER_SECOND,
+        ..BASE
+    },
+    // second marker passes twelve o'clock, the comet tail has to wrap
+    Case {
+        name: "wrap",
+        start: "2025-06-01T08:20:59",
+        ..BASE
+    },
+    // local time jumps from 01:59:59 to 03:00:00
+    Case {
+        name: "dst-start",
+        tz: "CET-1CEST,M3.5.0,M10.5.0/3",
+        start: "2025-03-30T00:59:59",
+        ..BASE
+    },
+    Case {
+        name: "chase",
+        start: "2025-01-01T06:00:00",
+        frames: 3 * FRAMES_PER_SECOND,
+        animation: Builtin::Chase,
+        ..BASE
+    },
+    Case {
+        name: "rainbow",
+        start: "2025-01-01T06:00:00",
+        animation: Builtin::Rainbow,
+        ..BASE
+    },
+    // comet fades out, rainbow fades in over one second
+    Case {
+        name: "crossfade",
+        start: "2025-01-01T18:45:00",
+        frames: 3 * FRAMES_PER_SECOND,
+        switch: Some((FRAMES_PER_SECOND / 2, Builtin::Rainbow)),
+        ..BASE
+    },
+    // arc of two minutes over the comet turns into the pulsing last minute
+    Case {
+        name: "timer",
+        start: "2025-01-01T16:30:00",
+        timer: Some(Duration::from_secs(61)),
+        ..BASE
+    },
+    // last two seconds, then the celebration starts
+    Case {
+        name: "timer-end",
+        start: "2025-01-01T16:44:58",
+        frames: 3 * FRAMES_PER_SECOND,
+        animation: Builtin::Off,
+        timer: Some(Duration::from_secs(2)),
+        ..BASE
+    },
+    // a block running over midnight hands over to the morning block, which
+    // brings its own animation
+    Case {
+        name: "schedule",
+        start: "2025-01-06T06:59:58",
+        frames: 3 * FRAMES_PER_SECOND,
+        blocks: &[
+            "19:30-07:00 sun-thu 000030 sleep",
+            "07:00-07:45 weekdays 003000 breakfast rainbow",
+        ],
+        ..BASE
+    },
+    // dimmed hands at night, then the wake light comes on at 07:00
+    Case {
+        name: "wake",
+        start: "2025-01-06T06:59:58",
+        frames: 3 * FRAMES_PER_SECOND,
+        night: true,
+        ..BASE
+    },
+    // the marks pulse under the hands and the timer, the comet is gone
+    Case {
+        name: "unset",
+        start: "1970-01-01T00:00:05",
+        timer: Some(Duration::from_secs(180)),
+        unset: true,
+        ..BASE
+    },
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Check,
+    Bless,
+}
+
+/// Run all cases, returns `false` if any of them failed.
+pub fn run(mode: Mode) -> bool {
+    let mut ok = true;
+    for case in CASES {
+        let result = render(case).and_then(|frames| match mode {
+            Mode::Check => check(case, &frames),
+            Mode::Bless => bless(case, &frames),
+        });
+        match result {
+            Ok(message) => println!("{}: {message}", case.name),
+            Err(message) => {
+                println!("{}: FAILED\n{message}", case.name);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn path(case: &Case) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("golden")
+        .join(case.name)
+        .with_extension("txt")
+}
+
+fn render(case: &Case) -> Result<Vec<Frame>, String> {
+    let start = NaiveDateTime::parse_from_str(case.start, "%Y-%m-%dT%H:%M:%S")
+        .map_err(|e| format!("  invalid start time ({e})"))?;
+    let tz = TimeZone::parse(case.tz).map_err(|e| format!("  invalid time zone ({e:?})"))?;
+    let mut settings = Settings {
+        animation: case.animation,
+        ..Default::default()
+    };
+    settings.night.enabled = case.night;
+    for text in case.blocks {
+        let block = Block::parse(text).map_err(|e| format!("  invalid block {text} ({e:?})"))?;
+        settings
+            .schedule
+            .blocks
+            .push(block)
+            .map_err(|_| "  too many blocks".to_string())?;
+    }
+    let mut scene = Scene::new(VirtualClock::new(start, tz), settings);
+    if let Some(duration) = case.timer {
+        scene.start_timer(duration);
+    }
+    scene.set_unset(case.unset);
+    let mut frames = vec![[RGB8::default(); RING_SIZE]; case.frames];
+    for (index, frame) in frames.iter_mut().enumerate() {
+        match case.switch {
+            Some((at, animation)) if at == index => scene.select(animation),
+            _ => {}
+        }
+        // recorded as shown, with the brightness but before current limiting
+        let level = scene.render(frame);
+        let shown: Vec<RGB8> = brightness(frame.iter().copied(), level).collect();
+        frame.copy_from_slice(&shown);
+    }
+    Ok(frames)
+}
+
+fn check(case: &Case, actual: &[Frame]) -> Result<String, String> {
+    let path = path(case);
+    let text = fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "  unable to read {} ({e}), run `golden bless`",
+            path.display()
+        )
+    })?;
+    let expected = parse(&text).map_err(|e| format!("  {}: {e}", path.display()))?;
+
+    let mut report = String::new();
+    let mut differences = 0;
+    if expected.len() != actual.len() {
+        let _ = writeln!(
+            report,
+            "  expected {} frames, got {}",
+            expected.len(),
+            actual.len()
+        );
+    }
+    for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        for (led, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+            if expected == actual {
+                continue;
+            }
+            differences += 1;
+            if differences <= MAX_REPORTED {
+                let _ = writeln!(
+                    report,
+                    "  frame {index} (second {}, frame {}), LED {led}: expected {}, got {}",
+                    index / FRAMES_PER_SECOND,
+                    index % FRAMES_PER_SECOND,
+                    color(*expected),
+                    color(*actual)
+                );
+            }
+        }
+    }
+    if differences > MAX_REPORTED {
+        let _ = writeln!(report, "  ... {} more", differences - MAX_REPORTED);
+    }
+    if report.is_empty() {
+        Ok(format!("ok ({} frames)", actual.len()))
+    } else {
+        let _ = write!(report, "  {differences} pixels differ");
+        Err(report)
+    }
+}
+
+fn bless(case: &Case, frames: &[Frame]) -> Result<String, String> {
+    let mut text = format!(
+        "# {}: {} frames of {} from {} UTC, TZ {}\n\
+         # one line per frame, one RRGGBB per LED starting at twelve o'clock, {OFF} is off\n",
+        case.name,
+        frames.len(),
+        case.animation.name(),
+        case.start,
+        case.tz
+    );
+    if let Some(timer) = case.timer {
+        let _ = writeln!(text, "# timer {} s", timer.as_secs());
+    }
+    for block in case.blocks {
+        let _ = writeln!(text, "# block {block}");
+    }
+    if case.night {
+        let _ = writeln!(text, "# night mode");
+    }
+    if case.unset {
+        let _ = writeln!(text, "# time not set");
+    }
+    for (index, frame) in frames.iter().enumerate() {
+        let _ = write!(text, "{index:04}");
+        for &pixel in frame {
+            text.push(' ');
+            if pixel == RGB8::default() {
+                text.push_str(OFF);
+            } else {
+                let _ = write!(text, "{:02x}{:02x}{:02x}", pixel.r, pixel.g, pixel.b);
+            }
+        }
+        text.push('\n');
+    }
+
+    let path = path(case);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("  {} ({e})", dir.display()))?;
+    }
+    fs::write(&path, text).map_err(|e| format!("  {} ({e})", path.display()))?;
+    Ok(format!("wrote {}", path.display()))
+}
+
+fn parse(text: &str) -> Result<Vec<Frame>, String> {
+    let mut frames = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let invalid = || format!("line {}: invalid frame", number + 1);
+        let mut fields = line.split_whitespace().skip(1);
+        let mut frame = [RGB8::default(); RING_SIZE];
+        for pixel in frame.iter_mut() {
+            let field = fields.next().ok_or_else(invalid)?;
+            if field != OFF {
+                let value = u32::from_str_radix(field, 16).map_err(|_| invalid())?;
+                let [_, r, g, b] = value.to_be_bytes();
+                *pixel = RGB8::new(r, g, b);
+            }
+        }
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+fn color(pixel: RGB8) -> String {
+    format!("#{:02x}{:02x}{:02x}", pixel.r, pixel.g, pixel.b)
+}
@@ -0,0 +1,348 @@
+//! Host simulator for the clocked LED ring.
+//!
+//! Runs the same rendering code as the firmware against a virtual clock and
+//! draws the ring in an ANSI terminal (24 bit colors), so animations can be
+//! checked without flashing a clock or starting Wokwi.
+//!
+//! ```text
+//! cargo run -- --time 2025-03-30T01:59:50 --speed 10
+//! ```
+//!
+//! `cargo run -- golden` compares the rendered frames with the stored golden
+//! frames, see [`golden`], `cargo run -- api` does the same for the answers
+//! of the REST API, see [`api`], `cargo run -- mqtt` for the MQTT packets
+//! and Home Assistant commands, see [`mqtt`], `cargo run -- mdns` for the
+//! answers to mDNS queries, see [`mdns`], `cargo run -- ntp` for the
+//! choice among NTP servers and the clock discipline, see [`ntp`],
+//! `cargo run -- manual` for setting the time by hand, see [`manual`],
+//! `cargo run -- rtc` for the battery-backed RTC chips, see [`rtc`], and
+//! `cargo run -- ota` for firmware updates and their rollback, see [`ota`].
+//!
+//! `cargo run -- keygen` and `cargo run -- sign` make the key for updates
+//! and sign firmware images with it, see [`sign`].
+
+mod api;
+mod clock;
+mod golden;
+mod manual;
+mod mdns;
+mod mqtt;
+mod ntp;
+mod ota;
+mod power;
+mod rtc;
+mod scene;
+mod sign;
+mod terminal;
+mod transcript;
+
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use clocked_core::{
+    animation::Builtin,
+    layout::{Layout, Mapped},
+    power::{estimate_ma, Limiter},
+    ring::{self, brightness, Frame, FRAMES_PER_SECOND, RING_SIZE},
+    schedule::{parse_time, Block, MAX_BLOCKS},
+    settings::Settings,
+    tz::TimeZone,
+};
+
+use clock::VirtualClock;
+use golden::Mode;
+use scene::Scene;
+use terminal::Terminal;
+
+const USAGE: &str = "\
+Usage: clocked-sim [OPTIONS]
+       clocked-sim golden [check|bless]
+       clocked-sim api [check|bless]
+       clocked-sim mqtt [check|bless]
+       clocked-sim mdns [check|bless]
+       clocked-sim ntp [check|bless]
+       clocked-sim manual [check|bless]
+       clocked-sim rtc [check|bless]
+       clocked-sim ota [check|bless]
+       clocked-sim power [--max-current <MA>] [--leds <N>]
+       clocked-sim keygen <KEY FILE>
+       clocked-sim sign <KEY FILE> <IMAGE>
+
+Options:
+  --time <YYYY-MM-DDTHH:MM:SS>  Start at this UTC time instead of now
+  --tz <POSIX TZ>               Time zone, defaults to the firmware default
+  --speed <FACTOR>              Run the clock faster than real time [default: 1]
+  --seconds <N>                 Stop after N simulated seconds
+  --brightness <0-255>          Brightness setting [default: 255]
+  --animation <NAME>            off, comet, chase or rainbow [default: comet]
+  --max-current <MA>            LED current budget [default: 2000]
+  --leds <N>                    LEDs on the strip, 120 for the 144 LED/m mirror [default: 60]
+  --timer <MINUTES>             Start a countdown, fractions count seconds (0.1 is 6 s)
+  --block <BLOCK>               Add a schedule block, e.g. \"19:30-07:00 daily 000030 sleep\"
+  --night <HH:MM-HH:MM>         Night mode from the first time until the wake light at the second
+  --night-level <0-255>         Brightness at night [default: 16]
+  -h, --help                    Show this help";
+
+struct Options {
+    time: Option<NaiveDateTime>,
+    tz: TimeZone,
+    speed: f64,
+    seconds: Option<u64>,
+    /// Settings of the simulated clock, the time zone is `tz`.
+    settings: Settings,
+    leds: usize,
+    timer: Option<Duration>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let settings = Settings::default();
+    let mut options = Options {
+        time: None,
+        tz: TimeZone::parse(&settings.time_zone).unwrap_or_else(|_| TimeZone::utc()),
+        speed: 1.0,
+        seconds: None,
+        settings,
+        leds: RING_SIZE,
+        timer: None,
+    };
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(USAGE.into());
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {arg}"))?;
+        let invalid = || format!("Invalid value for {arg}: {value}");
+        match arg.as_str() {
+            "--time" => {
+                options.time = Some(
+                    NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S")
+                        .map_err(|_| invalid())?,
+                )
+            }
+            "--tz" => {
+                options.tz =
+                    TimeZone::parse(&value).map_err(|e| format!("Invalid time zone ({e:?})"))?
+            }
+            "--speed" => {
+                options.speed = value.parse().map_err(|_| invalid())?;
+                if !options.speed.is_finite() || options.speed <= 0.0 {
+                    return Err(invalid());
+                }
+            }
+            "--seconds" => options.seconds = Some(value.parse().map_err(|_| invalid())?),
+            "--brightness" => options.settings.brightness = value.parse().map_err(|_| invalid())?,
+            "--animation" => {
+                options.settings.animation = Builtin::from_name(&value).ok_or_else(invalid)?
+            }
+            "--max-current" => {
+                options.settings.max_current_ma = value.parse().map_err(|_| invalid())?
+            }
+            "--leds" => {
+                options.leds = value.parse().map_err(|_| invalid())?;
+                if options.leds == 0 {
+                    return Err(invalid());
+                }
+            }
+            "--timer" => {
+                let minutes: f64 = value.parse().map_err(|_| invalid())?;
+                let duration =
+                    Duration::try_from_secs_f64(minutes * 60.0).map_err(|_| invalid())?;
+                options.timer = Some(duration);
+            }
+            "--block" => {
+                let block =
+                    Block::parse(&value).map_err(|e| format!("Invalid block {value} ({e:?})"))?;
+                options
+                    .settings
+                    .schedule
+                    .blocks
+                    .push(block)
+                    .map_err(|_| format!("At most {MAX_BLOCKS} blocks"))?;
+            }
+            "--night" => {
+                let night = &mut options.settings.night;
+                let (start, wake) = value.split_once('-').ok_or_else(invalid)?;
+                night.start = parse_time(start).ok_or_else(invalid)?;
+                night.wake = parse_time(wake).ok_or_else(invalid)?;
+                night.enabled = true;
+            }
+            "--night-level" => {
+                options.settings.night.level = value.parse().map_err(|_| invalid())?
+            }
+            _ => return Err(format!("Unknown option {arg}\n\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    let checks: Option<fn(Mode) -> bool> = match args.peek().map(String::as_str) {
+        Some("golden") => Some(golden::run),
+        Some("api") => Some(api::run),
+        Some("mqtt") => Some(mqtt::run),
+        Some("mdns") => Some(mdns::run),
+        Some("ntp") => Some(ntp::run),
+        Some("manual") => Some(manual::run),
+        Some("rtc") => Some(rtc::run),
+        Some("ota") => Some(ota::run),
+        _ => None,
+    };
+    if let Some(command @ ("keygen" | "sign")) = args.peek().map(String::as_str) {
+        let command = command.to_owned();
+        let args: Vec<String> = args.skip(1).collect();
+        return match sign::run(&command, &args) {
+            Ok(message) => {
+                println!("{message}");
+                ExitCode::SUCCESS
+            }
+            Err(message) => {
+                eprintln!("{message}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if let Some(run) = checks {
+        let mode = match args.nth(1).as_deref() {
+            None | Some("check") => Mode::Check,
+            Some("bless") => Mode::Bless,
+            Some(other) => {
+                eprintln!("Unknown check command {other}\n\n{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        };
+        return if run(mode) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    let power_check = args.peek().map(String::as_str) == Some("power");
+    if power_check {
+        args.next();
+    }
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if power_check {
+        let max_current_ma = options.settings.max_current_ma as u32;
+        return if power::run(max_current_ma, &Layout::new(options.leds)) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    let clock = VirtualClock::new(options.time.unwrap_or_else(clock::system_time), options.tz);
+    let limiter = Limiter::new(options.settings.max_current_ma as u32);
+    let mut scene = Scene::new(clock, options.settings);
+    if let Some(duration) = options.timer {
+        scene.start_timer(duration);
+    }
+    let layout = Layout::new(options.leds);
+    let mut strip = Mapped::new(Terminal::new(std::io::stdout(), options.leds), layout);
+    let mut data: Frame = [Default::default(); RING_SIZE];
+
+    // Frames are drawn for the virtual clock, only the pacing follows --speed.
+    let frame_duration = Duration::from_secs(1).div_f64(options.speed) / FRAMES_PER_SECOND as u32;
+    let frames = options
+        .seconds
+        .map_or(u64::MAX, |s| s * FRAMES_PER_SECOND as u64);
+    let mut next_frame = Instant::now();
+    for _ in 0..frames {
+        let now = scene.now();
+        let timer = scene.timer_remaining();
+        let block = scene.block().map(|b| b.name.clone());
+        let level = scene.render(&mut data);
+        // same as on the clock, see the firmware's animation module
+        let level = limiter
+            .limit(layout.map(&data), level)
+            .map_or(level, |limited| limited.level);
+        let current = estimate_ma(brightness(layout.map(&data), level));
+        let mut caption = format!(
+            "{} {} {current} mA",
+            now.format("%H:%M:%S"),
+            scene.tz_name()
+        );
+        if let Some(block) = block {
+            caption += &format!(" {block}");
+        }
+        if let Some(timer) = timer {
+            let seconds = timer.as_secs() + u64::from(timer.subsec_nanos() > 0);
+            caption += &format!(" timer {}:{:02}", seconds / 60, seconds % 60);
+        }
+        strip.sink.set_caption(&caption);
+        if let Err(e) = ring::show(&mut strip, &data, level) {
+            eprintln!("Unable to draw: {e}");
+            return ExitCode::FAILURE;
+        }
+
+        next_frame += frame_duration;
+        thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `cargo test` runs every check as `clocked-sim <name> check` does
+
+    #[test]
+    fn golden_check() {
+        assert!(golden::run(Mode::Check));
+    }
+
+    #[test]
+    fn api_check() {
+        assert!(api::run(Mode::Check));
+    }
+
+    #[test]
+    fn mqtt_check() {
+        assert!(mqtt::run(Mode::Check));
+    }
+
+    #[test]
+    fn mdns_check() {
+        assert!(mdns::run(Mode::Check));
+    }
+
+    #[test]
+    fn ntp_check() {
+        assert!(ntp::run(Mode::Check));
+    }
+
+    #[test]
+    fn manual_check() {
+        assert!(manual::run(Mode::Check));
+    }
+
+    #[test]
+    fn rtc_check() {
+        assert!(rtc::run(Mode::Check));
+    }
+
+    #[test]
+    fn ota_check() {
+        assert!(ota::run(Mode::Check));
+    }
+
+    #[test]
+    fn power_check() {
+        let max_current_ma = Settings::default().max_current_ma as u32;
+        assert!(power::run(max_current_ma, &Layout::new(RING_SIZE)));
+        assert!(power::run(max_current_ma, &Layout::new(120)));
+    }
+}
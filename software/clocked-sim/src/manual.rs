@@ -0,0 +1,281 @@
+//! Fixtures for setting the clock by hand.
+//!
+//! Feeds console lines, good and mistyped, to the command parser and
+//! applies them to a fixed local time, turns local times around the
+//! daylight saving changes into UTC and walks the button editor through
+//! press sequences, drawing every field. Also covers what a start makes of
+//! the RTC and the saved time, when that is saved again, the marks shown
+//! while the time is not set and the saved time in the settings. The
+//! transcript is compared with `golden/manual.txt`, see [`crate::transcript`].
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use clocked_core::{
+    manual::{self, Command, Editor, LastTime, SAVE_INTERVAL},
+    ring::{ClockFace, Frame, BLACK, RING_SIZE},
+    settings::{Settings, MAX_PAYLOAD_LEN, SCHEMA_VERSION},
+    tz::TimeZone,
+};
+
+use crate::{golden::Mode, transcript};
+
+/// Local time the console commands are applied to.
+const NOW: &str = "2026-10-17T14:30:20";
+const CET: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+const LINES: &[&str] = &[
+    "help",
+    "time",
+    "  time  ",
+    "time 7:05",
+    "time 07:05:09",
+    "time 24:00",
+    "time 12:60",
+    "time 12:30:60",
+    "time 12",
+    "time 12:30:00:00",
+    "time 1a:30",
+    "date 2027-02-28",
+    "date 2027-02-29",
+    "date 2028-02-29",
+    "date 2024-12-31",
+    "date 2085-01-01",
+    "date 2026-1-5",
+    "date 17.10.2026",
+    "set 2026-12-24 18:00",
+    "set 2026-12-24T18:00:30",
+    "set 2026-12-24",
+    "set 2026-12-24 18:00 now",
+    "date",
+    "TIME 12:00",
+    "reboot",
+];
+
+/// Button presses, `s` short and `l` long, from a start at `from`.
+const EDITS: &[(&str, &str, &str)] = &[
+    ("hour and minute", NOW, "s s l s s s l l l l"),
+    ("wrap around", "2026-12-31T23:59:00", "s l s l s l s l s l"),
+    ("leap day", "2028-02-29T08:00:00", "l l l l s l"),
+    ("month end", "2026-01-31T08:00:00", "l l l s l"),
+    ("never set", "1970-01-01T00:00:42", "l l l l l"),
+    ("last year", "2084-06-01T08:00:00", "l l l l s l"),
+];
+
+/// Run the fixtures, returns `false` if the transcript differs.
+pub fn run(mode: Mode) -> bool {
+    transcript::run(
+        "manual",
+        || {
+            let summary = format!("{} commands, {} edits", LINES.len(), EDITS.len());
+            Ok((transcript(), summary))
+        },
+        mode,
+    )
+}
+
+fn time(text: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").expect("valid fixture time")
+}
+
+fn transcript() -> String {
+    let mut out = String::from(
+        "# manual time setting transcript, see src/manual.rs\n\
+         # console commands, the button editor and resuming after a power cut\n",
+    );
+    let _ = writeln!(out, "\n=== console, local time {NOW}");
+    for line in LINES {
+        let parsed = Command::parse(line);
+        let _ = write!(out, "{line:?}: {parsed:?}");
+        if let Some(set) = parsed.ok().and_then(|command| command.apply(time(NOW))) {
+            let _ = write!(out, " -> {set}");
+        }
+        out.push('\n');
+    }
+
+    to_utc(&mut out);
+    edits(&mut out);
+    resume(&mut out);
+    save_due(&mut out);
+    unset(&mut out);
+    settings(&mut out);
+    out
+}
+
+fn to_utc(out: &mut String) {
+    let _ = writeln!(out, "\n=== local to UTC, TZ {CET}");
+    let tz = TimeZone::parse(CET).expect("valid fixture time zone");
+    for local in [
+        "2026-01-15T12:00:00",
+        "2026-07-15T12:00:00",
+        // skipped when the clocks go forward
+        "2026-03-29T02:30:00",
+        "2026-03-29T03:00:00",
+        // repeated when they go back
+        "2026-10-25T02:30:00",
+        "2026-10-25T03:00:00",
+    ] {
+        let utc = manual::to_utc(&tz, time(local));
+        let _ = writeln!(out, "{local} -> {utc} UTC, shown as {}", tz.to_local(utc));
+    }
+}
+
+/// Lit LEDs of `frame` as `index=rrggbb`.
+fn lit(frame: &Frame) -> String {
+    let lit: Vec<String> = frame
+        .iter()
+        .enumerate()
+        .filter(|(_, pixel)| **pixel != BLACK)
+        .map(|(index, p)| format!("{index}={:02x}{:02x}{:02x}", p.r, p.g, p.b))
+        .collect();
+    if lit.is_empty() {
+        "dark".into()
+    } else {
+        lit.join(" ")
+    }
+}
+
+/// The editor drawn in the first and the second half of a second.
+fn draw(out: &mut String, editor: &Editor, elapsed: Duration) {
+    for (half, at) in [
+        ("on", elapsed),
+        ("off", elapsed + Duration::from_millis(500)),
+    ] {
+        let mut frame = [BLACK; RING_SIZE];
+        editor.render(at, &ClockFace::default(), &mut frame);
+        let _ = writeln!(out, "  {half:3} {}", lit(&frame));
+    }
+}
+
+fn edits(out: &mut String) {
+    for (name, from, presses) in EDITS {
+        let _ = writeln!(out, "\n=== editor: {name}, from {from}, presses {presses}");
+        let mut elapsed = Duration::from_secs(100);
+        let mut editor = Editor::new(time(from), elapsed);
+        let _ = writeln!(out, "{:?} {}", editor.field(), editor.time());
+        draw(out, &editor, elapsed);
+        for press in presses.split(' ') {
+            elapsed += Duration::from_secs(2);
+            let done = match press {
+                "l" => editor.next(elapsed),
+                _ => {
+                    editor.increment(elapsed);
+                    None
+                }
+            };
+            if let Some(set) = done {
+                let _ = writeln!(out, "{press}: set {set}");
+                break;
+            }
+            let _ = writeln!(out, "{press}: {:?} {}", editor.field(), editor.time());
+            if press == "l" {
+                draw(out, &editor, elapsed);
+            }
+        }
+    }
+
+    out.push_str("\n=== editor timeout\n");
+    let editor = Editor::new(time(NOW), Duration::from_secs(100));
+    for seconds in [100, 159, 160, 300] {
+        let _ = writeln!(
+            out,
+            "at {seconds} s: expired {}",
+            editor.is_expired(Duration::from_secs(seconds))
+        );
+    }
+}
+
+fn resume(out: &mut String) {
+    out.push_str("\n=== resume\n");
+    let saved = time("2026-10-17T12:00:00");
+    let cases = [
+        ("never saved", "1970-01-01T00:00:03", None),
+        ("power cut", "1970-01-01T00:00:03", Some(true)),
+        ("power cut, not set", "1970-01-01T00:00:03", Some(false)),
+        ("restart", "2026-10-17T12:40:00", Some(true)),
+        ("restart, not set", "2026-10-17T12:40:00", Some(false)),
+        (
+            "restart at the saved time",
+            "2026-10-17T12:00:00",
+            Some(true),
+        ),
+    ];
+    for (name, rtc, known) in cases {
+        let last = known.map(|known| LastTime { time: saved, known });
+        let _ = writeln!(
+            out,
+            "{name}: rtc {rtc}, saved {}: {:?}",
+            last.map_or("nothing".into(), |last| format!("{last:?}")),
+            manual::resume(time(rtc), last)
+        );
+    }
+}
+
+fn save_due(out: &mut String) {
+    let _ = writeln!(out, "\n=== save due, every {} s", SAVE_INTERVAL.as_secs());
+    let saved = time("2026-10-17T12:00:00");
+    let now = time("2026-10-17T12:10:00");
+    let before = time("2026-10-17T11:00:00");
+    let minutes = Duration::from_secs(600);
+    let cases = [
+        ("nothing saved, not set", None, now, false, minutes),
+        ("nothing saved, set", None, now, true, minutes),
+        ("set since", Some(false), now, true, minutes),
+        ("unchanged", Some(true), now, true, minutes),
+        ("interval over", Some(true), now, true, SAVE_INTERVAL),
+        (
+            "not set, interval over",
+            Some(false),
+            now,
+            false,
+            SAVE_INTERVAL,
+        ),
+        ("set back", Some(true), before, true, minutes),
+    ];
+    for (name, known_before, now, known, since) in cases {
+        let last = known_before.map(|known| LastTime { time: saved, known });
+        let _ = writeln!(out, "{name}: {}", manual::save_due(last, now, known, since));
+    }
+}
+
+fn unset(out: &mut String) {
+    out.push_str("\n=== time not set marks\n");
+    for millis in [0, 250, 500, 1000, 1500, 2000] {
+        let mut frame = [BLACK; RING_SIZE];
+        manual::render_unset(Duration::from_millis(millis), &mut frame);
+        let _ = writeln!(out, "at {millis} ms: {}", lit(&frame));
+    }
+}
+
+fn settings(out: &mut String) {
+    out.push_str("\n=== saved time in the settings\n");
+    let mut buf = [0u8; MAX_PAYLOAD_LEN];
+    for last in [
+        None,
+        Some(LastTime {
+            time: time("2026-10-17T12:00:00"),
+            known: true,
+        }),
+        Some(LastTime {
+            time: time("2026-10-17T12:00:00"),
+            known: false,
+        }),
+    ] {
+        let settings = Settings {
+            last_time: last,
+            ..Default::default()
+        };
+        let Some(len) = settings.encode(&mut buf) else {
+            out.push_str("too large\n");
+            continue;
+        };
+        let decoded = Settings::decode(SCHEMA_VERSION, &buf[..len]).map(|s| s.last_time);
+        let _ = writeln!(out, "{last:?}: {len} bytes, decoded {decoded:?}");
+    }
+    // schema 10 wrote no time, the payload ends before it and the empty API
+    // token of schema 12
+    let len = Settings::default().encode(&mut buf).unwrap_or(0);
+    let older = Settings::decode(10, &buf[..len - 6]).map(|s| s.last_time);
+    let _ = writeln!(out, "schema 10: {older:?}");
+}
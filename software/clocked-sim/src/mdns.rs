@@ -0,0 +1,267 @@
+//! Byte-level fixtures for the mDNS responder.
+//!
+//! Sends queries as browsers, `avahi-browse` or a plain resolver ask them,
+//! including broken ones, to a clock with the default and a configured name
+//! and dumps the responses and announcements, raw and decoded. The
+//! transcript is compared with `golden/mdns.txt`, see [`crate::transcript`].
+
+use std::fmt::Write as _;
+
+use clocked_core::mdns::{self, Host, PORT};
+
+use crate::{golden::Mode, mqtt::hex, transcript};
+
+const ADDRESS: [u8; 4] = [192, 168, 1, 42];
+/// Source port of a resolver that does not speak mDNS.
+const RESOLVER_PORT: u16 = 40000;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_AAAA: u16 = 28;
+const TYPE_ANY: u16 = 255;
+/// Class IN with the unicast response bit.
+const CLASS_QU: u16 = 0x8001;
+
+struct Case {
+    name: &'static str,
+    host: &'static str,
+    from_port: u16,
+    query: Vec<u8>,
+}
+
+/// Run the fixtures, returns `false` if the transcript differs.
+pub fn run(mode: Mode) -> bool {
+    transcript::run(
+        "mdns",
+        || {
+            let cases = cases();
+            Ok((transcript(&cases), format!("{} queries", cases.len())))
+        },
+        mode,
+    )
+}
+
+/// Query with the questions `(name, type, class)`, the flags set as given.
+fn query(id: u16, flags: u16, questions: &[(&str, u16, u16)]) -> Vec<u8> {
+    let mut query = Vec::new();
+    query.extend(id.to_be_bytes());
+    query.extend(flags.to_be_bytes());
+    query.extend((questions.len() as u16).to_be_bytes());
+    query.extend([0; 6]);
+    for (name, qtype, qclass) in questions {
+        for label in name.split('.') {
+            query.push(label.len() as u8);
+            query.extend(label.as_bytes());
+        }
+        query.push(0);
+        query.extend(qtype.to_be_bytes());
+        query.extend(qclass.to_be_bytes());
+    }
+    query
+}
+
+fn case(name: &'static str, query: Vec<u8>) -> Case {
+    Case {
+        name,
+        host: "clocked-1a2b",
+        from_port: PORT,
+        query,
+    }
+}
+
+fn cases() -> Vec<Case> {
+    let question = |name, qtype| query(0, 0, &[(name, qtype, 1)]);
+
+    // the second question points to "local" in the first
+    let mut compressed = query(0, 0, &[("clocked-1a2b.local", TYPE_A, 1)]);
+    compressed[5] = 2;
+    compressed.extend([5, b'_', b'h', b't', b't', b'p', 4, b'_', b't', b'c', b'p']);
+    compressed.extend([0xc0, 12 + 13]);
+    compressed.extend(TYPE_PTR.to_be_bytes());
+    compressed.extend(1u16.to_be_bytes());
+
+    let mut looping = query(0, 0, &[]);
+    looping[5] = 1;
+    looping.extend([0xc0, 12, 0, 1, 0, 1]);
+
+    let mut cut_off = question("clocked-1a2b.local", TYPE_A);
+    cut_off.truncate(cut_off.len() - 2);
+
+    vec![
+        case("address", question("clocked-1a2b.local", TYPE_A)),
+        case(
+            "address in upper case",
+            question("CLOCKED-1A2B.LOCAL", TYPE_A),
+        ),
+        case("ipv6 address", question("clocked-1a2b.local", TYPE_AAAA)),
+        case("browse web servers", question("_http._tcp.local", TYPE_PTR)),
+        case(
+            "browse service types",
+            question("_services._dns-sd._udp.local", TYPE_PTR),
+        ),
+        case(
+            "service instance",
+            question("clocked-1a2b._http._tcp.local", TYPE_ANY),
+        ),
+        case(
+            "service instance srv",
+            question("clocked-1a2b._http._tcp.local", TYPE_SRV),
+        ),
+        case(
+            "unicast response requested",
+            query(0, 0, &[("clocked-1a2b.local", TYPE_A, CLASS_QU)]),
+        ),
+        Case {
+            from_port: RESOLVER_PORT,
+            ..case(
+                "plain resolver",
+                query(0x1234, 0x0100, &[("clocked-1a2b.local", TYPE_A, 1)]),
+            )
+        },
+        case("compressed second question", compressed),
+        case("other host", question("printer.local", TYPE_A)),
+        case(
+            "one question for the clock",
+            query(
+                0,
+                0,
+                &[
+                    ("printer.local", TYPE_A, 1),
+                    ("_http._tcp.local", TYPE_PTR, 1),
+                ],
+            ),
+        ),
+        case(
+            "response from another host",
+            query(0, 0x8400, &[("clocked-1a2b.local", TYPE_A, 1)]),
+        ),
+        case("pointer loop", looping),
+        case("cut off question", cut_off),
+        case("header only", query(0, 0, &[])[..6].to_vec()),
+        Case {
+            host: "kitchen-clock",
+            ..case("configured name", question("kitchen-clock.local", TYPE_A))
+        },
+        Case {
+            host: "kitchen-clock",
+            ..case(
+                "device id after renaming",
+                question("clocked-1a2b.local", TYPE_A),
+            )
+        },
+    ]
+}
+
+fn transcript(cases: &[Case]) -> String {
+    let mut out = String::from(
+        "# mDNS transcript, see src/mdns.rs\n\
+         # queries to the clock at 192.168.1.42 and its responses and announcements\n",
+    );
+    let mut buf = [0u8; 512];
+    for case in cases {
+        let host = Host {
+            name: case.host,
+            address: ADDRESS,
+            port: 80,
+        };
+        let _ = writeln!(out, "\n=== {}", case.name);
+        let _ = writeln!(out, "> from port {}", case.from_port);
+        hex(&mut out, &case.query);
+        match mdns::respond(&case.query, case.from_port, &host, &mut buf) {
+            Some((len, destination)) => {
+                let _ = writeln!(out, "< {destination:?}");
+                hex(&mut out, &buf[..len]);
+                decode(&mut out, &buf[..len]);
+            }
+            None => out.push_str("< no response\n"),
+        }
+    }
+
+    for name in ["clocked-1a2b", "kitchen-clock", "-invalid"] {
+        let host = Host {
+            name,
+            address: ADDRESS,
+            port: 80,
+        };
+        let _ = writeln!(out, "\n=== announce {name}");
+        match mdns::announce(&host, &mut buf) {
+            Some(len) => {
+                hex(&mut out, &buf[..len]);
+                decode(&mut out, &buf[..len]);
+            }
+            None => out.push_str("no announcement\n"),
+        }
+    }
+    out
+}
+
+/// List the questions and records of a response.
+fn decode(out: &mut String, message: &[u8]) {
+    let u16_at = |at: usize| u16::from_be_bytes([message[at], message[at + 1]]);
+    let _ = writeln!(out, "id {:04x}, flags {:04x}", u16_at(0), u16_at(2));
+    let mut pos = 12;
+    for _ in 0..u16_at(4) {
+        let Some((name, end)) = name(message, pos) else {
+            out.push_str("broken question\n");
+            return;
+        };
+        let _ = writeln!(
+            out,
+            "question {name} type {} class {:04x}",
+            u16_at(end),
+            u16_at(end + 2)
+        );
+        pos = end + 4;
+    }
+    for (section, records) in [("answer", u16_at(6)), ("additional", u16_at(10))] {
+        for _ in 0..records {
+            let Some((owner, end)) = name(message, pos) else {
+                out.push_str("broken record\n");
+                return;
+            };
+            let rtype = u16_at(end);
+            let class = u16_at(end + 2);
+            let ttl = u32::from_be_bytes(message[end + 4..end + 8].try_into().unwrap());
+            let len = u16_at(end + 8) as usize;
+            let data_at = end + 10;
+            let data = &message[data_at..data_at + len];
+            let value = match rtype {
+                TYPE_A => format!("A {}.{}.{}.{}", data[0], data[1], data[2], data[3]),
+                TYPE_PTR => format!("PTR {}", name(message, data_at).unwrap().0),
+                TYPE_SRV => format!(
+                    "SRV port {} target {}",
+                    u16::from_be_bytes([data[4], data[5]]),
+                    name(message, data_at + 6).unwrap().0
+                ),
+                TYPE_TXT => format!("TXT {:?}", String::from_utf8_lossy(&data[1..])),
+                other => format!("type {other}"),
+            };
+            let _ = writeln!(
+                out,
+                "{section} {owner} class {class:04x} ttl {ttl}: {value}"
+            );
+            pos = data_at + len;
+        }
+    }
+}
+
+/// Dotted name at `pos` and the offset past it.
+fn name(message: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    loop {
+        let len = *message.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(pos + 2);
+            pos = (len & 0x3f) << 8 | *message.get(pos + 1)? as usize;
+            continue;
+        }
+        labels.push(String::from_utf8_lossy(message.get(pos + 1..pos + 1 + len)?).into_owned());
+        pos += 1 + len;
+    }
+}
@@ -0,0 +1,236 @@
+//! Byte-level fixtures for the MQTT client and the Home Assistant device.
+//!
+//! Encodes the packets the clock sends, decodes packets as a broker sends
+//! them, including split and broken ones, and runs a sequence of Home
+//! Assistant commands against the default settings. The transcript is
+//! compared with `golden/mqtt.txt`, see [`crate::transcript`].
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use clocked_core::{
+    home_assistant::{Entity, Light, State, Topics, OFFLINE},
+    mqtt::{self, Connect, Publish, Will},
+    settings::Settings,
+};
+
+use crate::{golden::Mode, transcript};
+
+const ID: &str = "clocked-1a2b";
+/// Firmware version in the discovery messages.
+const VERSION: &str = "0.1.0";
+
+/// Packets from the broker, as raw bytes.
+const DECODE: &[(&str, &[u8])] = &[
+    ("connack accepted", &[0x20, 0x02, 0x00, 0x00]),
+    ("connack not authorized", &[0x20, 0x02, 0x00, 0x05]),
+    ("suback", &[0x90, 0x04, 0x00, 0x01, 0x00, 0x80]),
+    (
+        "publish",
+        &[
+            0x30, 0x0c, 0x00, 0x05, b'a', b'/', b'b', b'/', b'c', b'h', b'e', b'l', b'l', b'o',
+        ],
+    ),
+    (
+        "publish qos 1 retained",
+        &[0x33, 0x08, 0x00, 0x01, b't', 0x12, 0x34, b'o', b'n', b'!'],
+    ),
+    ("puback", &[0x40, 0x02, 0x00, 0x07]),
+    ("pingresp", &[0xd0, 0x00]),
+    ("two packets", &[0xd0, 0x00, 0x20, 0x02, 0x00, 0x00]),
+    ("unexpected type", &[0xa0, 0x02, 0x00, 0x01]),
+    ("empty", &[]),
+    ("head only", &[0x30]),
+    ("cut off body", &[0x30, 0x0c, 0x00, 0x05, b'a']),
+    ("cut off length", &[0x30, 0x80]),
+    ("length over 4 bytes", &[0x30, 0xff, 0xff, 0xff, 0xff, 0x01]),
+    ("topic longer than packet", &[0x30, 0x03, 0x00, 0x09, b'a']),
+    ("qos 2", &[0x34, 0x05, 0x00, 0x01, b't', 0x00, 0x01]),
+    ("invalid topic", &[0x30, 0x04, 0x00, 0x02, 0xc3, 0x28]),
+];
+
+/// Messages from Home Assistant, applied one after the other.
+const COMMANDS: &[(&str, &str)] = &[
+    ("set", r#"{"state": "ON", "brightness": 64}"#),
+    ("set", r#"{"state": "OFF"}"#),
+    ("set", r#"{"state": "ON"}"#),
+    (
+        "set",
+        r#"{"color": {"r": 255, "g": 128, "b": 0}, "color_mode": "rgb"}"#,
+    ),
+    ("set", r#"{"effect": "rainbow", "transition": 2}"#),
+    ("set", r#"{"effect": "fireworks"}"#),
+    ("set", r#"{"brightness": 256}"#),
+    ("set", r#"{"color": {"r": 1}}"#),
+    ("set", r#"{"volume": 11}"#),
+    ("set", "ON"),
+    ("timer/set", "15"),
+    ("timer/set", "5.0"),
+    ("timer/set", "120"),
+    ("timer/set", "0"),
+    ("timer/set", "soon"),
+    ("state", r#"{"state": "ON"}"#),
+];
+
+/// Run the fixtures, returns `false` if the transcript differs.
+pub fn run(mode: Mode) -> bool {
+    transcript::run(
+        "mqtt",
+        || {
+            let summary = format!("{} packets, {} commands", DECODE.len(), COMMANDS.len());
+            Ok((transcript(), summary))
+        },
+        mode,
+    )
+}
+
+fn transcript() -> String {
+    let mut transcript = String::from(
+        "# MQTT transcript, see src/mqtt.rs\n\
+         # packets sent by the clock, packets received from the broker and Home Assistant commands\n",
+    );
+    encoded(&mut transcript);
+    decoded(&mut transcript);
+    home_assistant(&mut transcript);
+    transcript
+}
+
+fn encoded(out: &mut String) {
+    let topics = Topics::new(ID);
+    let mut state = String::new();
+    let _ = State::new(&Settings::default(), None).write(&mut state);
+
+    packet(out, "connect", |buf| {
+        let connect = Connect {
+            client_id: ID,
+            keep_alive: 60,
+            will: None,
+            username: None,
+            password: None,
+        };
+        mqtt::connect(&connect, buf)
+    });
+    packet(out, "connect with will and login", |buf| {
+        let connect = Connect {
+            client_id: ID,
+            keep_alive: 60,
+            will: Some(Will {
+                topic: &topics.availability,
+                payload: OFFLINE.as_bytes(),
+                retain: true,
+            }),
+            username: Some("clock"),
+            password: Some("secret"),
+        };
+        mqtt::connect(&connect, buf)
+    });
+    packet(out, "subscribe", |buf| {
+        mqtt::subscribe(1, &topics.commands(), buf)
+    });
+    packet(out, "publish retained state", |buf| {
+        let publish = Publish {
+            topic: &topics.state,
+            payload: state.as_bytes(),
+            retain: true,
+            packet_id: None,
+        };
+        mqtt::publish(&publish, buf)
+    });
+    packet(out, "puback", |buf| mqtt::puback(0x1234, buf));
+    packet(out, "pingreq", mqtt::ping);
+    packet(out, "disconnect", mqtt::disconnect);
+    packet(out, "publish too large", |buf| {
+        let publish = Publish {
+            topic: &topics.state,
+            payload: &[0; 600],
+            retain: false,
+            packet_id: None,
+        };
+        mqtt::publish(&publish, buf)
+    });
+}
+
+/// Encode a packet with `encode` and dump it.
+fn packet(
+    out: &mut String,
+    name: &str,
+    encode: impl FnOnce(&mut [u8]) -> Result<usize, mqtt::Error>,
+) {
+    let mut buf = [0u8; 512];
+    let _ = writeln!(out, "\n=== {name}");
+    match encode(&mut buf) {
+        Ok(len) => hex(out, &buf[..len]),
+        Err(e) => {
+            let _ = writeln!(out, "error: {e:?}");
+        }
+    }
+}
+
+fn decoded(out: &mut String) {
+    for (name, bytes) in DECODE {
+        let _ = writeln!(out, "\n=== decode {name}");
+        hex(out, bytes);
+        let mut rest = *bytes;
+        loop {
+            match mqtt::decode(rest) {
+                Ok((packet, len)) => {
+                    let _ = writeln!(out, "< {packet:?} ({len} bytes)");
+                    rest = &rest[len..];
+                    if rest.is_empty() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = writeln!(out, "< error: {e:?}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn home_assistant(out: &mut String) {
+    for entity in Entity::ALL {
+        let _ = writeln!(out, "\n=== discovery {entity:?}");
+        let _ = writeln!(out, "topic: {}", entity.config_topic(ID));
+        let _ = entity.write_config(ID, VERSION, out);
+        out.push('\n');
+    }
+
+    let topics = Topics::new(ID);
+    let mut settings = Settings::default();
+    let mut light = Light::default();
+    let timer = Some(Duration::from_millis(299_500));
+    let _ = writeln!(out, "\n=== state");
+    let _ = State::new(&settings, timer).write(out);
+    out.push('\n');
+
+    for (suffix, payload) in COMMANDS {
+        let topic = format!("clocked/{ID}/{suffix}");
+        let _ = writeln!(out, "\n=== {topic}");
+        let _ = writeln!(out, "> {payload}");
+        let mut changed = settings.clone();
+        match light.handle(&topics, &topic, payload.as_bytes(), &mut changed) {
+            Ok(command) => {
+                let _ = writeln!(out, "command: {command:?}");
+                settings = changed;
+                let _ = State::new(&settings, None).write(out);
+                out.push('\n');
+            }
+            Err(problem) => {
+                let _ = writeln!(out, "error: {problem}");
+            }
+        }
+    }
+}
+
+/// Hex dump, 16 bytes per line.
+pub fn hex(out: &mut String, bytes: &[u8]) {
+    if bytes.is_empty() {
+        out.push_str("(no bytes)\n");
+    }
+    for line in bytes.chunks(16) {
+        let text: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+        let _ = writeln!(out, "{}", text.join(" "));
+    }
+}
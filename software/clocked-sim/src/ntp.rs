@@ -0,0 +1,492 @@
+//! Fixtures for the SNTP client policy.
+//!
+//! Parses made-up server replies, good ones and the ones a client has to
+//! drop, picks the offset out of synthetic samples with and without
+//! falsetickers and lets the clock discipline run for a day against an
+//! oscillator that is 37 ppm slow, with network jitter and a server that is
+//! way off. Also covers the retry backoff, asking the DHCP server for its
+//! NTP servers and the fallback to the `Date` of a web server, with the date
+//! formats found in the wild. The transcript is compared with
+//! `golden/ntp.txt`, see [`crate::transcript`].
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use clocked_core::{
+    dhcp::{self, Builder, Packet, OPTION_MESSAGE_TYPE, OPTION_NTP_SERVERS},
+    http::{self, Response, Url},
+    ntp::{
+        self, Adjustment, Config, Discipline, NtpTimestamp, Reply, Sample, PACKET_LEN,
+        SLEW_RATE_PPM,
+    },
+};
+
+use crate::{golden::Mode, mqtt::hex, transcript};
+
+/// 2025-06-15 15:06:40 UTC in microseconds, when the requests are sent.
+const SENT: i64 = 1_750_000_000_000_000;
+/// How much faster real time runs than the simulated oscillator.
+const DRIFT_PPB: i64 = 37_000;
+const SYNC_INTERVAL: Duration = Duration::from_secs(3600);
+const HOURS: u64 = 24;
+/// Servers asked per sync, the last one is 30 s off.
+const SERVERS: usize = 4;
+
+/// Run the fixtures, returns `false` if the transcript differs.
+pub fn run(mode: Mode) -> bool {
+    transcript::run(
+        "ntp",
+        || {
+            let summary = format!(
+                "{} replies, {} selections, {HOURS} h disciplined",
+                replies().len(),
+                selections().len()
+            );
+            Ok((transcript(), summary))
+        },
+        mode,
+    )
+}
+
+fn transcript() -> String {
+    let mut out = String::from(
+        "# NTP transcript, see src/ntp.rs\n\
+         # replies, selections among servers and a day of clock discipline\n",
+    );
+    let originate = NtpTimestamp::from_unix_micros(SENT);
+    out.push_str("\n=== request\n");
+    hex(&mut out, &ntp::request(originate));
+
+    for (name, packet) in replies() {
+        let _ = writeln!(out, "\n=== reply: {name}");
+        hex(&mut out, &packet);
+        match Reply::parse(&packet, originate) {
+            Ok(reply) => {
+                let _ = writeln!(
+                    out,
+                    "stratum {}, root delay {} us, root dispersion {} us",
+                    reply.stratum, reply.root_delay, reply.root_dispersion
+                );
+                // received 30 ms after sending
+                let _ = writeln!(out, "{:?}", reply.sample(SENT, SENT + 30_000));
+            }
+            Err(e) => {
+                let _ = writeln!(out, "rejected: {e:?}");
+            }
+        }
+    }
+
+    for (name, samples) in selections() {
+        let _ = writeln!(out, "\n=== select: {name}");
+        for sample in &samples {
+            let _ = writeln!(
+                out,
+                "offset {} us, delay {} us, error {} us",
+                sample.offset, sample.delay, sample.error
+            );
+        }
+        let _ = writeln!(out, "{:?}", ntp::select(&samples));
+    }
+
+    steps(&mut out);
+    day(&mut out);
+    backoff(&mut out);
+    dhcp_servers(&mut out);
+    http_dates(&mut out);
+    http_fallback(&mut out);
+    out
+}
+
+/// A server reply to the request sent at [`SENT`], the server clock
+/// `ahead` microseconds ahead and 10 ms of network delay each way.
+fn reply(leap_version_mode: u8, stratum: u8, ahead: i64) -> Vec<u8> {
+    let mut packet = vec![0; PACKET_LEN];
+    packet[0] = leap_version_mode;
+    packet[1] = stratum;
+    packet[2] = 6;
+    packet[3] = 0xe9;
+    // 1.5 ms root delay, 0.5 ms root dispersion in 16.16 seconds
+    packet[4..8].copy_from_slice(&98u32.to_be_bytes());
+    packet[8..12].copy_from_slice(&33u32.to_be_bytes());
+    packet[12..16].copy_from_slice(b"GPS\0");
+    let at = |micros| NtpTimestamp::from_unix_micros(micros).to_be_bytes();
+    packet[16..24].copy_from_slice(&at(SENT - 20_000_000 + ahead));
+    packet[24..32].copy_from_slice(&at(SENT));
+    packet[32..40].copy_from_slice(&at(SENT + 10_000 + ahead));
+    packet[40..48].copy_from_slice(&at(SENT + 20_000 + ahead));
+    packet
+}
+
+fn replies() -> Vec<(&'static str, Vec<u8>)> {
+    let good = || reply(0x24, 2, 1_250_000);
+    let mut wrong_originate = good();
+    wrong_originate[31] ^= 0x01;
+    let mut no_transmit = good();
+    no_transmit[40..48].fill(0);
+    let mut kiss = reply(0x24, 0, 0);
+    kiss[12..16].copy_from_slice(b"RATE");
+
+    vec![
+        ("server 1.25 s ahead", good()),
+        ("server behind", reply(0x24, 1, -730_000)),
+        ("version 3 server", reply(0x1c, 3, 0)),
+        ("leap second ahead", reply(0x64, 2, 0)),
+        ("trailing bytes", {
+            let mut packet = good();
+            packet.extend([0; 20]);
+            packet
+        }),
+        ("cut off", good()[..40].to_vec()),
+        ("client request", reply(0x23, 2, 0)),
+        ("broadcast", reply(0x25, 2, 0)),
+        ("kiss of death", kiss),
+        ("unsynchronized server", reply(0xe4, 2, 0)),
+        ("stratum 16", reply(0x24, 16, 0)),
+        ("wrong originate", wrong_originate),
+        ("no transmit timestamp", no_transmit),
+    ]
+}
+
+fn sample(offset: i64, delay: i64, error: i64) -> Sample {
+    Sample {
+        offset,
+        delay,
+        error,
+    }
+}
+
+fn selections() -> Vec<(&'static str, Vec<Sample>)> {
+    vec![
+        (
+            "four agree",
+            vec![
+                sample(1_200, 20_000, 1_000),
+                sample(-3_500, 24_000, 500),
+                sample(2_800, 18_000, 2_000),
+                sample(400, 40_000, 1_500),
+            ],
+        ),
+        (
+            "one falseticker",
+            vec![
+                sample(1_200, 20_000, 1_000),
+                sample(30_001_000, 22_000, 800),
+                sample(-2_000, 24_000, 500),
+            ],
+        ),
+        (
+            "falseticker with a huge delay",
+            vec![
+                sample(1_200, 20_000, 1_000),
+                sample(250_000, 600_000, 0),
+                sample(-2_000, 24_000, 500),
+            ],
+        ),
+        (
+            "two against two",
+            vec![
+                sample(1_000, 10_000, 0),
+                sample(2_000, 10_000, 0),
+                sample(900_000, 10_000, 0),
+                sample(901_000, 10_000, 0),
+            ],
+        ),
+        (
+            "touching intervals",
+            vec![sample(0, 20_000, 0), sample(20_000, 20_000, 0)],
+        ),
+        (
+            "two disagree",
+            vec![sample(0, 20_000, 0), sample(20_001, 20_000, 0)],
+        ),
+        ("single server", vec![sample(-450_000, 35_000, 2_000)]),
+        ("no servers", vec![]),
+        ("more servers than looked at", {
+            let mut samples = vec![sample(5_000, 10_000, 0); 8];
+            samples.extend([sample(-900_000, 10_000, 0); 4]);
+            samples
+        }),
+    ]
+}
+
+/// Updates of a fresh discipline with the offsets `(minutes later, offset)`,
+/// no offset for a restart from a time set by other means.
+fn steps(out: &mut String) {
+    out.push_str("\n=== discipline steps and slews\n");
+    let mut discipline = Discipline::new();
+    let updates = [
+        (0, Some(3_600_000_000)),
+        (60, Some(1_500_000)),
+        (61, Some(-800_000)),
+        (120, Some(2_000_001)),
+        (180, Some(-2_000_000)),
+        (240, Some(-1_000)),
+        (300, None),
+        (360, Some(400_000)),
+        (420, Some(-1_000)),
+    ];
+    for (minutes, offset) in updates {
+        let now = Duration::from_secs(minutes * 60);
+        let correction = discipline.correction(now);
+        let Some(offset) = offset else {
+            discipline.restart(now);
+            let _ = writeln!(out, "{minutes:3} min: correction {correction} us, restart");
+            continue;
+        };
+        let adjustment = discipline.update(now, offset);
+        let _ = writeln!(
+            out,
+            "{minutes:3} min: correction {correction} us, offset {offset} us -> \
+             {adjustment:?}, drift {} ppb",
+            discipline.drift_ppb()
+        );
+    }
+}
+
+/// Pseudo random jitter between -2 and 2 ms, the same on every run.
+struct Jitter(u64);
+
+impl Jitter {
+    fn next(&mut self) -> i64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1);
+        ((self.0 >> 33) % 4_001) as i64 - 2_000
+    }
+}
+
+/// A day of hourly syncs. The oscillator counts uptime and the time base
+/// with it, real time runs [`DRIFT_PPB`] faster. Checks every second that
+/// the displayed time only jumps at a step and never runs faster or slower
+/// than the slew rate allows.
+fn day(out: &mut String) {
+    let _ = writeln!(
+        out,
+        "\n=== a day against an oscillator {} ppm slow",
+        DRIFT_PPB / 1_000
+    );
+    let real = |uptime: Duration| {
+        let micros = uptime.as_micros() as i64;
+        SENT + micros + micros * DRIFT_PPB / 1_000_000_000
+    };
+    let mut discipline = Discipline::new();
+    // the RTC starts at the epoch
+    let mut base: i64 = 0;
+    let displayed = |base: i64, discipline: &Discipline, uptime: Duration| {
+        base + uptime.as_micros() as i64 + discipline.correction(uptime)
+    };
+    let mut jitter = Jitter(2025);
+    // largest rate deviation between two syncs in ppm, worst error in the
+    // last twelve hours in microseconds
+    let mut max_rate = 0;
+    let mut worst = 0;
+    let mut jumps = 0;
+
+    let first = Duration::from_secs(5);
+    for sync in 0..=HOURS {
+        let now = first + SYNC_INTERVAL * sync as u32;
+        let offset = real(now) - displayed(base, &discipline, now);
+        let samples: Vec<Sample> = (0..SERVERS)
+            .map(|server| {
+                let wrong = if server == SERVERS - 1 { 30_000_000 } else { 0 };
+                sample(
+                    offset + wrong + jitter.next(),
+                    20_000 + jitter.next().abs(),
+                    500,
+                )
+            })
+            .collect();
+        let Some(selection) = ntp::select(&samples) else {
+            let _ = writeln!(out, "hour {sync:2}: servers disagree");
+            continue;
+        };
+        let adjustment = discipline.update(now, selection.offset);
+        base += adjustment.shift();
+        let kind = match adjustment {
+            Adjustment::Step(_) => "step",
+            Adjustment::Slew { .. } => "slew",
+        };
+        let _ = writeln!(
+            out,
+            "hour {sync:2}: off by {:+.3} ms, {}/{} servers agree on {:+.3} ms, {kind}, \
+             drift {} ppb",
+            offset as f64 / 1_000.0,
+            selection.agreeing,
+            selection.total,
+            selection.offset as f64 / 1_000.0,
+            discipline.drift_ppb()
+        );
+        if sync == HOURS {
+            break;
+        }
+
+        let mut previous = displayed(base, &discipline, now);
+        for second in 1..=SYNC_INTERVAL.as_secs() {
+            let uptime = now + Duration::from_secs(second);
+            let time = displayed(base, &discipline, uptime);
+            let rate = (time - previous - 1_000_000).abs();
+            if rate > SLEW_RATE_PPM + DRIFT_PPB / 1_000 * 2 {
+                jumps += 1;
+            }
+            max_rate = max_rate.max(rate);
+            if sync >= HOURS / 2 {
+                worst = worst.max((real(uptime) - time).abs());
+            }
+            previous = time;
+        }
+    }
+    let _ = writeln!(out, "fastest slew {max_rate} ppm, {jumps} jumps");
+    let _ = writeln!(
+        out,
+        "worst error in the last 12 h {:.3} ms, drift {} ppb, actual {DRIFT_PPB} ppb",
+        worst as f64 / 1_000.0,
+        discipline.drift_ppb()
+    );
+}
+
+/// Delays after failed syncs in a row, with the default and a short interval.
+fn backoff(out: &mut String) {
+    out.push_str("\n=== retry backoff\n");
+    let short = Config {
+        interval: 300,
+        retry: 20,
+        ..Config::default()
+    };
+    for config in [Config::default(), short] {
+        let delays: Vec<String> = [1, 2, 3, 4, 5, 8, 9, 40, u32::MAX]
+            .into_iter()
+            .map(|failures| format!("{}", config.retry_delay(failures).as_secs()))
+            .collect();
+        let _ = writeln!(
+            out,
+            "retry {} s, interval {} s: {}",
+            config.retry,
+            config.interval,
+            delays.join(" ")
+        );
+    }
+}
+
+/// The DHCPINFORM for the NTP servers and the servers read from answers.
+fn dhcp_servers(out: &mut String) {
+    const XID: u32 = 0x5eed_0042;
+    const MAC: [u8; 6] = [0x24, 0x6f, 0x28, 0x12, 0x1a, 0x2b];
+    let mut buf = [0u8; 576];
+    out.push_str("\n=== dhcp inform\n");
+    let len =
+        dhcp::inform(&mut buf, XID, MAC, [192, 168, 1, 42], &[OPTION_NTP_SERVERS]).expect("fits");
+    hex(out, &buf[..len]);
+
+    let answer = |xid: u32, message_type: u8, servers: &[u8]| {
+        let mut buf = [0u8; 576];
+        let mut message = Builder::new(&mut buf, 2, xid, MAC)
+            .unwrap()
+            .option(OPTION_MESSAGE_TYPE, &[message_type])
+            .unwrap();
+        if !servers.is_empty() {
+            message = message.option(OPTION_NTP_SERVERS, servers).unwrap();
+        }
+        let len = message.finish();
+        buf[..len].to_vec()
+    };
+    let answers = [
+        (
+            "two servers",
+            answer(XID, 5, &[192, 168, 1, 1, 10, 0, 0, 123]),
+        ),
+        ("no servers", answer(XID, 5, &[])),
+        ("odd length", answer(XID, 5, &[192, 168, 1, 1, 10, 0])),
+        ("other transaction", answer(XID + 1, 5, &[192, 168, 1, 1])),
+        ("nak", answer(XID, 6, &[192, 168, 1, 1])),
+    ];
+    for (name, message) in answers {
+        let _ = write!(out, "answer {name}: ");
+        let Some(packet) = Packet::parse(&message).filter(|p| p.is_ack_for(XID)) else {
+            out.push_str("ignored\n");
+            continue;
+        };
+        let servers: Vec<String> = packet
+            .addresses(OPTION_NTP_SERVERS)
+            .map(|[a, b, c, d]| format!("{a}.{b}.{c}.{d}"))
+            .collect();
+        let _ = writeln!(out, "servers [{}]", servers.join(", "));
+    }
+}
+
+/// `Date` headers as servers send them, parsed.
+fn http_dates(out: &mut String) {
+    out.push_str("\n=== http dates\n");
+    let dates = [
+        "Sun, 06 Nov 1994 08:49:37 GMT",
+        "Sunday, 06-Nov-94 08:49:37 GMT",
+        "Sun Nov  6 08:49:37 1994",
+        "Sun, 15 Jun 2025 15:06:40 GMT",
+        "Sun, 6 Jun 2025 15:06:40 GMT",
+        "sun, 15 JUN 2025 15:06:40 gmt",
+        "Sun, 15 Jun 2025 15:06:40 UTC",
+        "Sun, 15 Jun 2025 15:06:40 +0000",
+        "Sun, 15 Jun 2025 17:06:40 +0200",
+        "Sun, 15 Jun 2025 10:06:40 -0500",
+        "15 Jun 2025 15:06:40 GMT",
+        "Sun,15 Jun 2025 15:06:40 GMT",
+        "Sun, 15 Jun 2025 15:06 GMT",
+        "Sunday, 15-Jun-25 15:06:40 GMT",
+        "Thu, 01-Jan-70 00:00:00 GMT",
+        "Wed, 31 Dec 2036 23:59:60 GMT",
+        "Sun Jun 15 15:06:40 2025 GMT",
+        "Sun, 15 June 2025 15:06:40 GMT",
+        "Sun, 31 Jun 2025 15:06:40 GMT",
+        "Sun, 15 Jun 2025 25:06:40 GMT",
+        "Sun, 15 Jun 2025 15:06:40 PST",
+        "Sun, 15 Jun 2025 15:06:40 GMT GMT",
+        "Sun, 15 Foo 2025 15:06:40 GMT",
+        "Sun, 15 Junk 2025 15:06:40 GMT",
+        "Sun, 15 Jun 125 15:06:40 GMT",
+        "1750000000",
+        "",
+    ];
+    for date in dates {
+        let parsed = http::parse_date(date);
+        let _ = writeln!(
+            out,
+            "{date:?} -> {}",
+            parsed.map_or("invalid".into(), |time| time.to_string())
+        );
+    }
+}
+
+/// The `HEAD` request of the fallback and the sample taken from answers,
+/// sent at [`SENT`] and answered 80 ms later.
+fn http_fallback(out: &mut String) {
+    out.push_str("\n=== http fallback\n");
+    let url = Url::parse(ntp::DEFAULT_HTTP).expect("valid URL");
+    let mut request = String::new();
+    let _ = http::write_request(&mut request, "HEAD", &url);
+    for line in request.split_terminator("\r\n") {
+        let _ = writeln!(out, "> {line}");
+    }
+
+    let answers = [
+        "HTTP/1.1 301 Moved Permanently\r\nServer: nginx\r\n\
+         Date: Sun, 15 Jun 2025 15:06:43 GMT\r\nLocation: https://www.ntppool.org/\r\n\r\n",
+        "HTTP/1.1 200 OK\r\ndate: Sun, 15 Jun 2025 15:06:40 GMT\r\n\r\n",
+        "HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nDate: yesterday\r\n\r\n",
+    ];
+    for answer in answers {
+        let date = Response::parse_head(answer.as_bytes())
+            .ok()
+            .and_then(|(response, _)| response.date());
+        let Some(date) = date else {
+            let _ = writeln!(out, "< {:?}: no date", answer.lines().next().unwrap());
+            continue;
+        };
+        let sample = Sample::from_seconds(date, SENT, SENT + 80_000);
+        let verdict = match sample.offset.abs() > sample.bound() {
+            true => "set the clock",
+            false => "clock close enough",
+        };
+        let _ = writeln!(out, "< {date}: {sample:?}, {verdict}");
+    }
+}
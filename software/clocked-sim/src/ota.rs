@@ -0,0 +1,491 @@
+//! Over-the-air updates on a simulated flash.
+//!
+//! Checks made-up app images, intact and broken ones, signed and unsigned,
+//! whole and in small pieces as they come from the network. Then plays updates through on an
+//! in-memory flash with the `otadata` sectors and two small app partitions:
+//! confirmed ones, ones that crash or never reach the network and are rolled
+//! back, and broken ones that are never selected. The transcript is compared
+//! with `golden/ota.txt`, see [`crate::transcript`].
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use clocked_core::{
+    ed25519::{self, PublicKey, Seed},
+    flash::MemFlash,
+    ota::{
+        self, Boot, ImageError, ImageState, Otadata, Slot, Trial, Update, Verifier, CHIP_ESP32S3,
+        HEALTH_TIMEOUT, OTADATA_SIZE, SIGNATURE_BLOCK_LEN,
+    },
+    sha256::{sha256, Digest},
+};
+
+use crate::{golden::Mode, transcript};
+
+const OTADATA: u32 = 0;
+const SLOT_SIZE: u32 = 0x4000;
+const FLASH_SIZE: usize = (OTADATA_SIZE + 2 * SLOT_SIZE) as usize;
+/// Bytes per piece when an image arrives over the network.
+const CHUNKS: [usize; 2] = [7, 1];
+/// Entry point in the made-up images.
+const ENTRY: u32 = 0x4037_5a40;
+/// Secret key the images are signed with, the clock knows its public key.
+const UPDATE_KEY: Seed = [0x5e; 32];
+/// Secret key of someone else.
+const OTHER_KEY: Seed = [0xa1; 32];
+
+type Flash = MemFlash<FLASH_SIZE>;
+
+fn slot_base(slot: Slot) -> u32 {
+    match slot {
+        Slot::Ota0 => OTADATA_SIZE,
+        Slot::Ota1 => OTADATA_SIZE + SLOT_SIZE,
+    }
+}
+
+/// Run the fixtures, returns `false` if the transcript differs.
+pub fn run(mode: Mode) -> bool {
+    transcript::run(
+        "ota",
+        || {
+            let (transcript, images) = transcript();
+            Ok((
+                transcript,
+                format!("{images} images, {} updates", UPDATES.len()),
+            ))
+        },
+        mode,
+    )
+}
+
+fn transcript() -> (String, usize) {
+    let mut transcript = String::from(
+        "# OTA transcript, see src/ota.rs\n\
+         # image checks, then updates on a flash with otadata, ota_0 and ota_1\n",
+    );
+    let images = verified(&mut transcript);
+    entry(&mut transcript);
+    for (name, update) in UPDATES {
+        let _ = writeln!(transcript, "\n=== {name}");
+        let mut clock = Clock::new(&mut transcript);
+        update(&mut clock);
+    }
+    (transcript, images)
+}
+
+/// A made-up app image for `chip` with the given segments.
+fn image(chip: u16, segments: &[(u32, &[u8])]) -> Vec<u8> {
+    let mut image = vec![0xe9, segments.len() as u8, 0x02, 0x20];
+    image.extend(ENTRY.to_le_bytes());
+    image.extend([0xee, 0, 0, 0]);
+    image.extend(chip.to_le_bytes());
+    image.extend([0; 9]);
+    image.push(1);
+    let mut checksum = 0xef;
+    for (address, data) in segments {
+        image.extend(address.to_le_bytes());
+        image.extend((data.len() as u32).to_le_bytes());
+        image.extend(*data);
+        checksum = data.iter().fold(checksum, |sum, b| sum ^ b);
+    }
+    while image.len() % 16 != 15 {
+        image.push(0);
+    }
+    image.push(checksum);
+    let digest = sha256(&image);
+    image.extend(digest);
+    image
+}
+
+/// `file` signed with `seed` like the signing tool does.
+fn signed(mut file: Vec<u8>, seed: &Seed) -> Vec<u8> {
+    let mut verifier = Verifier::new(CHIP_ESP32S3, u32::MAX, None, [0; 32]);
+    let _ = verifier.update(&file);
+    let digest = verifier.appended().expect("an image with a digest");
+    file.extend(ota::signature_block(seed, &digest));
+    file
+}
+
+fn update_key() -> PublicKey {
+    ed25519::public_key(&UPDATE_KEY)
+}
+
+/// The image most cases start from before signing it, `version` changes its
+/// contents.
+fn unsigned(version: u8) -> Vec<u8> {
+    let code: Vec<u8> = (0..600u32).map(|i| (i * 7) as u8 ^ version).collect();
+    let data: Vec<u8> = (0..52u32).map(|i| (i * 13 + 5) as u8).collect();
+    image(
+        CHIP_ESP32S3,
+        &[
+            (0x3c00_0020, &data),
+            (0x4200_0020, &code),
+            (0x3fc8_8000, &[]),
+        ],
+    )
+}
+
+/// A signed release.
+fn release(version: u8) -> Vec<u8> {
+    signed(unsigned(version), &UPDATE_KEY)
+}
+
+struct Case {
+    name: &'static str,
+    file: Vec<u8>,
+    expected: Option<Digest>,
+    max_len: u32,
+}
+
+fn cases() -> Vec<Case> {
+    let good = release(1);
+    let case = |name, file: Vec<u8>| Case {
+        name,
+        file,
+        expected: None,
+        max_len: SLOT_SIZE,
+    };
+    let flipped = |at: usize| {
+        let mut file = good.clone();
+        file[at] ^= 0x10;
+        file
+    };
+    // end of the image, the signature block follows
+    let len = good.len() - SIGNATURE_BLOCK_LEN;
+    let mut no_digest = good.clone();
+    no_digest[23] = 0;
+    let mut odd_segment = image(CHIP_ESP32S3, &[(0x3c00_0020, &[1, 2, 3])]);
+    odd_segment.truncate(40);
+    let mut trailer = unsigned(1);
+    trailer.extend([0xff; 20]);
+    let trailer = signed(trailer, &UPDATE_KEY);
+    let mut after_signature = good.clone();
+    after_signature.extend([0xff; 20]);
+    let mut signed_twice = good.clone();
+    signed_twice.extend(&good[len..]);
+    let mut wrong_magic = good.clone();
+    wrong_magic[len] = b'X';
+
+    vec![
+        case("release", good.clone()),
+        Case {
+            expected: Some(sha256(&good)),
+            ..case("release with published digest", good.clone())
+        },
+        Case {
+            expected: Some(sha256(b"another release")),
+            ..case("release with other digest", good.clone())
+        },
+        case("erased flash after the digest", trailer),
+        case(
+            "single segment",
+            signed(
+                image(CHIP_ESP32S3, &[(0x4200_0020, &[0x5a; 12])]),
+                &UPDATE_KEY,
+            ),
+        ),
+        case("unsigned", unsigned(1)),
+        case("signed with another key", signed(unsigned(1), &OTHER_KEY)),
+        case("signature of another release", {
+            let mut file = unsigned(1);
+            file.extend(&release(2)[len..]);
+            file
+        }),
+        case("flipped bit in the signature", flipped(good.len() - 3)),
+        case("signature without its magic", wrong_magic),
+        case("erased flash after the signature", after_signature),
+        case("signature block twice", signed_twice),
+        case("cut off in the signature", good[..good.len() - 1].to_vec()),
+        case("esp32 image", image(0, &[(0x4200_0020, &[0x5a; 12])])),
+        case("not an image", b"<html>Not Found</html>".repeat(8)),
+        case("no segments", {
+            let mut file = good.clone();
+            file[1] = 0;
+            file
+        }),
+        case("without digest", no_digest),
+        case("segment length not a multiple of 4", odd_segment),
+        case("flipped bit in a segment", flipped(100)),
+        case("flipped bit in the entry point", flipped(5)),
+        case("flipped bit in the digest", flipped(len - 3)),
+        case("cut off in a segment", good[..300].to_vec()),
+        case("cut off in the digest", good[..len - 1].to_vec()),
+        case("empty", Vec::new()),
+        Case {
+            max_len: 512,
+            ..case("larger than the partition", good.clone())
+        },
+    ]
+}
+
+fn verify(case: &Case, chunk: usize) -> Result<ota::Image, ImageError> {
+    let mut verifier = Verifier::new(CHIP_ESP32S3, case.max_len, case.expected, update_key());
+    for piece in case.file.chunks(chunk.max(1)) {
+        verifier.update(piece)?;
+    }
+    verifier.finish()
+}
+
+fn verified(out: &mut String) -> usize {
+    let cases = cases();
+    for case in &cases {
+        let _ = writeln!(
+            out,
+            "\n=== verify {} ({} bytes)",
+            case.name,
+            case.file.len()
+        );
+        let whole = verify(case, case.file.len());
+        match &whole {
+            Ok(image) => {
+                let _ = writeln!(
+                    out,
+                    "ok: segments {}, entry {:#010x}, sha256 {}",
+                    image.segments,
+                    image.entry,
+                    hex(&image.digest)
+                );
+            }
+            Err(e) => {
+                let _ = writeln!(out, "error: {e:?}");
+            }
+        }
+        for chunk in CHUNKS {
+            let pieces = verify(case, chunk);
+            if pieces != whole {
+                let _ = writeln!(out, "in pieces of {chunk} bytes: {pieces:?}");
+            }
+        }
+    }
+    cases.len()
+}
+
+fn entry(out: &mut String) {
+    let _ = writeln!(out, "\n=== otadata entry");
+    let entry = ota::Entry {
+        sequence: 1,
+        state: ImageState::Valid,
+    };
+    for line in entry.encode().chunks(16) {
+        let bytes: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+        let _ = writeln!(out, "{}", bytes.join(" "));
+    }
+}
+
+/// A clock with its flash, restarted and updated by the scenarios.
+struct Clock<'a> {
+    out: &'a mut String,
+    flash: Box<Flash>,
+    otadata: Otadata,
+    /// Uptime since the last start.
+    uptime: Duration,
+    trial: Option<Trial>,
+}
+
+impl<'a> Clock<'a> {
+    /// Flashed by cable: `ota_0` holds an image and `otadata` is erased.
+    fn new(out: &'a mut String) -> Self {
+        let mut flash = Box::new(Flash::new());
+        let base = slot_base(Slot::Ota0) as usize;
+        let image = release(0);
+        flash.data_mut()[base..base + image.len()].copy_from_slice(&image);
+        let mut clock = Self {
+            out,
+            flash,
+            otadata: Otadata::default(),
+            uptime: Duration::ZERO,
+            trial: None,
+        };
+        clock.start();
+        clock
+    }
+
+    fn log(&mut self, line: &str) {
+        let _ = writeln!(self.out, "{line}");
+    }
+
+    /// Start like the firmware does.
+    fn start(&mut self) {
+        self.uptime = Duration::ZERO;
+        self.trial = None;
+        self.otadata = Otadata::read(&mut *self.flash, OTADATA).unwrap();
+        let slot = self.otadata.boot_slot();
+        let boot = ota::boot(&self.otadata);
+        let _ = writeln!(self.out, "start {}: {boot:?}", slot.name());
+        match boot {
+            Boot::Confirmed => {}
+            Boot::Trial => {
+                self.otadata
+                    .mark(&mut *self.flash, OTADATA, ImageState::PendingVerify)
+                    .unwrap();
+                self.trial = Some(Trial::new(self.uptime));
+            }
+            Boot::RollBack => {
+                self.roll_back();
+                return;
+            }
+        }
+        self.dump();
+    }
+
+    fn roll_back(&mut self) {
+        let slot = self.otadata.roll_back(&mut *self.flash, OTADATA).unwrap();
+        let _ = writeln!(self.out, "rolled back to {}", slot.name());
+        self.dump();
+        self.start();
+    }
+
+    /// Run for `seconds`, `healthy` once on the network.
+    fn run(&mut self, seconds: u64, healthy: bool) {
+        self.uptime += Duration::from_secs(seconds);
+        let Some(trial) = self.trial else {
+            return;
+        };
+        let verdict = trial.check(self.uptime, healthy);
+        let _ = writeln!(
+            self.out,
+            "at {}s {}: {verdict:?}",
+            self.uptime.as_secs(),
+            if healthy { "healthy" } else { "offline" }
+        );
+        match verdict {
+            Some(ota::Verdict::Confirm) => {
+                self.trial = None;
+                self.otadata
+                    .mark(&mut *self.flash, OTADATA, ImageState::Valid)
+                    .unwrap();
+                self.dump();
+            }
+            Some(ota::Verdict::RollBack) => self.roll_back(),
+            None => {}
+        }
+    }
+
+    /// Write `file` to the other slot in pieces and select it if it is good.
+    fn update(&mut self, file: &[u8]) {
+        let slot = self.otadata.boot_slot().other();
+        let _ = writeln!(self.out, "update {} ({} bytes)", slot.name(), file.len());
+        let result = Update::new(
+            &mut *self.flash,
+            slot_base(slot),
+            SLOT_SIZE,
+            CHIP_ESP32S3,
+            None,
+            update_key(),
+        )
+        .and_then(|mut update| {
+            for piece in file.chunks(100) {
+                update.write(piece)?;
+            }
+            update.finish()
+        });
+        match result {
+            Ok((_, image)) => {
+                let _ = writeln!(self.out, "ok: sha256 {}", hex(&image.digest));
+                self.otadata
+                    .select(&mut *self.flash, OTADATA, slot)
+                    .unwrap();
+                self.dump();
+            }
+            Err(e) => {
+                let _ = writeln!(self.out, "error: {e:?}");
+            }
+        }
+    }
+
+    fn dump(&mut self) {
+        let entries: Vec<String> = self
+            .otadata
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                Some(entry) => format!(
+                    "{} {} {:?}",
+                    entry.sequence,
+                    entry.slot().name(),
+                    entry.state
+                ),
+                None => "-".into(),
+            })
+            .collect();
+        let _ = writeln!(self.out, "otadata: {}", entries.join(" | "));
+    }
+}
+
+type Scenario = fn(&mut Clock<'_>);
+
+const UPDATES: &[(&str, Scenario)] = &[
+    ("confirmed update", |clock| {
+        clock.update(&release(1));
+        clock.start();
+        clock.run(20, false);
+        clock.run(10, true);
+        clock.start();
+    }),
+    ("second update", |clock| {
+        clock.update(&release(1));
+        clock.start();
+        clock.run(10, true);
+        clock.update(&release(2));
+        clock.start();
+        clock.run(10, true);
+        clock.start();
+    }),
+    ("update that never reaches the network", |clock| {
+        clock.update(&release(1));
+        clock.start();
+        clock.run(30, true);
+        clock.update(&release(2));
+        clock.start();
+        clock.run(HEALTH_TIMEOUT.as_secs() - 1, false);
+        clock.run(1, false);
+    }),
+    ("update that crashes", |clock| {
+        clock.update(&release(1));
+        clock.start();
+        clock.run(30, true);
+        clock.update(&release(2));
+        clock.start();
+        clock.run(3, false);
+        clock.log("crash");
+        clock.start();
+    }),
+    ("first update of a clock flashed by cable fails", |clock| {
+        clock.update(&release(1));
+        clock.start();
+        clock.log("crash");
+        clock.start();
+        clock.run(30, true);
+        clock.update(&release(1));
+        clock.start();
+        clock.run(30, true);
+    }),
+    ("broken update", |clock| {
+        let mut broken = release(1);
+        broken[200] ^= 1;
+        clock.update(&broken);
+        clock.update(&release(1)[..500]);
+        clock.update(&image(0, &[(0x4200_0020, &[0; 8])]));
+        clock.update(&unsigned(1));
+        clock.update(&signed(unsigned(1), &OTHER_KEY));
+        clock.start();
+    }),
+    ("torn otadata entry", |clock| {
+        clock.update(&release(1));
+        clock.start();
+        clock.run(30, true);
+        clock.update(&release(2));
+        clock.start();
+        clock.run(30, true);
+        // cut the power while the active entry was rewritten
+        let active = clock.otadata.active().unwrap() as u32;
+        let at = (OTADATA + active * 0x1000 + 28) as usize;
+        clock.flash.data_mut()[at] ^= 0xff;
+        clock.log("tear the active entry");
+        clock.start();
+    }),
+];
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,50 @@
+//! Current budget check with worst-case frames.
+//!
+//! `clocked-sim power` runs a few frames that are far brighter than any
+//! animation through the firmware's [`Limiter`] at full brightness and fails
+//! if any of them would still exceed the budget.
+
+use clocked_core::{
+    layout::Layout,
+    power::{self, Limiter},
+    ring::{Frame, BLACK, RING_SIZE},
+};
+use rgb::RGB8;
+
+fn frames() -> [(&'static str, Frame); 4] {
+    let mut every_other = [BLACK; RING_SIZE];
+    for pixel in every_other.iter_mut().step_by(2) {
+        *pixel = RGB8::new(255, 255, 255);
+    }
+    [
+        ("all white", [RGB8::new(255, 255, 255); RING_SIZE]),
+        ("all warm white", [RGB8::new(255, 180, 100); RING_SIZE]),
+        ("all red", [RGB8::new(255, 0, 0); RING_SIZE]),
+        ("every other white", every_other),
+    ]
+}
+
+/// Returns `false` if a frame exceeds `max_ma` on a strip with `layout`
+/// after limiting.
+pub fn run(max_ma: u32, layout: &Layout) -> bool {
+    let limiter = Limiter::new(max_ma);
+    let mut ok = true;
+    println!("budget {max_ma} mA, {} LEDs", layout.leds);
+    for (name, frame) in frames() {
+        let requested_ma = power::estimate_ma(layout.map(&frame));
+        match limiter.limit(layout.map(&frame), 255) {
+            None => println!("{name}: {requested_ma} mA, within budget"),
+            Some(limited) => {
+                let fits = limited.limited_ma <= max_ma;
+                ok &= fits;
+                println!(
+                    "{name}: {requested_ma} mA, limited to {} mA at brightness {}{}",
+                    limited.limited_ma,
+                    limited.level,
+                    if fits { "" } else { ", OVER BUDGET" }
+                );
+            }
+        }
+    }
+    ok
+}
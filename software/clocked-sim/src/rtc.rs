@@ -0,0 +1,350 @@
+//! Fixtures for the battery-backed RTC chips.
+//!
+//! Runs the DS3231 and PCF8563 drivers against a made-up I²C bus with the
+//! register files of the chips, as they come from the factory, and logs
+//! every transfer. Covers the BCD encoding, the century bit from 2099 to
+//! 2100, the 12 hour mode of the DS3231, the flags for a lost time, invalid
+//! registers, the aging offset, finding the chip and a bus without one. Then
+//! walks the write-back through weeks of syncs. The transcript is compared
+//! with `golden/rtc.txt`, see [`crate::transcript`].
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::rc::Rc;
+
+use chrono::{NaiveDateTime, TimeDelta};
+use clocked_core::rtc::{
+    self, bcd, from_bcd, Bus, Ds3231, Pcf8563, TimeSource, WriteBack, MAX_ERROR, MIN_TRIM_INTERVAL,
+};
+
+use crate::{golden::Mode, transcript};
+
+const DS3231: u8 = 0x68;
+const PCF8563: u8 = 0x51;
+/// Registers after the first power-up, the DS3231 has its oscillator stop
+/// flag and the PCF8563 its voltage low flag set.
+const DS3231_RESET: &[(u8, u8)] = &[(0x0e, 0x1c), (0x0f, 0x88)];
+const PCF8563_RESET: &[(u8, u8)] = &[(0x00, 0x08), (0x02, 0x80)];
+
+/// Chips on a bus by address, with the registers to set at the start.
+type Chips = [(u8, &'static [(u8, u8)])];
+
+const TIMES: &[&str] = &[
+    "2026-10-17T14:30:20",
+    "2028-02-29T08:05:09",
+    "2099-12-31T23:59:59",
+    "2100-01-01T00:00:00",
+    "2199-12-31T23:59:59",
+    // the chips can't hold these
+    "1999-12-31T23:59:59",
+    "2200-01-01T00:00:00",
+];
+
+/// Run the fixtures, returns `false` if the transcript differs.
+pub fn run(mode: Mode) -> bool {
+    transcript::run(
+        "rtc",
+        || Ok((transcript(), format!("{} times on 2 chips", TIMES.len()))),
+        mode,
+    )
+}
+
+fn time(text: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").expect("valid fixture time")
+}
+
+/// A chip that doesn't acknowledge its address.
+#[derive(Debug)]
+struct Nack;
+
+#[derive(Default)]
+struct State {
+    /// Register files by address.
+    chips: BTreeMap<u8, [u8; 0x20]>,
+    log: Vec<String>,
+}
+
+/// The bus with the chips on it, clones share them so the fixture can look
+/// at the registers while a driver owns the bus.
+#[derive(Clone, Default)]
+struct Mock(Rc<RefCell<State>>);
+
+impl Mock {
+    fn with(chips: &Chips) -> Self {
+        let mock = Self::default();
+        for (address, reset) in chips {
+            mock.0.borrow_mut().chips.insert(*address, [0; 0x20]);
+            for (register, value) in *reset {
+                mock.poke(*address, *register, &[*value]);
+            }
+        }
+        mock
+    }
+
+    fn poke(&self, address: u8, register: u8, bytes: &[u8]) {
+        let mut state = self.0.borrow_mut();
+        let registers = state.chips.get_mut(&address).expect("chip on the bus");
+        let start = register as usize;
+        registers[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn peek(&self, address: u8, register: u8) -> u8 {
+        self.0.borrow().chips[&address][register as usize]
+    }
+
+    /// The transfers since the last call, indented.
+    fn take_log(&self, out: &mut String) {
+        for line in self.0.borrow_mut().log.drain(..) {
+            let _ = writeln!(out, "  {line}");
+        }
+    }
+
+    /// The register file at `address`, logs a NACK if there is none.
+    fn transfer<T>(
+        &mut self,
+        address: u8,
+        f: impl FnOnce(&mut [u8; 0x20]) -> (String, T),
+    ) -> Result<T, Nack> {
+        let mut state = self.0.borrow_mut();
+        let Some(registers) = state.chips.get_mut(&address) else {
+            state.log.push(format!("{address:02x} nack"));
+            return Err(Nack);
+        };
+        let (line, result) = f(registers);
+        state.log.push(format!("{address:02x} {line}"));
+        Ok(result)
+    }
+}
+
+impl Bus for Mock {
+    type Error = Nack;
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Nack> {
+        self.transfer(address, |registers| {
+            let start = write[0] as usize;
+            registers[start..start + write.len() - 1].copy_from_slice(&write[1..]);
+            (format!("write {}", hex(write)), ())
+        })
+    }
+
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Nack> {
+        self.transfer(address, |registers| {
+            let start = write[0] as usize;
+            read.copy_from_slice(&registers[start..start + read.len()]);
+            (format!("write {} read {}", hex(write), hex(read)), ())
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    bytes.join(" ")
+}
+
+fn transcript() -> String {
+    let mut out = String::from(
+        "# RTC chip transcript, see src/rtc.rs\n\
+         # register encoding of the DS3231 and PCF8563 and the write-back\n",
+    );
+    encoding(&mut out);
+    round_trips(&mut out);
+    registers(&mut out);
+    aging(&mut out);
+    detect(&mut out);
+    write_back(&mut out);
+    out
+}
+
+fn encoding(out: &mut String) {
+    out.push_str("\n=== BCD\n");
+    for value in [0, 7, 10, 42, 59, 99] {
+        let _ = writeln!(out, "{value} -> {:02x}", bcd(value));
+    }
+    for byte in [0x00, 0x09, 0x59, 0x99, 0x0a, 0xa0, 0xff] {
+        let _ = writeln!(out, "{byte:02x} -> {:?}", from_bcd(byte));
+    }
+}
+
+fn round_trips(out: &mut String) {
+    let mock = Mock::with(&[(DS3231, DS3231_RESET)]);
+    let mut chip = Ds3231::new(mock.clone());
+    let _ = writeln!(out, "\n=== DS3231 from the factory: {:?}", chip.read());
+    mock.take_log(out);
+    for text in TIMES {
+        let _ = writeln!(out, "write {text}: {:?}", chip.write(time(text)));
+        mock.take_log(out);
+        let _ = writeln!(out, "read {:?}", chip.read());
+        mock.take_log(out);
+    }
+
+    let mock = Mock::with(&[(PCF8563, PCF8563_RESET)]);
+    let mut chip = Pcf8563::new(mock.clone());
+    let _ = writeln!(out, "\n=== PCF8563 from the factory: {:?}", chip.read());
+    mock.take_log(out);
+    for text in TIMES {
+        let _ = writeln!(out, "write {text}: {:?}", chip.write(time(text)));
+        mock.take_log(out);
+        let _ = writeln!(out, "read {:?}", chip.read());
+        mock.take_log(out);
+    }
+}
+
+fn registers(out: &mut String) {
+    out.push_str("\n=== DS3231 registers\n");
+    let cases: &[(&str, [u8; 7], u8)] = &[
+        (
+            "2099 ticked over",
+            [0x00, 0x00, 0x00, 0x05, 0x01, 0x81, 0x00],
+            0x08,
+        ),
+        ("12 AM", [0x00, 0x15, 0x52, 0x06, 0x17, 0x10, 0x26], 0x08),
+        ("1 AM", [0x00, 0x15, 0x41, 0x06, 0x17, 0x10, 0x26], 0x08),
+        ("12 PM", [0x00, 0x15, 0x72, 0x06, 0x17, 0x10, 0x26], 0x08),
+        ("11 PM", [0x00, 0x15, 0x71, 0x06, 0x17, 0x10, 0x26], 0x08),
+        ("0 PM", [0x00, 0x15, 0x60, 0x06, 0x17, 0x10, 0x26], 0x08),
+        ("13 PM", [0x00, 0x15, 0x73, 0x06, 0x17, 0x10, 0x26], 0x08),
+        ("hour 24", [0x00, 0x15, 0x24, 0x06, 0x17, 0x10, 0x26], 0x08),
+        (
+            "seconds 5a",
+            [0x5a, 0x15, 0x14, 0x06, 0x17, 0x10, 0x26],
+            0x08,
+        ),
+        ("month 13", [0x00, 0x15, 0x14, 0x06, 0x17, 0x13, 0x26], 0x08),
+        (
+            "February 30",
+            [0x00, 0x15, 0x14, 0x06, 0x30, 0x02, 0x26],
+            0x08,
+        ),
+        (
+            "oscillator stopped",
+            [0x00, 0x15, 0x14, 0x06, 0x17, 0x10, 0x26],
+            0x88,
+        ),
+    ];
+    for (name, time, status) in cases {
+        let mock = Mock::with(&[(DS3231, &[])]);
+        mock.poke(DS3231, 0x00, time);
+        mock.poke(DS3231, 0x0f, &[*status]);
+        let read = Ds3231::new(mock).read();
+        let _ = writeln!(out, "{name}: {} status {status:02x}: {read:?}", hex(time));
+    }
+
+    out.push_str("\n=== PCF8563 registers\n");
+    let cases: &[(&str, [u8; 7])] = &[
+        (
+            "2099 ticked over",
+            [0x00, 0x00, 0x00, 0x01, 0x05, 0x81, 0x00],
+        ),
+        (
+            "unused bits set",
+            [0x00, 0x95, 0xd4, 0xd7, 0xfe, 0x70, 0x26],
+        ),
+        ("seconds 5a", [0x5a, 0x15, 0x14, 0x17, 0x06, 0x10, 0x26]),
+        ("day 0", [0x00, 0x15, 0x14, 0x00, 0x06, 0x10, 0x26]),
+        ("voltage low", [0x80, 0x15, 0x14, 0x17, 0x06, 0x10, 0x26]),
+    ];
+    for (name, time) in cases {
+        let mock = Mock::with(&[(PCF8563, &[])]);
+        mock.poke(PCF8563, 0x02, time);
+        let read = Pcf8563::new(mock).read();
+        let _ = writeln!(out, "{name}: {}: {read:?}", hex(time));
+    }
+}
+
+fn aging(out: &mut String) {
+    out.push_str("\n=== DS3231 aging offset\n");
+    let mock = Mock::with(&[(DS3231, DS3231_RESET)]);
+    let mut chip = Ds3231::new(mock.clone());
+    for ppb in [0, 49, 50, 250, -149, -150, 20_000, -1_000, -40_000] {
+        let result = chip.trim(ppb);
+        let aging = mock.peek(DS3231, 0x10);
+        let _ = writeln!(
+            out,
+            "trim {ppb} ppb: {result:?}, aging {aging:02x} ({})",
+            aging as i8
+        );
+        mock.take_log(out);
+    }
+
+    out.push_str("\n=== PCF8563 trim\n");
+    let mock = Mock::with(&[(PCF8563, PCF8563_RESET)]);
+    let result = Pcf8563::new(mock.clone()).trim(500);
+    let _ = writeln!(out, "trim 500 ppb: {result:?}");
+    mock.take_log(out);
+}
+
+fn detect(out: &mut String) {
+    out.push_str("\n=== detect\n");
+    let buses: &[(&str, &Chips)] = &[
+        ("DS3231", &[(DS3231, DS3231_RESET)]),
+        ("PCF8563", &[(PCF8563, PCF8563_RESET)]),
+        ("both", &[(DS3231, DS3231_RESET), (PCF8563, PCF8563_RESET)]),
+        ("none", &[]),
+    ];
+    for (name, chips) in buses {
+        let mock = Mock::with(chips);
+        match rtc::detect(mock.clone()) {
+            Ok(mut chip) => {
+                let _ = writeln!(out, "{name}: found {}", chip.name());
+                mock.take_log(out);
+                let text = TIMES[0];
+                let _ = writeln!(out, "write {text}: {:?}", chip.write(time(text)));
+                mock.take_log(out);
+                let _ = writeln!(out, "read {:?}", chip.read());
+                mock.take_log(out);
+            }
+            Err(_) => {
+                let _ = writeln!(out, "{name}: no chip");
+                mock.take_log(out);
+            }
+        }
+    }
+
+    out.push_str("\n=== chip gone\n");
+    let mock = Mock::with(&[]);
+    let _ = writeln!(out, "DS3231 read {:?}", Ds3231::new(mock.clone()).read());
+    let _ = writeln!(
+        out,
+        "PCF8563 write {:?}",
+        Pcf8563::new(mock.clone()).write(time(TIMES[0]))
+    );
+    let _ = writeln!(out, "DS3231 trim {:?}", Ds3231::new(mock.clone()).trim(500));
+    mock.take_log(out);
+}
+
+fn write_back(out: &mut String) {
+    let _ = writeln!(
+        out,
+        "\n=== write-back, off by {} s, trim after {} days",
+        MAX_ERROR.num_seconds(),
+        MIN_TRIM_INTERVAL.num_days()
+    );
+    let start = time("2026-10-01T12:00:00");
+    // days since the start, how many seconds the chip is ahead and whether
+    // the time came from NTP
+    let syncs: &[(&str, i64, Option<i64>, bool)] = &[
+        ("lost the time", 0, None, true),
+        ("on time", 0, Some(0), true),
+        ("1 s behind", 1, Some(-1), true),
+        ("1 s ahead", 2, Some(1), true),
+        ("2 s ahead, too soon to trim", 3, Some(2), true),
+        ("3 s ahead", 13, Some(3), true),
+        ("2 s behind, set by hand", 20, Some(-2), false),
+        ("3 s behind, last write by hand", 30, Some(-3), true),
+        ("4 s ahead", 90, Some(4), true),
+    ];
+    let mut write_back = WriteBack::new();
+    for (name, days, ahead, precise) in syncs {
+        let second = start + TimeDelta::days(*days);
+        // the clock is somewhere in the second when it asks the chip
+        let now = second + TimeDelta::milliseconds(300);
+        let source = ahead.map(|seconds| second + TimeDelta::seconds(seconds));
+        let update = write_back.update(source, now, *precise);
+        let _ = writeln!(
+            out,
+            "day {days} {name}: chip {}, clock {now}, precise {precise}: {update:?}",
+            source.map_or("lost".into(), |s| s.to_string())
+        );
+    }
+}
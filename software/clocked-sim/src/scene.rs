@@ -0,0 +1,103 @@
+//! What the ring shows, stepped one frame at a time.
+
+use core::time::Duration;
+
+use chrono::{NaiveDateTime, TimeDelta};
+use clocked_core::{
+    animation::{Builtin, Scheduler},
+    ring::{Frame, FRAMES_PER_SECOND},
+    schedule::Block,
+    settings::Settings,
+    timer::Countdown,
+};
+
+use crate::clock::VirtualClock;
+
+/// The firmware's animation scheduler running on the virtual clock, which
+/// moves on by exactly one frame per [`Scene::render`].
+pub struct Scene {
+    clock: VirtualClock,
+    settings: Settings,
+    scheduler: Scheduler,
+    timer: Option<Countdown>,
+    frames: u64,
+}
+
+impl Scene {
+    /// Show the clock with `settings`, the time zone comes from `clock`.
+    pub fn new(clock: VirtualClock, settings: Settings) -> Self {
+        let animation = settings.program(&clock.now_local()).animation;
+        Self {
+            clock,
+            settings,
+            scheduler: Scheduler::new(animation, Duration::ZERO),
+            timer: None,
+            frames: 0,
+        }
+    }
+
+    /// Local time of the next frame.
+    pub fn now(&self) -> NaiveDateTime {
+        self.clock.now_local()
+    }
+
+    pub fn tz_name(&self) -> &str {
+        self.clock.tz_name()
+    }
+
+    /// Schedule block active for the next frame.
+    pub fn block(&self) -> Option<&Block> {
+        self.settings.schedule.active(&self.clock.now_local())
+    }
+
+    /// Cross-fade to `animation` starting with the next frame, unless the
+    /// schedule or night mode override it.
+    pub fn select(&mut self, animation: Builtin) {
+        self.settings.animation = animation;
+    }
+
+    /// Start a countdown of `duration` with the next frame.
+    pub fn start_timer(&mut self, duration: Duration) {
+        self.timer = Some(Countdown::new(duration, elapsed_at(self.frames)));
+        self.scheduler.set_timer(self.timer);
+    }
+
+    /// Show that the time is not set, starting with the next frame.
+    pub fn set_unset(&mut self, unset: bool) {
+        self.scheduler.set_unset(unset);
+    }
+
+    /// Time left on the countdown, `None` without one or once it is up.
+    pub fn timer_remaining(&self) -> Option<Duration> {
+        let elapsed = elapsed_at(self.frames);
+        self.timer
+            .filter(|timer| !timer.is_done(elapsed))
+            .map(|timer| timer.remaining(elapsed))
+    }
+
+    /// Draw the next frame into `data`, returns the brightness to show it
+    /// with before current limiting.
+    pub fn render(&mut self, data: &mut Frame) -> u8 {
+        let elapsed = elapsed_at(self.frames);
+        let now = self.clock.now_local();
+        // same as on the clock, see the firmware's animation module
+        let program = self.settings.program(&now);
+        self.scheduler.select(program.animation, elapsed);
+        self.scheduler.set_wake(program.wake);
+        self.scheduler.set_block(program.block);
+        self.scheduler
+            .render(&now, elapsed, &self.settings.clock_face(), data);
+
+        self.frames += 1;
+        let step = elapsed_at(self.frames) - elapsed;
+        self.clock
+            .advance(TimeDelta::from_std(step).unwrap_or_default());
+        program.level
+    }
+}
+
+/// Time since the start at frame `frames`, rounded up to whole nanoseconds
+/// so every frame lands in its own slot.
+fn elapsed_at(frames: u64) -> Duration {
+    Duration::from_nanos((frames * 1_000_000_000).div_ceil(FRAMES_PER_SECOND as u64))
+}
@@ -0,0 +1,99 @@
+//! Keys and signatures for firmware updates, see [`clocked_core::ota`].
+//!
+//! `clocked-sim keygen <key file>` writes a new secret key and prints its
+//! public key, the firmware is built with it in `CLOCKED_UPDATE_KEY`.
+//! `clocked-sim sign <key file> <image>` appends the signature block to an
+//! image made with `espflash save-image`, only then does the clock take it.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use clocked_core::{
+    ed25519::{self, Seed},
+    ota::{self, ImageError, Verifier, CHIP_ESP32S3},
+};
+
+/// Run `keygen` or `sign` with their arguments, returns what to tell the
+/// user.
+pub fn run(command: &str, args: &[String]) -> Result<String, String> {
+    match (command, args) {
+        ("keygen", [key]) => keygen(Path::new(key)),
+        ("sign", [key, image]) => sign(Path::new(key), Path::new(image)),
+        ("keygen", _) => Err("Usage: clocked-sim keygen <KEY FILE>".into()),
+        _ => Err("Usage: clocked-sim sign <KEY FILE> <IMAGE>".into()),
+    }
+}
+
+fn keygen(path: &Path) -> Result<String, String> {
+    let mut seed: Seed = [0; 32];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut seed))
+        .map_err(|e| format!("Unable to get random bytes ({e})"))?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", hex(&seed)))
+        .map_err(|e| format!("Unable to write {} ({e})", path.display()))?;
+    Ok(format!(
+        "Wrote the secret key to {}, keep it safe\n\
+         Build the firmware with CLOCKED_UPDATE_KEY={}",
+        path.display(),
+        hex(&ed25519::public_key(&seed))
+    ))
+}
+
+fn sign(key: &Path, image: &Path) -> Result<String, String> {
+    let seed = fs::read_to_string(key)
+        .map_err(|e| format!("Unable to read {} ({e})", key.display()))
+        .and_then(|text| parse_seed(text.trim()).ok_or(format!("Invalid key {}", key.display())))?;
+    let public_key = ed25519::public_key(&seed);
+    let file = fs::read(image).map_err(|e| format!("Unable to read {} ({e})", image.display()))?;
+
+    let mut verifier = Verifier::new(CHIP_ESP32S3, u32::MAX, None, public_key);
+    let checked = verifier.update(&file);
+    let appended = verifier.appended();
+    match checked.and_then(|()| verifier.finish()) {
+        Err(ImageError::Unsigned) => {}
+        Ok(_) => return Err(format!("{} is signed already", image.display())),
+        Err(ImageError::BadSignature) => {
+            return Err(format!("{} is signed with another key", image.display()))
+        }
+        Err(e) => return Err(format!("{} is no good image ({e:?})", image.display())),
+    }
+    // an unsigned image got past its digest
+    let digest = appended.ok_or("No digest in the image")?;
+    OpenOptions::new()
+        .append(true)
+        .open(image)
+        .and_then(|mut file| file.write_all(&ota::signature_block(&seed, &digest)))
+        .map_err(|e| format!("Unable to write {} ({e})", image.display()))?;
+    Ok(format!(
+        "Signed {} for the key {}",
+        image.display(),
+        hex(&public_key)
+    ))
+}
+
+fn parse_seed(text: &str) -> Option<Seed> {
+    if text.len() != 64 {
+        return None;
+    }
+    let mut seed = [0; 32];
+    for (byte, pair) in seed.iter_mut().zip(text.as_bytes().chunks_exact(2)) {
+        let pair = std::str::from_utf8(pair).ok()?;
+        if !pair.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(seed)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,117 @@
+//! The LED ring drawn with ANSI escape codes.
+
+use std::f32::consts::TAU;
+use std::io::{self, Write};
+
+use clocked_core::ring::{FrameSink, RING_SIZE};
+use rgb::RGB8;
+
+/// Radius of a ring of [`RING_SIZE`] LEDs in terminal rows, larger strips
+/// get a proportionally larger ring.
+const RADIUS: usize = 12;
+
+/// Draws frames as a ring of colored dots, LED 0 at the top, running
+/// clockwise like on the clock.
+pub struct Terminal<W: Write> {
+    out: W,
+    positions: Vec<(usize, usize)>,
+    rows: usize,
+    columns: usize,
+    caption: String,
+    cleared: bool,
+}
+
+impl<W: Write> Terminal<W> {
+    /// A ring of `leds` LEDs.
+    pub fn new(out: W, leds: usize) -> Self {
+        let radius = RADIUS * leds.max(RING_SIZE) / RING_SIZE;
+        // cells are about twice as high as wide, columns are stretched by
+        // two to keep the ring round
+        let positions = (0..leds)
+            .map(|index| {
+                let angle = index as f32 / leds as f32 * TAU;
+                let r = radius as f32;
+                let row = r - r * angle.cos();
+                let column = 2.0 * (r + r * angle.sin());
+                (row.round() as usize, column.round() as usize)
+            })
+            .collect();
+        Self {
+            out,
+            positions,
+            rows: 2 * radius + 1,
+            columns: 4 * radius + 1,
+            caption: String::new(),
+            cleared: false,
+        }
+    }
+
+    /// Text shown in the middle of the ring.
+    pub fn set_caption(&mut self, caption: &str) {
+        self.caption.clear();
+        self.caption.push_str(caption);
+    }
+
+    fn draw(&mut self, pixels: &[RGB8]) -> io::Result<()> {
+        let mut screen = String::new();
+        if !self.cleared {
+            screen.push_str("\x1b[2J");
+            self.cleared = true;
+        }
+        screen.push_str("\x1b[H");
+        let caption_column = (self.columns - self.caption.len().min(self.columns)) / 2;
+        for row in 0..self.rows {
+            let mut column = 0;
+            while column < self.columns {
+                if row == self.rows / 2 && column == caption_column && !self.caption.is_empty() {
+                    screen.push_str(&self.caption);
+                    column += self.caption.len();
+                    continue;
+                }
+                match self.positions.iter().position(|&p| p == (row, column)) {
+                    Some(index) => {
+                        let color = pixels.get(index).copied().unwrap_or_default();
+                        push_pixel(&mut screen, color);
+                    }
+                    None => screen.push(' '),
+                }
+                column += 1;
+            }
+            screen.push_str("\x1b[K\n");
+        }
+        self.out.write_all(screen.as_bytes())?;
+        self.out.flush()
+    }
+}
+
+fn push_pixel(screen: &mut String, color: RGB8) {
+    use std::fmt::Write as _;
+
+    if color == RGB8::default() {
+        screen.push_str("\x1b[38;5;238m·\x1b[0m");
+        return;
+    }
+    // The LEDs are far brighter than a terminal cell showing the same value,
+    // lift dark colors so dimmed hands stay visible.
+    let lift = |c: u8| (255.0 * (c as f32 / 255.0).powf(1.0 / 2.2)).round() as u8;
+    let _ = write!(
+        screen,
+        "\x1b[38;2;{};{};{}m●\x1b[0m",
+        lift(color.r),
+        lift(color.g),
+        lift(color.b)
+    );
+}
+
+impl<W: Write> FrameSink for Terminal<W> {
+    type Error = io::Error;
+
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<RGB8>,
+    {
+        let pixels: Vec<RGB8> = iterator.into_iter().map(Into::into).collect();
+        self.draw(&pixels)
+    }
+}
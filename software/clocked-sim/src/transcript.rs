@@ -0,0 +1,89 @@
+//! Text transcripts compared with a golden file.
+//!
+//! The REST API, MQTT, mDNS, NTP, manual time, RTC and OTA checks each write
+//! what they did into one transcript, `golden/<name>.txt` holds the expected
+//! one. `clocked-sim <name> check` lists the lines that differ and
+//! `clocked-sim <name> bless` rewrites the file.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::golden::Mode;
+
+/// Differing lines listed before cutting the report short.
+const MAX_REPORTED: usize = 20;
+
+/// Check or bless the transcript of `name`, returns `false` if it differs.
+///
+/// `transcript` returns the transcript along with a summary of what it
+/// covers, e.g. `12 requests`, or a message if it could not be made.
+pub fn run(
+    name: &str,
+    transcript: impl FnOnce() -> Result<(String, String), String>,
+    mode: Mode,
+) -> bool {
+    let path = path(name);
+    let result = transcript()
+        .map_err(|message| format!("  {message}"))
+        .and_then(|(transcript, summary)| match mode {
+            Mode::Check => check(&path, &transcript, &format!("{name} bless"))
+                .map(|()| format!("ok ({summary})")),
+            Mode::Bless => fs::write(&path, &transcript)
+                .map(|()| format!("wrote {}", path.display()))
+                .map_err(|e| format!("  {} ({e})", path.display())),
+        });
+    match result {
+        Ok(message) => {
+            println!("{name}: {message}");
+            true
+        }
+        Err(message) => {
+            println!("{name}: FAILED\n{message}");
+            false
+        }
+    }
+}
+
+fn path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("golden")
+        .join(name)
+        .with_extension("txt")
+}
+
+/// Compare the transcript `actual` with the one stored at `path`, `bless` is
+/// the command updating it.
+fn check(path: &Path, actual: &str, bless: &str) -> Result<(), String> {
+    let expected = fs::read_to_string(path)
+        .map_err(|e| format!("  unable to read {} ({e}), run `{bless}`", path.display()))?;
+    let mut report = String::new();
+    let mut differences = 0;
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for number in 1.. {
+        let (expected, actual) = match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => break,
+            (expected, actual) if expected == actual => continue,
+            pair => pair,
+        };
+        differences += 1;
+        if differences <= MAX_REPORTED {
+            let _ = writeln!(
+                report,
+                "  line {number}:\n    expected {}\n    got      {}",
+                expected.unwrap_or("<end>"),
+                actual.unwrap_or("<end>")
+            );
+        }
+    }
+    if differences > MAX_REPORTED {
+        let _ = writeln!(report, "  ... {} more", differences - MAX_REPORTED);
+    }
+    if report.is_empty() {
+        Ok(())
+    } else {
+        let _ = write!(report, "  {differences} lines differ");
+        Err(report)
+    }
+}
@@ -1,4 +1,13 @@
+use std::{env, fs, io::Write, path::Path};
+
+use flate2::{write::GzEncoder, Compression};
+
+/// Sources of the web UI, see [`bundle_web_ui`].
+const WEB_DIR: &str = "web";
+
 fn main() {
+    bundle_web_ui();
+    check_update_key();
     linker_be_nice();
     println!("cargo:rustc-link-arg=-Tdefmt.x");
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
@@ -39,3 +48,62 @@ fn linker_be_nice() {
         std::env::current_exe().unwrap().display()
     );
 }
+
+/// Make sure `CLOCKED_UPDATE_KEY`, the public key updates over the air are
+/// signed for, is 64 hex digits. Without it the firmware refuses them.
+fn check_update_key() {
+    println!("cargo:rerun-if-env-changed=CLOCKED_UPDATE_KEY");
+    match env::var("CLOCKED_UPDATE_KEY") {
+        Ok(key) if key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit()) => {}
+        Ok(key) => panic!("CLOCKED_UPDATE_KEY is not 64 hex digits: {key}"),
+        Err(_) => println!(
+            "cargo:warning=CLOCKED_UPDATE_KEY is not set, the firmware refuses updates over the air"
+        ),
+    }
+}
+
+/// Inline the style sheets and scripts `web/index.html` links to and gzip the
+/// page into `$OUT_DIR/index.html.gz`, which the firmware serves as is.
+///
+/// Only `<link rel="stylesheet" href="...">` and `<script src="..."></script>`
+/// on a line of their own are inlined, that is all the page uses.
+fn bundle_web_ui() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={WEB_DIR}");
+
+    let dir = Path::new(WEB_DIR);
+    let read = |name: &str| {
+        let path = dir.join(name);
+        println!("cargo:rerun-if-changed={}", path.display());
+        fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("unable to read {}: {e}", path.display()))
+    };
+
+    let mut page = String::new();
+    for line in read("index.html").lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed
+            .strip_prefix(r#"<link rel="stylesheet" href=""#)
+            .and_then(|rest| rest.strip_suffix(r#"">"#))
+        {
+            page += &format!("<style>\n{}</style>\n", read(name));
+        } else if let Some(name) = trimmed
+            .strip_prefix(r#"<script src=""#)
+            .and_then(|rest| rest.strip_suffix(r#""></script>"#))
+        {
+            page += &format!("<script>\n{}</script>\n", read(name));
+        } else {
+            page += line;
+            page += "\n";
+        }
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    let compressed = encoder
+        .write_all(page.as_bytes())
+        .and_then(|()| encoder.finish())
+        .expect("unable to compress the web UI");
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("index.html.gz");
+    fs::write(&out, compressed)
+        .unwrap_or_else(|e| panic!("unable to write {}: {e}", out.display()));
+}
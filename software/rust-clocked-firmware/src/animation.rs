@@ -0,0 +1,92 @@
+//! LED output.
+//!
+//! Runs the animation [`Scheduler`] at a fixed frame rate and writes the
+//! frames to the strip. Which animation runs, the daily schedule, night
+//! mode, the hand colors, the brightness and the current budget come from
+//! the settings and are picked up on the next frame, as does the mounting of
+//! the strip and a running countdown.
+//!
+//! The brightness goes through [`Settings::program`], which dims it at night
+//! unless a schedule block sets its own, and then through the [`Limiter`]
+//! for frames that would exceed the budget.
+//!
+//! Until the time is known the ring shows that it is not set, while it is
+//! set with the button it shows the [`TimeSetter`]'s editor instead.
+//!
+//! [`Settings::program`]: clocked_core::settings::Settings::program
+
+use core::fmt::Debug;
+
+use clocked_core::{
+    animation::Scheduler,
+    layout::Mapped,
+    power::Limiter,
+    ring::{self, FrameSink, BLACK, FRAMES_PER_SECOND, RING_SIZE},
+};
+use embassy_time::{Duration, Ticker};
+use log::{info, warn};
+
+use crate::{
+    leds::LED_COUNT,
+    manual::TimeSetter,
+    settings::SettingsService,
+    time::Clock,
+    timer::{uptime, TimerService},
+};
+
+pub async fn run<S>(
+    mut leds: Mapped<S>,
+    clock: &'static Clock,
+    settings: &'static SettingsService,
+    timer: &'static TimerService,
+    setter: &'static TimeSetter,
+) -> !
+where
+    S: FrameSink,
+    S::Error: Debug,
+{
+    let mut scheduler = Scheduler::new(settings.read(|s| s.animation), uptime());
+    let mut ticker = Ticker::every(Duration::from_hz(FRAMES_PER_SECOND as u64));
+    let mut data = [BLACK; RING_SIZE];
+    let mut limiting = false;
+    loop {
+        let now = clock.now_local();
+        let (program, face, limiter) = settings.read(|s| {
+            leds.layout = s.layout(LED_COUNT);
+            let limiter = Limiter::new(s.max_current_ma as u32);
+            (s.program(&now), s.clock_face(), limiter)
+        });
+        let elapsed = uptime();
+        scheduler.select(program.animation, elapsed);
+        scheduler.set_wake(program.wake);
+        scheduler.set_block(program.block);
+        scheduler.set_timer(timer.get());
+        scheduler.set_unset(!clock.is_synced());
+        scheduler.render(&now, elapsed, &face, &mut data);
+        if let Some(editor) = setter.get() {
+            data.fill(BLACK);
+            editor.render(elapsed, &face, &mut data);
+        }
+
+        let mut level = program.level;
+        let limited = limiter.limit(leds.layout.map(&data), level);
+        match limited {
+            Some(limited) if !limiting => info!(
+                "Limiting LED current to {} mA ({} mA requested), brightness {} instead of {}",
+                limited.limited_ma, limited.requested_ma, limited.level, level
+            ),
+            None if limiting => info!("LED current back within {} mA", limiter.max_ma),
+            _ => {}
+        }
+        limiting = limited.is_some();
+        if let Some(limited) = limited {
+            level = limited.level;
+        }
+
+        if let Err(e) = ring::show(&mut leds, &data, level) {
+            warn!("LED write failed: {e:?}");
+        }
+
+        ticker.next().await;
+    }
+}
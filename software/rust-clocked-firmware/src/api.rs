@@ -0,0 +1,264 @@
+//! HTTP server for the web UI and the REST API on the home network.
+//!
+//! Routing and JSON live in [`clocked_core::api`], this task only reads the
+//! requests from the socket, hands them over with a snapshot of the clock and
+//! carries out the returned [`Command`].
+//!
+//! Firmware uploads with `PUT /api/firmware` are recognized from the request
+//! head and streamed into flash by [`ota::receive`], an installed update
+//! restarts the clock once the answer is sent.
+//!
+//! The web UI is a single page in `web/` that only talks to the REST API.
+//! `build.rs` inlines its style sheet and script and gzips it, so it is sent
+//! as is with `Content-Encoding: gzip`.
+
+use clocked_core::{
+    api::{self, Command, State},
+    http::{self, Request, Status},
+    ota::parse_digest,
+    settings::Error,
+    tz::TimeZone,
+};
+use embassy_net::{tcp::TcpSocket, Stack};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::Write;
+use heapless::String;
+use log::{debug, info, warn};
+
+use crate::{
+    net, ota,
+    settings::SettingsService,
+    time::Clock,
+    timer::{uptime, TimerService},
+};
+
+pub const PORT: u16 = 80;
+
+/// The web UI, bundled by `build.rs`.
+static WEB_UI: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/index.html.gz"));
+
+/// Pause between the answer to an installed update and the restart, so the
+/// client receives it.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Largest response body, `GET /api/settings` with a full schedule needs
+/// about 1 KiB.
+const MAX_BODY: usize = 2048;
+
+#[embassy_executor::task]
+pub async fn server(
+    stack: Stack<'static>,
+    clock: &'static Clock,
+    settings: &'static SettingsService,
+    timer: &'static TimerService,
+) {
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 1536];
+    let mut request = [0u8; 2048];
+    let mut body: String<MAX_BODY> = String::new();
+    let mut head: String<256> = String::new();
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        if let Err(e) = socket.accept(PORT).await {
+            warn!(target: "API", "Accept error {e:?}");
+            continue;
+        }
+
+        body.clear();
+        head.clear();
+        let mut len = 0;
+        let reply = loop {
+            match socket.read(&mut request[len..]).await {
+                Ok(0) | Err(_) => break None,
+                Ok(n) => len += n,
+            }
+            if let Ok((upload, body_start)) = Request::parse_head(&request[..len]) {
+                if upload.method == "PUT" && upload.path == "/api/firmware" {
+                    let received = &request[body_start..len];
+                    let reply = receive(&mut socket, &upload, received, settings, &mut body);
+                    break Some(reply.await);
+                }
+            }
+            match Request::parse(&request[..len]) {
+                Ok(request) if request.method == "GET" && is_web_ui(request.path) => {
+                    break Some(Reply::WebUi)
+                }
+                Ok(request) => {
+                    break Some(respond(&request, stack, clock, settings, timer, &mut body).await);
+                }
+                Err(http::Error::Incomplete) if len < request.len() => continue,
+                Err(e) => {
+                    debug!(target: "API", "Invalid request ({e:?})");
+                    let _ = api::write_error(&mut body, "invalid request");
+                    break Some(Reply::Api(Status::BAD_REQUEST));
+                }
+            }
+        };
+        let Some(reply) = reply else {
+            socket.abort();
+            continue;
+        };
+        let content = match reply {
+            Reply::WebUi => {
+                let gzip = "Content-Encoding: gzip\r\n";
+                let _ = http::write_head(&mut head, Status::OK, "text/html", WEB_UI.len(), gzip);
+                WEB_UI
+            }
+            Reply::Api(status) => {
+                let _ = http::write_head(&mut head, status, "application/json", body.len(), "");
+                body.as_bytes()
+            }
+            Reply::Restart => {
+                let _ = http::write_head(&mut head, Status::OK, "application/json", body.len(), "");
+                body.as_bytes()
+            }
+        };
+
+        let written = async {
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(content).await?;
+            socket.flush().await
+        }
+        .await;
+        if let Err(e) = written {
+            debug!(target: "API", "Write error {e:?}");
+        }
+        socket.close();
+        Timer::after(Duration::from_millis(50)).await;
+        socket.abort();
+        if matches!(reply, Reply::Restart) {
+            info!(target: "API", "Restarting into the update");
+            Timer::after(RESTART_DELAY).await;
+            esp_hal::system::software_reset();
+        }
+    }
+}
+
+enum Reply {
+    WebUi,
+    /// JSON response in the body buffer.
+    Api(Status),
+    /// An update was installed, the JSON response is in the body buffer and
+    /// the clock restarts after sending it.
+    Restart,
+}
+
+fn is_web_ui(path: &str) -> bool {
+    matches!(path, "/" | "/index.html")
+}
+
+/// Stream the image uploaded with `request` into flash, `received` is the
+/// part of the body that came with the head.
+async fn receive(
+    socket: &mut TcpSocket<'_>,
+    request: &Request<'_>,
+    received: &[u8],
+    settings: &SettingsService,
+    body: &mut String<MAX_BODY>,
+) -> Reply {
+    // checked before reading the image, unlike the rest of the API
+    if !settings.read(|s| api::authorized(request, s)) {
+        return failed(body, (Status::UNAUTHORIZED, "unauthorized"));
+    }
+    let expected = match request.header("x-sha256") {
+        Some(hex) => match parse_digest(hex) {
+            Some(digest) => Some(digest),
+            None => return failed(body, (Status::BAD_REQUEST, "invalid X-Sha256")),
+        },
+        None => None,
+    };
+    let len = match request.content_length() {
+        Ok(0) | Err(_) => return failed(body, (Status::BAD_REQUEST, "missing image")),
+        Ok(len) => len,
+    };
+    info!(target: "API", "Receiving a firmware upload of {len} bytes");
+    match ota::receive(socket, received, len, expected).await {
+        Ok(()) => {
+            let _ = api::write_installed(body, "upload");
+            Reply::Restart
+        }
+        Err(failure) => failed(body, failure),
+    }
+}
+
+/// Answer with the error of a failed update.
+fn failed(body: &mut String<MAX_BODY>, (status, message): ota::Failure) -> Reply {
+    body.clear();
+    let _ = api::write_error(body, message);
+    Reply::Api(status)
+}
+
+/// Answer `request` into `body` and carry out what it asks for.
+async fn respond(
+    request: &Request<'_>,
+    stack: Stack<'static>,
+    clock: &'static Clock,
+    settings: &'static SettingsService,
+    timer: &'static TimerService,
+    body: &mut String<MAX_BODY>,
+) -> Reply {
+    let time_zone = clock.time_zone_name();
+    let now = uptime();
+    let state = State {
+        now: clock.now_local(),
+        time_zone: &time_zone,
+        since_sync: clock
+            .last_sync()
+            .map(|at| core::time::Duration::from_micros(at.elapsed().as_micros())),
+        uptime: now,
+        heap_used: esp_alloc::HEAP.used(),
+        heap_free: esp_alloc::HEAP.free(),
+        // only the status shows it, measuring means a scan
+        rssi: if request.path == "/api/status" {
+            net::rssi().await
+        } else {
+            None
+        },
+        timer: timer
+            .get()
+            .filter(|countdown| !countdown.is_done(now))
+            .map(|countdown| countdown.remaining(now)),
+        firmware: ota::firmware(),
+    };
+    let mut changed = settings.get();
+    let response = api::handle(request, &state, &mut changed, body);
+    if response.status == Status::INTERNAL_SERVER_ERROR {
+        body.clear();
+        let _ = api::write_error(body, "response too large");
+    }
+
+    match response.command {
+        Some(Command::SaveSettings) => {
+            let zone_changed = settings.read(|s| s.time_zone != changed.time_zone);
+            if zone_changed {
+                // already validated by the API
+                if let Ok(tz) = TimeZone::parse(&changed.time_zone) {
+                    clock.set_time_zone(tz);
+                }
+            }
+            // in effect until the next restart even if saving fails
+            if let Err(e) = settings.update(|s| *s = changed).await {
+                body.clear();
+                let message = match e {
+                    Error::Unavailable => "settings storage unavailable",
+                    _ => "unable to save settings",
+                };
+                let _ = api::write_error(body, message);
+                return Reply::Api(Status::INTERNAL_SERVER_ERROR);
+            }
+            info!(target: "API", "Settings changed");
+        }
+        Some(Command::StartTimer(duration)) => timer.start(duration),
+        Some(Command::StopTimer) => timer.stop(),
+        Some(Command::UpdateFirmware(download)) => {
+            return match ota::download(stack, &download).await {
+                Ok(()) => Reply::Restart,
+                Err(failure) => failed(body, failure),
+            };
+        }
+        None => {}
+    }
+    Reply::Api(response.status)
+}
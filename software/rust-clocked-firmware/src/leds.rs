@@ -0,0 +1,26 @@
+//! The WS2812 strip as a [`FrameSink`].
+
+use clocked_core::ring::FrameSink;
+use smart_leds::{SmartLedsWrite, RGB8};
+
+/// LEDs on the strip, 60 on the 74 LED/m mirror and 120 on the 144 LED/m
+/// one (`leds-120` feature).
+#[cfg(not(feature = "leds-120"))]
+pub const LED_COUNT: usize = 60;
+#[cfg(feature = "leds-120")]
+pub const LED_COUNT: usize = 120;
+
+/// Wraps any `smart_leds` driver.
+pub struct Strip<W>(pub W);
+
+impl<W: SmartLedsWrite<Color = RGB8>> FrameSink for Strip<W> {
+    type Error = W::Error;
+
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<RGB8>,
+    {
+        self.0.write(iterator)
+    }
+}
@@ -0,0 +1,162 @@
+//! Setting the clock by hand, see [`clocked_core::manual`].
+//!
+//! [`TimeSetter`] holds the [`Editor`] while the time is set with the
+//! button, the LED task draws it instead of the clock. The [`console`] takes
+//! commands typed on UART0, the serial port the logs go to, and the
+//! [`keeper`] saves the time now and then to resume from after a power cut.
+
+use core::cell::Cell;
+
+use clocked_core::manual::{self, Command, Editor, LastTime, ParseError, HELP};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::{uart::UartRx, Async};
+use esp_println::println;
+use heapless::String;
+use log::{debug, info, warn};
+
+use crate::{settings::SettingsService, time::Clock, timer::uptime};
+
+/// Longest console line, longer ones are cut off.
+const MAX_LINE: usize = 64;
+/// How often the keeper checks whether the time is due to be saved.
+const KEEPER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Setting the time with the button, shared between the button and the LED
+/// task.
+pub struct TimeSetter {
+    editor: Mutex<CriticalSectionRawMutex, Cell<Option<Editor>>>,
+}
+
+impl TimeSetter {
+    pub const fn new() -> Self {
+        Self {
+            editor: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// The editor to draw, `None` when the time is not being set. Drops it
+    /// once nothing was pressed for [`manual::EDITOR_TIMEOUT`].
+    pub fn get(&self) -> Option<Editor> {
+        let now = uptime();
+        let (editor, expired) = self.editor.lock(|editor| {
+            let current = editor.get();
+            let kept = current.filter(|e| !e.is_expired(now));
+            editor.set(kept);
+            (kept, current.is_some() && kept.is_none())
+        });
+        if expired {
+            info!("Setting the time timed out");
+        }
+        editor
+    }
+
+    /// Start setting the time, from the current local time.
+    pub fn start(&self, clock: &Clock) {
+        info!("Setting the time");
+        let editor = Editor::new(clock.now_local(), uptime());
+        self.editor.lock(|e| e.set(Some(editor)));
+    }
+
+    /// Take a press of the button, returns `false` if the time is not
+    /// being set. The long press after the year sets the clock.
+    pub fn press(&self, long: bool, clock: &Clock) -> bool {
+        let Some(mut editor) = self.get() else {
+            return false;
+        };
+        let now = uptime();
+        let set = if long {
+            editor.next(now)
+        } else {
+            editor.increment(now);
+            None
+        };
+        match set {
+            Some(local) => {
+                self.editor.lock(|e| e.set(None));
+                clock.set_local(local);
+            }
+            None => self.editor.lock(|e| e.set(Some(editor))),
+        }
+        true
+    }
+}
+
+/// Reads commands from the serial port, see [`Command`].
+#[embassy_executor::task]
+pub async fn console(mut rx: UartRx<'static, Async>, clock: &'static Clock) {
+    let mut line: String<MAX_LINE> = String::new();
+    let mut buf = [0u8; 16];
+    loop {
+        let len = match rx.read_async(&mut buf).await {
+            Ok(len) => len,
+            Err(e) => {
+                warn!("Console read failed: {e:?}");
+                continue;
+            }
+        };
+        for &byte in &buf[..len] {
+            match byte {
+                b'\r' | b'\n' => {
+                    if !line.trim().is_empty() {
+                        run(&line, clock);
+                    }
+                    line.clear();
+                }
+                // backspace and delete
+                0x08 | 0x7f => {
+                    line.pop();
+                }
+                byte if byte.is_ascii() && !byte.is_ascii_control() => {
+                    let _ = line.push(byte as char);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn run(line: &str, clock: &Clock) {
+    match Command::parse(line) {
+        Ok(Command::Help) => println!("{HELP}"),
+        Ok(Command::Show) => {
+            let set = if clock.is_synced() { "set" } else { "not set" };
+            println!("{} {} ({set})", clock.now_local(), clock.time_zone_name());
+        }
+        Ok(command) => {
+            if let Some(local) = command.apply(clock.now_local()) {
+                clock.set_local(local);
+                println!("Time set to {local}");
+            }
+        }
+        Err(e) => {
+            let problem = match e {
+                ParseError::Command => "Unknown command",
+                ParseError::Date => "Invalid date",
+                ParseError::Time => "Invalid time",
+            };
+            println!("{problem}, try help");
+        }
+    }
+}
+
+/// Saves the time whenever [`manual::save_due`] says so.
+#[embassy_executor::task]
+pub async fn keeper(clock: &'static Clock, settings: &'static SettingsService) {
+    let mut saved = Instant::now();
+    loop {
+        Timer::after(KEEPER_INTERVAL).await;
+        let now = clock.now();
+        let known = clock.is_synced();
+        let since = core::time::Duration::from_micros(saved.elapsed().as_micros());
+        if !manual::save_due(settings.read(|s| s.last_time), now, known, since) {
+            continue;
+        }
+        debug!("Saving the time {now}");
+        // a failed write is logged, the time is then only saved in memory
+        let _ = settings
+            .update(|s| s.last_time = Some(LastTime { time: now, known }))
+            .await;
+        saved = Instant::now();
+    }
+}
@@ -0,0 +1,108 @@
+//! mDNS responder, see [`clocked_core::mdns`].
+//!
+//! Once the station has an address the clock joins the mDNS group, announces
+//! `<name>.local` and its web UI and answers queries for them. A new address
+//! or a renamed clock is announced again right away.
+
+use clocked_core::mdns::{self, Destination, Host, GROUP, MAX_NAME_LEN, PORT};
+use embassy_futures::select::{select, Either};
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    IpEndpoint, Ipv4Address, Stack,
+};
+use embassy_time::{Duration, Timer};
+use heapless::String;
+use log::{debug, info, warn};
+
+use crate::{api, net, settings::SettingsService};
+
+const GROUP_ADDRESS: Ipv4Address = Ipv4Address::new(GROUP[0], GROUP[1], GROUP[2], GROUP[3]);
+/// How often the address and the name are checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Announcements sent after a change, one per [`CHECK_INTERVAL`], RFC 6762
+/// section 8.3.
+const ANNOUNCEMENTS: u8 = 2;
+
+#[embassy_executor::task]
+pub async fn responder(
+    stack: Stack<'static>,
+    settings: &'static SettingsService,
+    id: &'static str,
+) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 1024];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(PORT).expect("mDNS port is free");
+    let group = IpEndpoint::new(GROUP_ADDRESS.into(), PORT);
+
+    let mut query = [0u8; 512];
+    let mut reply = [0u8; 512];
+    // what was announced last, and how many announcements are left
+    let mut announced: Option<(String<MAX_NAME_LEN>, Ipv4Address)> = None;
+    let mut announcements = 0;
+    loop {
+        let received =
+            match select(socket.recv_from(&mut query), Timer::after(CHECK_INTERVAL)).await {
+                Either::First(received) => Some(received),
+                Either::Second(()) => None,
+            };
+        let Some(address) = net::status().address else {
+            announced = None;
+            continue;
+        };
+        let name: String<MAX_NAME_LEN> =
+            settings.read(|s| String::try_from(s.device_name(id)).unwrap_or_default());
+        let host = Host {
+            name: &name,
+            address: address.octets(),
+            port: api::PORT,
+        };
+
+        if announced.as_ref() != Some(&(name.clone(), address)) {
+            if announced.is_none() {
+                // joining again after a new address is harmless
+                if let Err(e) = stack.join_multicast_group(GROUP_ADDRESS) {
+                    warn!(target: "MDNS", "Unable to join the mDNS group: {e:?}");
+                }
+            }
+            info!(target: "MDNS", "Announcing {name}.local");
+            announced = Some((name.clone(), address));
+            announcements = ANNOUNCEMENTS;
+        }
+
+        match received {
+            Some(Ok((len, remote))) => {
+                let Some((reply_len, destination)) =
+                    mdns::respond(&query[..len], remote.endpoint.port, &host, &mut reply)
+                else {
+                    continue;
+                };
+                let to = match destination {
+                    Destination::Multicast => group,
+                    Destination::Unicast => remote.endpoint,
+                };
+                if let Err(e) = socket.send_to(&reply[..reply_len], to).await {
+                    debug!(target: "MDNS", "Send error {e:?}");
+                }
+            }
+            Some(Err(e)) => debug!(target: "MDNS", "Receive error {e:?}"),
+            None if announcements > 0 => {
+                announcements -= 1;
+                if let Some(len) = mdns::announce(&host, &mut reply) {
+                    if let Err(e) = socket.send_to(&reply[..len], group).await {
+                        debug!(target: "MDNS", "Send error {e:?}");
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+}
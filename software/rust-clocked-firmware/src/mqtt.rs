@@ -0,0 +1,307 @@
+//! MQTT client for Home Assistant.
+//!
+//! While a broker is configured and the station has an address, the client
+//! keeps a connection to it, announces the clock through discovery, keeps
+//! the retained state up to date and carries out the commands. Packets and
+//! the Home Assistant specifics live in [`clocked_core::mqtt`] and
+//! [`clocked_core::home_assistant`].
+
+use clocked_core::{
+    api::Command,
+    home_assistant::{Entity, Light, State, Topics, OFFLINE, ONLINE},
+    mqtt::{self, Broker, Connect, Packet, Publish, Will},
+    settings::Settings,
+};
+use embassy_futures::select::{select, Either};
+use embassy_net::{tcp::TcpSocket, Stack};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::Write;
+use heapless::String;
+use log::{debug, info, warn};
+
+use crate::{
+    net,
+    settings::SettingsService,
+    timer::{uptime, TimerService},
+};
+
+/// Seconds the broker waits for a packet before it drops the client.
+const KEEP_ALIVE: u16 = 60;
+/// How often the state is compared with the last published one.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before the first reconnection attempt, doubled up to
+/// [`MAX_RECONNECT_DELAY`] while they keep failing.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+/// Largest packet sent or received, a discovery message is about 500 bytes.
+const MAX_PACKET: usize = 1024;
+
+#[derive(Debug)]
+enum Error {
+    Dns,
+    Tcp,
+    Mqtt(mqtt::Error),
+    /// The broker refused the connection with this return code.
+    Refused(u8),
+    Timeout,
+    Closed,
+}
+
+impl From<embassy_net::tcp::Error> for Error {
+    fn from(_: embassy_net::tcp::Error) -> Self {
+        Self::Tcp
+    }
+}
+
+impl From<mqtt::Error> for Error {
+    fn from(e: mqtt::Error) -> Self {
+        Self::Mqtt(e)
+    }
+}
+
+#[embassy_executor::task]
+pub async fn client(
+    stack: Stack<'static>,
+    settings: &'static SettingsService,
+    timer: &'static TimerService,
+    id: &'static str,
+) {
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 1024];
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let broker = settings.read(|s| s.mqtt.clone());
+        if !broker.enabled || broker.host.is_empty() || !stack.is_config_up() {
+            delay = RECONNECT_DELAY;
+            Timer::after(POLL_INTERVAL).await;
+            continue;
+        }
+
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(u64::from(KEEP_ALIVE) * 2)));
+        let mut session = Session {
+            stack,
+            socket: &mut socket,
+            settings,
+            timer,
+            id,
+            topics: Topics::new(id),
+            broker,
+            connected_at: None,
+        };
+        let result = session.run().await;
+        let connected = session.connected_at.is_some();
+        socket.close();
+        Timer::after(Duration::from_millis(50)).await;
+        socket.abort();
+
+        match result {
+            // the settings changed, reconnect right away
+            Ok(()) => continue,
+            Err(e) => warn!(target: "MQTT", "Connection lost ({e:?})"),
+        }
+        if connected {
+            delay = RECONNECT_DELAY;
+        }
+        Timer::after(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+struct Session<'s, 'b> {
+    stack: Stack<'static>,
+    socket: &'s mut TcpSocket<'b>,
+    settings: &'static SettingsService,
+    timer: &'static TimerService,
+    id: &'static str,
+    topics: Topics,
+    broker: Broker,
+    connected_at: Option<Instant>,
+}
+
+impl Session<'_, '_> {
+    /// Connect and serve until the connection breaks, `Ok` once the broker
+    /// settings changed.
+    async fn run(&mut self) -> Result<(), Error> {
+        let address = net::resolve(self.stack, &self.broker.host)
+            .await
+            .ok_or(Error::Dns)?;
+        info!(target: "MQTT", "Connecting to {}:{}", address, self.broker.port);
+        self.socket.connect((address, self.broker.port)).await?;
+
+        let mut rx = [0u8; MAX_PACKET];
+        let mut tx = [0u8; MAX_PACKET];
+        let connect = Connect {
+            client_id: self.id,
+            keep_alive: KEEP_ALIVE,
+            will: Some(Will {
+                topic: &self.topics.availability,
+                payload: OFFLINE.as_bytes(),
+                retain: true,
+            }),
+            username: Some(self.broker.username.as_str()).filter(|name| !name.is_empty()),
+            password: Some(self.broker.password.as_str())
+                .filter(|_| !self.broker.username.is_empty()),
+        };
+        let len = mqtt::connect(&connect, &mut tx)?;
+        self.socket.write_all(&tx[..len]).await?;
+
+        let mut received = 0;
+        match embassy_time::with_timeout(RESPONSE_TIMEOUT, self.receive(&mut rx, &mut received))
+            .await
+            .map_err(|_| Error::Timeout)??
+        {
+            (Packet::ConnAck { code: 0, .. }, len) => consume(&mut rx, &mut received, len),
+            (Packet::ConnAck { code, .. }, _) => return Err(Error::Refused(code)),
+            _ => return Err(Error::Mqtt(mqtt::Error::Malformed)),
+        }
+        self.connected_at = Some(Instant::now());
+        info!(target: "MQTT", "Connected as {}", self.id);
+
+        let len = mqtt::subscribe(1, &self.topics.commands(), &mut tx)?;
+        self.socket.write_all(&tx[..len]).await?;
+        if self.broker.discovery {
+            for entity in Entity::ALL {
+                let mut config: String<MAX_PACKET> = String::new();
+                let _ = entity.write_config(self.id, env!("CARGO_PKG_VERSION"), &mut config);
+                let topic = entity.config_topic(self.id);
+                self.publish(&topic, config.as_bytes(), &mut tx).await?;
+            }
+        }
+        let availability = self.topics.availability.clone();
+        self.publish(&availability, ONLINE.as_bytes(), &mut tx)
+            .await?;
+
+        let mut light = Light::default();
+        let mut published = None;
+        let mut last_sent = Instant::now();
+        let mut ping_sent: Option<Instant> = None;
+        loop {
+            let state = self.state();
+            if published != Some(state) {
+                let mut payload: String<256> = String::new();
+                let _ = state.write(&mut payload);
+                let topic = self.topics.state.clone();
+                self.publish(&topic, payload.as_bytes(), &mut tx).await?;
+                published = Some(state);
+                last_sent = Instant::now();
+            }
+            if self.settings.read(|s| s.mqtt != self.broker) {
+                info!(target: "MQTT", "Broker settings changed");
+                if let Ok(len) = mqtt::disconnect(&mut tx) {
+                    let _ = self.socket.write_all(&tx[..len]).await;
+                }
+                return Ok(());
+            }
+            if let Some(sent) = ping_sent {
+                if sent.elapsed() > RESPONSE_TIMEOUT {
+                    return Err(Error::Timeout);
+                }
+            } else if last_sent.elapsed() > Duration::from_secs(u64::from(KEEP_ALIVE) / 2) {
+                let len = mqtt::ping(&mut tx)?;
+                self.socket.write_all(&tx[..len]).await?;
+                ping_sent = Some(Instant::now());
+                last_sent = Instant::now();
+            }
+
+            let packet = match select(
+                self.receive(&mut rx, &mut received),
+                Timer::after(POLL_INTERVAL),
+            )
+            .await
+            {
+                Either::First(packet) => packet?,
+                Either::Second(()) => continue,
+            };
+            match packet {
+                (Packet::Publish(message), len) => {
+                    let mut changed = self.settings.get();
+                    let result =
+                        light.handle(&self.topics, message.topic, message.payload, &mut changed);
+                    let ack = message.packet_id;
+                    consume(&mut rx, &mut received, len);
+                    self.carry_out(result, changed).await;
+                    if let Some(id) = ack {
+                        let len = mqtt::puback(id, &mut tx)?;
+                        self.socket.write_all(&tx[..len]).await?;
+                    }
+                }
+                (Packet::PingResp, len) => {
+                    ping_sent = None;
+                    consume(&mut rx, &mut received, len);
+                }
+                (Packet::SubAck { codes, .. }, len) => {
+                    if codes.contains(&0x80) {
+                        warn!(target: "MQTT", "Broker refused a subscription");
+                    }
+                    consume(&mut rx, &mut received, len);
+                }
+                (_, len) => consume(&mut rx, &mut received, len),
+            }
+        }
+    }
+
+    fn state(&self) -> State {
+        let now = uptime();
+        let timer = self
+            .timer
+            .get()
+            .filter(|countdown| !countdown.is_done(now))
+            .map(|countdown| countdown.remaining(now));
+        self.settings.read(|s| State::new(s, timer))
+    }
+
+    async fn publish(&mut self, topic: &str, payload: &[u8], tx: &mut [u8]) -> Result<(), Error> {
+        let publish = Publish {
+            topic,
+            payload,
+            retain: true,
+            packet_id: None,
+        };
+        let len = mqtt::publish(&publish, tx)?;
+        self.socket.write_all(&tx[..len]).await?;
+        Ok(())
+    }
+
+    /// Read until a complete packet is in `rx[..*received]`, returns it and
+    /// its length. Incomplete packets stay in the buffer between calls.
+    async fn receive<'r>(
+        &mut self,
+        rx: &'r mut [u8],
+        received: &mut usize,
+    ) -> Result<(Packet<'r>, usize), Error> {
+        loop {
+            match mqtt::decode(&rx[..*received]) {
+                Ok(_) => break,
+                Err(mqtt::Error::Incomplete) if *received < rx.len() => {}
+                Err(e) => return Err(e.into()),
+            }
+            match self.socket.read(&mut rx[*received..]).await? {
+                0 => return Err(Error::Closed),
+                n => *received += n,
+            }
+        }
+        Ok(mqtt::decode(&rx[..*received])?)
+    }
+
+    async fn carry_out(&self, result: Result<Option<Command>, &'static str>, changed: Settings) {
+        match result {
+            Ok(Some(Command::SaveSettings)) => {
+                // failing to save was logged, the change is still in effect
+                let _ = self.settings.update(|s| *s = changed).await;
+            }
+            Ok(Some(Command::StartTimer(duration))) => self.timer.start(duration),
+            Ok(Some(Command::StopTimer)) => self.timer.stop(),
+            // only the REST API takes firmware updates
+            Ok(None) | Ok(Some(Command::UpdateFirmware(_))) => {}
+            Err(problem) => debug!(target: "MQTT", "Ignoring command ({problem})"),
+        }
+    }
+}
+
+/// Drop the first `len` bytes of the `received` ones in `rx`.
+fn consume(rx: &mut [u8], received: &mut usize, len: usize) {
+    rx.copy_within(len..*received, 0);
+    *received -= len;
+}
@@ -0,0 +1,342 @@
+//! Networking subsystem.
+//!
+//! [`start`] only creates the network stacks and spawns the tasks, so the
+//! ring shows the time right away while Wi-Fi, DHCP and NTP come up in the
+//! background. The supervisor keeps [`status`] up to date and starts the
+//! tasks that need an address again when they gave up.
+
+use core::cell::Cell;
+use core::fmt::Write as _;
+use core::net::Ipv4Addr;
+
+use clocked_core::wifi::Credentials;
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_net::{
+    dns::DnsQueryType, IpAddress, Ipv4Address, Ipv4Cidr, Runner, Stack, StackResources,
+    StaticConfigV4,
+};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use esp_hal::efuse::Efuse;
+use esp_wifi::wifi::{
+    ClientConfiguration, Configuration, Interfaces, WifiController, WifiDevice, WifiEvent,
+    WifiState,
+};
+use heapless::String;
+use log::{info, warn};
+
+use crate::{
+    api, mdns, mqtt, ntp, provisioning, settings::SettingsService, time::Clock, timer::TimerService,
+};
+
+/// Delay between connection attempts.
+const RECONNECT_DELAY: Duration = Duration::from_millis(5000);
+const SUPERVISOR_INTERVAL: Duration = Duration::from_millis(1000);
+/// How long a signal strength measurement is reused. Every scan interrupts
+/// the connection for a moment, a dashboard polling the status should not
+/// cause one per request.
+const RSSI_MAX_AGE: Duration = Duration::from_secs(60);
+/// Longest wait for a scan, one over all channels takes about 2 s.
+const RSSI_TIMEOUT: Duration = Duration::from_secs(5);
+/// Access points a scan for the own network is expected to find, there may
+/// be more than one with the same name.
+const MAX_ACCESS_POINTS: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Link {
+    Down,
+    Up,
+    /// Running the setup access point, see [`provisioning`].
+    Setup,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NtpStatus {
+    /// No attempt finished yet.
+    Waiting,
+    Synced,
+    /// The last attempt failed, the clock keeps running on the RTC.
+    Failing,
+    /// No NTP server answers, the time came from the `Date` of a web server
+    /// and is good to a second.
+    Fallback,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NetStatus {
+    pub link: Link,
+    pub address: Option<Ipv4Address>,
+    pub ntp: NtpStatus,
+    /// Signal strength of the network in dBm, last measured by [`rssi`]
+    /// while connected.
+    pub rssi: Option<i8>,
+}
+
+static STATUS: Mutex<CriticalSectionRawMutex, Cell<NetStatus>> = Mutex::new(Cell::new(NetStatus {
+    link: Link::Down,
+    address: None,
+    ntp: NtpStatus::Waiting,
+    rssi: None,
+}));
+
+/// Asks the connection task for a measurement of the signal strength.
+static RSSI_REQUEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Result of the last requested measurement.
+static RSSI_MEASURED: Signal<CriticalSectionRawMutex, Option<i8>> = Signal::new();
+/// When [`NetStatus::rssi`] was measured.
+static RSSI_AT: Mutex<CriticalSectionRawMutex, Cell<Option<Instant>>> = Mutex::new(Cell::new(None));
+
+/// Current state of the network.
+pub fn status() -> NetStatus {
+    STATUS.lock(Cell::get)
+}
+
+pub fn update_status(f: impl FnOnce(&mut NetStatus)) {
+    STATUS.lock(|status| {
+        let mut current = status.get();
+        f(&mut current);
+        status.set(current);
+    });
+}
+
+/// Signal strength of the network in dBm, `None` while not connected.
+///
+/// Scans for the network unless the last measurement is younger than
+/// [`RSSI_MAX_AGE`], nothing scans in the background.
+pub async fn rssi() -> Option<i8> {
+    let current = status();
+    if current.link != Link::Up {
+        return None;
+    }
+    let fresh = RSSI_AT
+        .lock(Cell::get)
+        .is_some_and(|at| at.elapsed() < RSSI_MAX_AGE);
+    if fresh {
+        return current.rssi;
+    }
+    RSSI_MEASURED.reset();
+    RSSI_REQUEST.signal(());
+    match with_timeout(RSSI_TIMEOUT, RSSI_MEASURED.wait()).await {
+        Ok(rssi) => rssi,
+        Err(_) => current.rssi,
+    }
+}
+
+/// Name of the clock on the network, `clocked-` and the end of its MAC
+/// address, e.g. `clocked-1a2b`.
+pub fn device_id() -> String<16> {
+    let mac = Efuse::read_base_mac_address();
+    let mut id = String::new();
+    let _ = write!(id, "clocked-{:02x}{:02x}", mac[4], mac[5]);
+    id
+}
+
+/// Address of `host`, given as name or as IPv4 address.
+pub async fn resolve(stack: Stack<'static>, host: &str) -> Option<IpAddress> {
+    if let Ok(address) = host.parse::<Ipv4Addr>() {
+        return Some(IpAddress::Ipv4(address));
+    }
+    stack
+        .dns_query(host, DnsQueryType::A)
+        .await
+        .ok()
+        .and_then(|addresses| addresses.first().copied())
+}
+
+/// Create the station and setup network stacks and spawn all networking tasks.
+pub fn start(
+    spawner: Spawner,
+    controller: WifiController<'static>,
+    interfaces: Interfaces<'static>,
+    seed: u64,
+    clock: &'static Clock,
+    settings: &'static SettingsService,
+    timer: &'static TimerService,
+) {
+    let (stack, runner) = embassy_net::new(
+        interfaces.sta,
+        embassy_net::Config::dhcpv4(Default::default()),
+        mk_static!(StackResources<8>, StackResources::<8>::new()),
+        seed,
+    );
+    let stack = &*mk_static!(Stack<'static>, stack);
+
+    // Setup network, only active while in provisioning mode
+    let (ap_stack, ap_runner) = embassy_net::new(
+        interfaces.ap,
+        embassy_net::Config::ipv4_static(StaticConfigV4 {
+            address: Ipv4Cidr::new(provisioning::AP_ADDRESS, 24),
+            gateway: Some(provisioning::AP_ADDRESS),
+            dns_servers: Default::default(),
+        }),
+        mk_static!(StackResources<4>, StackResources::<4>::new()),
+        seed.rotate_left(32),
+    );
+
+    let id = mk_static!(String<16>, device_id()).as_str();
+    info!("Device id {id}");
+
+    let credentials = settings.get().wifi;
+    if credentials.is_none() {
+        info!("No Wi-Fi credentials stored");
+    }
+
+    for spawned in [
+        spawner.spawn(connection(controller, credentials)),
+        spawner.spawn(net_task(runner)),
+        spawner.spawn(net_task(ap_runner)),
+        spawner.spawn(provisioning::dhcp_server(ap_stack)),
+        spawner.spawn(provisioning::captive_dns(ap_stack)),
+        spawner.spawn(provisioning::portal(ap_stack, settings)),
+        spawner.spawn(supervisor(stack, clock, settings)),
+        spawner.spawn(api::server(*stack, clock, settings, timer)),
+        spawner.spawn(mqtt::client(*stack, settings, timer, id)),
+        spawner.spawn(mdns::responder(*stack, settings, id)),
+    ] {
+        if let Err(e) = spawned {
+            warn!("Unable to spawn network task: {e:?}");
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn connection(mut controller: WifiController<'static>, credentials: Option<Credentials>) {
+    info!("start connection task");
+    info!("Device capabilities: {:?}", controller.capabilities());
+    let mut failures = 0;
+    loop {
+        let Some(credentials) = credentials
+            .as_ref()
+            .filter(|_| failures < provisioning::MAX_CONNECT_FAILURES)
+        else {
+            update_status(|s| s.link = Link::Setup);
+            provisioning::setup_mode(&mut controller, credentials.is_some()).await;
+            update_status(|s| s.link = Link::Down);
+            failures = 0;
+            continue;
+        };
+        if esp_wifi::wifi::wifi_state() == WifiState::StaConnected {
+            // measure the signal when asked until we're no longer connected
+            RSSI_REQUEST.reset();
+            loop {
+                let event = select(
+                    controller.wait_for_event(WifiEvent::StaDisconnected),
+                    RSSI_REQUEST.wait(),
+                )
+                .await;
+                if let Either::First(()) = event {
+                    break;
+                }
+                let rssi = measure_rssi(&mut controller, &credentials.ssid).await;
+                update_status(|s| s.rssi = rssi);
+                RSSI_AT.lock(|at| at.set(Some(Instant::now())));
+                RSSI_MEASURED.signal(rssi);
+                // a disconnect during the scan is not waited for again
+                if esp_wifi::wifi::wifi_state() != WifiState::StaConnected {
+                    break;
+                }
+            }
+            update_status(|s| s.rssi = None);
+            RSSI_AT.lock(|at| at.set(None));
+            Timer::after(RECONNECT_DELAY).await
+        }
+        if !matches!(controller.is_started(), Ok(true)) {
+            let client_config = Configuration::Client(ClientConfiguration {
+                ssid: credentials.ssid.clone(),
+                password: credentials.password.clone(),
+                ..Default::default()
+            });
+            if let Err(e) = controller.set_configuration(&client_config) {
+                warn!("Failed to configure wifi: {e:?}");
+                Timer::after(RECONNECT_DELAY).await;
+                continue;
+            }
+            info!("Starting wifi");
+            if let Err(e) = controller.start_async().await {
+                warn!("Failed to start wifi: {e:?}");
+                Timer::after(RECONNECT_DELAY).await;
+                continue;
+            }
+            info!("Wifi started!");
+        }
+        info!("About to connect...");
+
+        match controller.connect_async().await {
+            Ok(_) => {
+                info!("Wifi connected!");
+                failures = 0;
+            }
+            Err(e) => {
+                failures += 1;
+                warn!(
+                    "Failed to connect to wifi ({failures}/{}): {e:?}",
+                    provisioning::MAX_CONNECT_FAILURES
+                );
+                Timer::after(RECONNECT_DELAY).await
+            }
+        }
+    }
+}
+
+/// Signal strength of the strongest access point named `ssid`.
+async fn measure_rssi(controller: &mut WifiController<'static>, ssid: &str) -> Option<i8> {
+    match controller.scan_n_async::<MAX_ACCESS_POINTS>().await {
+        Ok((found, _)) => found
+            .iter()
+            .filter(|ap| ap.ssid == ssid)
+            .map(|ap| ap.signal_strength)
+            .max(),
+        Err(e) => {
+            warn!("Unable to measure signal strength: {e:?}");
+            None
+        }
+    }
+}
+
+#[embassy_executor::task(pool_size = 2)]
+async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
+    runner.run().await
+}
+
+/// Tracks link and address of the station and (re)starts the tasks that
+/// need an address.
+#[embassy_executor::task]
+async fn supervisor(
+    stack: &'static Stack<'static>,
+    clock: &'static Clock,
+    settings: &'static SettingsService,
+) {
+    let spawner = Spawner::for_current_executor().await;
+    loop {
+        let link_up = stack.is_link_up();
+        let address = stack.config_v4().map(|config| config.address.address());
+        let previous = status();
+        update_status(|s| {
+            if s.link != Link::Setup {
+                s.link = if link_up { Link::Up } else { Link::Down };
+            }
+            s.address = address;
+        });
+        if address != previous.address {
+            match address {
+                Some(address) => info!("Got IP: {address}"),
+                None => info!("Lost IP address"),
+            }
+        }
+
+        if address.is_some() {
+            // fails while the task is still running, which is fine
+            if spawner
+                .spawn(ntp::ntp_sync_task(stack, clock, settings))
+                .is_ok()
+            {
+                info!("Started NTP sync");
+            }
+        }
+        Timer::after(SUPERVISOR_INTERVAL).await;
+    }
+}
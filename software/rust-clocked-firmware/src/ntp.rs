@@ -0,0 +1,308 @@
+//! Time synchronisation over SNTP, see [`clocked_core::ntp`].
+//!
+//! Every sync asks the servers the DHCP server announces and the ones in
+//! the settings a few times each, keeps the answer with the shortest round
+//! trip per server and hands the offset most of them agree on to the
+//! [`Clock`], which slews it in and learns the drift of the oscillator.
+//! Failed syncs are retried with a growing delay, see
+//! [`Config::retry_delay`].
+//!
+//! Where NTP is blocked altogether the `Date` header of a web server sets the
+//! clock, see [`http_fallback`]. It is only good to a second, so it never
+//! overrides NTP and leaves a clock alone that is already that close.
+
+use chrono::TimeDelta;
+use clocked_core::{
+    dhcp::{self, Packet, OPTION_NTP_SERVERS},
+    http::{self, Response, Url},
+    ntp::{self, Config, NtpTimestamp, Reply, Sample, MAX_SAMPLES, MAX_SERVERS, PACKET_LEN},
+};
+use embassy_net::{
+    tcp::TcpSocket,
+    udp::{PacketMetadata, UdpSocket},
+    IpAddress, IpEndpoint, Ipv4Address, Stack,
+};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embedded_io_async::Write;
+use esp_hal::efuse::Efuse;
+use heapless::{String, Vec};
+use log::{debug, info, warn};
+
+use crate::net::{self, NtpStatus};
+use crate::settings::SettingsService;
+use crate::time::Clock;
+
+/// Requests per server, the reply with the shortest round trip is used.
+const REQUESTS: usize = 2;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+const NTP_LOCAL_PORT: u16 = 12345;
+/// Time for the web server of the fallback to connect and answer.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest response head of the fallback.
+const MAX_HEAD: usize = 2048;
+
+#[derive(Debug)]
+enum QueryError {
+    Send,
+    Timeout,
+    Reply(ntp::Error),
+}
+
+/// Wait before the next attempt after `failures` failed syncs in a row.
+async fn retry_later(config: &Config, failures: u32) {
+    let delay = config.retry_delay(failures);
+    info!(target: "NTP", "Retry in {}s", delay.as_secs());
+    Timer::after(Duration::from_secs(delay.as_secs())).await;
+}
+
+/// NTP servers the DHCP server announces, asked for with a DHCPINFORM.
+async fn dhcp_servers(stack: Stack<'static>, address: Ipv4Address) -> Vec<IpAddress, MAX_SERVERS> {
+    let mut rx_buffer = [0u8; 600];
+    let mut tx_buffer = [0u8; 600];
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    // the answer goes to the client port, the socket only exists for the
+    // moment of the exchange
+    if socket.bind(dhcp::CLIENT_PORT).is_err() {
+        return Vec::new();
+    }
+    let xid = Instant::now().as_ticks() as u32;
+    let mut message = [0u8; 576];
+    let Some(len) = dhcp::inform(
+        &mut message,
+        xid,
+        Efuse::read_base_mac_address(),
+        address.octets(),
+        &[OPTION_NTP_SERVERS],
+    ) else {
+        return Vec::new();
+    };
+    let broadcast = IpEndpoint::new(Ipv4Address::BROADCAST.into(), dhcp::SERVER_PORT);
+    if socket.send_to(&message[..len], broadcast).await.is_err() {
+        return Vec::new();
+    }
+    with_timeout(REPLY_TIMEOUT, async {
+        loop {
+            let Ok((len, _)) = socket.recv_from(&mut message).await else {
+                continue;
+            };
+            let Some(packet) = Packet::parse(&message[..len]).filter(|p| p.is_ack_for(xid)) else {
+                continue;
+            };
+            return packet
+                .addresses(OPTION_NTP_SERVERS)
+                .take(MAX_SERVERS)
+                .map(|address| Ipv4Address::from(address).into())
+                .collect();
+        }
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Ask `server` for the time once.
+async fn query(
+    socket: &UdpSocket<'_>,
+    clock: &Clock,
+    server: IpAddress,
+) -> Result<Sample, QueryError> {
+    let sent = clock.now().and_utc().timestamp_micros();
+    let originate = NtpTimestamp::from_unix_micros(sent);
+    socket
+        .send_to(&ntp::request(originate), IpEndpoint::new(server, ntp::PORT))
+        .await
+        .map_err(|_| QueryError::Send)?;
+
+    let mut buf = [0u8; PACKET_LEN];
+    with_timeout(REPLY_TIMEOUT, async {
+        loop {
+            let Ok((len, remote)) = socket.recv_from(&mut buf).await else {
+                continue;
+            };
+            let received = clock.now().and_utc().timestamp_micros();
+            if remote.endpoint.addr != server {
+                continue;
+            }
+            match Reply::parse(&buf[..len], originate) {
+                // a late reply to an earlier request
+                Err(ntp::Error::WrongOriginate) => continue,
+                Err(e) => return Err(QueryError::Reply(e)),
+                Ok(reply) => return Ok(reply.sample(sent, received)),
+            }
+        }
+    })
+    .await
+    .map_err(|_| QueryError::Timeout)?
+}
+
+/// The sample with the shortest round trip out of [`REQUESTS`] to `server`.
+async fn best_sample(socket: &UdpSocket<'_>, clock: &Clock, server: IpAddress) -> Option<Sample> {
+    let mut best: Option<Sample> = None;
+    for _ in 0..REQUESTS {
+        match query(socket, clock, server).await {
+            Ok(sample) => {
+                debug!(target: "NTP", "{server}: {sample:?}");
+                if best.is_none_or(|best| sample.delay < best.delay) {
+                    best = Some(sample);
+                }
+            }
+            Err(e) => info!(target: "NTP", "No time from {server} ({e:?})"),
+        }
+    }
+    best
+}
+
+/// Sample of the clock from the `Date` header of a `HEAD` request to `url`.
+async fn http_sample(stack: Stack<'static>, clock: &Clock, url: &str) -> Option<Sample> {
+    let url = Url::parse(url)?;
+    let address = net::resolve(stack, url.host).await?;
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 256];
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+    socket.set_timeout(Some(HTTP_TIMEOUT));
+    socket.connect((address, url.port)).await.ok()?;
+    let mut request: String<256> = String::new();
+    http::write_request(&mut request, "HEAD", &url).ok()?;
+
+    // the round trip starts with the request, not with the connection
+    let sent = clock.now().and_utc().timestamp_micros();
+    socket.write_all(request.as_bytes()).await.ok()?;
+    let mut buf = [0u8; MAX_HEAD];
+    let mut len = 0;
+    let mut received = None;
+    loop {
+        match socket.read(&mut buf[len..]).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => len += n,
+        }
+        let received = *received.get_or_insert_with(|| clock.now().and_utc().timestamp_micros());
+        match Response::parse_head(&buf[..len]) {
+            Ok((response, _)) => {
+                socket.close();
+                return Some(Sample::from_seconds(response.date()?, sent, received));
+            }
+            Err(http::Error::Incomplete) if len < buf.len() => {}
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Set the clock from the `Date` of the web server at `url` if it is further
+/// off than that can tell, returns whether the server told the time.
+async fn http_fallback(stack: Stack<'static>, clock: &Clock, url: &str) -> bool {
+    let Some(sample) = http_sample(stack, clock, url).await else {
+        info!(target: "NTP", "No date from {url} either");
+        return false;
+    };
+    if sample.offset.abs() > sample.bound() {
+        info!(target: "NTP", "Taking the time from {url}, {} us off", sample.offset);
+        clock.sync(clock.now() + TimeDelta::microseconds(sample.offset));
+    } else {
+        debug!(target: "NTP", "{url} agrees with the clock");
+    }
+    true
+}
+
+/// Periodically sets the clock from the configured NTP servers.
+///
+/// Only returns if the socket cannot be set up, the network supervisor
+/// starts it again.
+#[embassy_executor::task]
+pub async fn ntp_sync_task(
+    stack: &'static Stack<'static>,
+    clock: &'static Clock,
+    settings: &'static SettingsService,
+) {
+    info!(target: "NTP", "Started NTP task");
+
+    let mut rx_buffer = [0u8; 256];
+    let mut tx_buffer = [0u8; 256];
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut socket = UdpSocket::new(
+        *stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(NTP_LOCAL_PORT) {
+        warn!(target: "NTP", "Unable to bind UDP socket ({e:?})");
+        return;
+    }
+    debug!(target: "NTP", "Bound UDP socket");
+
+    let mut failures = 0;
+    // when the web server last told the time
+    let mut fallback: Option<Instant> = None;
+    loop {
+        let config = settings.read(|s| s.ntp.clone());
+        let mut servers: Vec<IpAddress, MAX_SAMPLES> = Vec::new();
+        if let Some(address) = net::status().address.filter(|_| config.dhcp) {
+            for server in dhcp_servers(*stack, address).await {
+                info!(target: "NTP", "DHCP announces {server}");
+                let _ = servers.push(server);
+            }
+        }
+        for host in &config.servers {
+            match net::resolve(*stack, host).await {
+                // the pool zones may hand out the same server twice
+                Some(address) if !servers.contains(&address) => {
+                    debug!(target: "NTP", "Resolved {host} to {address}");
+                    let _ = servers.push(address);
+                }
+                Some(_) => {}
+                None => info!(target: "NTP", "No address for {host}"),
+            }
+        }
+
+        let mut samples: Vec<Sample, MAX_SAMPLES> = Vec::new();
+        for &server in &servers {
+            if let Some(sample) = best_sample(&socket, clock, server).await {
+                let _ = samples.push(sample);
+            }
+        }
+        let Some(selection) = ntp::select(&samples) else {
+            if samples.is_empty() {
+                info!(target: "NTP", "None of {} NTP servers answered", servers.len());
+            } else {
+                warn!(target: "NTP", "{} NTP servers disagree", samples.len());
+            }
+            failures += 1;
+            let interval = Duration::from_secs(config.interval as u64);
+            let fallback_due = fallback.is_none_or(|at| at.elapsed() >= interval);
+            if !config.http.is_empty()
+                && fallback_due
+                && http_fallback(*stack, clock, &config.http).await
+            {
+                fallback = Some(Instant::now());
+            }
+            let status = match fallback {
+                Some(at) if at.elapsed() < interval => NtpStatus::Fallback,
+                _ => NtpStatus::Failing,
+            };
+            net::update_status(|s| s.ntp = status);
+            retry_later(&config, failures).await;
+            continue;
+        };
+
+        info!(
+            target: "NTP",
+            "{} of {} servers agree on an offset of {} us",
+            selection.agreeing, selection.total, selection.offset
+        );
+        clock.adjust(selection.offset);
+        net::update_status(|s| s.ntp = NtpStatus::Synced);
+        failures = 0;
+        fallback = None;
+
+        Timer::after(Duration::from_secs(config.interval().as_secs())).await;
+    }
+}
@@ -0,0 +1,315 @@
+//! Firmware updates over the air, see [`clocked_core::ota`].
+//!
+//! Updates come in through the REST API, uploaded with `PUT /api/firmware`
+//! or downloaded from a plain HTTP server after `POST /api/firmware`. They
+//! are written into the app partition that is not running and selected once
+//! they checked out, the API server then restarts the clock.
+//!
+//! [`start`] decides about the running image right after a restart and
+//! [`watchdog`] confirms a new one once the station has an address, or rolls
+//! it back after [`HEALTH_TIMEOUT`].
+//!
+//! Updates have to be signed with the secret key of the public key the
+//! firmware is built with, 64 hex digits in `CLOCKED_UPDATE_KEY` (see
+//! `clocked-sim keygen`). A firmware built without one refuses all updates
+//! over the air.
+
+use core::cell::Cell;
+
+use clocked_core::{
+    ed25519::{self, PublicKey},
+    http::{self, Response, Status, Url},
+    ota::{
+        self, Boot, Error, Firmware, ImageError, ImageState, Otadata, Slot, Trial, Update, Verdict,
+        CHIP_ESP32S3, HEALTH_TIMEOUT,
+    },
+    sha256::Digest,
+};
+use embassy_net::{tcp::TcpSocket, Stack};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_io_async::Write;
+use esp_storage::{FlashStorage, FlashStorageError};
+use heapless::String;
+use log::{info, warn};
+
+use crate::{net, timer::uptime};
+
+/// Flash offsets of the partitions in `partitions.csv`.
+pub const OTADATA_OFFSET: u32 = 0xd000;
+pub const OTA_0_OFFSET: u32 = 0x1_0000;
+pub const OTA_1_OFFSET: u32 = 0x20_0000;
+pub const SLOT_SIZE: u32 = 0x1f_0000;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Time without data before an upload or download is given up.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest response head of a download.
+const MAX_HEAD: usize = 1024;
+/// Public key updates are signed for, checked by `build.rs`.
+const UPDATE_KEY: Option<&str> = option_env!("CLOCKED_UPDATE_KEY");
+
+/// Why an update failed, with the status to answer the API request with.
+pub type Failure = (Status, &'static str);
+
+#[derive(Copy, Clone)]
+struct Running {
+    slot: Slot,
+    /// Set while a new image proves itself.
+    trial: Option<Trial>,
+}
+
+static RUNNING: Mutex<CriticalSectionRawMutex, Cell<Running>> = Mutex::new(Cell::new(Running {
+    slot: Slot::Ota0,
+    trial: None,
+}));
+
+/// What the API reports about the running firmware.
+pub fn firmware() -> Firmware<'static> {
+    let running = RUNNING.lock(Cell::get);
+    Firmware {
+        version: env!("CARGO_PKG_VERSION"),
+        slot: running.slot,
+        trial: running.trial.is_some(),
+    }
+}
+
+fn slot_offset(slot: Slot) -> u32 {
+    match slot {
+        Slot::Ota0 => OTA_0_OFFSET,
+        Slot::Ota1 => OTA_1_OFFSET,
+    }
+}
+
+/// Find out which image runs and put a new one on trial. An update that
+/// started before without confirming itself is rolled back right away.
+pub fn start() {
+    let mut flash = FlashStorage::new();
+    let mut otadata = match Otadata::read(&mut flash, OTADATA_OFFSET) {
+        Ok(otadata) => otadata,
+        Err(e) => {
+            warn!(target: "OTA", "Unable to read otadata ({e:?})");
+            return;
+        }
+    };
+    let slot = otadata.boot_slot();
+    info!(target: "OTA", "Running {} from {}", env!("CARGO_PKG_VERSION"), slot.name());
+    if UPDATE_KEY.is_none() {
+        warn!(target: "OTA", "Built without CLOCKED_UPDATE_KEY, refusing updates");
+    }
+    let trial = match ota::boot(&otadata) {
+        Boot::Confirmed => None,
+        Boot::Trial => {
+            info!(target: "OTA", "New firmware, confirming it once on the network");
+            if let Err(e) = otadata.mark(&mut flash, OTADATA_OFFSET, ImageState::PendingVerify) {
+                warn!(target: "OTA", "Unable to mark the firmware ({e:?})");
+            }
+            Some(Trial::new(uptime()))
+        }
+        Boot::RollBack => {
+            warn!(target: "OTA", "New firmware restarted before confirming itself");
+            roll_back(&mut flash, &mut otadata);
+            None
+        }
+    };
+    RUNNING.lock(|running| running.set(Running { slot, trial }));
+}
+
+/// Mark the running image invalid and restart into the previous one.
+fn roll_back(flash: &mut FlashStorage, otadata: &mut Otadata) {
+    match otadata.roll_back(flash, OTADATA_OFFSET) {
+        Ok(slot) => warn!(target: "OTA", "Rolling back to {}", slot.name()),
+        Err(e) => warn!(target: "OTA", "Unable to roll back ({e:?})"),
+    }
+    esp_hal::system::software_reset();
+}
+
+/// Confirms a new image once the station has an address, rolls it back if
+/// that does not happen within [`HEALTH_TIMEOUT`].
+#[embassy_executor::task]
+pub async fn watchdog() {
+    while let Some(trial) = RUNNING.lock(Cell::get).trial {
+        let healthy = net::status().address.is_some();
+        let mut flash = FlashStorage::new();
+        match trial.check(uptime(), healthy) {
+            Some(Verdict::Confirm) => {
+                let marked = Otadata::read(&mut flash, OTADATA_OFFSET).and_then(|mut otadata| {
+                    otadata.mark(&mut flash, OTADATA_OFFSET, ImageState::Valid)
+                });
+                match marked {
+                    Ok(()) => info!(target: "OTA", "New firmware confirmed"),
+                    Err(e) => warn!(target: "OTA", "Unable to confirm the firmware ({e:?})"),
+                }
+                RUNNING.lock(|running| {
+                    running.set(Running {
+                        trial: None,
+                        ..running.get()
+                    })
+                });
+            }
+            Some(Verdict::RollBack) => {
+                warn!(
+                    target: "OTA",
+                    "New firmware not on the network after {} s",
+                    HEALTH_TIMEOUT.as_secs()
+                );
+                match Otadata::read(&mut flash, OTADATA_OFFSET) {
+                    Ok(mut otadata) => roll_back(&mut flash, &mut otadata),
+                    Err(e) => warn!(target: "OTA", "Unable to read otadata ({e:?})"),
+                }
+                return;
+            }
+            None => Timer::after(CHECK_INTERVAL).await,
+        }
+    }
+}
+
+/// Receive an uploaded image of `len` bytes from `socket` and select it,
+/// `received` are the bytes that came with the request head.
+pub async fn receive(
+    socket: &mut TcpSocket<'_>,
+    received: &[u8],
+    len: usize,
+    expected: Option<Digest>,
+) -> Result<(), Failure> {
+    let key = update_key()?;
+    let mut update = begin(expected, key)?;
+    let received = &received[..received.len().min(len)];
+    update.write(received).map_err(failure)?;
+    let mut left = len - received.len();
+    let mut buf = [0u8; 1024];
+    while left > 0 {
+        let limit = left.min(buf.len());
+        match with_timeout(RECEIVE_TIMEOUT, socket.read(&mut buf[..limit])).await {
+            Ok(Ok(0)) | Ok(Err(_)) => return Err((Status::BAD_REQUEST, "upload cut off")),
+            Err(_) => return Err((Status::BAD_REQUEST, "upload timed out")),
+            Ok(Ok(n)) => {
+                update.write(&buf[..n]).map_err(failure)?;
+                left -= n;
+            }
+        }
+    }
+    finish(update)
+}
+
+/// Download an update from a plain HTTP server and select it.
+pub async fn download(stack: Stack<'static>, download: &ota::Download) -> Result<(), Failure> {
+    const UNREACHABLE: Failure = (Status::BAD_GATEWAY, "unable to download the image");
+    let key = update_key()?;
+    let url = Url::parse(&download.url).ok_or((Status::BAD_REQUEST, "invalid URL"))?;
+    let address = net::resolve(stack, url.host).await.ok_or(UNREACHABLE)?;
+    info!(target: "OTA", "Downloading {}", download.url);
+
+    let mut rx_buffer = [0u8; 2048];
+    let mut tx_buffer = [0u8; 512];
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+    socket.set_timeout(Some(RECEIVE_TIMEOUT));
+    socket
+        .connect((address, url.port))
+        .await
+        .map_err(|_| UNREACHABLE)?;
+    let mut request: String<256> = String::new();
+    http::write_request(&mut request, "GET", &url)
+        .map_err(|_| (Status::BAD_REQUEST, "URL too long"))?;
+    socket
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|_| UNREACHABLE)?;
+
+    let mut buf = [0u8; MAX_HEAD];
+    let mut len = 0;
+    let (body_start, content_length) = loop {
+        match socket.read(&mut buf[len..]).await {
+            Ok(0) | Err(_) => return Err(UNREACHABLE),
+            Ok(n) => len += n,
+        }
+        match Response::parse_head(&buf[..len]) {
+            Ok((response, _)) if response.status != 200 => {
+                warn!(target: "OTA", "Server answered {}", response.status);
+                return Err((Status::BAD_GATEWAY, "server did not send the image"));
+            }
+            Ok((response, _)) if response.header("transfer-encoding").is_some() => {
+                return Err((Status::BAD_GATEWAY, "chunked downloads are not supported"));
+            }
+            Ok((response, body_start)) => match response.content_length() {
+                Ok(content_length) => break (body_start, content_length),
+                Err(_) => return Err((Status::BAD_GATEWAY, "invalid response")),
+            },
+            Err(http::Error::Incomplete) if len < buf.len() => {}
+            Err(_) => return Err((Status::BAD_GATEWAY, "invalid response")),
+        }
+    };
+
+    let mut update = begin(download.digest, key)?;
+    update.write(&buf[body_start..len]).map_err(failure)?;
+    // without a length the image ends with the connection
+    while content_length.is_none_or(|total| (update.received() as usize) < total) {
+        match socket.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(n) => update.write(&buf[..n]).map_err(failure)?,
+            Err(_) => return Err(UNREACHABLE),
+        }
+    }
+    socket.close();
+    finish(update)
+}
+
+/// The key updates have to be signed for.
+fn update_key() -> Result<PublicKey, Failure> {
+    UPDATE_KEY
+        .and_then(ed25519::parse_key)
+        .ok_or((Status::FORBIDDEN, "built without an update key"))
+}
+
+/// Start writing to the partition that is not running.
+fn begin(expected: Option<Digest>, key: PublicKey) -> Result<Update<FlashStorage>, Failure> {
+    let slot = RUNNING.lock(Cell::get).slot.other();
+    info!(target: "OTA", "Writing the update to {}", slot.name());
+    Update::new(
+        FlashStorage::new(),
+        slot_offset(slot),
+        SLOT_SIZE,
+        CHIP_ESP32S3,
+        expected,
+        key,
+    )
+    .map_err(failure)
+}
+
+/// Check the complete image and start it from the next restart on.
+fn finish(update: Update<FlashStorage>) -> Result<(), Failure> {
+    let (mut flash, image) = update.finish().map_err(failure)?;
+    let slot = RUNNING.lock(Cell::get).slot.other();
+    Otadata::read(&mut flash, OTADATA_OFFSET)
+        .and_then(|mut otadata| otadata.select(&mut flash, OTADATA_OFFSET, slot))
+        .map_err(|e| {
+            warn!(target: "OTA", "Unable to select the update ({e:?})");
+            (Status::INTERNAL_SERVER_ERROR, "unable to select the update")
+        })?;
+    info!(target: "OTA", "Installed {} bytes to {}", image.len, slot.name());
+    Ok(())
+}
+
+fn failure(e: Error<FlashStorageError>) -> Failure {
+    let problem = match e {
+        Error::Flash(e) => {
+            warn!(target: "OTA", "Unable to write the update ({e:?})");
+            return (Status::INTERNAL_SERVER_ERROR, "unable to write to flash");
+        }
+        Error::InvalidRegion => return (Status::INTERNAL_SERVER_ERROR, "invalid partition"),
+        Error::Image(problem) => problem,
+    };
+    warn!(target: "OTA", "Rejected the update ({problem:?})");
+    let message = match problem {
+        ImageError::NotAnImage => "not a firmware image",
+        ImageError::WrongChip(_) => "firmware for another chip",
+        ImageError::TooLarge => "image too large",
+        ImageError::Truncated => "image cut off",
+        ImageError::Checksum | ImageError::Corrupted => "image corrupted",
+        ImageError::NoDigest => "image without SHA-256 digest",
+        ImageError::Mismatch => "image does not match the sha256",
+        ImageError::Unsigned => "image not signed",
+        ImageError::BadSignature => "image not signed with the update key",
+    };
+    (Status::BAD_REQUEST, message)
+}
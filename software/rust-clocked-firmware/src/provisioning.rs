@@ -0,0 +1,327 @@
+//! Wi-Fi setup through a captive portal.
+//!
+//! Without stored credentials, or after the stored ones failed to connect
+//! [`MAX_CONNECT_FAILURES`] times in a row, the clock opens the open access
+//! point [`AP_SSID`]. It hands out addresses itself and answers every DNS
+//! query with its own address, so phones joining the network show the setup
+//! page right away. The entered credentials are stored in the settings and
+//! the clock restarts with them.
+
+use core::cell::RefCell;
+use core::fmt::Write as _;
+
+use clocked_core::{
+    dhcp, dns,
+    http::{self, Request, Status},
+    wifi::{Credentials, MAX_PASSWORD_LEN, MAX_SSID_LEN},
+};
+use embassy_net::{
+    tcp::TcpSocket,
+    udp::{PacketMetadata, UdpSocket},
+    IpAddress, IpEndpoint, Ipv4Address, Stack,
+};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::Write;
+use esp_wifi::wifi::{
+    AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration, WifiController,
+};
+use heapless::{String, Vec};
+use log::{debug, info, warn};
+
+use crate::settings::SettingsService;
+
+/// Name of the setup access point.
+pub const AP_SSID: &str = "clocked-setup";
+/// Address of the clock on the setup network.
+pub const AP_ADDRESS: Ipv4Address = Ipv4Address::new(192, 168, 4, 1);
+/// Consecutive failed connection attempts before falling back to setup mode.
+pub const MAX_CONNECT_FAILURES: u8 = 5;
+
+/// With stored credentials the clock goes back to trying them after this
+/// long, the network might just have been down for a while.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const MAX_NETWORKS: usize = 16;
+
+/// Networks found by the last scan, offered on the setup page.
+static NETWORKS: Mutex<CriticalSectionRawMutex, RefCell<Vec<String<MAX_SSID_LEN>, MAX_NETWORKS>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+/// Scan for networks, then run the setup access point on `controller`.
+///
+/// Returns after [`SETUP_TIMEOUT`] if `has_credentials` is set, otherwise
+/// only the portal ends setup mode by restarting the clock. The controller is
+/// stopped on return.
+pub async fn setup_mode(controller: &mut WifiController<'static>, has_credentials: bool) {
+    info!(target: "SETUP", "Entering setup mode");
+    scan(controller).await;
+
+    let ap_config = Configuration::AccessPoint(AccessPointConfiguration {
+        ssid: AP_SSID.try_into().unwrap(),
+        auth_method: AuthMethod::None,
+        ..Default::default()
+    });
+    let _ = controller.stop_async().await;
+    if let Err(e) = controller.set_configuration(&ap_config) {
+        warn!(target: "SETUP", "Failed to configure access point: {e:?}");
+        return;
+    }
+    if let Err(e) = controller.start_async().await {
+        warn!(target: "SETUP", "Failed to start access point: {e:?}");
+        return;
+    }
+    info!(target: "SETUP", "Access point {AP_SSID} started, setup page at http://{AP_ADDRESS}/");
+
+    if has_credentials {
+        Timer::after(SETUP_TIMEOUT).await;
+        info!(target: "SETUP", "No new credentials, retrying the stored ones");
+    } else {
+        core::future::pending::<()>().await;
+    }
+    let _ = controller.stop_async().await;
+}
+
+async fn scan(controller: &mut WifiController<'static>) {
+    let _ = controller.stop_async().await;
+    let config = Configuration::Client(ClientConfiguration::default());
+    if controller.set_configuration(&config).is_err() || controller.start_async().await.is_err() {
+        warn!(target: "SETUP", "Unable to start station for scanning");
+        return;
+    }
+    match controller.scan_n_async::<MAX_NETWORKS>().await {
+        Ok((found, _)) => NETWORKS.lock(|networks| {
+            let mut networks = networks.borrow_mut();
+            networks.clear();
+            for ap in found.iter().filter(|ap| !ap.ssid.is_empty()) {
+                if !networks.contains(&ap.ssid) {
+                    let _ = networks.push(ap.ssid.clone());
+                }
+            }
+            info!(target: "SETUP", "Found {} networks", networks.len());
+        }),
+        Err(e) => warn!(target: "SETUP", "Scan failed: {e:?}"),
+    }
+}
+
+/// Hands out addresses on the setup network.
+#[embassy_executor::task]
+pub async fn dhcp_server(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 1024];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket
+        .bind(dhcp::SERVER_PORT)
+        .expect("DHCP server port is free");
+
+    let mut server = dhcp::Server::<8>::new(AP_ADDRESS.octets(), 2, 3600);
+    let mut request = [0u8; 576];
+    let mut reply = [0u8; 576];
+    let broadcast = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::BROADCAST), dhcp::CLIENT_PORT);
+    loop {
+        let len = match socket.recv_from(&mut request).await {
+            Ok((len, _)) => len,
+            Err(e) => {
+                warn!(target: "SETUP", "DHCP receive error {e:?}");
+                continue;
+            }
+        };
+        if let Some(reply_len) = server.handle(&request[..len], &mut reply) {
+            if let Err(e) = socket.send_to(&reply[..reply_len], broadcast).await {
+                warn!(target: "SETUP", "DHCP send error {e:?}");
+            }
+        }
+    }
+}
+
+/// Answers every DNS query with [`AP_ADDRESS`].
+#[embassy_executor::task]
+pub async fn captive_dns(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 1024];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(53).expect("DNS port is free");
+
+    let mut query = [0u8; 512];
+    let mut reply = [0u8; 512];
+    loop {
+        let (len, remote) = match socket.recv_from(&mut query).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!(target: "SETUP", "DNS receive error {e:?}");
+                continue;
+            }
+        };
+        if let Some(reply_len) =
+            dns::captive_response(&query[..len], AP_ADDRESS.octets(), &mut reply)
+        {
+            if let Err(e) = socket.send_to(&reply[..reply_len], remote).await {
+                warn!(target: "SETUP", "DNS send error {e:?}");
+            }
+        }
+    }
+}
+
+enum Outcome {
+    Page,
+    Redirect,
+    Saved,
+    Invalid,
+    /// Valid credentials that could not be written to flash.
+    Unsaved,
+}
+
+/// Serves the setup page and stores submitted credentials.
+#[embassy_executor::task]
+pub async fn portal(stack: Stack<'static>, settings: &'static SettingsService) {
+    let mut rx_buffer = [0u8; 1536];
+    let mut tx_buffer = [0u8; 1536];
+    let mut request = [0u8; 1024];
+    let mut page: String<4096> = String::new();
+    let mut head: String<256> = String::new();
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        if let Err(e) = socket.accept(80).await {
+            warn!(target: "SETUP", "Accept error {e:?}");
+            continue;
+        }
+
+        let mut len = 0;
+        let outcome = loop {
+            match socket.read(&mut request[len..]).await {
+                Ok(0) | Err(_) => break None,
+                Ok(n) => len += n,
+            }
+            match Request::parse(&request[..len]) {
+                Ok(request) => break Some(handle(&request, settings).await),
+                Err(http::Error::Incomplete) if len < request.len() => continue,
+                Err(_) => break Some(Outcome::Invalid),
+            }
+        };
+        let Some(outcome) = outcome else {
+            socket.abort();
+            continue;
+        };
+
+        page.clear();
+        head.clear();
+        let _ = match outcome {
+            Outcome::Page => render_page(&mut page, None),
+            Outcome::Saved => render_page(&mut page, Some("Saved, the clock restarts now.")),
+            Outcome::Invalid => render_page(
+                &mut page,
+                Some(
+                    "Please check the network name and password. Leave the password \
+                     empty for an open network, otherwise it has 8 to 63 characters \
+                     or 64 hex digits.",
+                ),
+            ),
+            Outcome::Unsaved => render_page(
+                &mut page,
+                Some("The settings could not be saved, please try again."),
+            ),
+            Outcome::Redirect => Ok(()),
+        };
+        let _ = match outcome {
+            Outcome::Redirect => {
+                let mut location: String<40> = String::new();
+                let _ = write!(location, "Location: http://{AP_ADDRESS}/\r\n");
+                http::write_head(&mut head, Status::FOUND, "text/plain", 0, &location)
+            }
+            _ => http::write_head(&mut head, Status::OK, "text/html", page.len(), ""),
+        };
+
+        let written = async {
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(page.as_bytes()).await?;
+            socket.flush().await
+        }
+        .await;
+        if let Err(e) = written {
+            debug!(target: "SETUP", "Write error {e:?}");
+        }
+        socket.close();
+        Timer::after(Duration::from_millis(50)).await;
+        socket.abort();
+
+        if matches!(outcome, Outcome::Saved) {
+            Timer::after(Duration::from_secs(1)).await;
+            esp_hal::system::software_reset();
+        }
+    }
+}
+
+async fn handle(request: &Request<'_>, settings: &SettingsService) -> Outcome {
+    match (request.method, request.path) {
+        ("GET", "/") => Outcome::Page,
+        ("POST", "/") => {
+            let form = request.body_str();
+            let ssid = http::form_field::<MAX_SSID_LEN>(form, "ssid");
+            let password = http::form_field::<MAX_PASSWORD_LEN>(form, "password");
+            let Some(credentials) = ssid
+                .zip(password)
+                .and_then(|(ssid, password)| Credentials::new(&ssid, &password))
+            else {
+                return Outcome::Invalid;
+            };
+            info!(target: "SETUP", "Storing credentials for {}", credentials.ssid);
+            match settings.update(|s| s.wifi = Some(credentials)).await {
+                Ok(()) => Outcome::Saved,
+                Err(_) => Outcome::Unsaved,
+            }
+        }
+        // operating systems probe well known URLs to detect captive portals
+        _ => Outcome::Redirect,
+    }
+}
+
+fn render_page(page: &mut impl core::fmt::Write, message: Option<&str>) -> core::fmt::Result {
+    page.write_str(concat!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">",
+        "<meta name=\"viewport\" content=\"width=device-width,initial-scale=1\">",
+        "<title>clocked setup</title>",
+        "<style>body{font-family:sans-serif;max-width:24em;margin:2em auto;padding:0 1em}",
+        "input,button{width:100%;font-size:1.2em;margin:.3em 0 1em}</style>",
+        "</head><body><h1>clocked</h1>",
+    ))?;
+    if let Some(message) = message {
+        page.write_str("<p><b>")?;
+        http::write_html_escaped(page, message)?;
+        page.write_str("</b></p>")?;
+    }
+    page.write_str(concat!(
+        "<form method=\"post\" action=\"/\">",
+        "<label>Network<input name=\"ssid\" list=\"networks\" maxlength=\"32\" required></label>",
+        "<datalist id=\"networks\">",
+    ))?;
+    NETWORKS.lock(|networks| {
+        for ssid in networks.borrow().iter() {
+            page.write_str("<option value=\"")?;
+            http::write_html_escaped(page, ssid)?;
+            page.write_str("\">")?;
+        }
+        Ok(())
+    })?;
+    page.write_str(concat!(
+        "</datalist>",
+        "<label>Password<input name=\"password\" type=\"password\" maxlength=\"64\"></label>",
+        "<button type=\"submit\">Connect</button></form></body></html>",
+    ))
+}
@@ -0,0 +1,115 @@
+//! Battery-backed RTC chip, see [`clocked_core::rtc`].
+//!
+//! An optional DS3231 or PCF8563 on I2C0, SDA on GPIO8 and SCL on GPIO9,
+//! keeps the time while the clock is unplugged. [`start`] sets the clock from
+//! it before anything else runs, without one the clock resumes from the saved
+//! time. The [`keeper`] sets the chip again whenever the clock got a new
+//! time and trims it against NTP.
+
+use chrono::Timelike;
+use clocked_core::{
+    manual::LastTime,
+    rtc::{self, Bus, Chip, TimeSource, Update, WriteBack},
+};
+use embassy_time::{Duration, Timer};
+use esp_hal::{
+    i2c::master::{Error, I2c},
+    Blocking,
+};
+use log::{debug, info, warn};
+
+use crate::net::{self, NtpStatus};
+use crate::time::Clock;
+
+/// How often the keeper looks for a new time of the clock.
+const KEEPER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The I2C driver as a [`Bus`]. It blocks, a transfer of the chips takes
+/// about a millisecond.
+pub struct I2cBus(I2c<'static, Blocking>);
+
+impl Bus for I2cBus {
+    type Error = Error;
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Error> {
+        self.0.write(address, write)
+    }
+
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        self.0.write_read(address, write, read)
+    }
+}
+
+/// Look for a chip on `i2c` and set the clock from it, or from the saved
+/// time `last` if there is none or it lost the time, see [`Clock::resume`].
+pub fn start(
+    i2c: Option<I2c<'static, Blocking>>,
+    clock: &Clock,
+    last: Option<LastTime>,
+) -> Option<Chip<I2cBus>> {
+    let mut chip = match i2c.map(|i2c| rtc::detect(I2cBus(i2c))) {
+        Some(Ok(chip)) => chip,
+        _ => {
+            info!(target: "CLOCK", "No RTC chip");
+            clock.resume(last);
+            return None;
+        }
+    };
+    match chip.read() {
+        Ok(Some(time)) => {
+            info!(target: "CLOCK", "{} has the time {time}", chip.name());
+            clock.sync(time);
+        }
+        Ok(None) => {
+            warn!(target: "CLOCK", "{} lost the time, is its battery empty?", chip.name());
+            clock.resume(last);
+        }
+        Err(e) => {
+            warn!(target: "CLOCK", "Reading the {} failed: {e:?}", chip.name());
+            clock.resume(last);
+        }
+    }
+    Some(chip)
+}
+
+/// Sets the chip after every sync of the clock when [`WriteBack`] says so.
+#[embassy_executor::task]
+pub async fn keeper(mut chip: Chip<I2cBus>, clock: &'static Clock) {
+    let mut write_back = WriteBack::new();
+    let mut synced = clock.last_sync();
+    loop {
+        Timer::after(KEEPER_INTERVAL).await;
+        if clock.last_sync() == synced {
+            continue;
+        }
+        synced = clock.last_sync();
+        let source = match chip.read() {
+            Ok(source) => source,
+            Err(e) => {
+                warn!(target: "CLOCK", "Reading the {} failed: {e:?}", chip.name());
+                continue;
+            }
+        };
+        let precise = net::status().ntp == NtpStatus::Synced;
+        let Update::Write { trim } = write_back.update(source, clock.now(), precise) else {
+            debug!(target: "CLOCK", "{} is on time", chip.name());
+            continue;
+        };
+        if let Some(ppb) = trim {
+            info!(target: "CLOCK", "Trimming the {} by {ppb} ppb", chip.name());
+            if let Err(e) = chip.trim(ppb) {
+                warn!(target: "CLOCK", "Trimming the {} failed: {e:?}", chip.name());
+            }
+        }
+        // the chip starts the second when it is written
+        let fraction = clock.now().nanosecond() % 1_000_000_000;
+        let wait = 1_000_000 - fraction / 1000;
+        Timer::after(Duration::from_micros(wait.into())).await;
+        let now = clock.now();
+        let time = now.with_nanosecond(0).unwrap_or(now);
+        match chip.write(time) {
+            Ok(()) => info!(target: "CLOCK", "Set the {} to {time}", chip.name()),
+            Err(e) => warn!(target: "CLOCK", "Setting the {} failed: {e:?}", chip.name()),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Settings shared between tasks and persisted in the `settings` partition.
+//!
+//! Reading is cheap and synchronous from an in-memory copy, updates go
+//! through [`SettingsService::update`] which writes them to flash first.
+
+use core::cell::RefCell;
+
+use clocked_core::settings::{Error, Settings, Store};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    mutex::Mutex as AsyncMutex,
+};
+use esp_storage::{FlashStorage, FlashStorageError};
+use log::{info, warn};
+
+/// Flash offset of the `settings` partition in `partitions.csv`.
+pub const SETTINGS_OFFSET: u32 = 0x3f_0000;
+pub const SETTINGS_SIZE: u32 = 0x1_0000;
+
+pub struct SettingsService {
+    /// `None` if the partition could not be read, settings then only live
+    /// until the next restart.
+    store: AsyncMutex<CriticalSectionRawMutex, Option<Store<FlashStorage>>>,
+    current: Mutex<CriticalSectionRawMutex, RefCell<Settings>>,
+}
+
+impl SettingsService {
+    pub fn new(flash: FlashStorage) -> Self {
+        let (store, current) = match Store::open(flash, SETTINGS_OFFSET, SETTINGS_SIZE)
+            .and_then(|mut store| store.load().map(|settings| (store, settings)))
+        {
+            Ok((store, settings)) => {
+                info!(target: "SETTINGS", "Loaded settings");
+                (Some(store), settings)
+            }
+            Err(e) => {
+                warn!(target: "SETTINGS", "Unable to load settings ({e:?}), using defaults");
+                (None, Settings::default())
+            }
+        };
+        Self {
+            store: AsyncMutex::new(store),
+            current: Mutex::new(RefCell::new(current)),
+        }
+    }
+
+    /// Copy of the current settings.
+    pub fn get(&self) -> Settings {
+        self.read(Settings::clone)
+    }
+
+    /// Look at the current settings without copying them.
+    pub fn read<R>(&self, f: impl FnOnce(&Settings) -> R) -> R {
+        self.current.lock(|current| f(&current.borrow()))
+    }
+
+    /// Change the settings and persist them.
+    ///
+    /// The in-memory copy is updated even if writing to flash fails, so the
+    /// change is at least in effect until the next restart. Without a store
+    /// the change is only made in memory and [`Error::Unavailable`] returned,
+    /// callers decide whether that is good enough.
+    pub async fn update(
+        &self,
+        f: impl FnOnce(&mut Settings),
+    ) -> Result<(), Error<FlashStorageError>> {
+        let mut store = self.store.lock().await;
+        let mut settings = self.get();
+        f(&mut settings);
+        let result = match store.as_mut() {
+            Some(store) => store.save(&settings),
+            None => Err(Error::Unavailable),
+        };
+        if let Err(e) = &result {
+            warn!(target: "SETTINGS", "Failed to save settings: {e:?}");
+        }
+        self.current.lock(|current| current.replace(settings));
+        result
+    }
+}
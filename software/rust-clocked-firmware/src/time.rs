@@ -0,0 +1,154 @@
+//! Wall-clock time.
+//!
+//! [`Clock`] owns the RTC and is shared between all tasks, the NTP task
+//! writes into it and everybody else reads "now" from it. The RTC runs on
+//! UTC, the configured time zone is only applied when asking for local time.
+//!
+//! "Now" is the RTC plus the correction of a [`Discipline`], which slews NTP
+//! offsets in and makes up for the drift of the oscillator between syncs.
+//!
+//! The time counts as known once it came from any source, NTP, a web server
+//! or by hand, or survived a restart in the RTC, see [`Clock::resume`], or in
+//! a battery-backed chip, see [`crate::rtc`].
+
+use core::cell::{Cell, RefCell};
+
+use chrono::{NaiveDateTime, TimeDelta};
+use clocked_core::{
+    manual::{self, LastTime, Resume},
+    ntp::{Adjustment, Discipline},
+    tz::{TimeZone, MAX_NAME_LEN},
+};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::Instant;
+use esp_hal::rtc_cntl::Rtc;
+use heapless::String;
+use log::info;
+
+use crate::timer::uptime;
+
+/// Clock service shared between tasks.
+///
+/// All accessors take `&self` and only hold a critical section for the
+/// duration of a register access, so a `&'static Clock` can be handed to any
+/// number of tasks.
+pub struct Clock {
+    rtc: Mutex<CriticalSectionRawMutex, RefCell<Rtc<'static>>>,
+    discipline: Mutex<CriticalSectionRawMutex, Cell<Discipline>>,
+    last_sync: Mutex<CriticalSectionRawMutex, Cell<Option<Instant>>>,
+    tz: Mutex<CriticalSectionRawMutex, RefCell<TimeZone>>,
+}
+
+impl Clock {
+    pub fn new(rtc: Rtc<'static>) -> Self {
+        Self {
+            rtc: Mutex::new(RefCell::new(rtc)),
+            discipline: Mutex::new(Cell::new(Discipline::new())),
+            last_sync: Mutex::new(Cell::new(None)),
+            tz: Mutex::new(RefCell::new(TimeZone::utc())),
+        }
+    }
+
+    /// Current UTC time as kept by the RTC and corrected by the discipline.
+    ///
+    /// Before the first sync this counts from the epoch since boot.
+    pub fn now(&self) -> NaiveDateTime {
+        let correction = self.discipline.lock(|d| d.get().correction(uptime()));
+        self.rtc.lock(|rtc| rtc.borrow().current_time()) + TimeDelta::microseconds(correction)
+    }
+
+    /// Current local wall time in the configured time zone.
+    pub fn now_local(&self) -> NaiveDateTime {
+        let now = self.now();
+        self.tz.lock(|tz| tz.borrow().to_local(now))
+    }
+
+    pub fn set_time_zone(&self, tz: TimeZone) {
+        info!(target: "CLOCK", "Time zone {}", tz.name_at(self.now()));
+        self.tz.lock(|current| current.replace(tz));
+    }
+
+    /// Abbreviation of the time zone in effect now, e.g. `CEST`.
+    pub fn time_zone_name(&self) -> String<MAX_NAME_LEN> {
+        let now = self.now();
+        self.tz
+            .lock(|tz| String::try_from(tz.borrow().name_at(now)).unwrap_or_default())
+    }
+
+    /// Set the clock from a time source other than NTP and remember when.
+    ///
+    /// The discipline starts over from it, keeping the drift, see
+    /// [`Discipline::restart`].
+    pub fn sync(&self, time: NaiveDateTime) {
+        let before = self.now();
+        let now = uptime();
+        self.rtc.lock(|rtc| {
+            rtc.borrow_mut().set_current_time(time);
+            self.discipline.lock(|d| {
+                let mut discipline = d.get();
+                discipline.restart(now);
+                d.set(discipline);
+            });
+        });
+        self.last_sync.lock(|last| last.set(Some(Instant::now())));
+        info!(target: "CLOCK", "Set time to {} (was {})", time, before);
+    }
+
+    /// Set the clock to the local wall time `local`, see [`manual::to_utc`].
+    pub fn set_local(&self, local: NaiveDateTime) {
+        let utc = self.tz.lock(|tz| manual::to_utc(&tz.borrow(), local));
+        self.sync(utc);
+    }
+
+    /// Make sense of the RTC after a start with the time saved before, see
+    /// [`manual::resume`].
+    pub fn resume(&self, last: Option<LastTime>) {
+        let rtc = self.rtc.lock(|rtc| rtc.borrow().current_time());
+        match manual::resume(rtc, last) {
+            Resume::Kept => {
+                self.last_sync.lock(|last| last.set(Some(Instant::now())));
+                info!(target: "CLOCK", "RTC kept the time {rtc}");
+            }
+            Resume::Restored(time) => {
+                self.rtc.lock(|rtc| rtc.borrow_mut().set_current_time(time));
+                info!(target: "CLOCK", "Resuming from {time}, the time is not set");
+            }
+            Resume::Unknown => info!(target: "CLOCK", "The time is not set"),
+        }
+    }
+
+    /// Correct the clock by the `offset` in microseconds the NTP servers
+    /// agree on, stepped or slewed as the discipline decides.
+    pub fn adjust(&self, offset: i64) {
+        let now = uptime();
+        let (adjustment, drift) = self.rtc.lock(|rtc| {
+            let mut rtc = rtc.borrow_mut();
+            self.discipline.lock(|d| {
+                let mut discipline = d.get();
+                let adjustment = discipline.update(now, offset);
+                d.set(discipline);
+                let time = rtc.current_time() + TimeDelta::microseconds(adjustment.shift());
+                rtc.set_current_time(time);
+                (adjustment, discipline.drift_ppb())
+            })
+        });
+        self.last_sync.lock(|last| last.set(Some(Instant::now())));
+        match adjustment {
+            Adjustment::Step(_) => info!(target: "CLOCK", "Set time to {}", self.now()),
+            Adjustment::Slew { offset, .. } => {
+                info!(target: "CLOCK", "Slewing {offset} us, drift {drift} ppb")
+            }
+        }
+    }
+
+    /// Monotonic instant of the last successful sync, `None` if never synced.
+    ///
+    /// A time kept through a restart counts as synced at the start.
+    pub fn last_sync(&self) -> Option<Instant> {
+        self.last_sync.lock(|last| last.get())
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.last_sync().is_some()
+    }
+}
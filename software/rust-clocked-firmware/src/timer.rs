@@ -0,0 +1,117 @@
+//! Countdown timer.
+//!
+//! [`TimerService`] holds the countdown shown on the ring. It is started and
+//! stopped from the button and the network, the LED task picks it up on the
+//! next frame.
+//!
+//! The BOOT button starts a countdown of [`STEP`] with a short press and adds
+//! another [`STEP`] to a running one, a long press stops it. Holding it for
+//! [`SET_PRESS`] starts setting the time, which then takes all presses, see
+//! [`TimeSetter`].
+
+use core::cell::Cell;
+
+use clocked_core::timer::Countdown;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use esp_hal::gpio::Input;
+use log::info;
+
+use crate::{manual::TimeSetter, time::Clock};
+
+/// Time added per short button press.
+pub const STEP: core::time::Duration = core::time::Duration::from_secs(5 * 60);
+const LONG_PRESS: Duration = Duration::from_secs(1);
+/// Hold to start setting the time.
+pub const SET_PRESS: Duration = Duration::from_secs(5);
+const DEBOUNCE: Duration = Duration::from_millis(30);
+
+/// Time since boot, the time base of the countdown and the animations.
+pub fn uptime() -> core::time::Duration {
+    core::time::Duration::from_micros(Instant::now().as_micros())
+}
+
+/// Countdown shared between tasks.
+pub struct TimerService {
+    countdown: Mutex<CriticalSectionRawMutex, Cell<Option<Countdown>>>,
+}
+
+impl TimerService {
+    pub const fn new() -> Self {
+        Self {
+            countdown: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// The countdown to show, `None` once its celebration is over.
+    pub fn get(&self) -> Option<Countdown> {
+        let now = uptime();
+        self.countdown
+            .lock(|countdown| countdown.get().filter(|c| !c.is_over(now)))
+    }
+
+    /// Start counting down `duration`, replacing a running countdown.
+    pub fn start(&self, duration: core::time::Duration) {
+        let countdown = Countdown::new(duration, uptime());
+        info!("Timer started for {} s", countdown.duration.as_secs());
+        self.countdown.lock(|c| c.set(Some(countdown)));
+    }
+
+    /// Add `by` to the running countdown, or start one if there is none.
+    pub fn extend(&self, by: core::time::Duration) {
+        let now = uptime();
+        match self.get().filter(|c| !c.is_done(now)) {
+            Some(mut countdown) => {
+                countdown.extend(by, now);
+                info!("Timer at {} s", countdown.remaining(now).as_secs());
+                self.countdown.lock(|c| c.set(Some(countdown)));
+            }
+            None => self.start(by),
+        }
+    }
+
+    pub fn stop(&self) {
+        if self.countdown.lock(|c| c.take()).is_some() {
+            info!("Timer stopped");
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn button_task(
+    mut button: Input<'static>,
+    timer: &'static TimerService,
+    setter: &'static TimeSetter,
+    clock: &'static Clock,
+) {
+    loop {
+        button.wait_for_falling_edge().await;
+        Timer::after(DEBOUNCE).await;
+        if button.is_high() {
+            continue;
+        }
+        let long = match select(button.wait_for_high(), Timer::after(LONG_PRESS)).await {
+            Either::First(()) => false,
+            Either::Second(()) => true,
+        };
+        if setter.press(long, clock) {
+            // taken by setting the time
+        } else if long {
+            timer.stop();
+            // holding on starts setting the time
+            if with_timeout(SET_PRESS - LONG_PRESS, button.wait_for_high())
+                .await
+                .is_err()
+            {
+                setter.start(clock);
+            }
+        } else {
+            timer.extend(STEP);
+        }
+        if button.is_low() {
+            button.wait_for_high().await;
+        }
+        Timer::after(DEBOUNCE).await;
+    }
+}
@@ -0,0 +1,302 @@
+'use strict';
+
+const $ = (selector) => document.querySelector(selector);
+const MAX_BLOCKS = 8;
+let animations = [];
+
+async function api(method, path, body) {
+  // files such as firmware images go out as they are
+  const raw = body instanceof Blob;
+  const headers = body && !raw ? { 'Content-Type': 'application/json' } : {};
+  const token = localStorage.getItem('token');
+  if (token) {
+    headers.Authorization = 'Bearer ' + token;
+  }
+  const response = await fetch('/api/' + path, {
+    method,
+    headers,
+    body: body && !raw ? JSON.stringify(body) : body,
+  });
+  if (response.status === 401) {
+    // changes need the API token, ask for it and try again
+    const entered = prompt(token ? 'Wrong API token, please enter it again' : 'API token of the clock');
+    if (entered) {
+      localStorage.setItem('token', entered.trim());
+      return api(method, path, body);
+    }
+  }
+  const json = await response.json();
+  if (!response.ok) {
+    throw new Error(json.error || response.statusText);
+  }
+  return json;
+}
+
+function show(text, error) {
+  const message = $('#message');
+  message.textContent = text;
+  message.classList.toggle('error', !!error);
+  clearTimeout(show.timeout);
+  show.timeout = setTimeout(() => { message.textContent = ''; }, error ? 6000 : 2000);
+}
+
+/** Run `action`, report the outcome and reload what it changed. */
+async function run(action, done, reload) {
+  try {
+    await action();
+    show(done);
+    await reload();
+  } catch (e) {
+    show(e.message, true);
+  }
+}
+
+function options(select, values, extra) {
+  select.replaceChildren(...(extra ? [extra] : []).concat(values).map((value) => new Option(value, value)));
+}
+
+function duration(seconds) {
+  const minutes = Math.floor(seconds / 60);
+  return minutes + ':' + String(seconds % 60).padStart(2, '0');
+}
+
+async function status() {
+  try {
+    const s = await api('GET', 'status');
+    const sync = s.synced ? 'synced ' + duration(s.since_sync) + ' ago' : 'not synced yet';
+    const signal = s.rssi === null ? '' : ', Wi-Fi ' + s.rssi + ' dBm';
+    $('#status').textContent = s.time.replace('T', ' ') + ' ' + s.time_zone + ' (' + sync + signal + ')';
+    $('#timer').textContent = s.timer === null ? 'No timer running' : duration(s.timer) + ' left';
+  } catch (e) {
+    $('#status').textContent = 'Clock not reachable';
+  }
+}
+
+function fillDisplay(settings) {
+  const form = $('#display');
+  form.brightness.value = settings.brightness;
+  options(form.animation, animations);
+  form.animation.value = settings.animation;
+  for (const hand of ['hour_color', 'minute_color', 'second_color']) {
+    form[hand].value = settings[hand];
+  }
+}
+
+function fillTime(settings) {
+  const form = $('#time');
+  form.time_zone.value = settings.time_zone;
+  const known = [...form.preset.options].some((option) => option.value === settings.time_zone);
+  form.preset.value = known ? settings.time_zone : '';
+}
+
+function fillNight(night) {
+  const form = $('#night');
+  form.enabled.checked = night.enabled;
+  for (const key of ['start', 'wake', 'level', 'wake_color', 'wake_minutes']) {
+    form[key].value = night[key];
+  }
+}
+
+function fillNtp(ntp) {
+  const form = $('#ntp');
+  form.servers.value = ntp.servers.join('\n');
+  form.dhcp.checked = ntp.dhcp;
+  form.http.value = ntp.http;
+  form.interval.value = ntp.interval;
+  form.retry.value = ntp.retry;
+}
+
+function fillMqtt(mqtt) {
+  const form = $('#mqtt');
+  form.enabled.checked = mqtt.enabled;
+  form.discovery.checked = mqtt.discovery;
+  for (const key of ['host', 'port', 'username']) {
+    form[key].value = mqtt[key];
+  }
+  form.password.value = '';
+}
+
+/** Split a block in its text form, `HH:MM-HH:MM days rrggbb name [animation] [@level]`. */
+function parseBlock(text) {
+  const [times, days, color, name, ...rest] = text.split(' ');
+  const [start, end] = times.split('-');
+  const block = { start, end, days, color: '#' + color, name, animation: '', level: '' };
+  for (const part of rest) {
+    if (part.startsWith('@')) {
+      block.level = part.slice(1);
+    } else {
+      block.animation = part;
+    }
+  }
+  return block;
+}
+
+function formatBlock(block) {
+  const parts = [block.start + '-' + block.end, block.days.trim(), block.color.slice(1), block.name];
+  if (block.animation) {
+    parts.push(block.animation);
+  }
+  if (block.level !== '') {
+    parts.push('@' + block.level);
+  }
+  return parts.join(' ');
+}
+
+function addBlock(block) {
+  const blocks = $('#blocks');
+  if (blocks.children.length >= MAX_BLOCKS) {
+    show('At most ' + MAX_BLOCKS + ' blocks', true);
+    return;
+  }
+  const fieldset = $('#block').content.firstElementChild.cloneNode(true);
+  const field = (name) => fieldset.querySelector('[name=' + name + ']');
+  options(field('animation'), animations, '');
+  field('animation').options[0].textContent = 'as usual';
+  for (const [key, value] of Object.entries(block)) {
+    field(key).value = value;
+  }
+  fieldset.querySelector('.remove').onclick = () => fieldset.remove();
+  blocks.append(fieldset);
+}
+
+function fillSchedule(schedule) {
+  $('#blocks').replaceChildren();
+  schedule.map(parseBlock).forEach(addBlock);
+}
+
+function fillFirmware(firmware) {
+  const trial = firmware.trial ? ', on trial until it reaches the network' : '';
+  $('#version').textContent = 'Running ' + firmware.version + ' from ' + firmware.partition + trial + '.';
+}
+
+async function refresh() {
+  const [settings, animation, firmware] = await Promise.all([
+    api('GET', 'settings'), api('GET', 'animation'), api('GET', 'firmware'),
+  ]);
+  animations = animation.available;
+  fillDisplay(settings);
+  fillTime(settings);
+  fillNight(settings.night);
+  fillSchedule(settings.schedule);
+  $('#network').device_name.value = settings.device_name;
+  fillNtp(settings.ntp);
+  fillMqtt(settings.mqtt);
+  fillFirmware(firmware);
+  await status();
+}
+
+function submit(id, update) {
+  $(id).onsubmit = (event) => {
+    event.preventDefault();
+    run(() => api('PUT', 'settings', update(event.target)), 'Saved', refresh);
+  };
+}
+
+submit('#display', (form) => ({
+  brightness: Number(form.brightness.value),
+  animation: form.animation.value,
+  hour_color: form.hour_color.value,
+  minute_color: form.minute_color.value,
+  second_color: form.second_color.value,
+}));
+
+submit('#time', (form) => ({ time_zone: form.time_zone.value.trim() }));
+
+submit('#night', (form) => ({
+  night: {
+    enabled: form.enabled.checked,
+    start: form.start.value,
+    wake: form.wake.value,
+    level: Number(form.level.value),
+    wake_color: form.wake_color.value,
+    wake_minutes: Number(form.wake_minutes.value),
+  },
+}));
+
+submit('#schedule', () => ({
+  schedule: [...$('#blocks').children].map((fieldset) => {
+    const block = {};
+    for (const input of fieldset.querySelectorAll('[name]')) {
+      block[input.name] = input.value;
+    }
+    return formatBlock(block);
+  }),
+}));
+
+$('#network').onsubmit = (event) => {
+  event.preventDefault();
+  const form = event.target;
+  const update = { device_name: form.device_name.value.trim() };
+  // the token is never shown, only send it when it was entered or removed
+  const token = form.remove_token.checked ? '' : form.api_token.value.trim();
+  if (token || form.remove_token.checked) {
+    update.api_token = token;
+  }
+  run(async () => {
+    await api('PUT', 'settings', update);
+    if (token) {
+      localStorage.setItem('token', token);
+    } else if (form.remove_token.checked) {
+      localStorage.removeItem('token');
+    }
+    form.api_token.value = '';
+    form.remove_token.checked = false;
+  }, 'Saved', refresh);
+};
+
+submit('#ntp', (form) => ({
+  ntp: {
+    servers: form.servers.value.split('\n').map((line) => line.trim()).filter((line) => line),
+    dhcp: form.dhcp.checked,
+    http: form.http.value.trim(),
+    interval: Number(form.interval.value),
+    retry: Number(form.retry.value),
+  },
+}));
+
+submit('#mqtt', (form) => {
+  const mqtt = {
+    enabled: form.enabled.checked,
+    host: form.host.value.trim(),
+    port: Number(form.port.value),
+    username: form.username.value,
+    discovery: form.discovery.checked,
+  };
+  // the password is never shown, only send it when it was entered
+  if (form.password.value) {
+    mqtt.password = form.password.value;
+  }
+  return { mqtt };
+});
+
+$('#firmware').onsubmit = (event) => {
+  event.preventDefault();
+  const form = event.target;
+  const [file] = form.file.files;
+  const url = form.url.value.trim();
+  if (!file && !url) {
+    show('Choose an image file or enter a URL', true);
+    return;
+  }
+  const install = file
+    ? () => api('PUT', 'firmware', file)
+    : () => api('POST', 'firmware', { url, sha256: form.sha256.value.trim() || undefined });
+  // the clock restarts right after answering, nothing to reload
+  run(install, 'Installed, the clock restarts now', async () => {});
+};
+
+$('#time').preset.onchange = (event) => {
+  if (event.target.value) {
+    $('#time').time_zone.value = event.target.value;
+  }
+};
+
+$('#add-block').onclick = () => addBlock({ name: 'block', start: '08:00', end: '09:00', days: 'daily', color: '#003000' });
+
+for (const button of document.querySelectorAll('[data-minutes]')) {
+  button.onclick = () => run(() => api('POST', 'timer', { minutes: Number(button.dataset.minutes) }), 'Timer started', status);
+}
+$('#timer-stop').onclick = () => run(() => api('DELETE', 'timer'), 'Timer stopped', status);
+
+refresh().catch((e) => show(e.message, true));
+setInterval(status, 5000);